@@ -0,0 +1,77 @@
+#![feature(test)]
+
+extern crate rocket_file_cache;
+extern crate tempdir;
+extern crate test;
+extern crate tokio;
+
+use rocket_file_cache::{Cache, CacheBuilder};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempdir::TempDir;
+use test::Bencher;
+
+const THREADS: usize = 8;
+const FILES: usize = 64;
+const GETS_PER_THREAD: usize = 1000;
+
+/// Creates a cache that holds every file in the directory, and reads them into it.
+fn warm_cache(temp_dir: &TempDir, concurrency: u16) -> (Arc<Cache>, Vec<PathBuf>) {
+    let cache: Cache = CacheBuilder::new()
+        .size_limit(1024 * 1024 * 10)
+        .concurrency(concurrency)
+        .build()
+        .unwrap();
+    let paths: Vec<PathBuf> = (0..FILES)
+        .map(|index| {
+            let path: PathBuf = temp_dir.path().join(format!("{}.txt", index));
+            std::fs::write(&path, vec![b'a'; 4096]).unwrap();
+            path
+        })
+        .collect();
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        for path in &paths {
+            assert!(cache.get(path).await.is_in_memory());
+        }
+    });
+    (Arc::new(cache), paths)
+}
+
+/// Serves files from the cache on several threads at once, each thread going through the files in a different order.
+fn concurrent_hits(b: &mut Bencher, concurrency: u16) {
+    let temp_dir = TempDir::new("concurrent_reads").unwrap();
+    let (cache, paths) = warm_cache(&temp_dir, concurrency);
+    let paths: Arc<Vec<PathBuf>> = Arc::new(paths);
+
+    b.iter(|| {
+        let threads: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let cache: Arc<Cache> = cache.clone();
+                let paths: Arc<Vec<PathBuf>> = paths.clone();
+                std::thread::spawn(move || {
+                    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+                    runtime.block_on(async {
+                        for index in 0..GETS_PER_THREAD {
+                            let path: &PathBuf = &paths[(index * (thread + 1)) % FILES];
+                            assert!(cache.get(path).await.is_in_memory());
+                        }
+                    });
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    });
+}
+
+#[bench]
+fn concurrent_hits_one_shard(b: &mut Bencher) {
+    concurrent_hits(b, 1);
+}
+
+#[bench]
+fn concurrent_hits_default_shards(b: &mut Bencher) {
+    concurrent_hits(b, 16);
+}
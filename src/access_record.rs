@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+
+/// Keeps track of how often, and how recently, a file has been requested.
+///
+/// The record can be updated through a shared reference, so serving a file from the cache only
+/// needs to take out shared locks on the cache's maps.
+/// Both the count and the time of the last access are atomics, so concurrent requests for the same
+/// file don't wait on each other to record their access.
+#[derive(Debug)]
+pub(crate) struct AccessRecord {
+    access_count: AtomicUsize,
+    /// The time the record was created, which the time of the last access is measured from.
+    created: Instant,
+    /// The nanoseconds between the creation of the record and the last access.
+    last_accessed: AtomicU64,
+}
+
+impl AccessRecord {
+    pub(crate) fn new(access_count: usize) -> AccessRecord {
+        AccessRecord {
+            access_count: AtomicUsize::new(access_count),
+            created: Instant::now(),
+            last_accessed: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn access_count(&self) -> usize {
+        self.access_count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_access_count(&self, access_count: usize) {
+        self.access_count.store(access_count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn last_accessed(&self) -> Option<Instant> {
+        self.created.checked_add(Duration::from_nanos(self.last_accessed.load(Ordering::Relaxed)))
+    }
+
+    /// Increments the access count, and marks the file as having just been accessed.
+    /// If the access count bumps up against the usize max, the count stays the same.
+    pub(crate) fn record_access(&self) {
+        let _ = self.access_count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| count.checked_add(1));
+        // A record would have to be centuries old for the nanoseconds since it was created to overflow.
+        let since_created: u64 = self.created.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        self.last_accessed.fetch_max(since_created, Ordering::Relaxed);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_count_saturates() {
+        let record = AccessRecord::new(usize::MAX - 1);
+        record.record_access();
+        assert_eq!(record.access_count(), usize::MAX);
+        record.record_access();
+        assert_eq!(record.access_count(), usize::MAX);
+    }
+
+    #[test]
+    fn last_access_moves_forward() {
+        let record = AccessRecord::new(0);
+        let created: Instant = record.last_accessed().unwrap();
+        std::thread::sleep(Duration::from_millis(1));
+        record.record_access();
+        assert!(record.last_accessed().unwrap() > created);
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use crate::cache::Cache;
+
+
+/// The number of steps between the smallest and the largest capacity.
+const STEPS: usize = 10;
+
+/// How far above the target the hit ratio must be before the capacity is reduced, so the capacity
+/// doesn't alternate between two steps when the hit ratio is right at the target.
+const SHRINK_MARGIN: f64 = 0.05;
+
+
+/// Chooses the capacity of the cache from the hit ratio over the last interval.
+#[derive(Debug, Clone)]
+pub(crate) struct AdaptiveCapacity {
+    min_bytes: usize,
+    max_bytes: usize,
+    target_hit_ratio: f64,
+    last_hits: usize,
+    last_misses: usize,
+}
+
+impl AdaptiveCapacity {
+    pub(crate) fn new(min_bytes: usize, max_bytes: usize, target_hit_ratio: f64, hits: usize, misses: usize) -> AdaptiveCapacity {
+        AdaptiveCapacity {
+            min_bytes,
+            max_bytes,
+            target_hit_ratio,
+            last_hits: hits,
+            last_misses: misses,
+        }
+    }
+
+    fn step(&self) -> usize {
+        ((self.max_bytes - self.min_bytes) / STEPS).max(1)
+    }
+
+    /// Keeps the capacity within the bounds.
+    pub(crate) fn clamp(&self, capacity: usize) -> usize {
+        capacity.max(self.min_bytes).min(self.max_bytes)
+    }
+
+    /// The capacity for the next interval, given the current capacity and the total number of hits and misses so far.
+    pub(crate) fn next_capacity(&mut self, capacity: usize, hits: usize, misses: usize) -> usize {
+        let interval_hits: usize = hits.saturating_sub(self.last_hits);
+        let interval_misses: usize = misses.saturating_sub(self.last_misses);
+        self.last_hits = hits;
+        self.last_misses = misses;
+
+        let capacity: usize = self.clamp(capacity);
+        let requests: usize = interval_hits + interval_misses;
+        if requests == 0 {
+            return capacity;
+        }
+        let hit_ratio: f64 = interval_hits as f64 / requests as f64;
+        if hit_ratio < self.target_hit_ratio {
+            self.clamp(capacity.saturating_add(self.step()))
+        } else if hit_ratio > self.target_hit_ratio + SHRINK_MARGIN {
+            self.clamp(capacity.saturating_sub(self.step()))
+        } else {
+            capacity
+        }
+    }
+}
+
+
+impl Cache {
+    /// Spawns a task that adjusts the capacity of the cache every `interval`, keeping it between
+    /// `min_bytes` and `max_bytes`, so that it uses as little memory as possible while serving
+    /// `target_hit_ratio` of the files it finds from memory.
+    ///
+    /// The capacity is first moved into the bounds, evicting files if needed. Then, at the end of every interval:
+    ///
+    /// * The hit ratio is calculated from the hits and misses counted in `stats()` during that interval.
+    ///   If no files were served, the capacity doesn't change.
+    /// * If the hit ratio is below the target, the capacity grows by a tenth of the difference between
+    ///   `max_bytes` and `min_bytes`, unless it is already `max_bytes`.
+    /// * If the hit ratio is more than 0.05 above the target, the capacity shrinks by the same step,
+    ///   unless it is already `min_bytes`. Files are evicted to fit the new capacity, like with `set_capacity_bytes()`.
+    /// * Otherwise the capacity stays as it is.
+    ///
+    /// The capacity therefore takes at most 10 intervals to move from one bound to the other.
+    /// Changing the capacity with `set_capacity_bytes()` while the task is running is allowed, but the
+    /// task moves it back into the bounds at the end of the next interval.
+    ///
+    /// The task only holds a weak reference to the cache, and will stop once the cache is dropped.
+    /// It can be stopped earlier by aborting the returned handle, which leaves the capacity as it is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_bytes` is larger than `max_bytes`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Arc<Cache> = Arc::new(CacheBuilder::new().build().unwrap());
+    /// let _adaptive = cache.clone().enable_adaptive(1024 * 1024 * 16, 1024 * 1024 * 256, 0.9, Duration::from_secs(60));
+    /// # }
+    /// ```
+    pub fn enable_adaptive(self: Arc<Self>, min_bytes: usize, max_bytes: usize, target_hit_ratio: f64, interval: Duration) -> JoinHandle<()> {
+        assert!(min_bytes <= max_bytes, "min_bytes must not be larger than max_bytes");
+        let mut adaptive = AdaptiveCapacity::new(
+            min_bytes,
+            max_bytes,
+            target_hit_ratio,
+            self.counters.hits.load(Ordering::Relaxed),
+            self.counters.misses.load(Ordering::Relaxed),
+        );
+        self.set_capacity_bytes(adaptive.clamp(self.capacity_bytes()));
+        let cache: Weak<Cache> = Arc::downgrade(&self);
+        drop(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // The first tick completes immediately, before anything has been counted.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match cache.upgrade() {
+                    Some(cache) => {
+                        let capacity: usize = cache.capacity_bytes();
+                        let next_capacity: usize = adaptive.next_capacity(
+                            capacity,
+                            cache.counters.hits.load(Ordering::Relaxed),
+                            cache.counters.misses.load(Ordering::Relaxed),
+                        );
+                        if next_capacity != capacity {
+                            debug!("Changing the capacity of the cache from {} to {} bytes.", capacity, next_capacity);
+                            cache.set_capacity_bytes(next_capacity);
+                        }
+                    }
+                    None => break, // The cache has been dropped.
+                }
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_follows_hit_ratio_within_bounds() {
+        let mut adaptive = AdaptiveCapacity::new(1000, 2000, 0.8, 0, 0);
+        // Below the target, the capacity grows by a tenth of the range at a time.
+        assert_eq!(adaptive.next_capacity(1000, 50, 50), 1100);
+        assert_eq!(adaptive.next_capacity(1950, 100, 100), 2000);
+        // Only the hits and misses of the last interval count.
+        assert_eq!(adaptive.next_capacity(2000, 200, 100), 1900);
+        assert_eq!(adaptive.next_capacity(1900, 282, 120), 1900);
+        assert_eq!(adaptive.next_capacity(1900, 282, 120), 1900);
+        assert_eq!(adaptive.next_capacity(1050, 382, 120), 1000);
+        // Capacities outside of the bounds are moved into them.
+        assert_eq!(adaptive.next_capacity(5000, 382, 120), 2000);
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::lock;
+
+
+/// The maximum number of files that will have their requests counted before they are admitted into the cache.
+const ADMISSION_CAPACITY: usize = 10_000;
+
+
+/// Counts the requests for files that aren't in the cache, so files are only read into the cache
+/// once they have been requested a number of times.
+#[derive(Debug)]
+pub(crate) struct Admission {
+    admit_after: usize,
+    counts: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl Admission {
+    pub(crate) fn new(admit_after: usize) -> Admission {
+        Admission {
+            admit_after,
+            counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request for the file, returning true if the file has been requested often enough to be read into the cache.
+    ///
+    /// Once the counts for too many files are being kept, every count is halved, and the files that
+    /// have only been requested once are forgotten.
+    pub(crate) fn admit(&self, path: &PathBuf) -> bool {
+        let mut counts = self.counts();
+        if !counts.contains_key(path) && counts.len() >= ADMISSION_CAPACITY {
+            counts.retain(|_, count| {
+                *count /= 2;
+                *count > 0
+            });
+            if counts.len() >= ADMISSION_CAPACITY {
+                counts.clear();
+            }
+        }
+
+        let count: &mut usize = counts.entry(path.clone()).or_insert(0);
+        *count = count.saturating_add(1);
+        if *count >= self.admit_after {
+            counts.remove(path);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn counts(&self) -> MutexGuard<HashMap<PathBuf, usize>> {
+        lock::lock(&self.counts)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admits_after_enough_requests() {
+        let admission = Admission::new(3);
+        let path = PathBuf::from("a.txt");
+        assert!(!admission.admit(&path));
+        assert!(!admission.admit(&path));
+        assert!(admission.admit(&path));
+        // The count starts over once the file has been admitted.
+        assert!(!admission.admit(&path));
+    }
+
+    #[test]
+    fn counts_are_bounded() {
+        let admission = Admission::new(2);
+        for i in 0..ADMISSION_CAPACITY * 2 {
+            admission.admit(&PathBuf::from(format!("{}.txt", i)));
+            assert!(admission.counts().len() <= ADMISSION_CAPACITY);
+        }
+    }
+}
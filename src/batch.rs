@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use rocket::futures::stream::{self, StreamExt};
+
+use crate::cache::Cache;
+use crate::cached_file::CachedFile;
+
+
+/// The maximum number of files that will be gotten at once by `get_many()`.
+const BATCH_CONCURRENCY: usize = 16;
+
+
+impl Cache {
+    /// Gets each of the files, like `get()` does, returning them in the same order as the paths.
+    ///
+    /// The files are gotten concurrently, so files that aren't in the cache yet are read at the same time.
+    /// While the batch is being gotten, its files are pinned, so files that it reads into the cache
+    /// can't be evicted to make room for the other files in the batch.
+    /// If the batch doesn't fit in the cache, the files that come later are served from the
+    /// filesystem instead. The files that weren't already pinned are unpinned once the batch is done.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, CachedFile};
+    /// use std::path::PathBuf;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let files: Vec<CachedFile> = cache.get_many(vec![
+    ///     PathBuf::from("www/css/style.css"),
+    ///     PathBuf::from("www/js/app.js"),
+    /// ]).await;
+    /// assert_eq!(files.len(), 2);
+    /// # }
+    /// ```
+    pub async fn get_many<I: IntoIterator<Item = PathBuf>>(&self, paths: I) -> Vec<CachedFile> {
+        let paths: Vec<PathBuf> = paths.into_iter().collect();
+        let mut newly_pinned: Vec<PathBuf> = Vec::new();
+        for path in &paths {
+            if !self.is_pinned(path) {
+                self.pin(path);
+                newly_pinned.push(path.clone());
+            }
+        }
+
+        let files: Vec<CachedFile> = stream::iter(paths)
+            .map(|path| async move { self.get(path).await })
+            .buffered(BATCH_CONCURRENCY)
+            .collect()
+            .await;
+
+        for path in newly_pinned {
+            self.unpin(path);
+        }
+        files
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn files_are_returned_in_order() {
+        let temp_dir = TempDir::new("batch").unwrap();
+        let paths: Vec<PathBuf> = (0..4)
+            .map(|i| {
+                let path: PathBuf = temp_dir.path().join(format!("{}.txt", i));
+                std::fs::write(&path, vec![i as u8; 1024]).unwrap();
+                path
+            })
+            .collect();
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1024 * 10)
+            .build()
+            .unwrap();
+        cache.pin(&paths[3]);
+
+        let mut request: Vec<PathBuf> = paths.clone();
+        request.push(temp_dir.path().join("missing.txt"));
+        request.push(paths[0].clone());
+        let files: Vec<CachedFile> = cache.get_many(request).await;
+
+        assert_eq!(files.len(), 6);
+        for (i, file) in files.iter().take(4).enumerate() {
+            match *file {
+                CachedFile::InMemory(ref file) => assert_eq!(file.bytes(), &[i as u8; 1024][..]),
+                ref other => panic!("Expected the file to be cached, got {:?}", other),
+            }
+        }
+        assert_eq!(files[4], CachedFile::NotFound);
+        match files[5] {
+            CachedFile::InMemory(ref file) => assert_eq!(file.bytes(), &[0; 1024][..]),
+            ref other => panic!("Expected the file to be cached, got {:?}", other),
+        }
+
+        // Pins that existed before the batch are kept.
+        assert!(!cache.is_pinned(&paths[0]));
+        assert!(cache.is_pinned(&paths[3]));
+    }
+}
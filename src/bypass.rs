@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use rocket::request::Request;
+
+use crate::cache::Cache;
+use crate::cached_file::CachedFile;
+
+
+impl Cache {
+    /// Gets the file like `get()` does, unless the request asks to bypass the cache with the header
+    /// that was configured with `CacheBuilder::bypass_header()`.
+    ///
+    /// A request bypasses the cache if the header's value is `1` or `true`. The file is then served
+    /// from the filesystem, and its entry in the cache, if it has one, is neither read nor updated.
+    /// If no bypass header is configured, this is the same as `get()`.
+    ///
+    /// If the cache was built with `CacheBuilder::range_chunk_size()`, a request for a range of
+    /// a file that is too large to be cached is served as `CachedFile::Partial`, from the chunks of the file that are cached.
+    ///
+    /// This is the one call a handler needs for features that depend on the request. Those that decide
+    /// where the file comes from, like the bypass header and ranges of large files, are decided here.
+    /// Those that decide how the file is sent, like conditional requests, compressed copies, ranges of
+    /// cached files, and CORS headers, are decided when the returned `CachedFile` responds to the same request,
+    /// so they work with `get()` as well. `get()` remains for callers that have no request at hand, like preloading.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// #[macro_use]
+    /// # extern crate rocket;
+    /// # extern crate rocket_file_cache;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// use rocket_file_cache::{Cache, CachedFile};
+    /// use rocket::request::Request;
+    /// use rocket::State;
+    /// use std::path::{Path, PathBuf};
+    ///
+    /// #[get("/<file..>")]
+    /// async fn files(file: PathBuf, cache: &State<Cache>, request: &Request<'_>) -> CachedFile {
+    ///     cache.get_with_request(Path::new("www/").join(file), request).await
+    /// }
+    /// # }
+    /// ```
+    pub async fn get_with_request<P: AsRef<Path> + std::marker::Send>(&self, path: P, request: &Request<'_>) -> CachedFile {
+        let path: PathBuf = self.normalize_key(path);
+        if self.requests_bypass(request) {
+            debug!("Request for {:?} bypasses the cache.", path);
+            return self.get_bypassing_cache(&path).await;
+        }
+        if self.dotfiles.hides(&path) {
+            return self.get(path).await;
+        }
+        if let Some(partial_file) = self.get_range_from_chunks(&path, request).await {
+            return partial_file;
+        }
+        self.get(path).await
+    }
+
+
+    /// Gets the file for the request, like `get_with_request()` does.
+    #[deprecated(note = "use `Cache::get_with_request()` instead")]
+    pub async fn get_for_request<P: AsRef<Path> + std::marker::Send>(&self, path: P, request: &Request<'_>) -> CachedFile {
+        self.get_with_request(path, request).await
+    }
+
+    /// Returns true if the bypass header is configured, and the request sets it.
+    fn requests_bypass(&self, request: &Request<'_>) -> bool {
+        let header: &str = match self.bypass_header {
+            Some(ref header) => header,
+            None => return false,
+        };
+        match request.headers().get_one(header) {
+            Some(value) => value.trim() == "1" || value.trim().eq_ignore_ascii_case("true"),
+            None => false,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use rocket::http::Header;
+    use rocket::local::asynchronous::Client;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn bypass_header_serves_from_filesystem() {
+        let temp_dir = TempDir::new("bypass").unwrap();
+        let path: PathBuf = temp_dir.path().join("index.html");
+        std::fs::write(&path, b"<html></html>").unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .bypass_header("X-Cache-Bypass")
+            .build()
+            .unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("X-Cache-Bypass", "1"));
+        match cache.get_with_request(&path, request.inner()).await {
+            CachedFile::FileSystem(_) => {}
+            other => panic!("Expected the file to be served from the filesystem, got {:?}", other),
+        }
+        assert!(!cache.contains_key(&path));
+
+        let request = client.get("/").header(Header::new("X-Cache-Bypass", "0"));
+        match cache.get_with_request(&path, request.inner()).await {
+            CachedFile::InMemory(_) => {}
+            other => panic!("Expected the file to be cached, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn bypass_header_is_ignored_unless_configured() {
+        let temp_dir = TempDir::new("bypass").unwrap();
+        let path: PathBuf = temp_dir.path().join("index.html");
+        std::fs::write(&path, b"<html></html>").unwrap();
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("X-Cache-Bypass", "1"));
+        match cache.get_with_request(&path, request.inner()).await {
+            CachedFile::InMemory(_) => {}
+            other => panic!("Expected the file to be cached, got {:?}", other),
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::usize;
 use rocket::fs::NamedFile;
 use std::fs::Metadata;
 use std::fs;
+use std::io;
 use crate::named_in_memory_file::NamedInMemoryFile;
 use crate::cached_file::CachedFile;
 use crate::in_memory_file::InMemoryFile;
@@ -12,14 +13,58 @@ use std::fmt::Debug;
 use std::fmt;
 use std::fmt::Formatter;
 use crate::in_memory_file::FileStats;
+use crate::eviction_policy::{self, EvictionPolicy, VictimOrder};
+use std::collections::HashMap;
+use crate::hash_algorithm::HashAlgorithm;
+use crate::etag_mode::EtagMode;
+use crate::encoding::Encoding;
+use crate::cache_stats::{CacheStats, Counters};
+use crate::access_record::AccessRecord;
+use bytes::Bytes;
+use crate::in_flight::InFlight;
+use crate::open_limit::OpenLimit;
+use crate::preload::PreloadLimit;
+use crate::high_water::HighWater;
+use crate::fallback::Fallback;
+use crate::overflow_policy::OverflowPolicy;
+use crate::file_source::{FileSource, FsSource, SharedFileSource};
+use crate::spill::Spill;
+use crate::not_found::NotFoundPage;
+use crate::admission::Admission;
+use crate::cache_control::CacheControl;
+use crate::content_disposition::ContentDisposition;
+use crate::custom_headers::CustomHeaders;
+use crate::cors::Cors;
+use crate::sniff;
+use crate::default_content_type::DefaultContentType;
+use crate::precompress_filter::PrecompressFilter;
+use crate::extension_filter::ExtensionFilter;
+use crate::dotfiles::Dotfiles;
+use crate::file_reader::FileReader;
+use crate::instrumentation;
+use crate::streaming_file::{self, CompletedStreams, StreamedContents, StreamingFile, TeeClaim};
+use crate::cache_error::CacheError;
+use crate::revalidate::Revalidation;
+use crate::key_normalization::KeyNormalization;
+use crate::range_chunks;
+use crate::lock;
+use rocket::http::{ContentType, Header};
 use async_recursion::async_recursion;
+use glob::{Pattern, PatternError};
+use std::time::{Duration, Instant};
+use std::sync::{Arc, RwLock, Weak};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::panic::AssertUnwindSafe;
+use rocket::futures::FutureExt;
+use tokio::task::JoinHandle;
 
 #[derive(Debug, PartialEq)]
-enum CacheError {
+enum InsertError {
     NoMoreFilesToRemove,
     NewPriorityIsNotHighEnough,
     InvalidMetadata,
     InvalidPath,
+    NotARegularFile,
 }
 
 
@@ -45,17 +90,113 @@ enum CacheError {
 /// in which case, the new file isn't inserted.
 pub struct Cache {
     /// The number of bytes the file_map should be able hold at once.
-    pub(crate) size_limit: usize,
+    /// This can be changed while the cache is in use.
+    pub(crate) size_limit: AtomicUsize,
+    /// The maximum number of files the file_map should hold at once.
+    pub(crate) max_entries: Option<usize>,
     /// The minimum number of bytes a file must have in order to be accepted into the Cache.
     pub(crate) min_file_size: usize,
     /// The maximum number of bytes a file can have in order to be accepted into the Cache.
     pub(crate) max_file_size: usize,
+    /// Decides which files can be stored in the cache, based on their extensions.
+    pub(crate) extension_filter: ExtensionFilter,
+    /// Decides whether files and directories whose names start with a `.` are served.
+    pub(crate) dotfiles: Dotfiles,
     /// The function that is used to calculate the priority score that is used to determine which files should be in the cache.
-    pub(crate) priority_function: fn(usize, usize) -> usize,
+    /// It is behind a lock, so it can be replaced while the cache is managed by Rocket.
+    pub(crate) priority_function: RwLock<fn(usize, usize) -> usize>,
+    /// The time it takes for the priority score of a file that isn't accessed to be halved.
+    pub(crate) priority_half_life: Option<Duration>,
+    /// The strategy used to determine which files should be removed when the cache is full.
+    pub(crate) eviction_policy: Arc<dyn EvictionPolicy>,
+    /// The order in which the eviction policy selects victims, if it is one of the built in policies.
+    pub(crate) victim_order: Option<VictimOrder>,
+    /// Decides whether files are evicted to make room for new files when the cache is full.
+    pub(crate) overflow_policy: OverflowPolicy,
+    /// The algorithm used to generate the ETags of files in the cache.
+    pub(crate) hash_algorithm: HashAlgorithm,
+    /// How the entity tags of files that are read from the filesystem are generated.
+    pub(crate) etag_mode: EtagMode,
+    /// The encodings that files will be compressed with when they are read into the cache.
+    pub(crate) precompress: Vec<Encoding>,
+    /// The order in which encodings are preferred, when a client accepts several of them equally.
+    pub(crate) encoding_preference: Vec<Encoding>,
+    /// The minimum number of bytes a file must have in order to be compressed.
+    pub(crate) min_precompress_size: usize,
+    /// Skips compressing files that are already compressed, like images.
+    pub(crate) precompress_filter: PrecompressFilter,
+    /// If true, compressed copies of files that exist next to them in the filesystem are served.
+    pub(crate) precompressed_siblings: bool,
+    /// The number of bytes files are read in at a time, or `None` if it depends on the size of the file.
+    pub(crate) read_buffer_size: Option<usize>,
+    /// Where the contents of files are read from, if not from the filesystem.
+    pub(crate) file_source: Option<SharedFileSource>,
+    /// Files at least this large are streamed to the client while they are read into the cache.
+    pub(crate) stream_misses: Option<usize>,
+    /// The contents of files that were streamed in full, which are added to the cache by the next request.
+    pub(crate) completed_streams: CompletedStreams,
+    /// Counts how files have been served by the cache.
+    pub(crate) counters: Counters,
     /// If a given file's access count modulo this value equals 0, then that file will be refreshed from the FileSystem instead of from the Cache.
     pub(crate) accesses_per_refresh: Option<usize>,
     pub(crate) file_map: ConcHashMap<PathBuf, InMemoryFile, RandomState>, // Holds the files that the cache is caching
-    pub(crate) access_count_map: ConcHashMap<PathBuf, usize, RandomState>, // Every file that is accessed will have the number of times it is accessed logged in this map.
+    pub(crate) access_count_map: ConcHashMap<PathBuf, AccessRecord, RandomState>, // Every file that is accessed will have the number of times it is accessed logged in this map.
+    /// How long a file that could not be found will be considered missing, if the negative cache is enabled.
+    pub(crate) negative_cache_ttl: Option<Duration>,
+    pub(crate) negative_map: ConcHashMap<PathBuf, Instant, RandomState>, // Holds the time at which files that could not be found should be looked for again.
+    pub(crate) pinned_map: ConcHashMap<PathBuf, (), RandomState>, // Holds the paths of the files that should never be evicted.
+    pub(crate) priority_bias_map: ConcHashMap<PathBuf, i64, RandomState>, // Holds the amounts that are added to the priority scores of files.
+    /// The files that are currently being read into the cache.
+    pub(crate) in_flight: InFlight,
+    /// Limits how many files are read at once when loading files in bulk.
+    pub(crate) preload_limit: PreloadLimit,
+    /// Limits how many responses hold the same file open, for files that are served from the filesystem.
+    pub(crate) open_limit: Option<OpenLimit>,
+    /// The size of the chunks that ranges of files too large to be cached as a whole are cached in.
+    pub(crate) range_chunk_size: Option<usize>,
+    /// The file that is served in place of files that could not be found.
+    pub(crate) fallback: Option<Fallback>,
+    /// The response that is sent for files that could not be found.
+    pub(crate) not_found_page: Option<NotFoundPage>,
+    /// Files at least this large are memory mapped instead of being read into memory.
+    #[cfg(feature = "mmap")]
+    pub(crate) mmap_threshold: Option<usize>,
+    /// Holds the files that were evicted, so they can be read back without going to where they came from.
+    pub(crate) spill: Option<Spill>,
+    /// Called whenever a file leaves the cache.
+    pub(crate) on_evict: Option<Box<dyn Fn(&Path, &FileStats) + Send + Sync>>,
+    /// Called when the cache fills up beyond a fraction of its capacity.
+    pub(crate) high_water: Option<HighWater>,
+    /// The canonical path of the directory that files must be in to be served.
+    pub(crate) root: Option<PathBuf>,
+    /// Counts the requests for files that haven't been requested often enough to be read into the cache.
+    pub(crate) admission: Option<Admission>,
+    /// Chooses the `Cache-Control` header that is sent with files from the cache.
+    pub(crate) cache_control: CacheControl,
+    /// Chooses the `Content-Disposition` header that is sent with files from the cache.
+    pub(crate) content_disposition: ContentDisposition,
+    /// The name of the request header that makes `get_with_request()` serve files from the filesystem.
+    pub(crate) bypass_header: Option<String>,
+    /// True if responses include an `X-Cache` header, telling whether the file was served from memory.
+    pub(crate) x_cache_header: bool,
+    /// True if the size and modification time of files are compared to the filesystem whenever they are served.
+    pub(crate) detect_changes: bool,
+    /// The custom headers that are sent with files from the cache.
+    pub(crate) custom_headers: CustomHeaders,
+    /// Adds CORS headers to files from the cache, for requests from allowed origins.
+    pub(crate) cors: Option<Arc<Cors>>,
+    /// True if the `Content-Type` of files without a recognized extension is detected from their contents.
+    pub(crate) sniff_content_type: bool,
+    /// Chooses the `Content-Type` of files whose type can't be determined from their extension or contents.
+    pub(crate) default_content_type: DefaultContentType,
+    /// Reads files that have changed on disk again in the background, if stale-while-revalidate is enabled.
+    pub(crate) revalidation: Option<Revalidation>,
+    /// Incremented by `bump_generation()`. Files that were read under an older generation are stale.
+    pub(crate) generation: AtomicUsize,
+    /// True if the cache panicked, after which files are served from the filesystem.
+    pub(crate) poisoned: AtomicBool,
+    /// The transformations that are applied to paths before they are used as keys.
+    pub(crate) key_normalization: KeyNormalization,
 }
 
 
@@ -74,8 +215,11 @@ impl Cache {
     /// Either gets the file from the cache if it exists there, gets it from the filesystem and
     /// tries to cache it, or fails to find the file.
     ///
-    /// The CachedFile that is returned takes a lock out on that file in the cache, if that file happens to exist in the cache.
-    /// This lock will release when the CachedFile goes out of scope.
+    /// If the file is in the cache, the CachedFile that is returned shares the file's contents with the cache.
+    /// It doesn't hold a lock on the cache, so it can be kept around for as long as needed.
+    ///
+    /// Handlers that have the request at hand should use `get_with_request()` instead, so features
+    /// that depend on the request, like the bypass header, apply as well.
     ///
     /// # Arguments
     ///
@@ -103,40 +247,402 @@ impl Cache {
     ///
     ///
     /// #[get("/<file..>")]
-    /// async fn files<'a>(file: PathBuf,  cache: &'a State<Cache> ) -> CachedFile<'a> {
+    /// async fn files(file: PathBuf,  cache: &State<Cache> ) -> CachedFile {
     ///     let path: PathBuf = Path::new("www/").join(file).to_owned();
     ///     cache.inner().get(path).await
     /// }
     /// # }
     /// ```
     pub async fn get<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> CachedFile {
+        self.get_reporting(path).await.unwrap_or_else(CacheError::into_cached_file)
+    }
+
+    /// Gets the file like `get()`, keeping the reason the file could not be gotten.
+    async fn get_reporting<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> Result<CachedFile, CacheError> {
+        let path: PathBuf = self.normalize_key(path);
+        if self.dotfiles.hides(&path) {
+            debug!("File: {:?} is hidden, and is not served.", path);
+            self.counters.record(&CachedFile::NotFound);
+            return Err(CacheError::NotFound);
+        }
+        if self.is_poisoned() {
+            return found(self.get_bypassing_cache(&path).await);
+        }
+
+        // A panic while a lock on one of the maps is held, for example in the priority function,
+        // poisons the lock, and would cause every later request for the file to panic as well.
+        // Files are served from the filesystem instead, so the server stays available.
+        let get = AssertUnwindSafe(self.get_cached(&path)).catch_unwind();
+        #[cfg(feature = "tracing")]
+        let get = tracing::Instrument::instrument(get, tracing::debug_span!("get", path = ?path));
+        match get.await {
+            Ok(result) => result,
+            Err(_) => {
+                error!("The cache panicked while getting {:?}. Files will be served from the filesystem until `Cache::recover()` is called.", path);
+                self.poisoned.store(true, Ordering::Relaxed);
+                found(self.get_bypassing_cache(&path).await)
+            }
+        }
+    }
+
+    /// Returns true if the cache panicked while getting a file, and only serves files from the filesystem.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Resumes caching files after the cache panicked.
+    ///
+    /// The state of the cache can't be trusted after a panic, so the maps that back the cache are emptied.
+    /// This removes every file from the cache, and forgets their access counts, pins, and priority biases.
+    /// It only needs a shared reference, so a cache that Rocket manages can be recovered from a handler.
+    ///
+    /// The priority function and the eviction policy are called without holding a lock on the maps,
+    /// so a panic in either of them leaves the maps usable. If a map was poisoned by a panic while
+    /// it was locked anyway, it can't be emptied, and the cache keeps serving files from the filesystem,
+    /// which `is_poisoned()` tells.
+    pub fn recover(&self) {
+        let emptied = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            clear_map(&self.file_map);
+            clear_map(&self.access_count_map);
+            clear_map(&self.negative_map);
+            clear_map(&self.pinned_map);
+            clear_map(&self.priority_bias_map);
+        }));
+        match emptied {
+            Ok(()) => self.poisoned.store(false, Ordering::Relaxed),
+            Err(_) => error!("The maps of the cache are poisoned, and can't be emptied. Files will still be served from the filesystem."),
+        }
+    }
+
+    /// Gets the file without counting the access, or changing anything else about the cache.
+    ///
+    /// If the file is in the cache, it is served from memory, with the same headers as it would be by `get()`.
+    /// Otherwise it is served from the filesystem, without being read into the cache, even if there
+    /// is room for it. Expired files are served from the filesystem as well, but aren't removed.
+    /// Because nothing is added to the cache, peeking at a file never causes other files to be evicted.
+    ///
+    /// This is meant for requests that shouldn't affect which files are kept in the cache, like health checks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, CachedFile};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let cached_file: CachedFile = cache.peek("www/index.html").await;
+    /// assert!(!cache.contains_key("www/index.html"));
+    /// # }
+    /// ```
+    pub async fn peek<P: AsRef<Path>>(&self, path: P) -> CachedFile {
+        let path: PathBuf = self.normalize_key(path);
+        if self.dotfiles.hides(&path) {
+            return CachedFile::NotFound;
+        }
+        let cached_file: CachedFile = if self.is_expired(&path) || self.is_outdated(&path) {
+            CachedFile::NotFound
+        } else {
+            self.get_from_cache(&path).await
+        };
+        let cached_file: CachedFile = match cached_file {
+            CachedFile::NotFound => self.open_from_fs(&path).await,
+            cached_file => cached_file,
+        };
+        self.add_response_headers(cached_file)
+    }
+
+    /// Serves the file from the filesystem without touching the cache's maps.
+    pub(crate) async fn get_bypassing_cache(&self, path: &PathBuf) -> CachedFile {
+        let cached_file: CachedFile = self.open_from_fs(path).await;
+        self.counters.record(&cached_file);
+        self.add_x_cache_header(cached_file)
+    }
+
+    /// Opens the file in the filesystem, if it is a regular file within the root directory.
+    async fn open_from_fs(&self, path: &PathBuf) -> CachedFile {
+        if self.dotfiles.hides(path) || !self.is_within_root(path).await || !is_regular_file(path).await {
+            return CachedFile::NotFound;
+        }
+        self.open_uncached(path).await.unwrap_or(CachedFile::NotFound)
+    }
+
+    /// Opens the file so it can be served from the filesystem, without reading it into the cache.
+    ///
+    /// If the number of responses that hold the same file open is limited, this waits until one of them is done,
+    /// and the file is streamed, so the cache can tell when the response is done with it.
+    /// Files are streamed as well if responses get an `X-Cache` header, as a `NamedFile` can't carry it.
+    pub(crate) async fn open_uncached(&self, path: &Path) -> io::Result<CachedFile> {
+        match self.open_limit {
+            Some(ref open_limit) => {
+                let permit = open_limit.acquire(path).await;
+                StreamingFile::open_uncached(path.to_path_buf(), Some(permit)).await.map(CachedFile::from)
+            }
+            None if self.x_cache_header => StreamingFile::open_uncached(path.to_path_buf(), None).await.map(CachedFile::from),
+            None => NamedFile::open(path).await.map(CachedFile::from),
+        }
+    }
+
+    /// Gets the file from the cache, trying the fallback file if it can't be found.
+    async fn get_cached(&self, path: &PathBuf) -> Result<CachedFile, CacheError> {
+        let started: Instant = Instant::now();
+        let mut result: Result<CachedFile, CacheError> = self.get_file_reporting(path.clone()).await;
+        match result {
+            Ok(_) | Err(CacheError::TooLarge(_)) => {}
+            Err(_) => {
+                if let Some(fallback_file) = self.get_fallback(path).await {
+                    result = found(fallback_file);
+                }
+            }
+        }
+        match result {
+            Ok(ref cached_file) | Err(CacheError::TooLarge(ref cached_file)) => {
+                self.counters.record(cached_file);
+                instrumentation::served(path, cached_file, started.elapsed());
+            }
+            Err(_) => {
+                self.counters.record(&CachedFile::NotFound);
+                instrumentation::served(path, &CachedFile::NotFound, started.elapsed());
+            }
+        }
+        match result {
+            Ok(cached_file) => Ok(self.add_response_headers(cached_file)),
+            Err(CacheError::TooLarge(cached_file)) => Err(CacheError::TooLarge(self.add_response_headers(cached_file))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Gets the file like `get()`, but reports why the file could not be gotten, instead of returning `CachedFile::NotFound`.
+    ///
+    /// Files that aren't stored in the cache, because they don't have a high enough priority or haven't been
+    /// requested often enough, are not errors. They are returned as `CachedFile::FileSystem`, as they would be by `get()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CacheError::TooLarge` with the file opened from the filesystem if it is too large to ever be cached,
+    /// `CacheError::PermissionDenied` if the file can't be read by the process,
+    /// `CacheError::NotFound` if it doesn't exist, and `CacheError::Io` with the error if reading it failed for
+    /// another reason, including errors of the cache's file source.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, CacheError};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// match cache.try_get("www/missing.html").await {
+    ///     Ok(_) => {}
+    ///     Err(CacheError::NotFound) => {}
+    ///     Err(e) => panic!("Unexpected error: {}", e),
+    /// }
+    /// # }
+    /// ```
+    pub async fn try_get<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> Result<CachedFile, CacheError> {
+        self.get_reporting(path).await
+    }
+
+    /// Converts the path to the key it is stored under, according to the cache's `KeyNormalization`.
+    pub(crate) fn normalize_key<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.key_normalization.normalize(path.as_ref())
+    }
+
+    /// Attaches the headers that are configured for the file, so they are sent when the file is served from memory.
+    pub(crate) fn add_response_headers(&self, mut cached_file: CachedFile) -> CachedFile {
+        match cached_file {
+            CachedFile::InMemory(ref mut file) => {
+                let headers: Vec<Header<'static>> = self.configured_headers(&file.path);
+                file.headers.extend(headers);
+                file.cors = self.cors.clone();
+                if !has_known_extension(&file.path) {
+                    let sniffed: Option<ContentType> = if self.sniff_content_type {
+                        sniff::sniff(file.bytes())
+                    } else {
+                        None
+                    };
+                    let content_type: Option<String> = sniffed
+                        .map(|content_type| content_type.to_string())
+                        .or_else(|| self.default_content_type.content_type(&file.path).map(ContentType::to_string));
+                    if let Some(content_type) = content_type {
+                        file.headers.push(Header::new("Content-Type", content_type));
+                    }
+                }
+            }
+            // The contents haven't been read yet, so they can't be sniffed.
+            CachedFile::Streaming(ref mut file) => {
+                let headers: Vec<Header<'static>> = self.configured_headers(&file.path);
+                file.headers.extend(headers);
+                file.cors = self.cors.clone();
+                if !has_known_extension(&file.path) {
+                    if let Some(content_type) = self.default_content_type.content_type(&file.path) {
+                        file.headers.push(Header::new("Content-Type", content_type.to_string()));
+                    }
+                }
+            }
+            // Chunks aren't sniffed, as the range may not include the start of the file.
+            CachedFile::Partial(ref mut file) => {
+                let headers: Vec<Header<'static>> = self.configured_headers(&file.path);
+                file.headers.extend(headers);
+                file.cors = self.cors.clone();
+                if !has_known_extension(&file.path) {
+                    if let Some(content_type) = self.default_content_type.content_type(&file.path) {
+                        file.headers.push(Header::new("Content-Type", content_type.to_string()));
+                    }
+                }
+            }
+            CachedFile::FileSystem(_) | CachedFile::NotFound => {}
+        }
+        self.add_x_cache_header(cached_file)
+    }
+
+    /// Adds an `X-Cache` header, which is `HIT` if the file is served from memory, and `MISS` otherwise,
+    /// if the cache was built with `CacheBuilder::x_cache_header()`.
+    fn add_x_cache_header(&self, mut cached_file: CachedFile) -> CachedFile {
+        if !self.x_cache_header {
+            return cached_file;
+        }
+        let header: Header<'static> = Header::new("X-Cache", if cached_file.is_in_memory() { "HIT" } else { "MISS" });
+        match cached_file {
+            CachedFile::InMemory(ref mut file) => file.headers.push(header),
+            CachedFile::Streaming(ref mut file) => file.headers.push(header),
+            CachedFile::Partial(ref mut file) => file.headers.push(header),
+            CachedFile::FileSystem(_) | CachedFile::NotFound => {}
+        }
+        cached_file
+    }
+
+    /// The `Cache-Control`, `Content-Disposition`, and custom headers that are configured for the path.
+    fn configured_headers(&self, path: &Path) -> Vec<Header<'static>> {
+        let mut headers: Vec<Header<'static>> = Vec::new();
+        if let Some(directive) = self.cache_control.directive(path) {
+            headers.push(Header::new("Cache-Control", directive.to_string()));
+        }
+        if let Some(disposition) = self.content_disposition.header_value(path) {
+            headers.push(Header::new("Content-Disposition", disposition));
+        }
+        headers.extend(self.custom_headers.headers(path));
+        headers
+    }
+
+    /// Gets the file, without recording where it was gotten from in the cache's stats.
+    pub(crate) async fn get_file<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> CachedFile {
+        self.get_file_reporting(path).await.unwrap_or_else(CacheError::into_cached_file)
+    }
+
+    /// Gets the file like `get_file()`, keeping the reason the file could not be gotten.
+    async fn get_file_reporting<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> Result<CachedFile, CacheError> {
         trace!("{:#?}", self);
+        self.insert_streamed_files().await;
         // First, try to get the file in the cache that corresponds to the desired path.
 
         if self.contains_key(&path.as_ref().to_path_buf()) {
-            // File is in the cache, increment the count, update the stats attached to the cache entry.
+            // If the file was read before the generation was bumped, read it again, keeping its time to live.
+            if self.is_outdated(&path) {
+                debug!("Entry for path: {:?} is from an older generation, reloading it", path.as_ref());
+                match self.reload(path.as_ref()).await {
+                    Ok(true) => {}
+                    Ok(false) | Err(_) => {
+                        self.remove_entry(&path.as_ref().to_path_buf());
+                        return Ok(self.get_file_from_fs(&path).await?)
+                    }
+                }
+            }
+
+            // If the file has outlived its time to live, remove it and serve the file from the filesystem instead.
+            if self.is_expired(&path) {
+                debug!("Entry for path: {:?} has expired", path.as_ref());
+                self.remove(&path);
+                return Ok(self.get_file_from_fs(&path).await?)
+            }
+
+            // Memory mapped files reflect changes made to the file, so they need to be mapped again once the file changes.
+            // Other files are only checked if the cache was configured to detect changes.
+            if self.has_changed_on_disk(&path.as_ref().to_path_buf()).await {
+                debug!("File: {:?} has changed since it was read, refreshing it.", path.as_ref());
+                let refreshed_file: CachedFile = self.refresh(path.as_ref()).await;
+                if let CachedFile::NotFound = refreshed_file {
+                    // The file was removed from the filesystem, so the old copy shouldn't be served either.
+                    self.remove_entry(&path.as_ref().to_path_buf());
+                }
+                return found(refreshed_file)
+            }
+
+            if let Some(refreshed_file) = self.revalidate(&path.as_ref().to_path_buf()).await {
+                return found(refreshed_file);
+            }
+
+            // File is in the cache, increment the count.
+            // The stats attached to the cache entry are brought up to date with the access count when they are needed,
+            // so serving a file from the cache only requires shared access to the cache's maps.
             self.increment_access_count(&path);
-            self.update_stats(&path);
 
             // See if the file should be refreshed
             if let Some(accesses_per_refresh) = self.accesses_per_refresh {
-                match self.access_count_map.find(&path.as_ref().to_path_buf()) {
-                    Some(a) => {
-                        let access_count: usize = a.get().clone();
+                let access_count: Option<usize> = self.access_count_map
+                    .find(&path.as_ref().to_path_buf())
+                    .map(|record| record.get().access_count());
+                match access_count {
+                    Some(access_count) => {
                         // If the access count is a multiple of the refresh parameter, then refresh the file.
                         if access_count % accesses_per_refresh == 0 {
                             debug!( "Refreshing entry for path: {:?}", path.as_ref() );
-                            return self.refresh(path.as_ref()).await
+                            return found(self.refresh(path.as_ref()).await)
                         }
                     }
                     None => warn!("Cache contains entry for {:?}, but does not tract its access counts.", path.as_ref())
                 }
             }
 
-            self.get_from_cache(&path).await
+            found(self.get_from_cache(&path).await)
 
         } else {
-            self.try_insert(path).await
+            let key: PathBuf = path.as_ref().to_path_buf();
+            // Files that were spilled when they were evicted are read back from the spill, before the filesystem.
+            if let Some(cached_file) = self.get_spilled_file(&key).await {
+                return found(cached_file);
+            }
+            // Don't touch the filesystem if the file was recently found to be missing.
+            if self.is_known_to_be_missing(&key) {
+                trace!("File: {:?} is known to be missing.", key);
+                return Err(CacheError::NotFound);
+            }
+
+            // Files that haven't been requested often enough are served from the filesystem without being read.
+            if let Some(ref admission) = self.admission {
+                if !admission.admit(&key) {
+                    trace!("File: {:?} has not been requested often enough to be cached.", key);
+                    if !self.is_within_root(&key).await || !is_regular_file(&key).await {
+                        return Err(CacheError::NotFound);
+                    }
+                    // The access count isn't incremented, so only the admission counts are kept for the file.
+                    return match self.open_uncached(&key).await {
+                        Ok(cached_file) => Ok(cached_file),
+                        Err(e) => {
+                            self.record_missing(key);
+                            Err(CacheError::from(e))
+                        }
+                    };
+                }
+            }
+
+            // Only one request reads a given file at a time, the others wait for it to finish.
+            let _load_guard = self.in_flight.acquire(&key).await;
+            if self.contains_key(&key) {
+                trace!("File: {:?} was loaded by another request.", key);
+                self.increment_access_count(&key);
+                return found(self.get_from_cache(&key).await)
+            }
+            if self.is_known_to_be_missing(&key) {
+                return Err(CacheError::NotFound);
+            }
+
+            let result: Result<CachedFile, CacheError> = self.try_insert_reporting(path).await;
+            match result {
+                Ok(_) | Err(CacheError::TooLarge(_)) => {}
+                Err(_) => self.record_missing(key),
+            }
+            result
         }
 
     }
@@ -157,1025 +663,4125 @@ impl Cache {
     ///
     /// The CachedFile will indicate NotFound if the file isn't already in the cache or if it can't
     /// be found in the filesystem.
+    /// If the file has grown larger than the cache's `max_file_size`, or shrunk below its `min_file_size`,
+    /// it is removed from the cache, and the CachedFile will be the FileSystem variant.
     /// It will otherwise return a CachedFile::InMemory variant.
     pub async fn refresh<P: AsRef<Path>>(&self, path: P) -> CachedFile {
+        let path: PathBuf = self.normalize_key(path);
+        // Chunks that were read before the file changed shouldn't be served either.
+        self.remove_chunks(&path);
 
         let mut is_ok_to_refresh: bool = false;
+        let mut is_out_of_bounds: bool = false;
+        let mut size: usize = 0;
 
         // Check if the file exists in the cache
-        if self.contains_key(&path.as_ref().to_path_buf()) {
+        if self.contains_key(&path) {
             // See if the new file exists.
-            let path_string: String = match path.as_ref().to_str() {
-                Some(s) => String::from(s),
-                None => return CachedFile::NotFound,
-            };
-            if let Ok(metadata) = fs::metadata(path_string.as_str()) {
-                if metadata.is_file() {
-                    // If the entry for the old file exists
-                    if self.file_map.find(&path.as_ref().to_path_buf()).is_some() {
-                        is_ok_to_refresh = true;
-                    }
+            if let Ok(stats) = self.read_stats(&path).await {
+                // If the entry for the old file exists
+                if self.file_map.find(&path).is_some() {
+                    is_ok_to_refresh = true;
+                    is_out_of_bounds = stats.size > self.max_file_size || stats.size < self.min_file_size;
+                    size = stats.size;
                 }
             };
         }
 
+        if is_out_of_bounds {
+            // Don't read the new file into memory just to find out that it can't be stored.
+            debug!("File: {:?} no longer fits the size constraints of the cache.", path);
+            self.remove_entry(&path);
+            return self.get_file_from_fs(path).await.unwrap_or(CachedFile::NotFound)
+        }
+
         if is_ok_to_refresh {
-            if let Ok(new_file) = InMemoryFile::open(path.as_ref().to_path_buf()).await {
-                debug!("Refreshing file: {:?}", path.as_ref());
-                {
-                    self.file_map.remove(&path.as_ref().to_path_buf());
-                    self.file_map.insert(path.as_ref().to_path_buf(), new_file);
-                }
-                self.update_stats(&path);
+            match self.reread_file(&path, size).await {
+                Ok(new_file) => {
+                    debug!("Refreshing file: {:?}", path);
+                    {
+                        self.file_map.remove(&path);
+                        self.file_map.insert(path.clone(), new_file);
+                    }
+                    self.update_stats(&path);
 
-                return self.get_from_cache(path).await
+                    return self.get_from_cache(path).await
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::InvalidData => {
+                    return self.get_file_from_fs(path).await.unwrap_or(CachedFile::NotFound)
+                }
+                Err(_) => {}
             }
         }
 
         CachedFile::NotFound
     }
 
-    /// Removes the file from the cache.
-    /// This will not reset the access count, so the next time the file is accessed, it will be added to the cache again.
-    /// The access count will have to be reset separately using `alter_access_count()`.
+    /// Re-reads a file that is in the cache from the filesystem, replacing its contents in the cache.
     ///
-    /// # Arguments
+    /// Unlike `refresh()`, this reports why the file could not be re-read, and it doesn't get the file.
+    /// The file keeps its access count and time to live, while its size, priority, ETag, and
+    /// modification time are updated to reflect the new contents.
     ///
-    /// * `path` - A path that acts as a key to look up the file that should be removed from the cache.
+    /// # Return
+    ///
+    /// Returns `Ok(true)` if the file was replaced.
+    /// Returns `Ok(false)` if the file isn't in the cache, in which case it isn't added, or if it has grown
+    /// larger than the cache's `max_file_size` or shrunk below its `min_file_size`, in which case it is removed from the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that occurred while reading the file. The old contents stay in the cache,
+    /// unless the file changed size while it was being read, in which case it is removed from the cache,
+    /// and the error is of the kind `InvalidData`.
     ///
     /// # Example
     ///
     /// ```
     /// use rocket_file_cache::{Cache, CacheBuilder};
-    /// use std::path::PathBuf;
     ///
-    /// let mut cache = CacheBuilder::new().build().unwrap();
-    /// let pathbuf = PathBuf::new();
-    /// cache.remove(&pathbuf);
-    /// assert!(cache.contains_key(&pathbuf) == false);
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// // The file was never added to the cache, so it isn't read.
+    /// assert!(!cache.reload("www/index.html").await.unwrap());
+    /// # }
     /// ```
-    pub fn remove<P: AsRef<Path>>(&self, path: P) -> bool {
-        if let Some(_) = self.file_map.remove(&path.as_ref().to_path_buf()) {
-            true
-        } else {
-            false
+    pub async fn reload<P: AsRef<Path>>(&self, path: P) -> io::Result<bool> {
+        let path: PathBuf = self.normalize_key(path);
+        if !self.contains_key(&path) {
+            return Ok(false);
+        }
+        let size: usize = self.read_stats(&path).await?.size;
+        if size > self.max_file_size || size < self.min_file_size {
+            debug!("File: {:?} is no longer within the file size limits of the cache.", path);
+            self.remove_entry(&path);
+            return Ok(false);
         }
+
+        let new_file: InMemoryFile = self.reread_file(&path, size).await?;
+        Ok(self.replace_contents(&path, new_file))
     }
 
-    /// Returns a boolean indicating if the cache has an entry corresponding to the given key.
+    /// Reads a file that is in the cache again, after its stats reported that it has `expected_size` bytes.
     ///
-    /// # Arguments
+    /// If a different number of bytes was read, the file changed while it was being read, so neither
+    /// the new nor the old contents can be trusted. The file is removed from the cache, and an error of the kind
+    /// `InvalidData` is returned.
+    async fn reread_file(&self, path: &PathBuf, expected_size: usize) -> io::Result<InMemoryFile> {
+        let new_file: InMemoryFile = self.read_file_of_size(path, expected_size).await?;
+        if new_file.stats.size != expected_size {
+            debug!("File: {:?} changed size while it was being read, removing it from the cache.", path);
+            self.remove_entry(path);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the file changed size while it was being read"));
+        }
+        Ok(new_file)
+    }
+
+    /// Replaces the contents of a file in the cache with a file that was read again, keeping its time to live.
+    /// Returns false if the file was removed from the cache while it was being read, in which case it stays out of the cache.
+    pub(crate) fn replace_contents(&self, path: &PathBuf, mut new_file: InMemoryFile) -> bool {
+        match self.file_map.find_mut(path) {
+            Some(mut file_entry) => {
+                new_file.expires_at = file_entry.get().expires_at;
+                *file_entry.get() = new_file;
+            }
+            None => return false,
+        }
+        self.update_stats(path);
+        true
+    }
+
+    /// Gets the file from the cache, or tries to add it to the cache if it is not already present,
+    /// and marks it to expire after the provided duration.
     ///
-    /// * `path` - A path that is used as a key to look up the file.
+    /// Once the file has expired, `get()` will serve it from the filesystem and remove it from the cache.
+    /// If the file is already in the cache, its time to live will be replaced.
     ///
-    /// # Example
+    /// # Arguments
     ///
-    /// ```
-    /// use rocket_file_cache::{CacheBuilder};
-    /// use std::path::PathBuf;
+    /// * `path` - A path that represents the path of the file in the filesystem, and key to the file in the cache.
+    /// * `ttl` - How long the file can be served from the cache.
     ///
-    /// let mut cache = CacheBuilder::new().build().unwrap();
-    /// let pathbuf: PathBuf = PathBuf::new();
-    /// cache.get(&pathbuf);
-    /// assert!(cache.contains_key(&pathbuf) == false);
-    /// ```
-    pub fn contains_key<P: AsRef<Path>>(&self, path: P) -> bool {
-        self.file_map.find(&path.as_ref().to_path_buf()).is_some()
+    /// # Return
+    ///
+    /// If the file could not be added to the cache, the CachedFile will indicate that it was gotten
+    /// from the filesystem, or that it could not be found.
+    pub async fn insert_with_ttl<P: AsRef<Path> + std::marker::Send>(&self, path: P, ttl: Duration) -> CachedFile {
+        let path: PathBuf = self.normalize_key(path);
+
+        if !self.contains_key(&path) {
+            match self.try_insert(&path).await {
+                CachedFile::InMemory(_) => {}
+                other => return other, // The file was not accepted into the cache.
+            }
+        }
+
+        if let Some(mut file_entry) = self.file_map.find_mut(&path) {
+            file_entry.get().expires_at = Some(Instant::now() + ttl);
+        }
+
+        self.get_from_cache(&path).await
     }
 
-    /// Alters the access count value of one file in the access_count_map.
-    /// # Arguments
+    /// Reads the file into the cache, even if the cache would otherwise decide not to store it,
+    /// replacing the file if it is already in the cache.
     ///
-    /// * `path` - The key to look up the file.
-    /// * `alter_count_function` - A function that determines how to alter the access_count for the file.
+    /// This gives control over the contents of the cache, for warming it up, or in tests.
+    /// The file is stored regardless of `admit_after()`, `min_file_size()`, `max_file_size()`, and the extensions
+    /// that are allowed, and it doesn't have to outrank the files that are evicted to make room for it.
+    /// Which files are evicted is still decided by the eviction policy, and pinned files are never evicted.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of reading the file, an error of the kind `InvalidInput` if the file is outside
+    /// of the cache's root directory, or of the kind `OutOfMemory` if it is larger than the capacity of the cache,
+    /// or can't fit without evicting pinned files.
+    /// If the file was already in the cache, the old copy is replaced once the new one fits, without being
+    /// reported as evicted, and it stays in the cache if the new one can't be stored.
     ///
     /// # Example
     ///
     /// ```
-    /// use rocket_file_cache::{Cache, CacheBuilder};
-    /// use std::path::PathBuf;
+    /// use rocket_file_cache::{Cache, CacheBuilder, CachedFile};
     ///
-    /// let mut cache = CacheBuilder::new().build().unwrap();
-    /// let pathbuf = PathBuf::new();
-    /// cache.get(&pathbuf); // Add a file to the cache
-    /// cache.remove(&pathbuf); // Removing the file will not reset its access count.
-    /// cache.alter_access_count(&pathbuf, | x | { 0 }); // Set the access count to 0.
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().admit_after(3).build().unwrap();
+    /// if let Ok(CachedFile::InMemory(file)) = cache.insert_file("www/index.html").await {
+    ///     println!("Cached {} bytes", file.bytes().len());
+    /// }
+    /// # }
     /// ```
-    ///
-    pub fn alter_access_count<P: AsRef<Path>>(&self, path: P, alter_count_function: fn(&usize) -> usize) -> bool {
-        let new_count: usize;
-        {
-            match self.access_count_map.find(&path.as_ref().to_path_buf()) {
-                Some(access_count_entry) => {
-                    new_count = alter_count_function(&access_count_entry.get());
-                }
-                None => return false, // Can't update a file that isn't in the cache.
-            }
+    pub async fn insert_file<P: AsRef<Path>>(&self, path: P) -> io::Result<CachedFile> {
+        let key: PathBuf = self.normalize_key(path);
+        if !self.is_within_root(&key).await {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the file is outside of the cache's root directory"));
         }
-        {
-            self.access_count_map.insert(
-                path.as_ref().to_path_buf(),
-                new_count,
+        let read_started: Instant = Instant::now();
+        let file: InMemoryFile = self.read_file(&key).await?;
+        let size: usize = file.stats.size;
+        if size > self.capacity_bytes() {
+            return Err(io::Error::new(io::ErrorKind::OutOfMemory, "the file is larger than the capacity of the cache"));
+        }
+
+        // The old contents shouldn't count towards the size of the cache while making room for the new contents.
+        let replaced_size: Option<usize> = self.file_map.find(&key).map(|file_entry| file_entry.get().stats.size);
+        let required_space: usize = (self.used_bytes() + size)
+            .saturating_sub(replaced_size.unwrap_or(0))
+            .saturating_sub(self.capacity_bytes());
+        let required_entries: usize = match replaced_size {
+            Some(_) => 0,
+            None => self.required_entries_for_new_file(),
+        };
+        if required_space > 0 || required_entries > 0 {
+            let candidates: HashMap<PathBuf, FileStats> = self.current_stats()
+                .into_iter()
+                .filter(|&(ref path, _)| !self.is_pinned(path) && path != &key)
+                .collect();
+            let victims: Vec<(PathBuf, FileStats)> = eviction_policy::select_victims(
+                &*self.eviction_policy,
+                self.victim_order,
+                candidates,
+                required_space,
+                required_entries,
             );
+            let freed_space: usize = victims.iter().map(|&(_, ref stats)| stats.size).sum();
+            if freed_space < required_space || victims.len() < required_entries {
+                return Err(io::Error::new(io::ErrorKind::OutOfMemory, "the file can't fit in the cache without evicting pinned files"));
+            }
+            self.evict(victims.into_iter().map(|(path, _)| path).collect());
         }
-        self.update_stats(&path);
-        return true;
+
+        self.file_map.insert(key.clone(), file);
+        self.update_stats(&key);
+        self.record_insert(&key, Some(read_started.elapsed()));
+        let cached_file: CachedFile = self.get_from_cache(&key).await;
+        Ok(self.add_response_headers(cached_file))
     }
 
-    /// Alters the access count value of every file in the access_count_map.
-    /// This is useful for manually aging-out entries in the cache.
-    ///
-    /// # Arguments
+    /// Marks every file that is currently in the cache as stale, without removing any of them.
     ///
-    /// * `alter_count_function` - A function that determines how to alter the access_count for the file.
+    /// This is a cheap way to flush the cache, for example after deploying new files.
+    /// A stale file is treated like an expired file: the next `get()` reads it from the filesystem again,
+    /// and `remove_expired_files()` reclaims the memory it holds.
     ///
     /// # Example
     ///
     /// ```
     /// use rocket_file_cache::{Cache, CacheBuilder};
-    /// use std::path::PathBuf;
     ///
-    /// let mut cache = CacheBuilder::new().build().unwrap();
-    /// let pathbuf = PathBuf::new();
-    /// let other_pathbuf = PathBuf::new();
-    /// cache.get(&pathbuf);
-    /// cache.get(&other_pathbuf);
-    /// // Reduce all access counts by half,
-    /// // allowing newer files to enter the cache more easily.
-    /// cache.alter_all_access_counts(| x | { x / 2 });
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// // Deploy new files...
+    /// cache.bump_generation();
     /// ```
+    pub fn bump_generation(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Removes every file from the cache that has outlived its time to live, or is stale after `bump_generation()` was called.
     ///
-    pub fn alter_all_access_counts(&self, alter_count_function: fn(&usize) -> usize) {
-        let all_counts: Vec<PathBuf>;
-        {
-            all_counts = self.access_count_map
-                .iter()
-                .map(|x: (&PathBuf, &usize)| x.0.clone())
-                .collect();
-        }
-        for pathbuf in all_counts {
-            self.alter_access_count(&pathbuf, alter_count_function);
-        }
+    /// # Return
+    ///
+    /// The number of files that were removed.
+    pub fn remove_expired_files(&self) -> usize {
+        let expired: Vec<PathBuf> = self.file_map
+            .iter()
+            .filter(|x| self.file_is_expired(x.1))
+            .map(|x| x.0.clone())
+            .collect();
+
+        let removed: usize = expired.iter()
+            .filter(|path| self.remove_entry(path).is_some())
+            .count();
 
+        self.remove_expired_missing_files();
+        removed
     }
 
-    /// Gets the sum of the sizes of the files that are stored in the cache.
+    /// Removes the records of files that were missing, once they have outlived the negative cache's time to live.
+    fn remove_expired_missing_files(&self) {
+        let now: Instant = Instant::now();
+        let expired: Vec<PathBuf> = self.negative_map
+            .iter()
+            .filter(|x| *x.1 <= now)
+            .map(|x| x.0.clone())
+            .collect();
+
+        for path in expired {
+            self.negative_map.remove(&path);
+        }
+    }
+
+    /// Spawns a task that will remove expired files from the cache every `interval`.
+    /// This reclaims the memory held by expired files, even if they are never requested again.
+    ///
+    /// The task only holds a weak reference to the cache, and will stop once the cache is dropped.
+    /// The expiry of a file is still checked on every `get()`, regardless of whether this task is running.
     ///
     /// # Example
     ///
     /// ```
     /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
     ///
-    /// let cache = CacheBuilder::new().build().unwrap();
-    /// assert!(cache.used_bytes() == 0);
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Arc<Cache> = Arc::new(CacheBuilder::new().build().unwrap());
+    /// let _sweeper = cache.clone().spawn_expiry_sweeper(Duration::from_secs(60));
+    /// # }
     /// ```
-    pub fn used_bytes(&self) -> usize {
-        self.file_map.iter().fold(
-            0usize,
-            |size, x| size + x.1.stats.size,
-        )
-    }
-
-    /// Gets the size of the file from the file's metadata.
-    /// This avoids having to read the file into memory in order to get the file size.
-    fn get_file_size_from_metadata<P: AsRef<Path>>(path: P) -> Result<usize, CacheError> {
-        let path_string: String = match path.as_ref().to_str() {
-            Some(s) => String::from(s),
-            None => return Err(CacheError::InvalidPath),
-        };
-        let metadata: Metadata = match fs::metadata(path_string.as_str()) {
-            Ok(m) => m,
-            Err(_) => return Err(CacheError::InvalidMetadata),
-        };
-        let size: usize = metadata.len() as usize;
-        Ok(size)
+    pub fn spawn_expiry_sweeper(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let cache: Weak<Cache> = Arc::downgrade(&self);
+        drop(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match cache.upgrade() {
+                    Some(cache) => {
+                        let removed: usize = cache.remove_expired_files();
+                        if removed > 0 {
+                            debug!("Removed {} expired files from the cache.", removed);
+                        }
+                    }
+                    None => break, // The cache has been dropped.
+                }
+            }
+        })
     }
 
-
-    /// Attempt to store a given file in the the cache.
-    /// Storing will fail if the current files have more access attempts than the file being added.
-    /// If the provided file has more more access attempts than one of the files in the cache,
-    /// but the cache is full, a file will have to be removed from the cache to make room
-    /// for the new file.
-    ///
-    /// If the insertion works, the cache will update the priority score for the file being inserted.
-    /// The cached priority score requires the file in question to exist in the file map, so it will
-    /// have a size to use when calculating.
-    ///
-    /// It will get the size of the file to be inserted.
-    /// If will use this size to check if the file could be inserted.
-    /// If it can be inserted, it reads the file into memory, stores a copy of the in-memory
-    /// file behind a pointer, and constructs a CachedFile to return.
-    ///
-    /// If the file can't be added, it will open a NamedFile and construct a CachedFile from that,
-    /// and return it.
-    /// This means that it doesn't need to read the whole file into memory before reading through it
-    /// again to set the response body.
-    /// The lack of the need to read the whole file twice keeps performance of cache misses on par
-    /// with just normally reading the file without a cache.
-    ///
+    /// Spawns a task that will remove the files that haven't been served within `max_idle` from the
+    /// cache every `interval`, like `evict_idle()` does.
+    /// This reclaims the memory held by files that are no longer requested, even if the cache isn't full.
     ///
-    /// # Arguments
+    /// Pinned files are kept, and the eviction callback is called for every file that is removed.
+    /// The task only holds a weak reference to the cache, and will stop once the cache is dropped.
+    /// It can be stopped earlier by aborting the returned handle.
     ///
-    /// * `path` - The path of the file to be stored. Acts as a key for the file in the cache. Is used
-    /// look up the location of the file in the filesystem if the file is not in the cache.
+    /// # Example
     ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
     ///
-    #[async_recursion]
-    async fn try_insert<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> CachedFile {
-        let path: PathBuf = path.as_ref().to_path_buf();
-        trace!("Trying to insert file {:?}", path);
-
-        // If the FS can read metadata for a file, then the file exists, and it should be safe to increment
-        // the access_count and update.
-        let size: usize = match Cache::get_file_size_from_metadata(&path) {
-            Ok(size) => size,
-            Err(_) => return CachedFile::NotFound // Could not open file to read metadata.
-        };
-
-        // Determine how much space can still be used (represented by a negative value) or how much
-        // space needs to be freed in order to make room for the new file
-        let required_space_for_new_file: isize = (self.used_bytes() as isize + size as isize) - self.size_limit as isize;
-
-
-        if size > self.max_file_size || size < self.min_file_size {
-            self.get_file_from_fs(&path).await
-        } else if required_space_for_new_file < 0 && size < self.size_limit {
-            self.get_file_from_fs_and_add_to_cache(&path).await
-        } else {
-            debug!("Trying to make room for the file");
-
-            // Because the size was gotten from the file's metadata, we know that it exists,
-            // so its fine to increment the account
-            self.increment_access_count(&path);
-
-            // The access_count should have incremented since the last time this was called, so the priority must be recalculated.
-            // Also, the size generally
-            let new_file_priority: usize;
-            {
-                let new_file_access_count: &usize = match self.access_count_map.find(&path) {
-                    Some(access_count) => &access_count.get(),
-                    None => &1,
-                };
-                new_file_priority = (self.priority_function)(new_file_access_count.clone(), size);
-            }
-
-
-            match self.make_room_for_new_file(required_space_for_new_file as usize, new_file_priority) {
-                Ok(files_to_be_removed) => {
-                    debug!("Made room for new file");
-                    match InMemoryFile::open(path.as_path()).await {
-                        Ok(file) => {
-
-                            // We have read a new file into memory, it is safe to
-                            // remove the old files.
-                            for file_key in files_to_be_removed {
-                                // The file was accessed with this key earlier when sorting priorities, which should make removal safe.
-                                match self.file_map.remove(&file_key) {
-                                    Some(_) => {},
-                                    None => warn!("Likely due to concurrent mutations, a file being removed from the cache was not found because another thread removed it first.")
-                                };
-                            }
-
-                            self.file_map.insert(path.clone(), file);
-                            self.update_stats(&path);
-
-                            let cache_file_accessor = match self.file_map.find(&path) {
-                                Some(accessor_to_file) => accessor_to_file,
-                                None => {
-                                    // If a concurrent remove operation removes the file before
-                                    // it can be gotten via an accessor lock, recursively try to add
-                                    // the file to the Cache until the lock can be attained.
-
-                                    // Because this action takes place after room was made for
-                                    // the new file in the cache, those files will be left out of the cache.
-                                    warn!("Tried to add file to cache, but it was removed before it could be added. Attempting to insert file again.");
-                                    // Because this recursion only occurs under extremely rare
-                                    // circumstances due to concurrent removal of the file being
-                                    // added between the insertion into the map, and locking an
-                                    // accessor, a stack overflow is almost impossible. This would require
-                                    // the file to be removed on every recursive attempt to re-insert it,
-                                    // with the exact same timing required to invalidate the `find()` method,
-                                    // for as many times as it takes to fill up the stack. It's not
-                                    // going to happen.
-                                    return self.try_insert(path).await;
-                                }
-                            };
-
-                            let named_in_memory_file: NamedInMemoryFile = NamedInMemoryFile::new(
-                                path.clone(),
-                                cache_file_accessor
-                            );
-
-                            return CachedFile::from(named_in_memory_file);
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Arc<Cache> = Arc::new(CacheBuilder::new().build().unwrap());
+    /// let reaper = cache.clone().spawn_idle_reaper(Duration::from_secs(60 * 60), Duration::from_secs(60));
+    /// reaper.abort();
+    /// # }
+    /// ```
+    pub fn spawn_idle_reaper(self: Arc<Self>, max_idle: Duration, interval: Duration) -> JoinHandle<()> {
+        let cache: Weak<Cache> = Arc::downgrade(&self);
+        drop(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match cache.upgrade() {
+                    Some(cache) => {
+                        let removed: usize = cache.evict_idle(max_idle);
+                        if removed > 0 {
+                            debug!("Removed {} idle files from the cache.", removed);
                         }
-                        Err(_) => return CachedFile::NotFound
-                    }
-                }
-                Err(_) => {
-                    debug!("The file does not have enough priority or is too large to be accepted into the cache.");
-                    // The new file would not be accepted by the cache, so instead of reading the whole file
-                    // into memory, and then copying it yet again when it is attached to the body of the
-                    // response, use a NamedFile instead.
-                    match NamedFile::open(path.clone()).await {
-                        Ok(named_file) => CachedFile::from(named_file),
-                        Err(_) => CachedFile::NotFound,
                     }
+                    None => break, // The cache has been dropped.
                 }
             }
-        }
+        })
     }
 
-    /// Gets a file from the filesystem and converts it to a CachedFile.
+    /// Enables caching of files that could not be found.
     ///
-    /// This should be used when the cache knows that the new file won't make it into the cache.
-    async fn get_file_from_fs< P: AsRef<Path>>(&self, path: P) -> CachedFile{
-        debug!("File does not fit size constraints of the cache.");
-        match NamedFile::open(path.as_ref().to_path_buf()).await {
-            Ok(named_file) => {
-                self.increment_access_count(path);
-                return CachedFile::from(named_file);
-            }
-            Err(_) => return CachedFile::NotFound
-        }
+    /// Once a file is found to be missing, `get()` will return `CachedFile::NotFound` for that file
+    /// without checking the filesystem, until `ttl` has passed.
+    /// This prevents repeated requests for files that don't exist from hitting the disk every time.
+    ///
+    /// If the file is created before `ttl` has passed, calling `remove()` with its path will
+    /// allow it to be found immediately.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.enable_negative_cache(Duration::from_secs(5));
+    /// ```
+    pub fn enable_negative_cache(&mut self, ttl: Duration) {
+        self.negative_cache_ttl = Some(ttl);
     }
 
-    /// Reads a file from the filesystem into memory and stores it in the cache.
+    /// Registers a callback that is called with the path and final stats of every file that leaves the cache,
+    /// whether it is evicted to make room for another file, removed with `remove()` or `clear()`,
+    /// expires, or doesn't fit after `set_capacity_bytes()` is called.
+    /// Registering a new callback replaces the old one.
     ///
-    /// This is the slowest operation the cache can perform, slower than just getting the file.
-    /// It should only be used when the cache decides to store the file.
-    #[async_recursion]
-    async fn get_file_from_fs_and_add_to_cache<P: AsRef<Path> + std::marker::Send + std::marker::Sync>(&self, path: P) -> CachedFile {
-        debug!("Cache has room for the file.");
-        match InMemoryFile::open(&path).await {
-            Ok(file) => {
-                self.file_map.insert(path.as_ref().to_path_buf(), file);
+    /// The callback is called after the file has been removed, and no locks on the cache are held while it runs,
+    /// so it is safe to call the cache's methods from the callback.
+    /// Files being replaced by `refresh()` don't leave the cache, so the callback isn't called for them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let mut cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.on_evict(Box::new(|path, stats| {
+    ///     println!("{:?} left the cache after {} accesses", path, stats.access_count);
+    /// }));
+    /// ```
+    pub fn on_evict(&mut self, callback: Box<dyn Fn(&Path, &FileStats) + Send + Sync>) {
+        self.on_evict = Some(callback);
+    }
 
-                self.increment_access_count(&path);
-                self.update_stats(&path);
+    /// Registers a callback that is called with the cache's stats when `pressure()` reaches `fraction`,
+    /// so the cache can be shrunk, or an alert can be raised, before it starts evicting files.
+    /// Registering a new callback replaces the old one.
+    ///
+    /// The pressure is checked whenever a file is added to the cache. Once the callback has been called,
+    /// it isn't called again until the pressure has dropped more than 0.05 below `fraction`, so it
+    /// isn't called for every file that is added while the cache stays full.
+    /// The callback is called from the task that added the file, with no locks on the cache held.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let mut cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.on_high_water(0.9, Box::new(|stats| {
+    ///     println!("The cache is almost full: {}", stats);
+    /// }));
+    /// ```
+    pub fn on_high_water(&mut self, fraction: f64, callback: Box<dyn Fn(&CacheStats) + Send + Sync>) {
+        self.high_water = Some(HighWater::new(fraction, callback));
+    }
 
-                let cache_file_accessor = match self.file_map.find(path.as_ref()) {
-                    Some(accessor_to_file) => accessor_to_file,
-                    None => {
-                        // If for whatever reason, a concurrent remove operation removes the file
-                        // before it can be gotten via an accessor lock, recursively try to add
-                        // the file to the Cache until the lock can be attained.
-                        warn!("Tried to add file to cache, but it was removed before it could be added. Attempting to get file again.");
-                        // Because this recursion only occurs under extremely rare circumstances
-                        // due to a concurrent removal of the file being added between the insertion
-                        // into the map, and locking an accessor, a stack overflow is almost impossible.
-                        return self.get_file_from_fs_and_add_to_cache(path).await;
-                    }
-                };
+    /// Replaces the function that calculates the priority scores of files, and recalculates the
+    /// priority of every file in the cache, so the new function takes effect immediately.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// // Prefer files that are requested often, regardless of their size.
+    /// cache.set_priority_function(|access_count, _size| access_count);
+    /// ```
+    pub fn set_priority_function(&self, priority_function: fn(usize, usize) -> usize) {
+        *lock::write(&self.priority_function) = priority_function;
+        self.recompute_priorities();
+    }
 
-                let cached_file: NamedInMemoryFile = NamedInMemoryFile::new(
-                    path.as_ref().to_path_buf(),
-                    cache_file_accessor
-                );
+    /// The function that calculates the priority scores of files.
+    pub fn priority_function(&self) -> fn(usize, usize) -> usize {
+        *lock::read(&self.priority_function)
+    }
 
-                return CachedFile::from(cached_file);
-            }
-            Err(_) => return CachedFile::NotFound,
+    /// Recalculates the priority of every file in the cache from its current access count and size.
+    ///
+    /// Priorities are otherwise only recalculated when a file is accessed, so this is useful when
+    /// the inputs to the priority have changed for every file, for example when a half life is
+    /// configured and the files haven't been requested in a while.
+    pub fn recompute_priorities(&self) {
+        let paths: Vec<PathBuf> = self.file_map
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in paths {
+            self.update_stats(&path);
         }
     }
 
+    /// Removes the file from the cache map, and calls the eviction callback with its final stats.
+    pub(crate) fn remove_entry(&self, path: &PathBuf) -> Option<InMemoryFile> {
+        // The lock on the map is released once `remove()` returns, before the callback is called.
+        let removed: InMemoryFile = self.file_map.remove(path)?;
+        if let Some(ref on_evict) = self.on_evict {
+            let mut stats: FileStats = removed.stats.clone();
+            self.fill_in_stats(path, &mut stats);
+            on_evict(path, &stats);
+        }
+        self.rearm_high_water();
+        Some(removed)
+    }
 
-
-    /// Remove the n lowest priority files to make room for a file with a size: required_space.
+    /// Adds the bias to the priority score of the file, whenever its priority is calculated.
+    /// A positive bias makes the file less likely to be evicted, without pinning it,
+    /// and a negative bias makes it more likely to be evicted.
     ///
-    /// If this returns an OK, this function has removed the required file space from the file_map.
-    /// If this returns an Err, then either not enough space could be freed, or the priority of
-    /// files that would need to be freed to make room for the new file is greater than the
-    /// new file's priority, and as result no memory was freed.
+    /// The bias is kept when the file is removed from the cache or refreshed,
+    /// and it can be set before the file is added to the cache.
     ///
     /// # Arguments
     ///
-    /// * `required_space` - A `usize` representing the number of bytes that must be freed to make room for a new file.
-    /// * `new_file_priority` - A `usize` representing the priority of the new file to be added. If the priority of the files possibly being removed
-    /// is greater than this value, then the files won't be removed.
-    fn make_room_for_new_file(&self, required_space: usize, new_file_priority: usize) -> Result<Vec<PathBuf>, CacheError> {
-        let mut possibly_freed_space: usize = 0;
-        let mut priority_score_to_free: usize = 0;
-        let mut file_paths_to_remove: Vec<PathBuf> = vec![];
-
-        let mut stats: Vec<(PathBuf, FileStats)> = self.sorted_priorities();
-        while possibly_freed_space < required_space {
-            // pop the priority group with the lowest priority off of the vector
-            match stats.pop() {
-                Some(lowest) => {
-                    let (lowest_key, lowest_stats) = lowest;
-
-                    possibly_freed_space += lowest_stats.size;
-                    priority_score_to_free += lowest_stats.priority;
-                    file_paths_to_remove.push(lowest_key.clone());
-
-                    // Check if total priority to free is greater than the new file's priority,
-                    // If it is, then don't free the files, as they in aggregate, are more important
-                    // than the new file.
-                    if priority_score_to_free > new_file_priority {
-                        return Err(CacheError::NewPriorityIsNotHighEnough);
-                    }
-                }
-                None => return Err(CacheError::NoMoreFilesToRemove),
-            };
+    /// * `path` - A path that is used as a key to look up the file.
+    /// * `bias` - The amount to add to the file's priority score. The score can't go below 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.set_priority_bias("www/css/login.css", 10_000);
+    /// ```
+    pub fn set_priority_bias<P: AsRef<Path>>(&self, path: P, bias: i64) {
+        let path: PathBuf = self.normalize_key(path);
+        self.priority_bias_map.insert(path.clone(), bias);
+        if self.contains_key(&path) {
+            self.update_stats(&path);
         }
-        Ok(file_paths_to_remove)
-
     }
 
-    ///Helper function that gets the file from the cache if it exists there.
-    async fn get_from_cache<P: AsRef<Path>>(&self, path: P) -> CachedFile {
-        match self.file_map.find(&path.as_ref().to_path_buf()) {
-            Some(in_memory_file) => {
-                trace!("Found file: {:?} in cache.", path.as_ref());
-                CachedFile::from(NamedInMemoryFile::new(
-                    path.as_ref().to_path_buf(),
-                    in_memory_file,
-                ))
-            }
-            None => CachedFile::NotFound,
+    /// Removes the bias from the priority score of the file, restoring its default score.
+    /// Returns true if the file had a bias.
+    pub fn clear_priority_bias<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path: PathBuf = self.normalize_key(path);
+        let cleared: bool = self.priority_bias_map.remove(&path).is_some();
+        if cleared && self.contains_key(&path) {
+            self.update_stats(&path);
         }
+        cleared
+    }
 
+    /// Gets the bias that is added to the priority of the file.
+    fn priority_bias(&self, path: &PathBuf) -> i64 {
+        match self.priority_bias_map.find(path) {
+            Some(bias) => *bias.get(),
+            None => 0,
+        }
     }
 
-    /// Helper function for incrementing the access count for a given file name.
+    /// Removes the file from the cache.
+    /// This will not reset the access count, so the next time the file is accessed, it will be added to the cache again.
+    /// The access count will have to be reset separately using `alter_access_count()`.
     ///
-    /// This should only be used in cases where the file is known to exist, to avoid bloating the access count map with useless values.
-    fn increment_access_count<P: AsRef<Path>>(&self, path: P) {
-        self.access_count_map.upsert(
-            path.as_ref().to_path_buf(),
-            1, // insert 1 if nothing at key. The closure will not execute.
-            &|access_count| {
-                *access_count = match usize::checked_add(access_count.clone(), 1) {
-                    Some(v) => v, // return the incremented value
-                    None => usize::MAX, // If the access count bumps up against the usize max, keep the value the same.
-                }
-            },
-        );
+    /// # Arguments
+    ///
+    /// * `path` - A path that acts as a key to look up the file that should be removed from the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::path::PathBuf;
+    ///
+    /// let mut cache = CacheBuilder::new().build().unwrap();
+    /// let pathbuf = PathBuf::new();
+    /// cache.remove(&pathbuf);
+    /// assert!(cache.contains_key(&pathbuf) == false);
+    /// ```
+    pub fn remove<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path: PathBuf = self.normalize_key(path);
+        self.negative_map.remove(&path);
+        if let Some(ref spill) = self.spill {
+            spill.discard_matching(|key| key == path.as_path());
+        }
+        let removed: bool = self.remove_entry(&path).is_some();
+        self.remove_chunks(&path);
+        removed
     }
 
-
-    /// Update the stats associated with this file.
-    fn update_stats<P: AsRef<Path>>(&self, path: P) {
-
-        let access_count: usize = match self.access_count_map.find(&path.as_ref().to_path_buf()) {
-            Some(access_count) => access_count.get().clone(),
-            None => 1,
+    /// Removes every file from the cache, returning the number of files that were removed.
+    /// Files that were remembered as missing by the negative cache are forgotten as well.
+    ///
+    /// Like `remove()`, this will not reset the access counts of the files.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// assert_eq!(cache.clear(), 0);
+    /// assert_eq!(cache.used_bytes(), 0);
+    /// ```
+    pub fn clear(&self) -> usize {
+        self.remove_matching(|_| true)
+    }
+
+    /// Removes every file whose path matches the glob pattern from the cache, returning the number
+    /// of files that were removed.
+    /// The pattern is matched against the paths the files were gotten with, so it should be written
+    /// in the same form as the paths passed to `get()`.
+    ///
+    /// Files that were remembered as missing by the negative cache, and match the pattern, are forgotten as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `PatternError` if the pattern is not a valid glob.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let removed: usize = cache.invalidate_glob("www/js/*.js").unwrap();
+    /// ```
+    pub fn invalidate_glob(&self, pattern: &str) -> Result<usize, PatternError> {
+        let pattern: Pattern = Pattern::new(pattern)?;
+        Ok(self.remove_matching(|path| pattern.matches_path(path)))
+    }
+
+    /// Removes every file that hasn't been served from the cache within the duration, returning the
+    /// number of files that were removed.
+    /// Pinned files are kept, and the files that are removed are counted as evictions.
+    ///
+    /// This is intended to be called periodically, to remove files that only take up space.
+    /// The time each file was last served can be seen in the `last_accessed` field of its stats in `snapshot()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::time::Duration;
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let removed: usize = cache.evict_idle(Duration::from_secs(60 * 60));
+    /// ```
+    pub fn evict_idle(&self, idle: Duration) -> usize {
+        let idle_files: Vec<PathBuf> = self.current_stats()
+            .into_iter()
+            .filter(|&(ref path, ref stats)| stats.last_accessed.elapsed() >= idle && !self.is_pinned(path))
+            .map(|(path, _)| path)
+            .collect();
+        let mut removed: usize = 0;
+        for path in idle_files {
+            // Another thread may have removed the file in the meantime.
+            if let Some(file) = self.remove_entry(&path) {
+                self.record_eviction(&path, &file);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Removes the files whose paths match the predicate from the cache and the negative cache.
+    fn remove_matching<F: Fn(&Path) -> bool>(&self, predicate: F) -> usize {
+        // The chunks of a file are removed along with it, but aren't counted as files.
+        let matching: Vec<PathBuf> = self.file_map
+            .iter()
+            .filter(|x| predicate(&range_chunks::file_of_key(x.0)))
+            .map(|x| x.0.clone())
+            .collect();
+
+        let removed: usize = matching.iter()
+            .filter(|path| self.remove_entry(path).is_some() && !range_chunks::is_chunk_key(path))
+            .count();
+
+        let missing: Vec<PathBuf> = self.negative_map
+            .iter()
+            .filter(|x| predicate(x.0))
+            .map(|x| x.0.clone())
+            .collect();
+        for path in missing {
+            self.negative_map.remove(&path);
+        }
+        if let Some(ref spill) = self.spill {
+            spill.discard_matching(&predicate);
+        }
+
+        removed
+    }
+
+    /// Returns a boolean indicating if the cache has an entry corresponding to the given key.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path that is used as a key to look up the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{CacheBuilder};
+    /// use std::path::PathBuf;
+    ///
+    /// let mut cache = CacheBuilder::new().build().unwrap();
+    /// let pathbuf: PathBuf = PathBuf::new();
+    /// cache.get(&pathbuf);
+    /// assert!(cache.contains_key(&pathbuf) == false);
+    /// ```
+    pub fn contains_key<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.file_map.find(&self.normalize_key(path)).is_some()
+    }
+
+    /// Returns a boolean indicating if the file is in the cache.
+    ///
+    /// This is the same as `contains_key()`, and uses the path as a key in the same way as `get()` and `remove()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path that is used as a key to look up the file.
+    pub fn contains<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.contains_key(path)
+    }
+
+    /// Marks the file as one that should never be evicted to make room for other files.
+    ///
+    /// The file doesn't need to be in the cache to be pinned. It will stay pinned once it is added.
+    /// If the cache is full, and only pinned files remain, new files will be served from the filesystem instead.
+    /// Pinned files can still be removed from the cache with `remove()`, or by expiring.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path that is used as a key to look up the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.pin("www/js/app.js");
+    /// assert!(cache.is_pinned("www/js/app.js"));
+    /// ```
+    pub fn pin<P: AsRef<Path>>(&self, path: P) {
+        self.pinned_map.insert(self.normalize_key(path), ());
+    }
+
+    /// Allows the file to be evicted again.
+    /// Returns true if the file was pinned.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path that is used as a key to look up the file.
+    pub fn unpin<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.pinned_map.remove(&self.normalize_key(path)).is_some()
+    }
+
+    /// Returns a boolean indicating if the file has been pinned with `pin()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path that is used as a key to look up the file.
+    pub fn is_pinned<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.pinned_map.find(&self.normalize_key(path)).is_some()
+    }
+
+    /// Alters the access count value of one file in the access_count_map.
+    /// # Arguments
+    ///
+    /// * `path` - The key to look up the file.
+    /// * `alter_count_function` - A function that determines how to alter the access_count for the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::path::PathBuf;
+    ///
+    /// let mut cache = CacheBuilder::new().build().unwrap();
+    /// let pathbuf = PathBuf::new();
+    /// cache.get(&pathbuf); // Add a file to the cache
+    /// cache.remove(&pathbuf); // Removing the file will not reset its access count.
+    /// cache.alter_access_count(&pathbuf, | x | { 0 }); // Set the access count to 0.
+    /// ```
+    ///
+    pub fn alter_access_count<P: AsRef<Path>>(&self, path: P, alter_count_function: fn(&usize) -> usize) -> bool {
+        let path: PathBuf = self.normalize_key(path);
+        {
+            match self.access_count_map.find(&path) {
+                Some(access_count_entry) => {
+                    let new_count: usize = alter_count_function(&access_count_entry.get().access_count());
+                    access_count_entry.get().set_access_count(new_count);
+                }
+                None => return false, // Can't update a file that isn't in the cache.
+            }
+        }
+        self.update_stats(&path);
+        return true;
+    }
+
+    /// Alters the access count value of every file in the access_count_map.
+    /// This is useful for manually aging-out entries in the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `alter_count_function` - A function that determines how to alter the access_count for the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::path::PathBuf;
+    ///
+    /// let mut cache = CacheBuilder::new().build().unwrap();
+    /// let pathbuf = PathBuf::new();
+    /// let other_pathbuf = PathBuf::new();
+    /// cache.get(&pathbuf);
+    /// cache.get(&other_pathbuf);
+    /// // Reduce all access counts by half,
+    /// // allowing newer files to enter the cache more easily.
+    /// cache.alter_all_access_counts(| x | { x / 2 });
+    /// ```
+    ///
+    pub fn alter_all_access_counts(&self, alter_count_function: fn(&usize) -> usize) {
+        let all_counts: Vec<PathBuf>;
+        {
+            all_counts = self.access_count_map
+                .iter()
+                .map(|x: (&PathBuf, &AccessRecord)| x.0.clone())
+                .collect();
+        }
+        for pathbuf in all_counts {
+            self.alter_access_count(&pathbuf, alter_count_function);
+        }
+
+    }
+
+    /// Sets the access count of the file to 0, and recalculates its priority score.
+    ///
+    /// This is useful when the access count no longer reflects how popular the file is, for example
+    /// after it was requested many times by a crawler, or was altered with `alter_access_count()`.
+    /// The file stays in the cache, and the number of bytes used by the cache is unchanged.
+    ///
+    /// # Return
+    ///
+    /// False if the file isn't in the cache. The access count of a file that was requested, but isn't
+    /// in the cache, is still reset, without adding the file to the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// assert!(!cache.reset_access_count("www/index.html"));
+    /// ```
+    pub fn reset_access_count<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path: PathBuf = self.normalize_key(path);
+        self.reset_access_count_of(&path)
+    }
+
+    /// Resets the access count of every file like `reset_access_count()` does, so every file is
+    /// treated as if it had never been requested.
+    pub fn reset_all_stats(&self) {
+        let paths: Vec<PathBuf> = self.access_count_map
+            .iter()
+            .map(|x: (&PathBuf, &AccessRecord)| x.0.clone())
+            .collect();
+        for path in paths {
+            self.reset_access_count_of(&path);
+        }
+    }
+
+    /// Sets the access count of the file to 0, only recalculating its stats if it is in the cache,
+    /// as `update_stats()` would otherwise add an empty entry for it.
+    /// Returns true if the file is in the cache.
+    fn reset_access_count_of(&self, path: &PathBuf) -> bool {
+        if let Some(access_count_entry) = self.access_count_map.find(path) {
+            access_count_entry.get().set_access_count(0);
+        }
+        if !self.contains_key(path) {
+            return false;
+        }
+        self.update_stats(path);
+        true
+    }
+
+    /// Gets the sum of the sizes of the files that are stored in the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache = CacheBuilder::new().build().unwrap();
+    /// assert!(cache.used_bytes() == 0);
+    /// ```
+    pub fn used_bytes(&self) -> usize {
+        self.file_map.iter().fold(
+            0usize,
+            |size, x| size + x.1.stats.size,
+        )
+    }
+
+    /// Gets the number of bytes the cache can hold.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache = CacheBuilder::new().size_limit(1024 * 1024).build().unwrap();
+    /// assert_eq!(cache.capacity_bytes(), 1024 * 1024);
+    /// ```
+    pub fn capacity_bytes(&self) -> usize {
+        self.size_limit.load(Ordering::Relaxed)
+    }
+
+    /// Gets the fraction of the cache's capacity that is used, from 0 when it is empty to 1 when it is full.
+    /// A cache with a capacity of 0 is always full.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache = CacheBuilder::new().size_limit(1024 * 1024).build().unwrap();
+    /// assert_eq!(cache.pressure(), 0.0);
+    /// ```
+    pub fn pressure(&self) -> f64 {
+        let capacity: usize = self.capacity_bytes();
+        if capacity == 0 {
+            return 1.0;
+        }
+        self.used_bytes() as f64 / capacity as f64
+    }
+
+    /// Gets the paths of the files that are stored in the cache, in no particular order.
+    ///
+    /// This is cheaper than `snapshot()`, as the stats of the files aren't gathered.
+    /// Like `snapshot()`, the paths are collected in a single pass over the cache, so files that are
+    /// added or removed while the paths are being collected may or may not be included.
+    /// The chunks of files that are cached with `CacheBuilder::range_chunk_size()` aren't files, so they
+    /// aren't included, here or in the other ways of listing the files in the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// assert!(cache.keys().is_empty());
+    /// ```
+    pub fn keys(&self) -> Vec<PathBuf> {
+        self.file_map
+            .iter()
+            .filter(|&(path, _)| !range_chunks::is_chunk_key(path))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Gets the number of files that are stored in the cache.
+    pub fn entry_count(&self) -> usize {
+        self.file_map
+            .iter()
+            .filter(|&(path, _)| !range_chunks::is_chunk_key(path))
+            .count()
+    }
+
+    /// Gets the number of files that are stored in the cache, like `entry_count()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// assert_eq!(cache.len(), 0);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.entry_count()
+    }
+
+    /// Returns true if no files are stored in the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// assert!(cache.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.file_map.iter().all(|(path, _)| range_chunks::is_chunk_key(path))
+    }
+
+    /// Iterates over the paths and up to date stats of every file in the cache, sorted by path.
+    ///
+    /// The iterator is created from a `snapshot()`, so no locks on the cache are held while it is
+    /// used, and files that are added or removed in the meantime don't affect it.
+    /// Iterating over `&Cache` does the same.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// for (path, stats) in &cache {
+    ///     println!("{:?}: {} bytes", path, stats.size);
+    /// }
+    /// assert_eq!(cache.iter().count(), 0);
+    /// ```
+    pub fn iter(&self) -> std::vec::IntoIter<(PathBuf, FileStats)> {
+        self.snapshot().into_iter()
+    }
+
+    /// Changes the number of bytes the cache can hold.
+    /// If the cache holds more than the new capacity, files are removed according to the cache's
+    /// eviction policy until it fits. Pinned files are not removed, so the cache may remain over capacity.
+    ///
+    /// Returns the number of files that were removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache = CacheBuilder::new().size_limit(1024 * 1024 * 10).build().unwrap();
+    /// // Under memory pressure, halve the size of the cache.
+    /// cache.set_capacity_bytes(1024 * 1024 * 5);
+    /// assert_eq!(cache.capacity_bytes(), 1024 * 1024 * 5);
+    /// ```
+    pub fn set_capacity_bytes(&self, capacity: usize) -> usize {
+        self.size_limit.store(capacity, Ordering::Relaxed);
+
+        let used_bytes: usize = self.used_bytes();
+        if used_bytes <= capacity {
+            return 0;
+        }
+        let candidates: HashMap<PathBuf, FileStats> = self.current_stats()
+            .into_iter()
+            .filter(|&(ref path, _)| !self.is_pinned(path))
+            .collect();
+        // If only pinned files are left, the cache stays over its capacity.
+        let victims: Vec<(PathBuf, FileStats)> = eviction_policy::select_victims(
+            &*self.eviction_policy,
+            self.victim_order,
+            candidates,
+            used_bytes - capacity,
+            0,
+        );
+        let mut removed: usize = 0;
+        for (victim_key, _) in victims {
+            if let Some(file) = self.remove_entry(&victim_key) {
+                self.record_eviction(&victim_key, &file);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Removes the one file that the cache's eviction policy would remove next, as if room had to be made
+    /// for a new file, and returns its path.
+    /// Like other evictions, it is counted in the stats, and passed to the eviction callback.
+    /// Pinned files are never removed.
+    ///
+    /// Returns `None` if the cache holds no files that can be evicted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, LfuPolicy};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().eviction_policy(LfuPolicy).build().unwrap();
+    /// cache.insert_bytes("www/rare.html", b"rare".to_vec()).await;
+    /// cache.insert_bytes("www/popular.html", b"popular".to_vec()).await;
+    /// cache.get("www/popular.html").await;
+    /// assert_eq!(cache.evict_once(), Some("www/rare.html".into()));
+    /// # }
+    /// ```
+    pub fn evict_once(&self) -> Option<PathBuf> {
+        let candidates: HashMap<PathBuf, FileStats> = self.current_stats()
+            .into_iter()
+            .filter(|&(ref path, _)| !self.is_pinned(path))
+            .collect();
+        let victims: Vec<(PathBuf, FileStats)> = eviction_policy::select_victims(
+            &*self.eviction_policy,
+            self.victim_order,
+            candidates,
+            0,
+            1,
+        );
+        let (victim_key, _) = victims.into_iter().next()?;
+        // Another task may have removed the file since the stats were collected.
+        let file: InMemoryFile = self.remove_entry(&victim_key)?;
+        self.record_eviction(&victim_key, &file);
+        Some(victim_key)
+    }
+
+    /// Helper function that checks if the file was recently found to be missing.
+    fn is_known_to_be_missing(&self, path: &PathBuf) -> bool {
+        if self.negative_cache_ttl.is_none() {
+            return false;
+        }
+        let is_expired: bool = match self.negative_map.find(path) {
+            Some(expires_at) => *expires_at.get() <= Instant::now(),
+            None => return false,
+        };
+        if is_expired {
+            self.negative_map.remove(path);
+        }
+        !is_expired
+    }
+
+    /// Helper function that records that the file could not be found, if the negative cache is enabled.
+    fn record_missing(&self, path: PathBuf) {
+        if let Some(ttl) = self.negative_cache_ttl {
+            self.negative_map.insert(path, Instant::now() + ttl);
+        }
+    }
+
+    /// Gets the stats of the file from the cache's file source, or from its metadata in the filesystem,
+    /// without reading the file.
+    pub(crate) async fn read_stats(&self, path: &Path) -> io::Result<FileStats> {
+        match self.file_source {
+            Some(ref source) => source.0.stats(path).await,
+            None => FsSource.stats(path).await,
+        }
+    }
+
+    /// Reads the file at the path into memory, according to the configuration of the cache.
+    async fn read_file<P: AsRef<Path>>(&self, path: P) -> io::Result<InMemoryFile> {
+        self.file_reader().read(path.as_ref()).await
+    }
+
+    /// Reads the file at the path into memory, after its metadata reported that it has `expected_size` bytes.
+    ///
+    /// If the file is empty by the time it is read, it was most likely truncated in order to be rewritten,
+    /// so an error is returned instead of an empty file, which would be served until the file is refreshed.
+    /// Files that were already empty when their metadata was read are read as usual.
+    async fn read_file_of_size(&self, path: &Path, expected_size: usize) -> io::Result<InMemoryFile> {
+        let file: InMemoryFile = self.read_file(path).await?;
+        if file.stats.size == 0 && expected_size > 0 {
+            debug!("File: {:?} was truncated before it could be read, it won't be cached.", path);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the file was truncated before it could be read"));
+        }
+        Ok(file)
+    }
+
+    /// The configuration used to read files into memory.
+    pub(crate) fn file_reader(&self) -> FileReader {
+        FileReader {
+            hash_algorithm: self.hash_algorithm,
+            etag_mode: self.etag_mode,
+            precompress: self.precompress.clone(),
+            encoding_preference: self.encoding_preference.clone(),
+            min_precompress_size: self.min_precompress_size,
+            precompress_filter: self.precompress_filter.clone(),
+            precompressed_siblings: self.precompressed_siblings,
+            read_buffer_size: self.read_buffer_size,
+            source: self.file_source.clone(),
+            generation: self.generation.load(Ordering::Relaxed),
+            #[cfg(feature = "mmap")]
+            mmap_threshold: self.mmap_threshold,
+        }
+    }
+
+    /// Returns true if the file has changed size or been modified since it was read or mapped.
+    ///
+    /// Only memory mapped files are checked, unless `detect_changes` is enabled.
+    /// Entries without a modification time, like those generated by `get_or_insert_with()`, are never checked.
+    async fn has_changed_on_disk(&self, path: &PathBuf) -> bool {
+        let (size, modified) = match self.file_map.find(path) {
+            Some(file_entry) if file_entry.get().mapped => (file_entry.get().stats.size, file_entry.get().stats.modified),
+            Some(file_entry) if self.detect_changes && file_entry.get().stats.modified.is_some() => {
+                (file_entry.get().stats.size, file_entry.get().stats.modified)
+            }
+            _ => return false,
+        };
+        match self.read_stats(path).await {
+            Ok(stats) => stats.size != size || stats.modified != modified,
+            Err(_) => true,
+        }
+    }
+
+    /// Compresses the file, if it is large enough to be compressed and isn't already compressed.
+    pub(crate) fn prepare_file(&self, path: &Path, file: InMemoryFile) -> InMemoryFile {
+        self.file_reader().prepare(path, file)
+    }
+
+    /// Helper function that checks if the entry for the file has outlived its time to live,
+    /// or was read before the current generation.
+    pub(crate) fn is_expired<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self.file_map.find(&path.as_ref().to_path_buf()) {
+            Some(file_entry) => self.file_is_expired(file_entry.get()),
+            None => false,
+        }
+    }
+
+    fn file_is_expired(&self, file: &InMemoryFile) -> bool {
+        file.is_expired() || file.generation != self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Helper function that checks if the file was read before the current generation.
+    fn is_outdated<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self.file_map.find(&path.as_ref().to_path_buf()) {
+            Some(file_entry) => file_entry.get().generation != self.generation.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    /// Gets the paths and up to date stats of every file in the cache, sorted by path.
+    ///
+    /// The entries are collected in a single pass over the cache, so files that are added or removed
+    /// while the snapshot is being taken may or may not be included.
+    /// With the `serde` feature, the snapshot can be serialized, for example to serve it from a debugging route.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, FileStats};
+    /// use std::path::PathBuf;
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let snapshot: Vec<(PathBuf, FileStats)> = cache.snapshot();
+    /// assert!(snapshot.is_empty());
+    /// ```
+    pub fn snapshot(&self) -> Vec<(PathBuf, FileStats)> {
+        let mut snapshot: Vec<(PathBuf, FileStats)> = self.current_stats();
+        snapshot.retain(|&(ref path, _)| !range_chunks::is_chunk_key(path));
+        snapshot.sort_by(|a, b| a.0.cmp(&b.0));
+        snapshot
+    }
+
+    /// Gets the up to date stats of the file, if it is in the cache.
+    ///
+    /// Unlike `snapshot()`, this only looks at the one file, which makes it cheap enough to call
+    /// for every request, for example to log why a file keeps being evicted.
+    /// Getting the stats doesn't count as an access of the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// assert!(cache.get_stats("www/index.html").is_none());
+    /// ```
+    pub fn get_stats<P: AsRef<Path>>(&self, path: P) -> Option<FileStats> {
+        let path: PathBuf = self.normalize_key(path);
+        let mut stats: FileStats = self.file_map.find(&path)?.get().stats.clone();
+        self.fill_in_stats(&path, &mut stats);
+        Some(stats)
+    }
+
+    /// Gets a snapshot of how effective the cache has been.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, CacheStats};
+    ///
+    /// let cache = CacheBuilder::new().build().unwrap();
+    /// let stats: CacheStats = cache.stats();
+    /// assert_eq!(stats.hits, 0);
+    /// assert_eq!(stats.hit_ratio(), 0.0);
+    /// ```
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            not_found: self.counters.not_found.load(Ordering::Relaxed),
+            evictions: self.counters.evictions.load(Ordering::Relaxed),
+            entries: self.entry_count(),
+            bytes: self.used_bytes(),
+            capacity_bytes: self.capacity_bytes(),
+            pinned: self.file_map
+                .iter()
+                .filter(|&(path, _)| self.is_pinned(path))
+                .count(),
+        }
+    }
+
+    /// Gets the size of the file from the file's metadata.
+    /// This avoids having to read the file into memory in order to get the file size.
+    fn get_file_size_from_metadata<P: AsRef<Path>>(path: P) -> Result<usize, InsertError> {
+        let path_string: String = match path.as_ref().to_str() {
+            Some(s) => String::from(s),
+            None => return Err(InsertError::InvalidPath),
+        };
+        let metadata: Metadata = match fs::metadata(path_string.as_str()) {
+            Ok(m) => m,
+            Err(_) => return Err(InsertError::InvalidMetadata),
+        };
+        // FIFOs, sockets, and devices can't be read into memory safely, and directories can't be served.
+        if !metadata.is_file() {
+            return Err(InsertError::NotARegularFile);
+        }
+        let size: usize = metadata.len() as usize;
+        Ok(size)
+    }
+
+
+    /// Attempt to store a given file in the the cache.
+    /// Storing will fail if the current files have more access attempts than the file being added.
+    /// If the provided file has more more access attempts than one of the files in the cache,
+    /// but the cache is full, a file will have to be removed from the cache to make room
+    /// for the new file.
+    ///
+    /// If the insertion works, the cache will update the priority score for the file being inserted.
+    /// The cached priority score requires the file in question to exist in the file map, so it will
+    /// have a size to use when calculating.
+    ///
+    /// It will get the size of the file to be inserted.
+    /// If will use this size to check if the file could be inserted.
+    /// If it can be inserted, it reads the file into memory, stores a copy of the in-memory
+    /// file behind a pointer, and constructs a CachedFile to return.
+    ///
+    /// If the file can't be added, it will open a NamedFile and construct a CachedFile from that,
+    /// and return it.
+    /// This means that it doesn't need to read the whole file into memory before reading through it
+    /// again to set the response body.
+    /// The lack of the need to read the whole file twice keeps performance of cache misses on par
+    /// with just normally reading the file without a cache.
+    ///
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file to be stored. Acts as a key for the file in the cache. Is used
+    /// look up the location of the file in the filesystem if the file is not in the cache.
+    ///
+    ///
+    pub(crate) async fn try_insert<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> CachedFile {
+        self.try_insert_reporting(path).await.unwrap_or_else(CacheError::into_cached_file)
+    }
+
+    /// Tries to insert the file like `try_insert()`, keeping the reason the file could not be gotten.
+    #[async_recursion]
+    async fn try_insert_reporting<P: AsRef<Path> + std::marker::Send>(&self, path: P) -> Result<CachedFile, CacheError> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        trace!("Trying to insert file {:?}", path);
+
+        if !self.is_within_root(&path).await {
+            debug!("File: {:?} is outside of the cache's root directory.", path);
+            return Err(CacheError::NotFound);
+        }
+
+        // If the FS can read metadata for a file, then the file exists, and it should be safe to increment
+        // the access_count and update.
+        let size: usize = match self.read_stats(&path).await {
+            Ok(stats) => stats.size,
+            // Directories and special files aren't read.
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => return Err(CacheError::NotFound),
+            Err(e) => return Err(CacheError::from(e)),
+        };
+
+        // Determine how much space can still be used (represented by a negative value) or how much
+        // space needs to be freed in order to make room for the new file
+        let required_space_for_new_file: isize = (self.used_bytes() as isize + size as isize) - self.capacity_bytes() as isize;
+        let required_entries_for_new_file: usize = self.required_entries_for_new_file();
+
+
+        if size > self.max_file_size || size > self.capacity_bytes() {
+            // The file wouldn't fit even if every other file was evicted, so don't look for files to evict.
+            debug!("File: {:?} is too large to be stored in the cache.", path);
+            Err(CacheError::TooLarge(self.get_file_from_fs(&path).await?))
+        } else if size < self.min_file_size || !self.extension_filter.allows(&path) {
+            Ok(self.get_file_from_fs(&path).await?)
+        } else if self.should_stream(size) {
+            Ok(self.get_streaming_file(&path, size, required_space_for_new_file, required_entries_for_new_file).await?)
+        } else if required_space_for_new_file < 0 && required_entries_for_new_file == 0 && size < self.capacity_bytes() {
+            Ok(self.get_file_from_fs_and_add_to_cache(&path, size).await?)
+        } else {
+            debug!("Trying to make room for the file");
+
+            // Because the size was gotten from the file's metadata, we know that it exists,
+            // so its fine to increment the account
+            self.increment_access_count(&path);
+
+            // The access_count should have incremented since the last time this was called, so the priority must be recalculated.
+            // Also, the size generally
+            let mut new_file_stats = FileStats {
+                size,
+                access_count: 1,
+                priority: 0,
+                last_accessed: Instant::now(),
+                modified: None,
+            };
+            self.fill_in_stats(&path, &mut new_file_stats);
+
+
+            match self.make_room_for_new_file(&path, required_space_for_new_file.max(0) as usize, required_entries_for_new_file, &new_file_stats) {
+                Ok(files_to_be_removed) => {
+                    debug!("Made room for new file");
+                    let read_started: Instant = Instant::now();
+                    match self.read_file_of_size(path.as_path(), size).await {
+                        Ok(file) => {
+
+                            // We have read a new file into memory, it is safe to
+                            // remove the old files.
+                            self.evict(files_to_be_removed);
+
+                            self.file_map.insert(path.clone(), file);
+                            self.update_stats(&path);
+                            self.record_insert(&path, Some(read_started.elapsed()));
+
+                            let cache_file_accessor = match self.file_map.find(&path) {
+                                Some(accessor_to_file) => accessor_to_file,
+                                None => {
+                                    // If a concurrent remove operation removes the file before
+                                    // it can be gotten via an accessor lock, recursively try to add
+                                    // the file to the Cache until the lock can be attained.
+
+                                    // Because this action takes place after room was made for
+                                    // the new file in the cache, those files will be left out of the cache.
+                                    warn!("Tried to add file to cache, but it was removed before it could be added. Attempting to insert file again.");
+                                    // Because this recursion only occurs under extremely rare
+                                    // circumstances due to concurrent removal of the file being
+                                    // added between the insertion into the map, and locking an
+                                    // accessor, a stack overflow is almost impossible. This would require
+                                    // the file to be removed on every recursive attempt to re-insert it,
+                                    // with the exact same timing required to invalidate the `find()` method,
+                                    // for as many times as it takes to fill up the stack. It's not
+                                    // going to happen.
+                                    return self.try_insert_reporting(path).await;
+                                }
+                            };
+
+                            let named_in_memory_file: NamedInMemoryFile = NamedInMemoryFile::new(
+                                path.clone(),
+                                cache_file_accessor
+                            );
+
+                            return Ok(CachedFile::from(named_in_memory_file));
+                        }
+                        Err(e) => return Err(CacheError::from(e))
+                    }
+                }
+                Err(_) => {
+                    debug!("The file does not have enough priority or is too large to be accepted into the cache.");
+                    // The new file would not be accepted by the cache, so instead of reading the whole file
+                    // into memory, and then copying it yet again when it is attached to the body of the
+                    // response, use a NamedFile instead.
+                    Ok(self.open_uncached(&path).await?)
+                }
+            }
+        }
+    }
+
+    /// Adds a file that is already in memory to the cache, if the cache accepts it.
+    /// The file is subject to the same size limits and eviction policy as files that are read from the filesystem.
+    ///
+    /// If the cache already holds a file under the path, it is replaced once the new file is accepted,
+    /// without being reported as evicted. The old file doesn't count towards the size of the cache
+    /// while room is made for the new one, and it stays in the cache if the new one isn't accepted.
+    ///
+    /// Returns true if the file was added to the cache.
+    pub(crate) fn insert_loaded_file(&self, path: PathBuf, file: InMemoryFile) -> bool {
+        let size: usize = file.stats.size;
+        if size > self.max_file_size || size < self.min_file_size || size > self.capacity_bytes() {
+            return false;
+        }
+
+        let replaced_size: Option<usize> = self.file_map.find(&path).map(|file_entry| file_entry.get().stats.size);
+        let required_space_for_new_file: isize = (self.used_bytes() as isize + size as isize)
+            - replaced_size.unwrap_or(0) as isize
+            - self.capacity_bytes() as isize;
+        let required_entries_for_new_file: usize = match replaced_size {
+            Some(_) => 0,
+            None => self.required_entries_for_new_file(),
+        };
+        if required_space_for_new_file >= 0 || required_entries_for_new_file > 0 {
+            let mut new_file_stats: FileStats = file.stats.clone();
+            self.fill_in_stats(&path, &mut new_file_stats);
+            match self.make_room_for_new_file(&path, required_space_for_new_file.max(0) as usize, required_entries_for_new_file, &new_file_stats) {
+                Ok(files_to_be_removed) => self.evict(files_to_be_removed),
+                Err(_) => {
+                    debug!("The file does not have enough priority or is too large to be accepted into the cache.");
+                    return false;
+                }
+            }
+        }
+
+        self.file_map.insert(path.clone(), file);
+        self.update_stats(&path);
+        self.record_insert(&path, None);
+        true
+    }
+
+    /// Returns true if a file of this size should be streamed to the client while it is read into the cache.
+    fn should_stream(&self, size: usize) -> bool {
+        // Streaming reads the file from the filesystem, rather than from the file source.
+        if self.file_source.is_some() {
+            return false;
+        }
+        #[cfg(feature = "mmap")]
+        {
+            if let Some(mmap_threshold) = self.mmap_threshold {
+                if size >= mmap_threshold {
+                    return false;
+                }
+            }
+        }
+        match self.stream_misses {
+            Some(min_size) => size >= min_size,
+            None => false,
+        }
+    }
+
+    /// Opens the file to be streamed to the client, if the cache would accept it once it has been read.
+    /// Files that wouldn't be accepted are served from the filesystem instead.
+    ///
+    /// Room is made for the file when its contents are added to the cache, as the eviction policy may
+    /// decide differently by then.
+    ///
+    /// Only one stream collects the contents of a file at a time. Other requests for the file that miss the cache
+    /// while it is streamed are served from the filesystem, without holding a copy of the file.
+    async fn get_streaming_file(&self, path: &PathBuf, size: usize, required_space: isize, required_entries: usize) -> io::Result<CachedFile> {
+        self.increment_access_count(path);
+        let claim: TeeClaim = match TeeClaim::claim(&self.completed_streams, path) {
+            Some(claim) => claim,
+            None => {
+                debug!("File: {:?} is already being streamed into the cache.", path);
+                return self.open_uncached(path).await;
+            }
+        };
+        if required_space >= 0 || required_entries > 0 {
+            let mut new_file_stats = FileStats {
+                size,
+                access_count: 1,
+                priority: 0,
+                last_accessed: Instant::now(),
+                modified: None,
+            };
+            self.fill_in_stats(path, &mut new_file_stats);
+            if self.make_room_for_new_file(path, required_space.max(0) as usize, required_entries, &new_file_stats).is_err() {
+                debug!("The file does not have enough priority or is too large to be accepted into the cache.");
+                return self.open_uncached(path).await;
+            }
+        }
+        debug!("Streaming file: {:?} while it is read into the cache.", path);
+        StreamingFile::open(path.clone(), claim).await.map(CachedFile::from)
+    }
+
+    /// Adds the contents of the files that have been streamed to clients in full to the cache.
+    pub(crate) async fn insert_streamed_files(&self) {
+        // Files are only streamed if `stream_misses()` is set, and the streamed files are only locked
+        // once a stream has completed, so serving a file from the cache doesn't need the lock.
+        if self.stream_misses.is_none() || !self.completed_streams.take_has_completed() {
+            return;
+        }
+        let streamed: Vec<StreamedContents> = streaming_file::lock_streams(&self.completed_streams).completed.drain(..).collect();
+        for contents in streamed {
+            // Another request may have read the file into the cache while it was being streamed.
+            if self.contains_key(&contents.path) {
+                continue;
+            }
+            let mut file: InMemoryFile = InMemoryFile::from_bytes_with_etag_mode(contents.bytes, contents.modified, self.hash_algorithm, self.etag_mode);
+            if self.precompressed_siblings {
+                file.add_precompressed_siblings(&contents.path).await;
+            }
+            let file: InMemoryFile = self.prepare_file(&contents.path, file);
+            if !self.insert_loaded_file(contents.path.clone(), file) {
+                debug!("File: {:?} was streamed, but is no longer accepted into the cache.", contents.path);
+            }
+        }
+    }
+
+    /// The number of files that must be removed before a new file can be added, without the cache
+    /// holding more than `max_entries` files.
+    fn required_entries_for_new_file(&self) -> usize {
+        match self.max_entries {
+            // Chunks take up entries like files do.
+            Some(max_entries) => (self.file_map.iter().count() + 1).saturating_sub(max_entries),
+            None => 0,
+        }
+    }
+
+    /// Removes the files that were chosen to make room for a new file.
+    fn evict(&self, files_to_be_removed: Vec<PathBuf>) {
+        for file_key in files_to_be_removed {
+            // The file was accessed with this key earlier when sorting priorities, which should make removal safe.
+            match self.remove_entry(&file_key) {
+                Some(file) => self.record_eviction(&file_key, &file),
+                None => warn!("Likely due to concurrent mutations, a file being removed from the cache was not found because another thread removed it first.")
+            };
+        }
+    }
+
+    /// Counts a file that was removed to make room for other files.
+    pub(crate) fn record_eviction(&self, path: &Path, file: &InMemoryFile) {
+        self.counters.record_eviction();
+        instrumentation::evicted(path, &file.stats);
+        self.spill_evicted(path, file);
+    }
+
+    /// Reports that a file was added to the cache, with the stats it was given.
+    fn record_insert(&self, path: &PathBuf, read_duration: Option<Duration>) {
+        let stats: Option<FileStats> = self.file_map.find(path).map(|file_entry| file_entry.get().stats.clone());
+        if let Some(stats) = stats {
+            instrumentation::inserted(path, &stats, read_duration);
+        }
+        self.check_high_water();
+    }
+
+    /// Gets a file from the filesystem and converts it to a CachedFile.
+    ///
+    /// This should be used when the cache knows that the new file won't make it into the cache.
+    async fn get_file_from_fs< P: AsRef<Path>>(&self, path: P) -> io::Result<CachedFile> {
+        debug!("File does not fit size constraints of the cache.");
+        let cached_file: CachedFile = self.open_uncached(path.as_ref()).await?;
+        self.increment_access_count(path);
+        Ok(cached_file)
+    }
+
+    /// Reads a file from the filesystem into memory and stores it in the cache.
+    ///
+    /// This is the slowest operation the cache can perform, slower than just getting the file.
+    /// It should only be used when the cache decides to store the file.
+    #[async_recursion]
+    async fn get_file_from_fs_and_add_to_cache<P: AsRef<Path> + std::marker::Send + std::marker::Sync>(&self, path: P, size: usize) -> io::Result<CachedFile> {
+        debug!("Cache has room for the file.");
+        let read_started: Instant = Instant::now();
+        match self.read_file_of_size(path.as_ref(), size).await {
+            Ok(file) => {
+                self.file_map.insert(path.as_ref().to_path_buf(), file);
+
+                self.increment_access_count(&path);
+                self.update_stats(&path);
+                self.record_insert(&path.as_ref().to_path_buf(), Some(read_started.elapsed()));
+
+                let cache_file_accessor = match self.file_map.find(path.as_ref()) {
+                    Some(accessor_to_file) => accessor_to_file,
+                    None => {
+                        // If for whatever reason, a concurrent remove operation removes the file
+                        // before it can be gotten via an accessor lock, recursively try to add
+                        // the file to the Cache until the lock can be attained.
+                        warn!("Tried to add file to cache, but it was removed before it could be added. Attempting to get file again.");
+                        // Because this recursion only occurs under extremely rare circumstances
+                        // due to a concurrent removal of the file being added between the insertion
+                        // into the map, and locking an accessor, a stack overflow is almost impossible.
+                        return self.get_file_from_fs_and_add_to_cache(path, size).await;
+                    }
+                };
+
+                let cached_file: NamedInMemoryFile = NamedInMemoryFile::new(
+                    path.as_ref().to_path_buf(),
+                    cache_file_accessor
+                );
+
+                return Ok(CachedFile::from(cached_file));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+
+
+    /// Remove the n lowest priority files to make room for a file with a size: required_space,
+    /// removing at least required_entries files.
+    ///
+    /// The files that are removed are chosen by the cache's eviction policy.
+    ///
+    /// If this returns an OK, this function has removed the required file space from the file_map.
+    /// If this returns an Err, then either not enough space could be freed, or the eviction policy
+    /// decided that the files that would need to be freed to make room for the new file are more
+    /// important than the new file, and as result no memory was freed.
+    ///
+    /// # Arguments
+    ///
+    /// * `required_space` - A `usize` representing the number of bytes that must be freed to make room for a new file.
+    /// * `required_entries` - The number of files that must be removed to make room for a new file.
+    /// * `new_file_stats` - The stats of the new file to be added, which the eviction policy weighs against the stats
+    /// of the files possibly being removed.
+    fn make_room_for_new_file(&self, new_path: &Path, required_space: usize, required_entries: usize, new_file_stats: &FileStats) -> Result<Vec<PathBuf>, InsertError> {
+        if self.overflow_policy == OverflowPolicy::RejectToDisk {
+            return Err(InsertError::NoMoreFilesToRemove);
+        }
+        // Pinned files are never candidates for removal, and neither is an old copy of the new file,
+        // which is replaced rather than evicted.
+        let candidates: HashMap<PathBuf, FileStats> = self.current_stats()
+            .into_iter()
+            .filter(|&(ref path, _)| !self.is_pinned(path) && path != new_path)
+            .collect();
+        // Every victim is selected in one go, and they are then removed together by `evict()`.
+        let victims: Vec<(PathBuf, FileStats)> = eviction_policy::select_victims(
+            &*self.eviction_policy,
+            self.victim_order,
+            candidates,
+            required_space,
+            required_entries,
+        );
+        let possibly_freed_space: usize = victims.iter().map(|&(_, ref stats)| stats.size).sum();
+        if possibly_freed_space < required_space || victims.len() < required_entries {
+            return Err(InsertError::NoMoreFilesToRemove);
+        }
+        let (file_paths_to_remove, stats_to_free): (Vec<PathBuf>, Vec<FileStats>) = victims.into_iter().unzip();
+
+        // Check if the files to free, in aggregate, are more important than the new file.
+        // If they are, then don't free the files.
+        // The built in policies only become less willing to evict files as more are selected, so
+        // checking all of them at once is enough. Other policies are asked after every victim, as
+        // they would be if the victims were selected one at a time.
+        let should_evict: bool = match self.victim_order {
+            Some(_) => self.eviction_policy.should_evict(&stats_to_free, new_file_stats),
+            None => (1..=stats_to_free.len()).all(|count| self.eviction_policy.should_evict(&stats_to_free[..count], new_file_stats)),
+        };
+        if !should_evict {
+            return Err(InsertError::NewPriorityIsNotHighEnough);
+        }
+        Ok(file_paths_to_remove)
+    }
+
+    ///Helper function that gets the file from the cache if it exists there.
+    pub(crate) async fn get_from_cache<P: AsRef<Path>>(&self, path: P) -> CachedFile {
+        match self.file_map.find(&path.as_ref().to_path_buf()) {
+            Some(in_memory_file) => {
+                trace!("Found file: {:?} in cache.", path.as_ref());
+                CachedFile::from(NamedInMemoryFile::new(
+                    path.as_ref().to_path_buf(),
+                    in_memory_file,
+                ))
+            }
+            None => CachedFile::NotFound,
+        }
+
+    }
+
+    /// Returns true if the file is within the root directory once its path is resolved, or if the cache has no root.
+    /// Files that can't be resolved, because they don't exist, are treated as being outside of the root.
+    ///
+    /// Files that come from a file source don't have to exist in the filesystem, so their paths are resolved
+    /// without following symbolic links.
+    pub(crate) async fn is_within_root(&self, path: &PathBuf) -> bool {
+        match self.root {
+            Some(ref root) if self.file_source.is_some() => match std::env::current_dir() {
+                Ok(current_dir) => resolve_lexically(&current_dir.join(path)).starts_with(root),
+                Err(_) => false,
+            },
+            Some(ref root) => match tokio::fs::canonicalize(path).await {
+                Ok(canonical_path) => canonical_path.starts_with(root),
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
+
+    /// Helper function for incrementing the access count for a given file name.
+    ///
+    /// This should only be used in cases where the file is known to exist, to avoid bloating the access count map with useless values.
+    pub(crate) fn increment_access_count<P: AsRef<Path>>(&self, path: P) {
+        // Files that have been accessed before only need a shared lock to be updated.
+        if let Some(record) = self.access_count_map.find(&path.as_ref().to_path_buf()) {
+            record.get().record_access();
+            return;
+        }
+        self.access_count_map.upsert(
+            path.as_ref().to_path_buf(),
+            AccessRecord::new(1), // insert 1 if nothing at key. The closure will not execute.
+            &|record| record.record_access(),
+        );
+    }
+
+    /// Brings the stats of a file up to date with the record of its accesses, and recalculates its priority.
+    fn fill_in_stats(&self, path: &PathBuf, stats: &mut FileStats) {
+        let (access_count, last_accessed) = match self.access_count_map.find(path) {
+            Some(record) => (record.get().access_count(), record.get().last_accessed()),
+            None => (1, None),
+        };
+        stats.access_count = access_count;
+        if let Some(last_accessed) = last_accessed {
+            stats.last_accessed = last_accessed;
+        }
+        stats.priority = self.priority_score(stats, self.priority_bias(path)); // update the priority score.
+        self.eviction_policy.on_access(stats);
+    }
+
+    /// Calculates the priority of a file with the priority function, decays it by the time since the
+    /// file was last accessed, and adds the file's bias.
+    fn priority_score(&self, stats: &FileStats, bias: i64) -> usize {
+        let mut priority: usize = self.priority_function()(stats.access_count, stats.size);
+        if let Some(half_life) = self.priority_half_life {
+            priority = apply_decay(priority, stats.last_accessed.elapsed(), half_life);
+        }
+        apply_bias(priority, bias)
+    }
+
+    /// Gets the up to date stats of every file in the cache.
+    pub(crate) fn current_stats(&self) -> Vec<(PathBuf, FileStats)> {
+        // The stats are collected before they are filled in, so the locks on the file map aren't held
+        // while the access count map is read.
+        let mut stats: Vec<(PathBuf, FileStats)> = self.file_map
+            .iter()
+            .map(|x| (x.0.clone(), x.1.stats.clone()))
+            .collect();
+        for &mut (ref path, ref mut file_stats) in stats.iter_mut() {
+            self.fill_in_stats(path, file_stats);
+        }
+        stats
+    }
+
+
+    /// Update the stats associated with this file.
+    pub(crate) fn update_stats<P: AsRef<Path>>(&self, path: P) {
+
+        let (access_count, last_accessed): (usize, Option<Instant>) = match self.access_count_map.find(&path.as_ref().to_path_buf()) {
+            Some(record) => (record.get().access_count(), record.get().last_accessed()),
+            None => (1, None),
         };
+        let bias: i64 = self.priority_bias(&path.as_ref().to_path_buf());
+
+        // The new stats are calculated without holding a lock on the map, so if the priority function or the
+        // eviction policy panics, the map isn't poisoned.
+        let current: Option<FileStats> = self.file_map.find(&path.as_ref().to_path_buf()).map(|file_entry| file_entry.get().stats.clone());
+        if let Some(mut stats) = current {
+            // If the size is initialized to 0, then try to get the actual size from the filesystem
+            let filled_in_size: Option<usize> = if stats.size == 0 && self.file_source.is_none() {
+                Cache::get_file_size_from_metadata(&path.as_ref().to_path_buf()).ok()
+            } else {
+                None
+            };
+            if let Some(size) = filled_in_size {
+                stats.size = size;
+            }
+            stats.access_count = access_count;
+            if let Some(last_accessed) = last_accessed {
+                stats.last_accessed = last_accessed;
+            }
+            stats.priority = self.priority_score(&stats, bias); // update the priority score.
+            self.eviction_policy.on_access(&mut stats);
+
+            if let Some(mut file_entry) = self.file_map.find_mut(&path.as_ref().to_path_buf()) {
+                let file_stats: &mut FileStats = &mut file_entry.get().stats;
+                if let Some(size) = filled_in_size {
+                    file_stats.size = size;
+                }
+                file_stats.access_count = stats.access_count;
+                file_stats.last_accessed = stats.last_accessed;
+                file_stats.priority = stats.priority;
+            }
+            return;
+        }
+
+        self.file_map.upsert(
+            // Key
+            path.as_ref().to_path_buf(),
+            // Default Value
+            InMemoryFile {
+                bytes: Bytes::new(),
+                stats: FileStats {
+                    size: 0,
+                    access_count: 0,
+                    priority: 0,
+                    last_accessed: Instant::now(),
+                    modified: None,
+                },
+                expires_at: None,
+                etag: String::new(),
+                checksum: Some(self.hash_algorithm.hash(&[])),
+                hash_algorithm: self.hash_algorithm,
+                encoded: Vec::new(),
+                mapped: false,
+                generation: self.generation.load(Ordering::Relaxed),
+            },
+            // Update Function
+            // The file was added since its stats were calculated, with stats of its own.
+            &|_| {},
+        );
+
+
+    }
+}
+
+
+impl<'a> IntoIterator for &'a Cache {
+    type Item = (PathBuf, FileStats);
+    type IntoIter = std::vec::IntoIter<(PathBuf, FileStats)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+
+/// Halves the priority score for every `half_life` that has elapsed.
+fn apply_decay(priority: usize, elapsed: Duration, half_life: Duration) -> usize {
+    if half_life.is_zero() {
+        return 0;
+    }
+    let half_lives: f64 = elapsed.as_secs_f64() / half_life.as_secs_f64();
+    (priority as f64 * 0.5f64.powf(half_lives)) as usize
+}
+
+/// Adds a bias to a priority score, without overflowing or going below 0.
+fn apply_bias(priority: usize, bias: i64) -> usize {
+    if bias >= 0 {
+        priority.saturating_add(bias as usize)
+    } else {
+        priority.saturating_sub(bias.unsigned_abs() as usize)
+    }
+}
+
+/// Returns true if the path is a regular file, rather than a directory, FIFO, socket, or device.
+/// Opening a FIFO blocks until it is opened for writing, so this should be checked before opening a file.
+async fn is_regular_file(path: &Path) -> bool {
+    tokio::fs::metadata(path).await
+        .map(|metadata| metadata.is_file())
+        .unwrap_or(false)
+}
+
+/// Reports a file that couldn't be gotten as not found.
+fn found(cached_file: CachedFile) -> Result<CachedFile, CacheError> {
+    match cached_file {
+        CachedFile::NotFound => Err(CacheError::NotFound),
+        cached_file => Ok(cached_file),
+    }
+}
+
+/// Removes every entry from the map.
+fn clear_map<V: Send + Sync>(map: &ConcHashMap<PathBuf, V, RandomState>) {
+    let keys: Vec<PathBuf> = map.iter().map(|(key, _)| key.clone()).collect();
+    for key in keys {
+        map.remove(&key);
+    }
+}
+
+/// Resolves the `.` and `..` components of the path, without looking at the filesystem.
+fn resolve_lexically(path: &Path) -> PathBuf {
+    let mut resolved: PathBuf = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// Returns true if the Content-Type of the file is implied by its extension, like when it is served.
+fn has_known_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| rocket::http::ContentType::from_extension(&ext.to_string_lossy()))
+        .is_some()
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+    extern crate tempdir;
+    extern crate rand;
+
+    use super::*;
+
+    use self::tempdir::TempDir;
+    use self::test::Bencher;
+    use self::rand::rngs::StdRng;
+    use tokio::io::{AsyncWrite, BufWriter};
+    use tokio::fs::File;
+    use rocket::fs::NamedFile;
+    use tokio::io::AsyncRead;
+    use crate::in_memory_file::InMemoryFile;
+    use std::sync::Arc;
+    use std::mem;
+    use crate::cache_builder::CacheBuilder;
+    use crate::eviction_policy::{LruPolicy, SizeWeightedLfuPolicy};
+    use crate::key_normalization::KeyNormalization;
+    use crate::content_disposition::Disposition;
+    use self::rand::FromEntropy;
+    use self::rand::RngCore;
+    use tokio::io::{AsyncWriteExt, AsyncReadExt};
+    use rocket::local::asynchronous::Client;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::response::Responder;
+
+    const MEG1: usize = 1024 * 1024;
+    const MEG2: usize = MEG1 * 2;
+    const MEG5: usize = MEG1 * 5;
+    const MEG10: usize = MEG1 * 10;
+
+    const DIR_TEST: &'static str = "test1";
+    const FILE_MEG1: &'static str = "meg1.txt";
+    const FILE_MEG2: &'static str = "meg2.txt";
+    const FILE_MEG5: &'static str = "meg5.txt";
+    const FILE_MEG10: &'static str = "meg10.txt";
+
+    // Helper function that creates test files in a directory that is cleaned up after the test runs.
+    async fn create_test_file(temp_dir: &TempDir, size: usize, name: &str) -> PathBuf {
+        let path = temp_dir.path().join(name);
+        let mut tmp_file = File::create(path.clone()).await.unwrap();
+        let mut rand_data: Vec<u8> = vec![0u8; size];
+        StdRng::from_entropy().fill_bytes(rand_data.as_mut());
+        tmp_file.write_all(&rand_data).await.unwrap();
+        // let mut buffer = BufWriter::new(tmp_file);
+        // let x: usize = buffer.write(&rand_data).await.unwrap();
+        // buffer.flush().await.unwrap();
+        // assert_eq!(x, size);
+        path
+    }
+
+
+    // Standardize the way a file is used in these tests.
+    impl CachedFile {
+        async fn dummy_write(self) {
+            match self {
+                CachedFile::InMemory(cached_file) => {
+                    let mut v: Vec<u8> = Vec::new();
+                    let _ = cached_file.bytes().read_to_end(&mut v).await.unwrap();
+                },
+                CachedFile::FileSystem(mut named_file) => {
+                    let mut v: Vec<u8> = Vec::new();
+                    let _ = named_file.read_to_end(&mut v).await.unwrap();
+                }
+                CachedFile::Streaming(streaming_file) => streaming_file.drain().await.unwrap(),
+                CachedFile::Partial(_) => {}
+                CachedFile::NotFound => {
+                    panic!("tried to write using a non-existent file")
+                }
+            }
+        }
+
+        fn get_in_memory_file(self) -> NamedInMemoryFile {
+            match self {
+                CachedFile::InMemory(n) => n,
+                _ => panic!("tried to get cached file for named file"),
+
+            }
+        }
+
+        fn get_named_file(self) -> NamedFile {
+            match self {
+                CachedFile::FileSystem(n) => n,
+                _ =>  panic!("tried to get cached file for named file"),
+            }
+        }
+    }
+
+    // #[bench]
+    // fn cache_get_10mb(b: &mut Bencher) {
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(MEG1 * 20)
+    //         .build()
+    //         .unwrap();
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
+    //     cache.get(&path_10m); // add the 10 mb file to the cache
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_10m);
+    //         cached_file.await.dummy_write()
+    //     });
+    // }
+
+    // #[bench]
+    // fn cache_miss_10mb(b: &mut Bencher) {
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(0)
+    //         .build()
+    //         .unwrap();
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_10m);
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+    // #[bench]
+    // fn named_file_read_10mb(b: &mut Bencher) {
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
+    //     b.iter(|| {
+    //         let named_file = CachedFile::from(NamedFile::open(&path_10m).unwrap());
+    //         named_file.dummy_write()
+    //     });
+    // }
+
+    // #[bench]
+    // fn cache_get_1mb(b: &mut Bencher) {
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(MEG1 * 20)
+    //         .build()
+    //         .unwrap(); //Cache can hold 20Mb
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
+    //     cache.get(&path_1m); // add the 10 mb file to the cache
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_1m);
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+    // #[bench]
+    // fn cache_miss_1mb(b: &mut Bencher) {
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(0)
+    //         .build()
+    //         .unwrap();
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_1m);
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+    // #[bench]
+    // fn named_file_read_1mb(b: &mut Bencher) {
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
+
+    //     b.iter(|| {
+    //         let named_file = CachedFile::from(NamedFile::open(&path_1m).unwrap());
+    //         named_file.dummy_write()
+    //     });
+    // }
+
+
+
+    // #[bench]
+    // fn cache_get_5mb(b: &mut Bencher) {
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(MEG1 * 20)
+    //         .build()
+    //         .unwrap();
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5);
+    //     cache.get(&path_5m); // add the 10 mb file to the cache
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_5m);
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+    // #[bench]
+    // fn cache_miss_5mb(b: &mut Bencher) {
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(0)
+    //         .build()
+    //         .unwrap();
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5);
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_5m);
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+    // #[bench]
+    // fn named_file_read_5mb(b: &mut Bencher) {
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5);
+
+    //     b.iter(|| {
+    //         let named_file = CachedFile::from(NamedFile::open(&path_5m).unwrap());
+    //         named_file.dummy_write()
+    //     });
+    // }
+
+
+
+    // // Constant time access regardless of size.
+    // #[bench]
+    // fn cache_get_1mb_from_1000_entry_cache(b: &mut Bencher) {
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(MEG1 * 3)
+    //         .build()
+    //         .unwrap();
+    //     cache.get(&path_1m); // add the file to the cache
+
+    //     // Add 1024 1kib files to the cache.
+    //     for i in 0..1024 {
+    //         let path = create_test_file(&temp_dir, 1024, format!("{}_1kib.txt", i).as_str());
+    //         cache.get(&path);
+    //     }
+    //     // make sure that the file has a high priority.
+    //     cache.alter_all_access_counts(|x| x + 1 * 100000);
+
+    //     assert_eq!(cache.used_bytes(), MEG1 * 2);
+
+    //     let named_file = CachedFile::from(NamedFile::open(&path_1m).unwrap());
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_1m);
+    //         assert!(mem::discriminant(&cached_file) != mem::discriminant(&named_file));
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+    // // There is a penalty for missing the cache.
+    // #[bench]
+    // fn cache_miss_1mb_from_1000_entry_cache(b: &mut Bencher) {
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(MEG1)
+    //         .build()
+    //         .unwrap();
+
+    //     // Add 1024 1kib files to the cache.
+    //     for i in 0..1024 {
+    //         let path = create_test_file(&temp_dir, 1024, format!("{}_1kib.txt", i).as_str());
+    //         cache.get(&path);
+    //     }
+    //     // make sure that the file has a high priority.
+    //     cache.alter_all_access_counts(|x| x + 1 * 100_000_000_000_000_000);
+    //     let named_file = CachedFile::from(NamedFile::open(&path_1m).unwrap());
+
+    //     b.iter(|| {
+    //         let cached_file = cache.get(&path_1m);
+    //         assert!(mem::discriminant(&cached_file) == mem::discriminant(&named_file)); // get() in this case should only return files in the FS
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+    // // This is pretty much a worst-case scenario, where every file would try to be removed to make room for the new file.
+    // // There is a penalty for missing the cache.
+    // #[bench]
+    // fn cache_miss_5mb_from_1000_entry_cache(b: &mut Bencher) {
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG1);
+    //     let cache: Cache = CacheBuilder::new()
+    //         .size_limit(MEG5)
+    //         .build()
+    //         .unwrap();
+
+    //     // Add 1024 5kib files to the cache.
+    //     for i in 0..1024 {
+    //         let path = create_test_file(&temp_dir, 1024 * 5, format!("{}_5kib.txt", i).as_str());
+    //         cache.get(&path);
+    //     }
+    //     // make sure that the file has a high priority.
+    //     cache.alter_all_access_counts(|x| x + 1 * 100_000_000_000_000_000);
+    //     let named_file = CachedFile::from(NamedFile::open(&path_5m).unwrap());
+
+    //     b.iter(|| {
+    //         let cached_file: CachedFile = cache.get(&path_5m);
+    //         // Mimic what is done when the response body is set.
+    //         assert!(mem::discriminant(&cached_file) == mem::discriminant(&named_file));  // get() in this case should only return files in the FS
+    //         cached_file.dummy_write()
+    //     });
+    // }
+
+
+    // #[bench]
+    // fn in_memory_file_read_10mb(b: &mut Bencher) {
+    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
+    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
+
+    //     b.iter(|| {
+    //         let in_memory_file = Arc::new(InMemoryFile::open(path_10m.clone()).unwrap());
+    //         let file: *const InMemoryFile = Arc::into_raw(in_memory_file);
+    //         unsafe {
+    //             let _ = (*file).bytes.clone();
+    //             let _ = Arc::from_raw(file);
+    //         }
+    //     });
+    // }
+
+
+    #[tokio::test]
+    async fn file_exceeds_size_limit() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 8) // Cache can hold only 8Mb
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10).await;
+
+        let named_file = NamedFile::open(path_10m.clone()).await.unwrap();
+
+        // expect the cache to get the item from the FS.
+        assert_eq!(cache.try_insert(path_10m).await, CachedFile::from(named_file));
+    }
+
+
+    #[tokio::test]
+    async fn file_replaces_other_file() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+
+        let named_file_1m = NamedFile::open(path_1m.clone()).await.unwrap();
+        let named_file_1m_2 = NamedFile::open(path_1m.clone()).await.unwrap();
+
+
+        let mut imf_5m = InMemoryFile::open(path_5m.clone()).await.unwrap();
+        let mut imf_1m = InMemoryFile::open(path_1m.clone()).await.unwrap();
+
+        // set expected stats for 5m
+        imf_5m.stats.access_count = 1;
+        imf_5m.stats.priority = 2289;
+
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(5500000) //Cache can hold only about 5.5Mib
+            .build()
+            .unwrap();
+
+        println!("0:\n{:#?}", cache);
+
+        assert_eq!(
+            cache
+                .try_insert(path_5m.clone()).await
+                .get_in_memory_file()
+                .file
+                .as_ref()
+                .get(),
+            &imf_5m
+        );
+        println!("1:\n{:#?}", cache);
+        assert_eq!(
+            cache.try_insert(path_1m.clone()).await,
+            CachedFile::from(named_file_1m)
+        );
+        println!("2:\n{:#?}", cache);
+        assert_eq!(
+            cache.try_insert(path_1m.clone()).await,
+            CachedFile::from(named_file_1m_2)
+        );
+        println!("3:\n{:#?}", cache);
+
+        // set the expected stats for 1m
+        imf_1m.stats.access_count = 3;
+        imf_1m.stats.priority = 3072;
+
+        assert_eq!(
+            cache
+                .try_insert(path_1m.clone()).await
+                .get_in_memory_file()
+                .file
+                .as_ref()
+                .get(),
+            &imf_1m
+        );
+        println!("4:\n{:#?}", cache);
+    }
+
+
+
+
+    #[tokio::test]
+    async fn new_file_replaces_lowest_priority_file() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
+        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+
+
+        #[allow(unused_variables)]
+        let named_file_1m = NamedFile::open(path_1m.clone()).await.unwrap();
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 7 + 2000) // cache can hold a little more than 7MB
+            .build()
+            .unwrap();
+
+        println!("1:\n{:#?}", cache);
+        let mut imf_5m: InMemoryFile = InMemoryFile::open(path_5m.clone()).await.unwrap();
+        imf_5m.stats.priority = 2289;
+        imf_5m.stats.access_count = 1;
+
+        assert_eq!(
+            cache.get(&path_5m).await
+               .get_in_memory_file()
+               .file
+               .as_ref()
+               .get(),
+            &imf_5m
+        );
+
+        println!("2:\n{:#?}", cache);
+        let mut imf_2m: InMemoryFile = InMemoryFile::open(path_2m.clone()).await.unwrap();
+        imf_2m.stats.priority = 1448;
+        imf_2m.stats.access_count = 1;
+        assert_eq!(
+            cache.get(&path_2m).await
+                .get_in_memory_file()
+                .file
+                .as_ref()
+                .get(),
+            &imf_2m
+        );
+
+
+        println!("3:\n{:#?}", cache);
+        let mut named_1m = NamedFile::open(path_1m.clone()).await.unwrap();
+        let mut v: Vec<u8> = Vec::new();
+        let _ = cache
+            .get(&path_1m).await
+            .get_named_file()
+            .read_to_end(&mut v)
+            .await
+            .unwrap();
+
+        let mut file_vec: Vec<u8> = Vec::new();
+        let _ = named_1m.read_to_end(&mut file_vec).await.unwrap();
+        assert_eq!(
+            v,
+            file_vec
+        );
+
+
+        println!("4:\n{:#?}", cache);
+        let mut imf_1m: InMemoryFile = InMemoryFile::open(path_1m.clone()).await.unwrap();
+        imf_1m.stats.priority = 2048; // This priority is higher than the in memory file - 2m's 1448, and therefore will replace it now
+        imf_1m.stats.access_count = 1;
+
+        // The cache will now accept the 1 meg file because (sqrt(2)_size * 1_access) for the old
+        // file is less than (sqrt(1)_size * 2_access) for the new file.
+        assert_eq!(
+            cache.get(&path_1m).await
+                .get_in_memory_file()
+                .file
+                .bytes,
+            imf_1m.bytes
+        );
+        println!("5:\n{:#?}", cache);
+
+
+        if let CachedFile::NotFound = cache.get_from_cache(&path_1m).await {
+            panic!("Expected 1m file to be in the cache");
+        }
+
+        // Check if the 5m file is still in the cache
+        if let CachedFile::NotFound = cache.get_from_cache(&path_5m).await {
+            panic!("Expected 5m file to be in the cache");
+        }
+
+        //
+        if let CachedFile::InMemory(_) = cache.get_from_cache(&path_2m).await {
+            panic!("Expected 2m file to not be in the cache");
+        }
+
+        drop(cache);
+    }
+
+
+
+
+    #[tokio::test]
+    async fn lru_removes_least_recently_accessed_file() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_a = create_test_file(&temp_dir, MEG1, "a.txt").await;
+        let path_b = create_test_file(&temp_dir, MEG1, "b.txt").await;
+        let path_c = create_test_file(&temp_dir, MEG1, "c.txt").await;
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG2 + 1000) // cache can hold only two of the files
+            .eviction_policy(LruPolicy)
+            .build()
+            .unwrap();
+
+        cache.get(&path_a).await;
+        cache.get(&path_b).await;
+        // Access a again, so b is now the least recently used file.
+        cache.get(&path_a).await;
+
+        cache.get(&path_c).await.get_in_memory_file();
+
+        assert!(cache.contains_key(&path_a));
+        assert!(!cache.contains_key(&path_b));
+        assert!(cache.contains_key(&path_c));
+    }
+
+
+    #[tokio::test]
+    async fn size_weighted_lfu_removes_large_cold_file() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_large = create_test_file(&temp_dir, MEG2, "large.txt").await;
+        let path_small = create_test_file(&temp_dir, 1024, "small.txt").await;
+        let path_new = create_test_file(&temp_dir, MEG1, "new.txt").await;
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG2 + 100 * 1024) // cache can hold the large and the small file
+            .eviction_policy(SizeWeightedLfuPolicy)
+            .build()
+            .unwrap();
+
+        cache.get(&path_large).await.get_in_memory_file();
+        for _ in 0..3 {
+            cache.get(&path_small).await.get_in_memory_file();
+        }
+
+        // The new file has as many accesses as the large file, in half the space.
+        cache.get(&path_new).await.get_in_memory_file();
+
+        assert!(!cache.contains_key(&path_large));
+        assert!(cache.contains_key(&path_small));
+        assert!(cache.contains_key(&path_new));
+        let small_priority: usize = cache.snapshot().into_iter()
+            .find(|&(ref path, _)| *path == path_small)
+            .unwrap().1.priority;
+        assert_eq!(small_priority, SizeWeightedLfuPolicy::score(3, 1024));
+    }
+
+    #[tokio::test]
+    async fn pinned_files_are_not_evicted() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_a = create_test_file(&temp_dir, MEG1, "a.txt").await;
+        let path_b = create_test_file(&temp_dir, MEG1, "b.txt").await;
+        let path_c = create_test_file(&temp_dir, MEG1, "c.txt").await;
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG2 + 1000) // cache can hold only two of the files
+            .eviction_policy(LruPolicy)
+            .build()
+            .unwrap();
+
+        cache.pin(&path_a);
+        cache.pin(&path_b);
+        cache.get(&path_a).await;
+        cache.get(&path_b).await;
+        assert_eq!(cache.stats().pinned, 2);
+
+        // Every file in the cache is pinned, so the new file is served from the filesystem.
+        cache.get(&path_c).await.get_named_file();
+        assert!(cache.contains_key(&path_a));
+        assert!(cache.contains_key(&path_b));
+
+        assert!(cache.unpin(&path_b));
+        assert!(!cache.is_pinned(&path_b));
+        cache.get(&path_c).await.get_in_memory_file();
+        assert!(cache.contains_key(&path_a));
+        assert!(!cache.contains_key(&path_b));
+        assert_eq!(cache.stats().pinned, 1);
+    }
+
+
+    #[tokio::test]
+    async fn concurrent_misses_load_file_once() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+
+        let (a, b, c) = tokio::join!(cache.get(&path_1m), cache.get(&path_1m), cache.get(&path_1m));
+        a.get_in_memory_file();
+        b.get_in_memory_file();
+        c.get_in_memory_file();
+
+        let access_count: usize = cache.access_count_map.find(&path_1m).unwrap().get().access_count();
+        assert_eq!(access_count, 3);
+        assert!(cache.in_flight.is_empty());
+    }
+
+
+    #[tokio::test]
+    async fn paths_outside_of_root_are_not_found() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let root = temp_dir.path().join("www");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("index.html"), vec![0u8; 1024]).unwrap();
+        let secret = temp_dir.path().join("secret.txt");
+        std::fs::write(&secret, vec![0u8; 1024]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&secret, root.join("link.txt")).unwrap();
+
+        let cache: Cache = CacheBuilder::new()
+            .root(&root)
+            .build()
+            .unwrap();
+
+        let payloads: Vec<PathBuf> = vec![
+            root.join("../secret.txt"),
+            root.join("./../secret.txt"),
+            root.join("sub/../../secret.txt"),
+            root.join("sub/../sub/../../secret.txt"),
+            root.join("link.txt"),
+            root.join("%2e%2e/secret.txt"),
+            root.join("..%2fsecret.txt"),
+            secret.canonicalize().unwrap(),
+            PathBuf::from("/etc/passwd"),
+            root.join("../../../../../../../../etc/passwd"),
+        ];
+        for payload in payloads {
+            assert_eq!(cache.get(&payload).await, CachedFile::NotFound, "{:?} escaped the root", payload);
+        }
+        assert!(cache.file_map.iter().next().is_none());
+
+        cache.get(root.join("index.html")).await.get_in_memory_file();
+        cache.get(root.join("sub/../index.html")).await.get_in_memory_file();
+    }
+
+
+    #[tokio::test]
+    async fn files_are_admitted_after_enough_requests() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .admit_after(3)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+
+        cache.get(&path_1m).await.get_named_file();
+        cache.get(&path_1m).await.get_named_file();
+        assert!(!cache.contains_key(&path_1m));
+        assert!(cache.access_count_map.find(&path_1m).is_none());
+
+        cache.get(&path_1m).await.get_in_memory_file();
+        assert!(cache.contains_key(&path_1m));
+    }
+
+
+    #[tokio::test]
+    async fn clear_and_invalidate_glob() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_js = create_test_file(&temp_dir, MEG1, "app.js").await;
+        let path_css = create_test_file(&temp_dir, MEG1, "app.css").await;
+        let path_txt = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        cache.get(&path_js).await.get_in_memory_file();
+        cache.get(&path_css).await.get_in_memory_file();
+        cache.get(&path_txt).await.get_in_memory_file();
+
+        let pattern: String = format!("{}/*.js", temp_dir.path().display());
+        assert_eq!(cache.invalidate_glob(&pattern).unwrap(), 1);
+        assert!(!cache.contains_key(&path_js));
+        assert!(cache.contains_key(&path_css));
+        assert!(cache.invalidate_glob("[").is_err());
+
+        assert_eq!(cache.clear(), 2);
+        assert_eq!(cache.used_bytes(), 0);
+    }
+
+
+    #[tokio::test]
+    async fn shrinking_capacity_evicts_files() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .eviction_policy(LruPolicy)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
+        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+        cache.get(&path_1m).await.get_in_memory_file();
+        cache.get(&path_2m).await.get_in_memory_file();
+        cache.get(&path_5m).await.get_in_memory_file();
+        assert_eq!(cache.entry_count(), 3);
+        assert_eq!(cache.used_bytes(), MEG1 + MEG2 + MEG5);
+
+        // The 1 and 2 MB files were used least recently.
+        assert_eq!(cache.set_capacity_bytes(MEG5), 2);
+        assert_eq!(cache.capacity_bytes(), MEG5);
+        assert_eq!(cache.entry_count(), 1);
+        assert!(cache.contains_key(&path_5m));
+        assert_eq!(cache.used_bytes(), MEG5);
+    }
+
+    #[tokio::test]
+    async fn evict_once_removes_the_next_victim() {
+        let mut cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .eviction_policy(LruPolicy)
+            .build()
+            .unwrap();
+        let evicted: Arc<std::sync::Mutex<Vec<PathBuf>>> = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let evicted_ref = evicted.clone();
+        cache.on_evict(Box::new(move |path, _| evicted_ref.lock().unwrap().push(path.to_path_buf())));
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
+        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+        cache.get(&path_1m).await.get_in_memory_file();
+        cache.get(&path_2m).await.get_in_memory_file();
+        cache.get(&path_5m).await.get_in_memory_file();
+        cache.pin(&path_2m);
+
+        assert_eq!(cache.evict_once(), Some(path_1m.clone()));
+        assert_eq!(cache.used_bytes(), MEG2 + MEG5);
+        assert_eq!(cache.stats().evictions, 1);
+        // The pinned file is skipped, even though it was used less recently.
+        assert_eq!(cache.evict_once(), Some(path_5m.clone()));
+        assert_eq!(cache.evict_once(), None);
+        assert_eq!(cache.keys(), vec![path_2m]);
+        assert_eq!(*evicted.lock().unwrap(), vec![path_1m, path_5m]);
+    }
+
+
+    #[tokio::test]
+    async fn on_evict_is_called_without_holding_locks() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_a = create_test_file(&temp_dir, MEG1, "a.txt").await;
+        let path_b = create_test_file(&temp_dir, MEG1, "b.txt").await;
+        let path_c = create_test_file(&temp_dir, MEG1, "c.txt").await;
+
+        let mut cache: Cache = CacheBuilder::new()
+            .size_limit(MEG2 + 1000) // cache can hold only two of the files
+            .eviction_policy(LruPolicy)
+            .build()
+            .unwrap();
+
+        let cache_slot: Arc<std::sync::Mutex<std::sync::Weak<Cache>>> = Arc::new(std::sync::Mutex::new(std::sync::Weak::new()));
+        let evicted: Arc<std::sync::Mutex<Vec<(PathBuf, usize)>>> = Arc::new(std::sync::Mutex::new(Vec::new()));
+        {
+            let cache_slot = cache_slot.clone();
+            let evicted = evicted.clone();
+            cache.on_evict(Box::new(move |path, stats| {
+                // Calling back into the cache must not deadlock.
+                if let Some(cache) = cache_slot.lock().unwrap().upgrade() {
+                    assert!(!cache.contains_key(path));
+                    let _ = cache.stats();
+                }
+                evicted.lock().unwrap().push((path.to_path_buf(), stats.access_count));
+            }));
+        }
+        let cache: Arc<Cache> = Arc::new(cache);
+        *cache_slot.lock().unwrap() = Arc::downgrade(&cache);
+
+        cache.get(&path_a).await;
+        cache.get(&path_b).await;
+        cache.get(&path_b).await;
+        cache.get(&path_c).await.get_in_memory_file();
+        assert_eq!(*evicted.lock().unwrap(), vec![(path_a.clone(), 1)]);
+
+        cache.remove(&path_b);
+        cache.clear();
+        assert_eq!(*evicted.lock().unwrap(), vec![(path_a, 1), (path_b, 2), (path_c, 1)]);
+    }
+
+
+    #[cfg(feature = "mmap")]
+    #[tokio::test]
+    async fn large_files_are_memory_mapped() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .mmap_threshold(MEG1)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_small = temp_dir.path().join("small.txt");
+        std::fs::write(&path_small, vec![b'a'; 1024]).unwrap();
+
+        assert!(cache.get(&path_1m).await.get_in_memory_file().file.is_mapped());
+        assert!(!cache.get(&path_small).await.get_in_memory_file().file.is_mapped());
+        assert_eq!(cache.used_bytes(), MEG1 + 1024);
+
+        // Replace the mapped file, the cache should notice and map the new file.
+        let path_new = temp_dir.path().join("new.txt");
+        std::fs::write(&path_new, vec![b'b'; MEG2]).unwrap();
+        std::fs::rename(&path_new, &path_1m).unwrap();
+        let file = cache.get(&path_1m).await.get_in_memory_file();
+        assert_eq!(file.bytes(), &vec![b'b'; MEG2][..]);
+        assert_eq!(cache.used_bytes(), MEG2 + 1024);
+    }
+
+
+    #[tokio::test]
+    async fn snapshot_contains_up_to_date_stats() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
+        cache.get(&path_1m).await;
+        cache.get(&path_2m).await;
+        cache.get(&path_2m).await;
+
+        let snapshot: Vec<(PathBuf, FileStats)> = cache.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].0, path_1m);
+        assert_eq!(snapshot[0].1.access_count, 1);
+        assert_eq!(snapshot[1].0, path_2m);
+        assert_eq!(snapshot[1].1.access_count, 2);
+        assert_eq!(snapshot[1].1.size, MEG2);
+
+        let mut keys: Vec<PathBuf> = cache.keys();
+        keys.sort();
+        assert_eq!(keys, vec![path_1m.clone(), path_2m.clone()]);
+    }
+
+    #[tokio::test]
+    async fn cache_can_be_iterated_over_while_it_changes() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
+        assert!(cache.is_empty());
+        cache.get(&path_1m).await;
+        cache.get(&path_2m).await;
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_empty());
+
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for (path, stats) in &cache {
+            // No lock is held, so the cache can be changed while iterating.
+            cache.remove(&path);
+            assert!(stats.size > 0);
+            paths.push(path);
+        }
+        assert_eq!(paths, vec![path_1m, path_2m]);
+        assert!(cache.is_empty());
+    }
+
+
+    #[tokio::test]
+    async fn peek_does_not_change_the_cache() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 2)
+            .cache_control("*", "no-cache")
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
+
+        // Files that aren't in the cache are served from the filesystem, even if they would fit.
+        match cache.peek(&path_1m).await {
+            CachedFile::FileSystem(_) => {}
+            other => panic!("Expected the file to be served from the filesystem, got {:?}", other),
+        }
+        assert!(!cache.contains_key(&path_1m));
+
+        cache.get(&path_1m).await;
+        match cache.peek(&path_1m).await {
+            CachedFile::InMemory(file) => assert_eq!(file.headers.len(), 1),
+            other => panic!("Expected the file to be served from memory, got {:?}", other),
+        }
+        assert_eq!(cache.get_stats(&path_1m).unwrap().access_count, 1);
+
+        // Peeking at a file that would replace the cached file doesn't evict it.
+        cache.peek(&path_2m).await;
+        assert!(cache.contains_key(&path_1m));
+        assert_eq!(cache.stats().hits + cache.stats().misses, 1);
+    }
+
+    #[tokio::test]
+    async fn get_stats_of_single_file() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .key_normalization(KeyNormalization { strip_query: true, ..KeyNormalization::default() })
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+
+        assert!(cache.get_stats(&path).is_none());
+        cache.get(&path).await;
+        cache.get(&path).await;
+
+        let path_with_query: PathBuf = PathBuf::from(format!("{}?v=2", path.to_str().unwrap()));
+        let stats: FileStats = cache.get_stats(&path_with_query).unwrap();
+        assert_eq!(stats.size, MEG1);
+        assert_eq!(stats.access_count, 2);
+        assert_eq!(stats.priority, crate::priority_function::default_priority_function(2, MEG1));
+        assert!(stats.modified.is_some());
+        // Getting the stats isn't an access.
+        assert_eq!(cache.get_stats(&path).unwrap().access_count, 2);
+    }
 
-        self.file_map.upsert(
-            // Key
-            path.as_ref().to_path_buf(),
-            // Default Value
-            InMemoryFile {
-                bytes: Vec::new(),
-                stats: FileStats {
-                    size: 0,
-                    access_count: 0,
-                    priority: 0,
-                },
-            },
-            // Update Function
-            &|file_entry| {
-                // If the size is initialized to 0, then try to get the actual size from the filesystem
-                if file_entry.stats.size == 0 {
-                    file_entry.stats.size = Cache::get_file_size_from_metadata(&path.as_ref().to_path_buf()).unwrap_or(0);
-                }
-                file_entry.stats.access_count = access_count;
-                file_entry.stats.priority = (self.priority_function)(file_entry.stats.access_count, file_entry.stats.size); // update the priority score.
-            },
-        );
+    #[tokio::test]
+    async fn idle_files_are_evicted() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_idle = create_test_file(&temp_dir, MEG1, "idle.txt").await;
+        let path_pinned = create_test_file(&temp_dir, MEG1, "pinned.txt").await;
+        let path_recent = create_test_file(&temp_dir, MEG1, "recent.txt").await;
+        cache.get(&path_idle).await;
+        cache.get(&path_pinned).await;
+        cache.pin(&path_pinned);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cache.get(&path_recent).await;
+
+        let snapshot: Vec<(PathBuf, FileStats)> = cache.snapshot();
+        assert!(snapshot[0].1.last_accessed < snapshot[2].1.last_accessed);
+
+        assert_eq!(cache.evict_idle(Duration::from_millis(50)), 1);
+        assert!(!cache.contains_key(&path_idle));
+        assert!(cache.contains_key(&path_pinned));
+        assert!(cache.contains_key(&path_recent));
+        assert_eq!(cache.stats().evictions, 1);
+    }
 
+    #[tokio::test]
+    async fn max_entries_limits_number_of_files() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .max_entries(2)
+            .eviction_policy(LruPolicy)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_a = create_test_file(&temp_dir, 1024, "a.txt").await;
+        let path_b = create_test_file(&temp_dir, 1024, "b.txt").await;
+        let path_c = create_test_file(&temp_dir, 1024, "c.txt").await;
+
+        cache.get(&path_a).await;
+        cache.get(&path_b).await;
+        cache.get(&path_a).await;
+        cache.get(&path_c).await.get_in_memory_file();
+
+        // The byte budget has plenty of room, but b was accessed least recently.
+        assert_eq!(cache.entry_count(), 2);
+        assert!(cache.contains_key(&path_a));
+        assert!(!cache.contains_key(&path_b));
+        assert!(cache.contains_key(&path_c));
+        assert_eq!(cache.stats().evictions, 1);
+    }
 
+    #[test]
+    fn decay_halves_priority() {
+        let half_life = Duration::from_secs(60);
+        assert_eq!(apply_decay(1000, Duration::from_secs(0), half_life), 1000);
+        assert_eq!(apply_decay(1000, Duration::from_secs(60), half_life), 500);
+        assert_eq!(apply_decay(1000, Duration::from_secs(120), half_life), 250);
+        assert_eq!(apply_decay(usize::MAX, Duration::from_secs(60 * 60 * 24 * 365), half_life), 0);
+        assert_eq!(apply_decay(1000, Duration::from_secs(1), Duration::from_secs(0)), 0);
     }
 
+    #[tokio::test]
+    async fn cold_files_lose_priority() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_cold = create_test_file(&temp_dir, MEG1, "cold.txt").await;
+        let path_warm = create_test_file(&temp_dir, MEG1, "warm.txt").await;
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .priority_half_life(Duration::from_millis(20))
+            .build()
+            .unwrap();
+        for _ in 0..10 {
+            cache.get(&path_cold).await;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        cache.get(&path_warm).await;
 
+        // The cold file was accessed ten times as often, but ten half lives ago.
+        let snapshot: Vec<(PathBuf, FileStats)> = cache.snapshot();
+        assert_eq!(snapshot[0].0, path_cold);
+        assert!(snapshot[0].1.priority < snapshot[1].1.priority);
+    }
 
+    #[tokio::test]
+    async fn priority_bias_changes_priority() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_a = create_test_file(&temp_dir, MEG1, "a.txt").await;
+        let path_b = create_test_file(&temp_dir, MEG1, "b.txt").await;
+        let path_c = create_test_file(&temp_dir, MEG1, "c.txt").await;
 
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG2 + 1000) // cache can hold only two of the files
+            .build()
+            .unwrap();
 
-    /// Gets a vector of tuples containing the Path, priority score, and size in bytes of all items
-    /// in the file_map.
-    ///
-    /// The vector is sorted from highest to lowest priority.
-    /// This allows the assumption that the last element to be popped from the vector will have the
-    /// lowest priority, and therefore is the most eligible candidate for elimination from the
-    /// cache.
-    ///
-    fn sorted_priorities(&self) -> Vec<(PathBuf, FileStats)> {
+        cache.get(&path_a).await;
+        cache.get(&path_b).await;
+        cache.set_priority_bias(&path_b, -1000);
+        assert_eq!(cache.file_map.find(&path_b).unwrap().get().stats.priority, 24);
+
+        // b is now the preferred victim.
+        cache.get(&path_c).await.get_in_memory_file();
+        assert!(cache.contains_key(&path_a));
+        assert!(!cache.contains_key(&path_b));
+
+        cache.set_priority_bias(&path_a, 5000);
+        assert_eq!(cache.file_map.find(&path_a).unwrap().get().stats.priority, 6024);
+        assert!(cache.clear_priority_bias(&path_a));
+        assert_eq!(cache.file_map.find(&path_a).unwrap().get().stats.priority, 1024);
+        assert!(!cache.clear_priority_bias(&path_a));
+    }
 
-        let mut priorities: Vec<(PathBuf, FileStats)> = self.file_map
-            .iter()
-            .map(|x| (x.0.clone(), x.1.stats.clone()))
-            .collect();
 
-        // Sort the priorities from highest priority to lowest, so when they are pop()ed later,
-        // the last element will have the lowest priority.
-        priorities.sort_by(|l, r| r.1.priority.cmp(&l.1.priority));
-        priorities
+    #[tokio::test]
+    async fn reload_replaces_contents() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 1024, "reload.txt").await;
+        let path_missing = temp_dir.path().join("missing.txt");
+
+        assert!(!cache.reload(&path).await.unwrap());
+        assert!(!cache.contains_key(&path));
+
+        cache.get(&path).await;
+        cache.get(&path).await;
+        std::fs::write(&path, vec![b'a'; 2048]).unwrap();
+        assert!(cache.reload(&path).await.unwrap());
+        assert_eq!(cache.used_bytes(), 2048);
+        assert_eq!(cache.snapshot()[0].1.access_count, 2);
+        match cache.get(&path).await {
+            CachedFile::InMemory(file) => assert_eq!(file.bytes(), &vec![b'a'; 2048][..]),
+            other => panic!("Expected the file to be in the cache, got {:?}", other),
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(cache.reload(&path).await.is_err());
+        assert!(cache.contains_key(&path));
+        assert!(!cache.reload(&path_missing).await.unwrap());
     }
-}
 
+    #[tokio::test]
+    async fn files_that_shrink_below_the_min_size_are_removed_on_reload() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .min_file_size(1024)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 2048, "shrink.txt").await;
 
+        cache.get(&path).await;
+        assert!(cache.contains_key(&path));
+        std::fs::write(&path, vec![b'a'; 16]).unwrap();
+        assert!(!cache.reload(&path).await.unwrap());
+        assert!(!cache.contains_key(&path));
+    }
 
-#[cfg(test)]
-mod tests {
-    extern crate test;
-    extern crate tempdir;
-    extern crate rand;
+    #[tokio::test]
+    async fn keys_are_normalized() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .key_normalization(KeyNormalization {
+                strip_query: true,
+                collapse_separators: true,
+                strip_trailing_slash: true,
+                lowercase: false,
+            })
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 1024, "app.js").await;
+        let path_string: String = path.to_str().unwrap().to_string();
+        let query_path: String = format!("{}?v=3", path_string);
+        let doubled_path: String = path_string.replace("/app.js", "//app.js");
+
+        cache.get(&query_path).await.get_in_memory_file();
+        cache.get(&doubled_path).await.get_in_memory_file();
+        assert_eq!(cache.entry_count(), 1);
+        assert!(cache.contains(&path));
+        assert!(cache.contains(&query_path));
+
+        assert!(cache.remove(&doubled_path));
+        assert!(!cache.contains(&path));
+    }
 
-    use super::*;
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn try_get_reports_errors() {
+        use std::os::unix::fs::PermissionsExt;
 
-    use self::tempdir::TempDir;
-    use self::test::Bencher;
-    use self::rand::rngs::StdRng;
-    use tokio::io::{AsyncWrite, BufWriter};
-    use tokio::fs::File;
-    use rocket::fs::NamedFile;
-    use tokio::io::AsyncRead;
-    use crate::in_memory_file::InMemoryFile;
-    use concurrent_hashmap::Accessor;
-    use std::sync::Arc;
-    use std::mem;
-    use crate::cache_builder::CacheBuilder;
-    use self::rand::FromEntropy;
-    use self::rand::RngCore;
-    use tokio::io::{AsyncWriteExt, AsyncReadExt};
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .max_file_size(1024)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_small = create_test_file(&temp_dir, 1024, "small.txt").await;
+        let path_large = create_test_file(&temp_dir, 2048, "large.txt").await;
+        let path_private = create_test_file(&temp_dir, 1024, "private.txt").await;
+        std::fs::set_permissions(&path_private, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        assert!(matches!(cache.try_get(&path_small).await, Ok(CachedFile::InMemory(_))));
+        assert!(matches!(cache.try_get(&path_large).await, Err(CacheError::TooLarge(CachedFile::FileSystem(_)))));
+        assert!(matches!(cache.get(&path_large).await, CachedFile::FileSystem(_)));
+        assert!(matches!(cache.try_get(temp_dir.path().join("missing.txt")).await, Err(CacheError::NotFound)));
+        // Permissions don't apply to the superuser.
+        if std::fs::read(&path_private).is_err() {
+            assert!(matches!(cache.try_get(&path_private).await, Err(CacheError::PermissionDenied)));
+        }
+    }
 
-    const MEG1: usize = 1024 * 1024;
-    const MEG2: usize = MEG1 * 2;
-    const MEG5: usize = MEG1 * 5;
-    const MEG10: usize = MEG1 * 10;
+    static PRIORITY_FUNCTION_PANICS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
-    const DIR_TEST: &'static str = "test1";
-    const FILE_MEG1: &'static str = "meg1.txt";
-    const FILE_MEG2: &'static str = "meg2.txt";
-    const FILE_MEG5: &'static str = "meg5.txt";
-    const FILE_MEG10: &'static str = "meg10.txt";
+    fn panicking_priority_function(access_count: usize, size: usize) -> usize {
+        if PRIORITY_FUNCTION_PANICS.load(Ordering::Relaxed) {
+            panic!("The priority function panicked.");
+        }
+        access_count * size
+    }
 
-    // Helper function that creates test files in a directory that is cleaned up after the test runs.
-    async fn create_test_file(temp_dir: &TempDir, size: usize, name: &str) -> PathBuf {
-        let path = temp_dir.path().join(name);
-        let mut tmp_file = File::create(path.clone()).await.unwrap();
-        let mut rand_data: Vec<u8> = vec![0u8; size];
-        StdRng::from_entropy().fill_bytes(rand_data.as_mut());
-        tmp_file.write_all(&rand_data).await.unwrap();
-        // let mut buffer = BufWriter::new(tmp_file);
-        // let x: usize = buffer.write(&rand_data).await.unwrap();
-        // buffer.flush().await.unwrap();
-        // assert_eq!(x, size);
-        path
+    #[tokio::test]
+    async fn panics_are_served_from_filesystem() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .priority_function(panicking_priority_function)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 1024, "panic.txt").await;
+
+        PRIORITY_FUNCTION_PANICS.store(true, Ordering::Relaxed);
+        cache.get(&path).await.get_named_file();
+        assert!(cache.is_poisoned());
+        PRIORITY_FUNCTION_PANICS.store(false, Ordering::Relaxed);
+        cache.get(&path).await.get_named_file();
+        assert_eq!(cache.get(temp_dir.path().join("missing.txt")).await, CachedFile::NotFound);
+
+        cache.recover();
+        assert!(!cache.is_poisoned());
+        cache.get(&path).await.get_in_memory_file();
     }
 
+    #[tokio::test]
+    async fn bumping_generation_makes_files_stale() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1 = create_test_file(&temp_dir, 1024, "one.txt").await;
+        let path_2 = create_test_file(&temp_dir, 1024, "two.txt").await;
+        cache.get(&path_1).await.get_in_memory_file();
+        cache.get(&path_2).await.get_in_memory_file();
+
+        cache.bump_generation();
+        std::fs::write(&path_1, b"new contents").unwrap();
+        // The stale file is read again, and tagged with the new generation.
+        assert_eq!(cache.get(&path_1).await.get_in_memory_file().bytes(), b"new contents");
+        assert!(!cache.is_expired(&path_1));
+        assert!(cache.is_expired(&path_2));
+
+        assert_eq!(cache.remove_expired_files(), 1);
+        assert!(cache.contains_key(&path_1));
+        assert!(!cache.contains_key(&path_2));
+    }
 
-    // Standardize the way a file is used in these tests.
-    impl<'a> CachedFile<'a> {
-        async fn dummy_write(self) {
-            match self {
-                CachedFile::InMemory(cached_file) => unsafe {
-                    let file: *const Accessor<'a, PathBuf, InMemoryFile> = Arc::into_raw(cached_file.file);
-                    let mut v: Vec<u8> = Vec::new();
-                    let _ = (*file).get().bytes.as_slice().read_to_end(&mut v).await.unwrap();
-                    let _ = Arc::from_raw(file); // To prevent a memory leak, an Arc needs to be reconstructed from the raw pointer.
-                },
-                CachedFile::FileSystem(mut named_file) => {
-                    let mut v: Vec<u8> = Vec::new();
-                    let _ = named_file.read_to_end(&mut v).await.unwrap();
-                }
-                CachedFile::NotFound => {
-                    panic!("tried to write using a non-existent file")
-                }
-            }
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn special_files_are_not_found() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        assert_eq!(cache.get(temp_dir.path()).await, CachedFile::NotFound);
+
+        let path = temp_dir.path().join("pipe");
+        let created = std::process::Command::new("mkfifo").arg(&path).status();
+        if !created.map(|status| status.success()).unwrap_or(false) {
+            return; // mkfifo isn't available.
         }
+        let cached_file = tokio::time::timeout(Duration::from_secs(5), cache.get(&path)).await;
+        assert_eq!(cached_file.expect("Getting the pipe blocked."), CachedFile::NotFound);
+        assert!(!cache.contains_key(&path));
+    }
 
-        fn get_in_memory_file(self) -> NamedInMemoryFile<'a> {
-            match self {
-                CachedFile::InMemory(n) => n,
-                _ => panic!("tried to get cached file for named file"),
+    #[tokio::test]
+    async fn changed_files_are_refreshed() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .detect_changes(true)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 1024, "changing.txt").await;
+        cache.get(&path).await.get_in_memory_file();
 
-            }
+        std::fs::write(&path, b"shorter").unwrap();
+        assert_eq!(cache.get(&path).await.get_in_memory_file().bytes(), b"shorter");
+        assert_eq!(cache.used_bytes(), 7);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cache.get(&path).await, CachedFile::NotFound);
+        assert!(!cache.contains_key(&path));
+
+        // Generated entries don't exist in the filesystem, and aren't removed.
+        cache.insert_bytes("generated/page", b"generated".to_vec()).await;
+        cache.get("generated/page").await.get_in_memory_file();
+    }
+
+    #[tokio::test]
+    async fn setting_priority_function_recomputes_priorities() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 1024, "file.txt").await;
+        cache.get(&path).await;
+        cache.get(&path).await;
+        let priority_of = |cache: &Cache| -> usize { cache.snapshot()[0].1.priority };
+        assert_eq!(priority_of(&cache), crate::priority_function::default_priority_function(2, 1024));
+
+        fn access_count_only(access_count: usize, _size: usize) -> usize {
+            access_count * 1000
         }
+        cache.set_priority_function(access_count_only);
+        assert_eq!(cache.priority_function()(2, 1024), 2000);
+        assert_eq!(cache.file_map.find(&path).unwrap().get().stats.priority, 2000);
+        assert_eq!(priority_of(&cache), 2000);
+    }
 
-        fn get_named_file(self) -> NamedFile {
-            match self {
-                CachedFile::FileSystem(n) => n,
-                _ =>  panic!("tried to get cached file for named file"),
-            }
+    #[tokio::test]
+    async fn reset_access_counts() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1 = create_test_file(&temp_dir, 1024, "one.txt").await;
+        let path_2 = create_test_file(&temp_dir, 1024, "two.txt").await;
+        for _ in 0..3 {
+            cache.get(&path_1).await;
+            cache.get(&path_2).await;
+        }
+        let stats_of = |path: &PathBuf| -> FileStats {
+            cache.snapshot().into_iter().find(|&(ref p, _)| p == path).unwrap().1
+        };
+        assert_eq!(stats_of(&path_1).access_count, 3);
+
+        assert!(cache.reset_access_count(&path_1));
+        let stats: FileStats = stats_of(&path_1);
+        assert_eq!(stats.access_count, 0);
+        assert_eq!(stats.priority, cache.priority_function()(0, 1024));
+        assert_eq!(stats_of(&path_2).access_count, 3);
+        assert!(!cache.reset_access_count(temp_dir.path().join("missing.txt")));
+
+        // Files that were removed keep their access counts, but resetting them doesn't add them back.
+        cache.remove(&path_2);
+        assert!(!cache.reset_access_count(&path_2));
+        assert!(!cache.contains_key(&path_2));
+
+        cache.get(&path_1).await;
+        cache.reset_all_stats();
+        assert_eq!(stats_of(&path_1).access_count, 0);
+        assert_eq!(cache.access_count_map.find(&path_2).unwrap().get().access_count(), 0);
+        assert!(!cache.contains_key(&path_2));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.used_bytes(), 1024);
+    }
+
+    #[tokio::test]
+    async fn files_larger_than_capacity_leave_cache_intact() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 3)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1 = create_test_file(&temp_dir, MEG1, "hot_1.txt").await;
+        let path_2 = create_test_file(&temp_dir, MEG1, "hot_2.txt").await;
+        let path_giant = create_test_file(&temp_dir, MEG1 * 4, "giant.txt").await;
+        cache.get(&path_1).await.get_in_memory_file();
+        cache.get(&path_2).await.get_in_memory_file();
+
+        // However often it is requested, the giant file doesn't evict the files that fit.
+        for _ in 0..10 {
+            cache.get(&path_giant).await.get_named_file();
         }
+        assert!(cache.contains_key(&path_1));
+        assert!(cache.contains_key(&path_2));
+        assert_eq!(cache.used_bytes(), MEG1 * 2);
+        assert_eq!(cache.stats().evictions, 0);
     }
 
-    // #[bench]
-    // fn cache_get_10mb(b: &mut Bencher) {
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(MEG1 * 20)
-    //         .build()
-    //         .unwrap();
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
-    //     cache.get(&path_10m); // add the 10 mb file to the cache
+    #[tokio::test]
+    async fn insert_file_bypasses_admission_but_not_capacity() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 3)
+            .min_file_size(MEG2)
+            .admit_after(5)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_pinned = create_test_file(&temp_dir, MEG2, "pinned.txt").await;
+        let path_small = create_test_file(&temp_dir, MEG1, "small.txt").await;
+        let path_other = create_test_file(&temp_dir, MEG1 + 1, "other.txt").await;
+        let path_giant = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+
+        cache.pin(&path_pinned);
+        cache.insert_file(&path_pinned).await.unwrap().get_in_memory_file();
+        // The file is smaller than the minimum size, and hasn't been requested often enough to be admitted.
+        let file: NamedInMemoryFile = cache.insert_file(&path_small).await.unwrap().get_in_memory_file();
+        assert_eq!(file.bytes().len(), MEG1);
+        assert_eq!(cache.used_bytes(), MEG1 * 3);
+
+        // Evicting the small file doesn't make enough room, and the pinned file can't be evicted.
+        assert_eq!(cache.insert_file(&path_other).await.unwrap_err().kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(cache.used_bytes(), MEG1 * 3);
+        cache.unpin(&path_pinned);
+        cache.insert_file(&path_other).await.unwrap().get_in_memory_file();
+        assert!(!cache.contains_key(&path_pinned));
+        assert!(cache.used_bytes() <= MEG1 * 3);
+
+        assert_eq!(cache.insert_file(&path_giant).await.unwrap_err().kind(), io::ErrorKind::OutOfMemory);
+        let missing: PathBuf = temp_dir.path().join("missing.txt");
+        assert_eq!(cache.insert_file(&missing).await.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_10m);
-    //         cached_file.await.dummy_write()
-    //     });
-    // }
+    #[tokio::test]
+    async fn insert_file_keeps_the_old_copy_if_the_new_one_does_not_fit() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 3)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_pinned = create_test_file(&temp_dir, MEG2, "pinned.txt").await;
+        let path_small = create_test_file(&temp_dir, MEG1, "small.txt").await;
+
+        cache.pin(&path_pinned);
+        cache.insert_file(&path_pinned).await.unwrap().get_in_memory_file();
+        cache.insert_file(&path_small).await.unwrap().get_in_memory_file();
+
+        // The file has grown, and the only other file in the cache is pinned.
+        std::fs::write(&path_small, vec![b'b'; MEG1 + 1]).unwrap();
+        assert_eq!(cache.insert_file(&path_small).await.unwrap_err().kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(cache.used_bytes(), MEG1 * 3);
+        let file: NamedInMemoryFile = cache.get(&path_small).await.get_in_memory_file();
+        assert_eq!(file.bytes().len(), MEG1);
+    }
 
-    // #[bench]
-    // fn cache_miss_10mb(b: &mut Bencher) {
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(0)
-    //         .build()
-    //         .unwrap();
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
+    #[tokio::test]
+    async fn concurrent_opens_of_uncached_files_are_limited() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .max_file_size(MEG1)
+            .max_concurrent_opens_per_path(1)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_large = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_10m);
-    //         cached_file.dummy_write()
-    //     });
-    // }
+        let first: CachedFile = cache.get(&path_large).await;
+        match first {
+            CachedFile::Streaming(ref file) => assert_eq!(file.size(), MEG2),
+            ref other => panic!("Expected the file to be streamed from the filesystem, got {:?}", other),
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(50), cache.get(&path_large)).await.is_err());
+
+        // Once the first response is done with the file, the next request can open it.
+        drop(first);
+        let second = tokio::time::timeout(Duration::from_millis(50), cache.get(&path_large)).await;
+        assert!(second.is_ok());
+        assert!(!cache.contains_key(&path_large));
+    }
+
+    #[tokio::test]
+    async fn files_that_disappear_before_they_are_read_are_not_cached() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_deleted = create_test_file(&temp_dir, MEG1, "deleted.txt").await;
+        let path_truncated = create_test_file(&temp_dir, MEG1, "truncated.txt").await;
+        let path_empty = create_test_file(&temp_dir, 0, "empty.txt").await;
+
+        // The sizes are read like `try_insert()` reads them, before the files change.
+        let size_deleted: usize = Cache::get_file_size_from_metadata(&path_deleted).unwrap();
+        let size_truncated: usize = Cache::get_file_size_from_metadata(&path_truncated).unwrap();
+        std::fs::remove_file(&path_deleted).unwrap();
+        std::fs::write(&path_truncated, b"").unwrap();
+
+        assert_eq!(cache.get_file_from_fs_and_add_to_cache(&path_deleted, size_deleted).await.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(cache.get_file_from_fs_and_add_to_cache(&path_truncated, size_truncated).await.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.get(&path_deleted).await, CachedFile::NotFound);
+
+        // Files that were empty all along are cached.
+        cache.get_file_from_fs_and_add_to_cache(&path_empty, 0).await.unwrap().get_in_memory_file();
+        assert!(cache.contains_key(&path_empty));
+    }
 
-    // #[bench]
-    // fn named_file_read_10mb(b: &mut Bencher) {
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
-    //     b.iter(|| {
-    //         let named_file = CachedFile::from(NamedFile::open(&path_10m).unwrap());
-    //         named_file.dummy_write()
-    //     });
-    // }
+    #[tokio::test]
+    async fn denied_extensions_are_served_from_filesystem() {
+        let temp_dir = TempDir::new("extension_filter").unwrap();
+        let js_path: PathBuf = create_test_file(&temp_dir, 1024, "app.js").await;
+        let php_path: PathBuf = create_test_file(&temp_dir, 1024, "index.php").await;
+        let extensionless_path: PathBuf = create_test_file(&temp_dir, 1024, "LICENSE").await;
 
-    // #[bench]
-    // fn cache_get_1mb(b: &mut Bencher) {
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(MEG1 * 20)
-    //         .build()
-    //         .unwrap(); //Cache can hold 20Mb
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
-    //     cache.get(&path_1m); // add the 10 mb file to the cache
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1024 * 4)
+            .deny_extensions(vec!["php"])
+            .cache_extensionless(false)
+            .build()
+            .unwrap();
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_1m);
-    //         cached_file.dummy_write()
-    //     });
-    // }
+        match cache.get(&js_path).await {
+            CachedFile::InMemory(_) => {}
+            other => panic!("Expected an allowed file to be cached, got {:?}", other),
+        }
+        for path in &[php_path, extensionless_path] {
+            match cache.get(path).await {
+                CachedFile::FileSystem(_) => {}
+                other => panic!("Expected a denied file to be served from the filesystem, got {:?}", other),
+            }
+            assert!(!cache.contains_key(path));
+        }
+    }
 
-    // #[bench]
-    // fn cache_miss_1mb(b: &mut Bencher) {
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(0)
-    //         .build()
-    //         .unwrap();
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
+    #[tokio::test]
+    async fn files_smaller_than_min_file_size_are_not_read() {
+        let temp_dir = TempDir::new("min_file_size").unwrap();
+        let at_limit_path: PathBuf = create_test_file(&temp_dir, 1024, "at_limit.txt").await;
+        let too_small_path: PathBuf = create_test_file(&temp_dir, 50, "too_small.txt").await;
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_1m);
-    //         cached_file.dummy_write()
-    //     });
-    // }
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1024 * 4)
+            .min_file_size(1024)
+            .build()
+            .unwrap();
 
-    // #[bench]
-    // fn named_file_read_1mb(b: &mut Bencher) {
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
+        match cache.get(&at_limit_path).await {
+            CachedFile::InMemory(_) => {}
+            other => panic!("Expected a file at the limit to be cached, got {:?}", other),
+        }
+        for _ in 0..3 {
+            match cache.get(&too_small_path).await {
+                CachedFile::FileSystem(_) => {}
+                other => panic!("Expected a file under the limit to be served from the filesystem, got {:?}", other),
+            }
+        }
+        assert!(!cache.contains_key(&too_small_path));
+        assert_eq!(cache.used_bytes(), 1024);
+
+        // A cached file that shrinks below the limit is removed when it is refreshed.
+        std::fs::write(&at_limit_path, vec![0u8; 10]).unwrap();
+        match cache.refresh(&at_limit_path).await {
+            CachedFile::FileSystem(_) => {}
+            other => panic!("Expected the shrunk file to be served from the filesystem, got {:?}", other),
+        }
+        assert!(!cache.contains_key(&at_limit_path));
+        assert_eq!(cache.used_bytes(), 0);
+    }
 
-    //     b.iter(|| {
-    //         let named_file = CachedFile::from(NamedFile::open(&path_1m).unwrap());
-    //         named_file.dummy_write()
-    //     });
-    // }
+    #[tokio::test]
+    async fn files_larger_than_max_file_size_are_not_read() {
+        let temp_dir = TempDir::new("max_file_size").unwrap();
+        let at_limit_path: PathBuf = create_test_file(&temp_dir, 1024, "at_limit.txt").await;
+        let too_large_path: PathBuf = create_test_file(&temp_dir, 1025, "too_large.txt").await;
 
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1024 * 4)
+            .max_file_size(1024)
+            .build()
+            .unwrap();
 
+        match cache.get(&at_limit_path).await {
+            CachedFile::InMemory(_) => {}
+            other => panic!("Expected a file at the limit to be cached, got {:?}", other),
+        }
+        match cache.get(&too_large_path).await {
+            CachedFile::FileSystem(_) => {}
+            other => panic!("Expected a file over the limit to be served from the filesystem, got {:?}", other),
+        }
+        assert!(!cache.contains_key(&too_large_path));
+        assert_eq!(cache.used_bytes(), 1024);
+
+        // A cached file that grows past the limit is removed when it is refreshed.
+        std::fs::write(&at_limit_path, vec![0u8; 2048]).unwrap();
+        match cache.refresh(&at_limit_path).await {
+            CachedFile::FileSystem(_) => {}
+            other => panic!("Expected the grown file to be served from the filesystem, got {:?}", other),
+        }
+        assert!(!cache.contains_key(&at_limit_path));
+        assert_eq!(cache.used_bytes(), 0);
+    }
 
-    // #[bench]
-    // fn cache_get_5mb(b: &mut Bencher) {
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(MEG1 * 20)
-    //         .build()
-    //         .unwrap();
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5);
-    //     cache.get(&path_5m); // add the 10 mb file to the cache
+    #[tokio::test]
+    async fn expired_file_is_gotten_from_fs() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_5m);
-    //         cached_file.dummy_write()
-    //     });
-    // }
+        cache.insert_with_ttl(&path_1m, Duration::from_millis(50)).await.get_in_memory_file();
+        cache.get(&path_1m).await.get_in_memory_file();
 
-    // #[bench]
-    // fn cache_miss_5mb(b: &mut Bencher) {
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(0)
-    //         .build()
-    //         .unwrap();
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5);
+        tokio::time::sleep(Duration::from_millis(100)).await;
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_5m);
-    //         cached_file.dummy_write()
-    //     });
-    // }
+        cache.get(&path_1m).await.get_named_file();
+        assert!(!cache.contains_key(&path_1m));
+    }
 
-    // #[bench]
-    // fn named_file_read_5mb(b: &mut Bencher) {
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5);
+    #[tokio::test]
+    async fn reaper_removes_idle_files() {
+        let mut cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let evicted: Arc<std::sync::Mutex<Vec<PathBuf>>> = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let evicted_ref = evicted.clone();
+        cache.on_evict(Box::new(move |path, _| evicted_ref.lock().unwrap().push(path.to_path_buf())));
+        let cache: Arc<Cache> = Arc::new(cache);
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_idle = create_test_file(&temp_dir, 1024, "idle.txt").await;
+        let path_pinned = create_test_file(&temp_dir, 1024, "pinned.txt").await;
+        cache.get(&path_idle).await.get_in_memory_file();
+        cache.get(&path_pinned).await.get_in_memory_file();
+        cache.pin(&path_pinned);
+
+        let reaper = cache.clone().spawn_idle_reaper(Duration::from_millis(20), Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!cache.contains_key(&path_idle));
+        assert!(cache.contains_key(&path_pinned));
+        assert_eq!(*evicted.lock().unwrap(), vec![path_idle]);
 
-    //     b.iter(|| {
-    //         let named_file = CachedFile::from(NamedFile::open(&path_5m).unwrap());
-    //         named_file.dummy_write()
-    //     });
-    // }
+        drop(cache);
+        reaper.await.unwrap(); // The reaper stops once the cache is dropped.
+    }
 
+    #[tokio::test]
+    async fn sweeper_removes_expired_file() {
+        let cache: Arc<Cache> = Arc::new(CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap());
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
 
+        cache.insert_with_ttl(&path_1m, Duration::from_millis(20)).await.get_in_memory_file();
+        let sweeper = cache.clone().spawn_expiry_sweeper(Duration::from_millis(10));
 
-    // // Constant time access regardless of size.
-    // #[bench]
-    // fn cache_get_1mb_from_1000_entry_cache(b: &mut Bencher) {
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(MEG1 * 3)
-    //         .build()
-    //         .unwrap();
-    //     cache.get(&path_1m); // add the file to the cache
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!cache.contains_key(&path_1m));
 
-    //     // Add 1024 1kib files to the cache.
-    //     for i in 0..1024 {
-    //         let path = create_test_file(&temp_dir, 1024, format!("{}_1kib.txt", i).as_str());
-    //         cache.get(&path);
-    //     }
-    //     // make sure that the file has a high priority.
-    //     cache.alter_all_access_counts(|x| x + 1 * 100000);
+        drop(cache);
+        sweeper.await.unwrap(); // The sweeper stops once the cache is dropped.
+    }
 
-    //     assert_eq!(cache.used_bytes(), MEG1 * 2);
+    #[tokio::test]
+    async fn streamed_files_are_cached_once_sent_in_full() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 20)
+            .stream_misses(MEG1)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+        let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
 
-    //     let named_file = CachedFile::from(NamedFile::open(&path_1m).unwrap());
+        let request = client.get("/");
+        let mut response = match cache.get(&path_5m).await {
+            streaming_file @ CachedFile::Streaming(_) => streaming_file.respond_to(request.inner()).unwrap(),
+            other => panic!("Expected the file to be streamed, got {:?}", other),
+        };
+        assert!(!cache.contains_key(&path_5m));
+        let body = response.body_mut().to_bytes().await.unwrap();
+        assert_eq!(body.len(), MEG5);
+        match cache.get(&path_5m).await {
+            CachedFile::InMemory(file) => assert_eq!(file.bytes(), &body[..]),
+            other => panic!("Expected the streamed file to be cached, got {:?}", other),
+        }
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_1m);
-    //         assert!(mem::discriminant(&cached_file) != mem::discriminant(&named_file));
-    //         cached_file.dummy_write()
-    //     });
-    // }
+        // A stream that is dropped part of the way through, as when the client disconnects, isn't cached.
+        match cache.get(&path_10m).await {
+            CachedFile::Streaming(_) => {}
+            other => panic!("Expected the file to be streamed, got {:?}", other),
+        }
+        match cache.get(&path_10m).await {
+            CachedFile::Streaming(_) => {}
+            other => panic!("Expected the file to be streamed again, got {:?}", other),
+        }
+        assert!(!cache.contains_key(&path_10m));
+        assert_eq!(cache.used_bytes(), MEG5);
+
+        // While the file is streamed into the cache, other misses for it are served from the filesystem.
+        let streaming_file: CachedFile = cache.get(&path_10m).await;
+        assert!(matches!(streaming_file, CachedFile::Streaming(_)));
+        match cache.get(&path_10m).await {
+            CachedFile::FileSystem(_) => {}
+            other => panic!("Expected the file to be served from the filesystem, got {:?}", other),
+        }
+        drop(streaming_file);
+    }
 
-    // // There is a penalty for missing the cache.
-    // #[bench]
-    // fn cache_miss_1mb_from_1000_entry_cache(b: &mut Bencher) {
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1);
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(MEG1)
-    //         .build()
-    //         .unwrap();
+    #[tokio::test]
+    async fn one_call_serves_conditional_and_compressed_responses() {
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path: PathBuf = temp_dir.path().join("app.js");
+        std::fs::write(&path, "console.log('hello');".repeat(100)).unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .precompress(vec![Encoding::Gzip])
+            .build()
+            .unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip"));
+        let response = cache.get_with_request(&path, request.inner()).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+        let etag: String = response.headers().get_one("ETag").unwrap().to_string();
+
+        let request = client.get("/")
+            .header(Header::new("Accept-Encoding", "gzip"))
+            .header(Header::new("If-None-Match", etag));
+        let response = cache.get_with_request(&path, request.inner()).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::NotModified);
+    }
 
-    //     // Add 1024 1kib files to the cache.
-    //     for i in 0..1024 {
-    //         let path = create_test_file(&temp_dir, 1024, format!("{}_1kib.txt", i).as_str());
-    //         cache.get(&path);
-    //     }
-    //     // make sure that the file has a high priority.
-    //     cache.alter_all_access_counts(|x| x + 1 * 100_000_000_000_000_000);
-    //     let named_file = CachedFile::from(NamedFile::open(&path_1m).unwrap());
+    #[tokio::test]
+    async fn responds_not_modified_for_matching_etag() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
 
-    //     b.iter(|| {
-    //         let cached_file = cache.get(&path_1m);
-    //         assert!(mem::discriminant(&cached_file) == mem::discriminant(&named_file)); // get() in this case should only return files in the FS
-    //         cached_file.dummy_write()
-    //     });
-    // }
+        let etag: String = cache.get(&path_1m).await.get_in_memory_file().file.etag.clone();
 
-    // // This is pretty much a worst-case scenario, where every file would try to be removed to make room for the new file.
-    // // There is a penalty for missing the cache.
-    // #[bench]
-    // fn cache_miss_5mb_from_1000_entry_cache(b: &mut Bencher) {
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG1);
-    //     let cache: Cache = CacheBuilder::new()
-    //         .size_limit(MEG5)
-    //         .build()
-    //         .unwrap();
+        let request = client.get("/");
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
 
-    //     // Add 1024 5kib files to the cache.
-    //     for i in 0..1024 {
-    //         let path = create_test_file(&temp_dir, 1024 * 5, format!("{}_5kib.txt", i).as_str());
-    //         cache.get(&path);
-    //     }
-    //     // make sure that the file has a high priority.
-    //     cache.alter_all_access_counts(|x| x + 1 * 100_000_000_000_000_000);
-    //     let named_file = CachedFile::from(NamedFile::open(&path_5m).unwrap());
+        let request = client.get("/").header(Header::new("If-None-Match", etag.clone()));
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::NotModified);
+        assert!(response.body().is_none());
+    }
 
-    //     b.iter(|| {
-    //         let cached_file: CachedFile = cache.get(&path_5m);
-    //         // Mimic what is done when the response body is set.
-    //         assert!(mem::discriminant(&cached_file) == mem::discriminant(&named_file));  // get() in this case should only return files in the FS
-    //         cached_file.dummy_write()
-    //     });
-    // }
+    #[tokio::test]
+    async fn responds_with_weak_etag_from_size_and_mtime() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .etag_mode(EtagMode::WeakSizeMtime)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let file: InMemoryFile = cache.get(&path_1m).await.get_in_memory_file().file;
+        assert!(file.etag.starts_with("W/\"100000-"));
+        assert_eq!(file.checksum, None);
+        // The contents weren't hashed, so they can't be verified.
+        assert_eq!(cache.verify(&path_1m), None);
+        assert!(cache.verify_all().is_empty());
+
+        // Weak comparison ignores the W/ prefix.
+        let strong_form: String = file.etag.trim_start_matches("W/").to_string();
+        let request = client.get("/").header(Header::new("If-None-Match", strong_form));
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::NotModified);
+        assert_eq!(response.headers().get_one("ETag"), Some(file.etag.as_str()));
+    }
 
+    #[tokio::test]
+    async fn responds_not_modified_since_last_modified() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
 
-    // #[bench]
-    // fn in_memory_file_read_10mb(b: &mut Bencher) {
-    //     let temp_dir = TempDir::new(DIR_TEST).unwrap();
-    //     let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10);
+        let request = client.get("/");
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        let last_modified: String = response.headers().get_one("Last-Modified").unwrap().to_string();
 
-    //     b.iter(|| {
-    //         let in_memory_file = Arc::new(InMemoryFile::open(path_10m.clone()).unwrap());
-    //         let file: *const InMemoryFile = Arc::into_raw(in_memory_file);
-    //         unsafe {
-    //             let _ = (*file).bytes.clone();
-    //             let _ = Arc::from_raw(file);
-    //         }
-    //     });
-    // }
+        let request = client.get("/").header(Header::new("If-Modified-Since", last_modified));
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::NotModified);
 
+        let request = client.get("/").header(Header::new("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT"));
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::Ok);
+    }
 
     #[tokio::test]
-    async fn file_exceeds_size_limit() {
+    async fn responds_with_cache_control() {
         let cache: Cache = CacheBuilder::new()
-            .size_limit(MEG1 * 8) // Cache can hold only 8Mb
+            .size_limit(MEG1 * 10)
+            .cache_control("*.js", "max-age=31536000, immutable")
             .build()
             .unwrap();
         let temp_dir = TempDir::new(DIR_TEST).unwrap();
-        let path_10m = create_test_file(&temp_dir, MEG10, FILE_MEG10).await;
+        let path_js = create_test_file(&temp_dir, MEG1, "app.js").await;
+        let path_txt = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
 
-        let named_file = NamedFile::open(path_10m.clone()).await.unwrap();
+        let request = client.get("/");
+        let response = cache.get(&path_js).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("max-age=31536000, immutable"));
 
-        // expect the cache to get the item from the FS.
-        assert_eq!(cache.try_insert(path_10m).await, CachedFile::from(named_file));
+        let request = client.get("/");
+        let response = cache.get(&path_txt).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Cache-Control"), None);
     }
 
-
     #[tokio::test]
-    async fn file_replaces_other_file() {
+    async fn responds_with_immutable_cache_control_for_hashed_assets() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .immutable_assets(r"\.[0-9a-f]{6,}\.js$")
+            .default_cache_control("no-cache")
+            .build()
+            .unwrap();
         let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_hashed = create_test_file(&temp_dir, 1024, "app.4f3a2b.js").await;
+        let path_js = create_test_file(&temp_dir, 1024, "app.js").await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
 
-        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
-        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
-
-        let named_file_1m = NamedFile::open(path_1m.clone()).await.unwrap();
-        let named_file_1m_2 = NamedFile::open(path_1m.clone()).await.unwrap();
-
+        let request = client.get("/");
+        let response = cache.get(&path_hashed).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("public, max-age=31536000, immutable"));
 
-        let mut imf_5m = InMemoryFile::open(path_5m.clone()).await.unwrap();
-        let mut imf_1m = InMemoryFile::open(path_1m.clone()).await.unwrap();
-
-        // set expected stats for 5m
-        imf_5m.stats.access_count = 1;
-        imf_5m.stats.priority = 2289;
+        let request = client.get("/");
+        let response = cache.get(&path_js).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("no-cache"));
+    }
 
 
+    #[tokio::test]
+    async fn responds_with_content_disposition() {
         let cache: Cache = CacheBuilder::new()
-            .size_limit(5500000) //Cache can hold only about 5.5Mib
+            .size_limit(MEG1 * 10)
+            .content_disposition("*.zip", Disposition::Attachment)
             .build()
             .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_zip = create_test_file(&temp_dir, 1024, "archive.zip").await;
+        let path_txt = create_test_file(&temp_dir, 1024, FILE_MEG1).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        // The header is sent with partial responses as well.
+        let request = client.get("/").header(Header::new("Range", "bytes=0-99"));
+        let response = cache.get(&path_zip).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.headers().get_one("Content-Disposition"), Some("attachment; filename=\"archive.zip\""));
+        assert!(response.headers().get_one("ETag").is_some());
+
+        let request = client.get("/");
+        let response = cache.get(&path_txt).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Disposition"), None);
+    }
 
-        println!("0:\n{:#?}", cache);
-
-        assert_eq!(
-            cache
-                .try_insert(path_5m.clone()).await
-                .get_in_memory_file()
-                .file
-                .as_ref()
-                .get(),
-            &imf_5m
-        );
-        println!("1:\n{:#?}", cache);
-        assert_eq!(
-            cache.try_insert(path_1m.clone()).await,
-            CachedFile::from(named_file_1m)
-        );
-        println!("2:\n{:#?}", cache);
-        assert_eq!(
-            cache.try_insert(path_1m.clone()).await,
-            CachedFile::from(named_file_1m_2)
-        );
-        println!("3:\n{:#?}", cache);
-
-        // set the expected stats for 1m
-        imf_1m.stats.access_count = 3;
-        imf_1m.stats.priority = 3072;
-
-        assert_eq!(
-            cache
-                .try_insert(path_1m.clone()).await
-                .get_in_memory_file()
-                .file
-                .as_ref()
-                .get(),
-            &imf_1m
-        );
-        println!("4:\n{:#?}", cache);
+    #[tokio::test]
+    async fn responds_with_sniffed_content_type() {
+        let mut builder = CacheBuilder::new();
+        builder.size_limit(MEG1 * 10);
+        let cache: Cache = builder.build().unwrap();
+        let sniffing_cache: Cache = builder.sniff_content_type(true).build().unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_image = temp_dir.path().join("image");
+        std::fs::write(&path_image, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let path_text = temp_dir.path().join("README");
+        std::fs::write(&path_text, b"plain text").unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/");
+        let response = sniffing_cache.get(&path_image).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Type"), Some("image/png"));
+        let response = sniffing_cache.get(&path_text).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Type"), None);
+        let response = cache.get(&path_image).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Type"), None);
     }
 
+    #[tokio::test]
+    async fn responds_with_default_content_type_when_none_is_determined() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .sniff_content_type(true)
+            .default_content_type_for("*/docs/*", "text/plain; charset=utf-8")
+            .default_content_type("application/octet-stream")
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        std::fs::create_dir(temp_dir.path().join("docs")).unwrap();
+        let path_docs = temp_dir.path().join("docs").join("README");
+        std::fs::write(&path_docs, b"plain text").unwrap();
+        let path_html = temp_dir.path().join("docs").join("index.html");
+        std::fs::write(&path_html, b"<html></html>").unwrap();
+        let path_image = temp_dir.path().join("docs").join("image");
+        std::fs::write(&path_image, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let path_other = temp_dir.path().join("LICENSE");
+        std::fs::write(&path_other, b"plain text").unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/");
+        let response = cache.get(&path_docs).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Type"), Some("text/plain; charset=utf-8"));
+        let response = cache.get(&path_other).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Type"), Some("application/octet-stream"));
+        // Types that are determined from the extension or the contents take precedence.
+        let response = cache.get(&path_html).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Type"), Some("text/html; charset=utf-8"));
+        let response = cache.get(&path_image).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Type"), Some("image/png"));
+    }
 
+    #[tokio::test]
+    async fn responds_with_custom_headers() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .header("X-Content-Type-Options", "nosniff")
+            .header_for("*.woff2", "Access-Control-Allow-Origin", "*")
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_font = create_test_file(&temp_dir, 1024, "font.woff2").await;
+        let path_txt = create_test_file(&temp_dir, 1024, FILE_MEG1).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/");
+        let response = cache.get(&path_font).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("X-Content-Type-Options"), Some("nosniff"));
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("*"));
+        assert_eq!(response.headers().get_one("Content-Type"), Some("font/woff2"));
+
+        let response = cache.get(&path_txt).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("X-Content-Type-Options"), Some("nosniff"));
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+    }
 
+    #[tokio::test]
+    async fn responds_with_cors_headers_for_allowed_origins() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .header("Access-Control-Allow-Origin", "*")
+            .cors_allow_origins(vec!["https://example.com"])
+            .cors_expose_headers(vec!["ETag", "Content-Length"])
+            .timing_allow_origin(true)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_font = create_test_file(&temp_dir, 1024, "font.woff2").await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("Origin", "https://example.com"));
+        let response = cache.get(&path_font).await.respond_to(request.inner()).unwrap();
+        let allow_origin: Vec<&str> = response.headers().get("Access-Control-Allow-Origin").collect();
+        assert_eq!(allow_origin, vec!["https://example.com"]);
+        assert_eq!(response.headers().get_one("Access-Control-Expose-Headers"), Some("ETag, Content-Length"));
+        assert_eq!(response.headers().get_one("Timing-Allow-Origin"), Some("https://example.com"));
+        assert_eq!(response.headers().get_one("Vary"), Some("Origin"));
+
+        // The custom header isn't sent to origins that aren't allowed.
+        let request = client.get("/").header(Header::new("Origin", "https://evil.example"));
+        let response = cache.get(&path_font).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.headers().get_one("Timing-Allow-Origin"), None);
+        assert_eq!(response.headers().get_one("Vary"), Some("Origin"));
+    }
 
     #[tokio::test]
-    async fn new_file_replaces_lowest_priority_file() {
+    async fn head_request_has_no_body() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
         let temp_dir = TempDir::new(DIR_TEST).unwrap();
         let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
-        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
-        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.head("/");
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Content-Length"), Some(MEG1.to_string().as_str()));
+        assert!(response.headers().get_one("ETag").is_some());
+        assert!(response.body().is_none());
+
+        let request = client.head("/").header(Header::new("Range", "bytes=0-99"));
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.headers().get_one("Content-Length"), Some("100"));
+        assert!(response.body().is_none());
+    }
 
 
-        #[allow(unused_variables)]
-        let named_file_1m = NamedFile::open(path_1m.clone()).await.unwrap();
+    #[tokio::test]
+    async fn responds_with_requested_range() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let bytes: Vec<u8> = cache.get(&path_1m).await.get_in_memory_file().bytes().to_vec();
+
+        let request = client.get("/").header(Header::new("Range", "bytes=100-199"));
+        let mut response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.headers().get_one("Content-Range"), Some(format!("bytes 100-199/{}", MEG1).as_str()));
+        assert_eq!(response.body_mut().to_bytes().await.unwrap(), &bytes[100..200]);
+
+        let request = client.get("/").header(Header::new("Range", format!("bytes={}-", MEG1)));
+        let response = cache.get(&path_1m).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::RangeNotSatisfiable);
+    }
 
+    #[tokio::test]
+    async fn if_range_sends_whole_file_once_it_has_changed() {
         let cache: Cache = CacheBuilder::new()
-            .size_limit(MEG1 * 7 + 2000) // cache can hold a little more than 7MB
+            .size_limit(MEG1 * 10)
             .build()
             .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 1000, "download.bin").await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let response = cache.get(&path).await.respond_to(client.get("/").inner()).unwrap();
+        let etag: String = response.headers().get_one("ETag").unwrap().to_string();
+        let last_modified: String = response.headers().get_one("Last-Modified").unwrap().to_string();
+
+        // The validators still match, so the range is sent.
+        for validator in [etag, last_modified] {
+            let request = client.get("/")
+                .header(Header::new("Range", "bytes=100-199"))
+                .header(Header::new("If-Range", validator));
+            let mut response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+            assert_eq!(response.status(), Status::PartialContent);
+            assert_eq!(response.body_mut().to_bytes().await.unwrap().len(), 100);
+        }
 
-        println!("1:\n{:#?}", cache);
-        let mut imf_5m: InMemoryFile = InMemoryFile::open(path_5m.clone()).await.unwrap();
-        imf_5m.stats.priority = 2289;
-        imf_5m.stats.access_count = 1;
+        // The file has changed since the client got the first part of it, so the whole file is sent.
+        let request = client.get("/")
+            .header(Header::new("Range", "bytes=100-199"))
+            .header(Header::new("If-Range", "\"an-older-version\""));
+        let mut response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Content-Range"), None);
+        assert_eq!(response.body_mut().to_bytes().await.unwrap().len(), 1000);
+        let request = client.get("/")
+            .header(Header::new("Range", "bytes=100-199"))
+            .header(Header::new("If-Range", "Sun, 06 Nov 1994 08:49:37 GMT"));
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::Ok);
+    }
 
-        assert_eq!(
-            cache.get(&path_5m).await
-               .get_in_memory_file()
-               .file
-               .as_ref()
-               .get(),
-            &imf_5m
-        );
+    #[tokio::test]
+    async fn responds_with_multiple_ranges() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = create_test_file(&temp_dir, 1000, "ranges.txt").await;
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let bytes: Vec<u8> = cache.get(&path).await.get_in_memory_file().bytes().to_vec();
+
+        let request = client.get("/").header(Header::new("Range", "bytes=200-299,0-99"));
+        let mut response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.headers().get_one("Content-Range"), None);
+        let content_type: String = response.headers().get_one("Content-Type").unwrap().to_string();
+        let boundary: &str = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let body: Vec<u8> = response.body_mut().to_bytes().await.unwrap();
+        let mut expected: Vec<u8> = Vec::new();
+        expected.extend_from_slice(format!("\r\n--{}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-99/1000\r\n\r\n", boundary).as_bytes());
+        expected.extend_from_slice(&bytes[0..100]);
+        expected.extend_from_slice(format!("\r\n--{}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 200-299/1000\r\n\r\n", boundary).as_bytes());
+        expected.extend_from_slice(&bytes[200..300]);
+        expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        assert_eq!(body, expected);
+
+        // Overlapping ranges are coalesced into a single range.
+        let request = client.get("/").header(Header::new("Range", "bytes=0-99,50-149"));
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Range"), Some("bytes 0-149/1000"));
+
+        let request = client.get("/").header(Header::new("Range", "bytes=1000-,2000-2999"));
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::RangeNotSatisfiable);
+    }
 
-        println!("2:\n{:#?}", cache);
-        let mut imf_2m: InMemoryFile = InMemoryFile::open(path_2m.clone()).await.unwrap();
-        imf_2m.stats.priority = 1448;
-        imf_2m.stats.access_count = 1;
-        assert_eq!(
-            cache.get(&path_2m).await
-                .get_in_memory_file()
-                .file
-                .as_ref()
-                .get(),
-            &imf_2m
-        );
+    #[tokio::test]
+    async fn compressed_media_is_not_precompressed() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .precompress(vec![Encoding::Gzip])
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        // The contents would compress well, but the extension says they are already compressed.
+        let path = temp_dir.path().join("archive.zip");
+        std::fs::write(&path, vec![b'a'; 1024 * 8]).unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
 
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip"));
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.headers().get_one("Vary"), None);
 
-        println!("3:\n{:#?}", cache);
-        let mut named_1m = NamedFile::open(path_1m.clone()).await.unwrap();
-        let mut v: Vec<u8> = Vec::new();
-        let _ = cache
-            .get(&path_1m).await
-            .get_named_file()
-            .read_to_end(&mut v)
-            .await
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .precompress(vec![Encoding::Gzip])
+            .precompress_deny_list(Vec::<String>::new())
+            .build()
             .unwrap();
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    }
 
-        let mut file_vec: Vec<u8> = Vec::new();
-        let _ = named_1m.read_to_end(&mut file_vec).await.unwrap();
-        assert_eq!(
-            v,
-            file_vec
-        );
-
+    #[tokio::test]
+    async fn responds_with_negotiated_encoding() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .precompress(vec![Encoding::Gzip])
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        // Random data doesn't compress, so write a file that does.
+        let path = temp_dir.path().join("compressible.txt");
+        std::fs::write(&path, vec![b'a'; 1024 * 8]).unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip, deflate"));
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+
+        let request = client.get("/").header(Header::new("Accept-Encoding", "deflate"));
+        let mut response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.headers().get_one("Vary"), Some("Accept-Encoding"));
+        assert_eq!(response.body_mut().to_bytes().await.unwrap(), vec![b'a'; 1024 * 8]);
+
+        // Files without compressed copies are the same for every request.
+        let path_small = create_test_file(&temp_dir, 16, "small.txt").await;
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip"));
+        let response = cache.get(&path_small).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.headers().get_one("Vary"), None);
+    }
 
-        println!("4:\n{:#?}", cache);
-        let mut imf_1m: InMemoryFile = InMemoryFile::open(path_1m.clone()).await.unwrap();
-        imf_1m.stats.priority = 2048; // This priority is higher than the in memory file - 2m's 1448, and therefore will replace it now
-        imf_1m.stats.access_count = 1;
+    #[tokio::test]
+    async fn responds_with_precompressed_sibling() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .precompressed_siblings(true)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = temp_dir.path().join("app.js");
+        std::fs::write(&path, b"console.log('hello');").unwrap();
+        std::fs::write(temp_dir.path().join("app.js.br"), b"brotli").unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip, br"));
+        let mut response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+        assert_eq!(response.content_type(), Some(ContentType::JavaScript));
+        assert_eq!(response.body_mut().to_bytes().await.unwrap(), b"brotli".to_vec());
+
+        // There is no gzip copy, so the plain file is served.
+        let request = client.get("/").header(Header::new("Accept-Encoding", "gzip"));
+        let mut response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), None);
+        assert_eq!(response.body_mut().to_bytes().await.unwrap(), b"console.log('hello');".to_vec());
+    }
 
-        // The cache will now accept the 1 meg file because (sqrt(2)_size * 1_access) for the old
-        // file is less than (sqrt(1)_size * 2_access) for the new file.
-        assert_eq!(
-            cache.get(&path_1m).await
-                .get_in_memory_file()
-                .file
-                .as_ref()
-                .get()
-                .bytes,
-            imf_1m.bytes
-        );
-        println!("5:\n{:#?}", cache);
+    #[tokio::test]
+    async fn encoding_preference_breaks_ties() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .precompressed_siblings(true)
+            .encoding_preference(vec![Encoding::Gzip])
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = temp_dir.path().join("app.js");
+        std::fs::write(&path, b"console.log('hello');").unwrap();
+        std::fs::write(temp_dir.path().join("app.js.br"), b"brotli").unwrap();
+        std::fs::write(temp_dir.path().join("app.js.gz"), b"gzip").unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("Accept-Encoding", "br, gzip"));
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+
+        // A higher quality still wins.
+        let request = client.get("/").header(Header::new("Accept-Encoding", "br, gzip;q=0.5"));
+        let response = cache.get(&path).await.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("Content-Encoding"), Some("br"));
+    }
 
+    #[tokio::test]
+    async fn stats_count_hits_and_misses() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 4)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_5m = create_test_file(&temp_dir, MEG5, FILE_MEG5).await;
+        let path_missing = temp_dir.path().join("missing.txt");
+
+        cache.get(&path_1m).await.get_in_memory_file();
+        cache.get(&path_1m).await.get_in_memory_file();
+        cache.get(&path_5m).await.get_named_file();
+        cache.get(&path_missing).await;
+
+        let stats: CacheStats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.not_found, 1);
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.bytes, MEG1);
+    }
 
-        if let CachedFile::NotFound = cache.get_from_cache(&path_1m).await {
-            panic!("Expected 1m file to be in the cache");
-        }
+    #[tokio::test]
+    async fn missing_file_is_cached() {
+        let mut cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 10)
+            .build()
+            .unwrap();
+        cache.enable_negative_cache(Duration::from_secs(60));
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path = temp_dir.path().join(FILE_MEG1);
 
-        // Check if the 5m file is still in the cache
-        if let CachedFile::NotFound = cache.get_from_cache(&path_5m).await {
-            panic!("Expected 5m file to be in the cache");
-        }
+        assert_eq!(cache.get(&path).await, CachedFile::NotFound);
 
-        //
-        if let CachedFile::InMemory(_) = cache.get_from_cache(&path_2m).await {
-            panic!("Expected 2m file to not be in the cache");
-        }
+        // The file won't be found until the negative entry is removed.
+        std::fs::write(&path, vec![0u8; 1024]).unwrap();
+        assert_eq!(cache.get(&path).await, CachedFile::NotFound);
 
-        drop(cache);
+        cache.remove(&path);
+        cache.get(&path).await.get_in_memory_file();
     }
 
-
-
-
     #[tokio::test]
     async fn remove_file() {
         let cache: Cache = CacheBuilder::new()
@@ -1202,9 +4808,42 @@ mod tests {
             &imf
         );
 
-        cache.remove(&path_5m);
+        assert!(cache.remove(&path_5m));
 
         assert_eq!(cache.contains_key(&path_5m.clone()), false);
+        assert!(!cache.contains(&path_5m));
+        assert!(!cache.remove(&path_5m)); // The file was already removed.
+    }
+
+    #[tokio::test]
+    async fn reject_to_disk_leaves_a_full_cache_untouched() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 2)
+            .overflow_policy(OverflowPolicy::RejectToDisk)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let path_1m = create_test_file(&temp_dir, MEG1, FILE_MEG1).await;
+        let path_2m = create_test_file(&temp_dir, MEG2, FILE_MEG2).await;
+
+        assert!(cache.get(&path_1m).await.is_in_memory());
+        // However often the new file is requested, it is served from the filesystem.
+        for _ in 0..5 {
+            assert!(cache.get(&path_2m).await.is_from_disk());
+        }
+        assert!(cache.contains_key(&path_1m));
+        assert!(!cache.contains_key(&path_2m));
+        assert_eq!(cache.stats().evictions, 0);
+        assert_eq!(cache.insert_bytes("www/generated.html", vec![0; MEG1 + 1]).await, CachedFile::NotFound);
+        assert!(cache.contains_key(&path_1m));
+
+        // Without the policy, the more popular file replaces the other one.
+        let cache: Cache = CacheBuilder::new().size_limit(MEG1 * 2).build().unwrap();
+        cache.get(&path_1m).await;
+        for _ in 0..5 {
+            cache.get(&path_2m).await;
+        }
+        assert!(cache.contains_key(&path_2m));
     }
 
     #[tokio::test]
@@ -1220,8 +4859,10 @@ mod tests {
 
         assert_eq!(
             match cache.get(&path_5m).await {
-                CachedFile::InMemory(c) => c.file.get().stats.size,
+                CachedFile::InMemory(c) => c.file.stats.size,
                 CachedFile::FileSystem(_) => unreachable!(),
+                CachedFile::Streaming(_) => unreachable!(),
+                CachedFile::Partial(_) => unreachable!(),
                 CachedFile::NotFound => unreachable!()
             },
             MEG5
@@ -1234,8 +4875,10 @@ mod tests {
 
         assert_eq!(
             match cache.get(&path_of_file_with_10mb_but_path_name_5m).await {
-                CachedFile::InMemory(c) => c.file.get().stats.size,
+                CachedFile::InMemory(c) => c.file.stats.size,
                 CachedFile::FileSystem(_) => unreachable!(),
+                CachedFile::Streaming(_) => unreachable!(),
+                CachedFile::Partial(_) => unreachable!(),
                 CachedFile::NotFound => unreachable!()
             },
             MEG10
@@ -1244,4 +4887,30 @@ mod tests {
         drop(cache);
     }
 
+    #[test]
+    fn concurrent_gets_count_every_access() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(MEG1 * 4)
+            .build()
+            .unwrap();
+        let temp_dir = TempDir::new(DIR_TEST).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let path_1m = runtime.block_on(create_test_file(&temp_dir, MEG1, FILE_MEG1));
+        runtime.block_on(cache.get(&path_1m)).get_in_memory_file();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+                    for _ in 0..100 {
+                        runtime.block_on(cache.get(&path_1m)).get_in_memory_file();
+                    }
+                });
+            }
+        });
+
+        let access_count: usize = cache.access_count_map.find(&path_1m).unwrap().get().access_count();
+        assert_eq!(access_count, 401);
+        assert_eq!(cache.stats().hits, 401);
+    }
 }
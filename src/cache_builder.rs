@@ -1,7 +1,35 @@
 use crate::cache::Cache;
 
 use crate::priority_function::default_priority_function;
+use crate::eviction_policy::{EvictionPolicy, PriorityScorePolicy, VictimOrder};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use crate::hash_algorithm::HashAlgorithm;
+use crate::etag_mode::EtagMode;
+use crate::overflow_policy::OverflowPolicy;
+use crate::encoding::Encoding;
+use crate::cache_stats::Counters;
+use crate::in_flight::InFlight;
+use crate::open_limit::OpenLimit;
+use crate::preload::{PreloadLimit, default_preload_concurrency};
+use crate::admission::Admission;
+use crate::revalidate::Revalidation;
+use crate::cache_control::CacheControl;
+use regex::Regex;
+use crate::content_disposition::{ContentDisposition, Disposition};
+use crate::custom_headers::{CustomHeaders, CustomHeaderError};
+use crate::default_content_type::{DefaultContentType, DefaultContentTypeError};
+use crate::cors::Cors;
+use crate::key_normalization::KeyNormalization;
+use crate::precompress_filter::PrecompressFilter;
+use crate::extension_filter::ExtensionFilter;
+use crate::dotfiles::{Dotfiles, DEFAULT_DOTFILE_EXCEPTIONS};
+use crate::spill::Spill;
+use crate::file_source::{FileSource, FsSource, SharedFileSource};
+use crate::streaming_file::CompletedStreams;
 use std::usize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use concurrent_hashmap::{ConcHashMap, Options};
 use std::collections::hash_map::RandomState;
@@ -11,6 +39,20 @@ use std::collections::hash_map::RandomState;
 #[derive(Debug, PartialEq)]
 pub enum CacheBuildError {
     MinFileSizeIsLargerThanMaxFileSize,
+    /// No file could ever be stored in the cache.
+    MinFileSizeIsLargerThanSizeLimit,
+    /// The root directory doesn't exist, or isn't a directory.
+    RootIsNotADirectory,
+    /// One of the glob patterns is invalid.
+    InvalidGlobPattern,
+    /// One of the custom headers has an invalid name or value, or is a header that the cache sets itself.
+    InvalidHeader,
+    /// The regex for immutable assets is invalid.
+    InvalidRegex,
+    /// One of the default content types can't be parsed.
+    InvalidContentType,
+    /// The spill directory can't be created, or the files left in it can't be removed.
+    SpillDirectoryUnavailable,
 }
 
 /// A builder for Caches.
@@ -20,8 +62,59 @@ pub struct CacheBuilder {
     accesses_per_refresh: Option<usize>,
     concurrency: Option<u16>,
     priority_function: Option<fn(usize, usize) -> usize>,
+    eviction_policy: Option<Arc<dyn EvictionPolicy>>,
+    victim_order: Option<VictimOrder>,
+    overflow_policy: OverflowPolicy,
+    hash_algorithm: Option<HashAlgorithm>,
+    etag_mode: EtagMode,
+    precompress: Vec<Encoding>,
+    encoding_preference: Option<Vec<Encoding>>,
+    min_precompress_size: Option<usize>,
+    precompress_deny_list: Option<Vec<String>>,
+    precompressed_siblings: bool,
+    read_buffer_size: Option<usize>,
+    file_source: Option<SharedFileSource>,
+    stream_misses: Option<usize>,
+    max_concurrent_opens_per_path: Option<usize>,
+    preload_concurrency: Option<usize>,
+    range_chunk_size: Option<usize>,
+    spill: Option<(PathBuf, usize)>,
+    detect_changes: bool,
     min_file_size: Option<usize>,
+    allowed_extensions: Option<Vec<String>>,
+    denied_extensions: Vec<String>,
+    cache_extensionless: Option<bool>,
+    serve_dotfiles: bool,
+    dotfile_exceptions: Option<Vec<String>>,
     max_file_size: Option<usize>,
+    max_entries: Option<usize>,
+    key_normalization: KeyNormalization,
+    root: Option<PathBuf>,
+    admit_after: Option<usize>,
+    stale_while_revalidate: Option<Duration>,
+    priority_half_life: Option<Duration>,
+    cache_control: Vec<(String, String)>,
+    default_cache_control: Option<String>,
+    immutable_assets: Option<String>,
+    content_disposition: Vec<(String, Disposition)>,
+    custom_headers: Vec<(Option<String>, String, String)>,
+    cors_allowed_origins: Vec<String>,
+    cors_expose_headers: Vec<String>,
+    timing_allow_origin: bool,
+    sniff_content_type: bool,
+    default_content_types: Vec<(String, String)>,
+    default_content_type: Option<String>,
+    bypass_header: Option<String>,
+    x_cache_header: bool,
+    #[cfg(feature = "mmap")]
+    mmap_threshold: Option<usize>,
+}
+
+
+impl Default for CacheBuilder {
+    fn default() -> Self {
+        CacheBuilder::new()
+    }
 }
 
 
@@ -35,8 +128,52 @@ impl CacheBuilder {
             accesses_per_refresh: None,
             concurrency: None,
             priority_function: None,
+            eviction_policy: None,
+            victim_order: VictimOrder::of::<PriorityScorePolicy>(),
+            hash_algorithm: None,
+            etag_mode: EtagMode::default(),
+            overflow_policy: OverflowPolicy::default(),
+            precompress: Vec::new(),
+            encoding_preference: None,
+            min_precompress_size: None,
+            precompress_deny_list: None,
+            precompressed_siblings: false,
+            read_buffer_size: None,
+            file_source: None,
+            stream_misses: None,
+            max_concurrent_opens_per_path: None,
+            preload_concurrency: None,
+            range_chunk_size: None,
+            spill: None,
+            detect_changes: false,
             min_file_size: None,
+            allowed_extensions: None,
+            denied_extensions: Vec::new(),
+            cache_extensionless: None,
+            serve_dotfiles: false,
+            dotfile_exceptions: None,
             max_file_size: None,
+            max_entries: None,
+            key_normalization: KeyNormalization::default(),
+            root: None,
+            admit_after: None,
+            stale_while_revalidate: None,
+            priority_half_life: None,
+            cache_control: Vec::new(),
+            default_cache_control: None,
+            immutable_assets: None,
+            content_disposition: Vec::new(),
+            custom_headers: Vec::new(),
+            cors_allowed_origins: Vec::new(),
+            cors_expose_headers: Vec::new(),
+            timing_allow_origin: false,
+            sniff_content_type: false,
+            default_content_types: Vec::new(),
+            default_content_type: None,
+            bypass_header: None,
+            x_cache_header: false,
+            #[cfg(feature = "mmap")]
+            mmap_threshold: None,
         }
     }
 
@@ -55,6 +192,10 @@ impl CacheBuilder {
     /// Sets the concurrency setting of the concurrent hashmap backing the cache.
     /// A higher concurrency setting allows more threads to access the hashmap at the expense of more memory use.
     /// The default is 16.
+    ///
+    /// The hashmap is split into this many shards by the hash of the path, each behind its own lock,
+    /// so requests for files in different shards never contend, and cache hits only take a shared lock on their shard.
+    /// `benches/concurrent_reads.rs` compares the throughput of concurrent cache hits with one shard and with the default.
     pub fn concurrency<'a>(&'a mut self, concurrency: u16) -> &mut Self {
         self.concurrency = Some(concurrency);
         self
@@ -115,18 +256,776 @@ impl CacheBuilder {
         self
     }
 
-    /// Set the minimum size in bytes for files that can be stored in the cache
+    /// Makes the priority scores of files decay over time, halving them for every `half_life` that
+    /// has passed since the file was last accessed.
+    /// This lets files that were requested often a long time ago be replaced by files that are
+    /// requested regularly now.
+    ///
+    /// The decay is applied to the score calculated by the priority function, whenever the score is
+    /// calculated, so no background task is needed. A bias set with `Cache::set_priority_bias()` is
+    /// added after the decay, so it doesn't decay.
+    /// By default, priority scores don't decay.
+    pub fn priority_half_life<'a>(&'a mut self, half_life: Duration) -> &mut Self {
+        self.priority_half_life = Some(half_life);
+        self
+    }
+
+    /// Sets what the cache does with a new file when it is full.
+    ///
+    /// With `OverflowPolicy::RejectToDisk`, a file that would only fit after other files were evicted
+    /// is served from the filesystem instead, as `CachedFile::FileSystem`, and the files in the cache are left as they are.
+    /// This keeps the files that are in the cache stable, at the cost of never letting newly popular files in while it is full.
+    /// Files added with `Cache::insert_bytes()` aren't added either, if they don't fit.
+    /// By default, `OverflowPolicy::Evict` is used, which evicts files according to the eviction policy.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, OverflowPolicy};
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .overflow_policy(OverflowPolicy::RejectToDisk)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn overflow_policy<'a>(&'a mut self, overflow_policy: OverflowPolicy) -> &mut Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets where the contents of files are read from when they are read into the cache.
+    /// This is mostly useful in tests, where a source that holds its files in memory gives control
+    /// over their contents and modification times, without writing them to disk.
+    ///
+    /// The size of a file is taken from the source as well, when deciding whether it is read into the cache.
+    /// Files that don't fit in the cache are still served from the filesystem, and files aren't
+    /// streamed while they are read, or memory mapped, as both read them from the filesystem.
+    /// By default, files are read from the filesystem, as `FsSource` does.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, FsSource};
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .file_source(FsSource)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn file_source<'a, S: FileSource + 'static>(&'a mut self, source: S) -> &mut Self {
+        self.file_source = Some(SharedFileSource(Arc::new(source)));
+        self
+    }
+
+    /// Sets the strategy used to determine which files are removed from the cache when it is full.
+    /// By default, the `PriorityScorePolicy` is used, which removes the files with the lowest
+    /// score as determined by the priority function.
+    ///
+    /// Custom strategies can be used by implementing the `EvictionPolicy` trait.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, LruPolicy};
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .eviction_policy(LruPolicy)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn eviction_policy<'a, E: EvictionPolicy + 'static>(&'a mut self, eviction_policy: E) -> &mut Self {
+        self.eviction_policy = Some(Arc::new(eviction_policy));
+        self.victim_order = VictimOrder::of::<E>();
+        self
+    }
+
+    /// Sets the algorithm used to hash the contents of files when they are read into the cache.
+    /// The hash is used as the `ETag` of the file when it is served from the cache.
+    /// By default, the FNV-1a hash is used.
+    pub fn hash_algorithm<'a>(&'a mut self, hash_algorithm: HashAlgorithm) -> &mut Self {
+        self.hash_algorithm = Some(hash_algorithm);
+        self
+    }
+
+    /// Sets how the `ETag` of files that are read from the filesystem is generated.
+    /// With `EtagMode::WeakSizeMtime`, files get a weak `ETag` from their size and modification time,
+    /// and aren't hashed, which is cheaper for large files. Weak `ETag`s still allow `If-None-Match`
+    /// requests to be answered with `304 Not Modified`.
+    /// By default, files get a strong `ETag` from the hash of their contents.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, EtagMode};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .etag_mode(EtagMode::WeakSizeMtime)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn etag_mode<'a>(&'a mut self, etag_mode: EtagMode) -> &mut Self {
+        self.etag_mode = etag_mode;
+        self
+    }
+
+    /// Sets the encodings that files will be compressed with when they are read into the cache.
+    /// When a file is served from the cache, the compressed copy that best matches the request's
+    /// `Accept-Encoding` header is sent, or the uncompressed file if none of them are accepted.
+    ///
+    /// The encodings are preferred in the order they are provided, when the client has no preference,
+    /// unless an order is set with `encoding_preference()`.
+    /// With the `zstd` feature, files can be compressed with `Encoding::Zstd` as well.
+    /// By default, files are not compressed.
+    ///
+    /// The compressed copies are kept in memory next to the file, but only the size of the file itself
+    /// counts towards the `size_limit()`, so the cache can use up to a few times as much memory as the limit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, Encoding};
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .precompress(vec![Encoding::Brotli, Encoding::Gzip])
+    ///     .min_precompress_size(1024) // Don't compress files smaller than 1 KB
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn precompress<'a>(&'a mut self, encodings: Vec<Encoding>) -> &mut Self {
+        self.precompress = encodings;
+        self
+    }
+
+    /// Sets the order in which encodings are preferred, when the `Accept-Encoding` header of a request
+    /// accepts several of the compressed copies of a file with the same quality, like `gzip, br, zstd`.
+    /// Encodings with a higher quality in the header are still preferred.
+    ///
+    /// The order applies to the copies made by `precompress()` and the copies that are read with
+    /// `precompressed_siblings()` alike. Encodings that aren't in the list are preferred less than
+    /// the ones that are, in the order the copies were made.
+    /// By default, the copies read with `precompressed_siblings()` come first, then the encodings in the order passed to `precompress()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, Encoding};
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .precompress(vec![Encoding::Gzip, Encoding::Brotli])
+    ///     .encoding_preference(vec![Encoding::Brotli, Encoding::Gzip])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn encoding_preference<'a>(&'a mut self, encodings: Vec<Encoding>) -> &mut Self {
+        self.encoding_preference = Some(encodings);
+        self
+    }
+
+    /// Sets the minimum size in bytes for files to be compressed when they are read into the cache.
+    /// The default is 1024 bytes.
+    pub fn min_precompress_size<'a>(&'a mut self, min_size: usize) -> &mut Self {
+        self.min_precompress_size = Some(min_size);
+        self
+    }
+
+    /// Sets the types of files that `precompress()` skips, because they are already compressed.
+    /// These files are stored and served as they are, without a `Content-Encoding`.
+    ///
+    /// Entries that contain a `/` are media types, like `image/png`, or `video/*` to match every subtype.
+    /// Other entries are file extensions, like `zip`.
+    /// The media type of a file is taken from its extension, or detected from its first bytes if the
+    /// extension doesn't imply one.
+    ///
+    /// The default list contains PNG, JPEG, GIF, WebP, and AVIF images, video, audio, archives like
+    /// `zip` and `gz`, and WOFF fonts. An empty list compresses every file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, Encoding};
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .precompress(vec![Encoding::Gzip])
+    ///     .precompress_deny_list(vec!["image/*", "video/*", "zip", "woff2"])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn precompress_deny_list<'a, S: Into<String>>(&'a mut self, types: Vec<S>) -> &mut Self {
+        self.precompress_deny_list = Some(types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Serves the compressed copies of files that were created ahead of time, like `app.js.br` and
+    /// `app.js.gz` for `app.js`, or `app.js.zst` with the `zstd` feature, when a file is requested with an `Accept-Encoding` header that
+    /// accepts them.
+    ///
+    /// The copies are read when the file is read into the cache, and are kept alongside it.
+    /// They are served with the `Content-Type` of the original file and the matching `Content-Encoding`.
+    /// Encodings that have a copy are not compressed again by `precompress()`.
+    /// By default, these copies are ignored.
+    pub fn precompressed_siblings<'a>(&'a mut self, enabled: bool) -> &mut Self {
+        self.precompressed_siblings = enabled;
+        self
+    }
+
+    /// Sets the number of bytes that files are read in at a time when they are read into the cache.
+    ///
+    /// By default, files up to 1 MB are read all at once, and larger files are read 1 MB at a time.
+    pub fn read_buffer_size<'a>(&'a mut self, buffer_size: usize) -> &mut Self {
+        self.read_buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Streams files of at least `min_size` bytes to the client when they aren't in the cache yet,
+    /// instead of reading them into memory before responding.
+    /// The contents are collected while they are sent, and added to the cache once the whole file
+    /// has been sent, so they are served from memory by later requests.
+    /// If the client disconnects before the whole file was sent, the collected contents are discarded.
+    ///
+    /// Files are only streamed if the cache would accept them, and the memory used to collect the
+    /// contents isn't counted towards the size limit until they are added to the cache.
+    /// Requests for a file that arrive while it is being streamed stream it as well.
+    /// Files that are memory mapped with `mmap_threshold()` are never streamed.
+    /// By default, every file is read into memory before responding.
+    pub fn stream_misses<'a>(&'a mut self, min_size: usize) -> &mut Self {
+        self.stream_misses = Some(min_size);
+        self
+    }
+
+    /// Limits the number of responses that hold the same file open, for files that are served from the
+    /// filesystem rather than from memory, like files that are too large to be cached.
+    /// This bounds the number of file descriptors a hot file that can't be cached uses.
+    ///
+    /// Requests for the file beyond the limit wait until one of the responses has been sent, or dropped
+    /// because its client disconnected. A slow client therefore delays the requests that are waiting.
+    /// While the limit is set, these files are served as `CachedFile::Streaming`, instead of
+    /// `CachedFile::FileSystem`, so the cache can tell when a response is done with the file.
+    /// A limit of 0 is treated as 1.
+    /// By default, the number of responses that hold a file open isn't limited.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .max_file_size(1024 * 1024 * 10)
+    ///     .max_concurrent_opens_per_path(8)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn max_concurrent_opens_per_path<'a>(&'a mut self, max_opens: usize) -> &mut Self {
+        self.max_concurrent_opens_per_path = Some(max_opens);
+        self
+    }
+
+    /// Limits the number of files that are read at once by `Cache::preload()`, `Cache::warm_dir()`,
+    /// and `Cache::warm_from_manifest()`, together.
+    ///
+    /// A higher limit warms the cache faster, as long as the disk can keep up, but every file that
+    /// is being read holds a file descriptor and a buffer of the size of the file, and competes for
+    /// the disk with the requests that are served while the cache is warming.
+    /// A lower limit keeps the load on the server down, at the cost of a slower warmup.
+    /// Files that are read by `Cache::get()` aren't limited by this.
+    /// A limit of 0 is treated as 1.
+    /// By default, the limit is twice the number of CPUs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .preload_concurrency(4)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn preload_concurrency<'a>(&'a mut self, permits: usize) -> &mut Self {
+        self.preload_concurrency = Some(permits);
+        self
+    }
+
+    /// Caches the parts of files that are too large to be cached as a whole, like videos, in chunks
+    /// of `chunk_size` bytes, so the ranges of them that clients request are served from memory.
+    ///
+    /// When `Cache::get_with_request()` gets a request for a single range of such a file, the range
+    /// is assembled from the chunks that hold it. Chunks that aren't cached yet are read from the filesystem,
+    /// and are added to the cache like any other file, so they count towards the size limit and
+    /// the number of entries, and are evicted according to how often they are requested.
+    /// Each chunk is cached under the path of the file, followed by a NUL byte and the index of the chunk,
+    /// which are listed by `Cache::keys()`. As no real path contains a NUL byte, they never collide with files.
+    ///
+    /// At most 4 chunks are sent in response to a request, so a request for a longer range, like the
+    /// `bytes=0-` that video players start with, gets the start of it, and the `Content-Range` header
+    /// tells the client which bytes were sent. Requests for several ranges, and requests with an
+    /// `If-Range` header, are handled as if this wasn't set.
+    /// Chunks are read again if the modification time of the file has changed since they were read.
+    /// A chunk size of 0 is treated as 1.
+    /// By default, files that are too large to be cached are always served from the filesystem.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .max_file_size(1024 * 1024 * 10)
+    ///     .range_chunk_size(1024 * 1024)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn range_chunk_size<'a>(&'a mut self, chunk_size: usize) -> &mut Self {
+        self.range_chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Writes the files that are evicted to make room for other files to `dir`, and reads them back
+    /// from there the next time they are requested, instead of from where they came from.
+    /// This is meant for contents that are expensive to produce again, like those added with
+    /// `Cache::insert_bytes()`, which can't be read from the filesystem at all once they are evicted.
+    ///
+    /// The files in `dir` take up at most `size_limit` bytes. When a newly evicted file doesn't fit,
+    /// the files that were spilled first are removed until it does.
+    /// Evicted files are written by a blocking task as they are evicted, so evicting a file doesn't wait for the disk,
+    /// and the memory they took up in the cache is freed once they have been written.
+    /// Files that are removed from the cache with `Cache::remove()`, `Cache::clear()` or
+    /// `Cache::invalidate_glob()` are removed from the spill as well, and files that expire aren't spilled.
+    /// A spilled copy is discarded instead of being served if `Cache::bump_generation()` was called
+    /// since it was read, or if the file it was read from has been modified since.
+    ///
+    /// The directory is created if it doesn't exist. The spill names its files `rocket-file-cache-<id>.spill`,
+    /// and the files with such names are removed when the cache is built, and when it is dropped.
+    /// Other files in the directory are left alone, but it shouldn't be shared with another cache.
+    /// Building the cache fails with `CacheBuildError::SpillDirectoryUnavailable` if the directory can't be set up.
+    /// By default, evicted files are dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let spill_dir = std::env::temp_dir().join("rocket-file-cache-spill");
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .size_limit(1024 * 1024 * 10)
+    ///     .spill_to_disk(&spill_dir, 1024 * 1024 * 100)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn spill_to_disk<'a, P: AsRef<Path>>(&'a mut self, dir: P, size_limit: usize) -> &mut Self {
+        self.spill = Some((dir.as_ref().to_path_buf(), size_limit));
+        self
+    }
+
+    /// Compares the size and modification time of a file in the cache to its metadata in the filesystem
+    /// whenever it is served, and reads it again if either has changed.
+    /// If the file has been removed from the filesystem, it is removed from the cache as well.
+    ///
+    /// This keeps the cache from serving outdated or truncated contents, at the cost of reading the
+    /// file's metadata on every request.
+    /// By default, files are only checked in this way if they are memory mapped.
+    pub fn detect_changes<'a>(&'a mut self, enabled: bool) -> &mut Self {
+        self.detect_changes = enabled;
+        self
+    }
+
+    /// Set the minimum size in bytes for files that can be stored in the cache.
+    ///
+    /// Files that are smaller are always served from the filesystem, as storing them would cost more
+    /// than reading them again. Their size is taken from their metadata, so they are never read into memory.
+    /// Files that are exactly this size are still stored.
+    /// Together with `max_file_size()`, this limits the cache to the range of sizes where it helps the most.
     pub fn min_file_size<'a>(&'a mut self, min_size: usize) -> &mut Self {
         self.min_file_size = Some(min_size);
         self
     }
 
-    /// Set the maximum size in bytes for files that can be stored in the cache
+    /// Only stores files with these extensions in the cache, like `vec!["js", "css", "png"]`.
+    /// Other files are served from the filesystem without being read into memory.
+    ///
+    /// Extensions are compared without regard to case, and a leading `.` is ignored.
+    /// Extensions that are also passed to `deny_extensions()` are denied.
+    /// Whether files without an extension are stored is set with `cache_extensionless()`.
+    /// By default, files with any extension can be stored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .allow_extensions(vec!["html", "js", "css", "png", "exe"])
+    ///     .deny_extensions(vec!["exe"])
+    ///     .cache_extensionless(false)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn allow_extensions<'a, S: Into<String>>(&'a mut self, extensions: Vec<S>) -> &mut Self {
+        self.allowed_extensions = Some(extensions.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Never stores files with these extensions in the cache, like `vec!["php", "exe"]`.
+    /// They are served from the filesystem without being read into memory.
+    ///
+    /// The denylist takes precedence over the list passed to `allow_extensions()`.
+    pub fn deny_extensions<'a, S: Into<String>>(&'a mut self, extensions: Vec<S>) -> &mut Self {
+        self.denied_extensions = extensions.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets whether files without an extension, like `LICENSE`, can be stored in the cache.
+    /// They aren't affected by `allow_extensions()` or `deny_extensions()`.
+    /// By default, they can be stored.
+    pub fn cache_extensionless<'a>(&'a mut self, enabled: bool) -> &mut Self {
+        self.cache_extensionless = Some(enabled);
+        self
+    }
+
+    /// Sets whether files are served if a component of their path starts with a `.`, like `www/.env` or `www/.git/config`.
+    ///
+    /// Hidden files often hold secrets or metadata that was never meant to be published, so by default,
+    /// requests for them result in `CachedFile::NotFound`, without the filesystem being touched.
+    /// Directories that are meant to be public can be excepted with `dotfile_exceptions()`.
+    /// If a `root()` is set, only the components of the path below the root are checked, so the root
+    /// itself may be hidden, like `.output/public/`. Without a root, every component of the path that is
+    /// passed to `get()` is checked, so a hidden directory that files are served from needs to be excepted.
+    /// Files that are explicitly added to the cache, for example with `Cache::preload()`, aren't affected.
+    pub fn serve_dotfiles<'a>(&'a mut self, enabled: bool) -> &mut Self {
+        self.serve_dotfiles = enabled;
+        self
+    }
+
+    /// Sets the names of files and directories starting with a `.` that are served even though `serve_dotfiles()` is disabled.
+    /// They replace the default exceptions, which only contain `.well-known`, the directory for
+    /// well-known URIs such as `/.well-known/security.txt`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .dotfile_exceptions(vec![".well-known", ".output"])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn dotfile_exceptions<'a, S: Into<String>>(&'a mut self, exceptions: Vec<S>) -> &mut Self {
+        self.dotfile_exceptions = Some(exceptions.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the maximum size in bytes for files that can be stored in the cache.
+    ///
+    /// Files that are larger are served from the filesystem without being read into memory,
+    /// so they never cause other files to be evicted. Their size is taken from their metadata.
+    /// Files that are exactly this size are still stored.
     pub fn max_file_size<'a>(&'a mut self, max_size: usize) -> &mut Self {
         self.max_file_size = Some(max_size);
         self
     }
 
+    /// Sets the maximum number of files that the cache can hold, in addition to the number of bytes set by `size_limit()`.
+    /// When a new file would exceed either limit, files are removed according to the eviction policy
+    /// until both limits are satisfied.
+    ///
+    /// By default the number of files is not limited.
+    pub fn max_entries<'a>(&'a mut self, max_entries: usize) -> &mut Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Sets the transformations that are applied to paths before they are used as keys in the cache.
+    /// Every method of the cache that takes a path applies them, so `get()`, `remove()`, and `contains()`
+    /// agree on which file a path refers to.
+    ///
+    /// By default, paths are used as they are.
+    pub fn key_normalization<'a>(&'a mut self, key_normalization: KeyNormalization) -> &mut Self {
+        self.key_normalization = key_normalization;
+        self
+    }
+
+    /// Sets the number of times a file must be requested before it is read into the cache.
+    /// Until then, the file is served from the filesystem.
+    /// This keeps files that are rarely requested from pushing files that are requested often out of the cache.
+    ///
+    /// By default, files are read into the cache the first time they are requested.
+    pub fn admit_after<'a>(&'a mut self, requests: usize) -> &mut Self {
+        self.admit_after = Some(requests);
+        self
+    }
+
+    /// Checks if files have been modified on disk whenever they are served from the cache, with a
+    /// single metadata call, and reads modified files again in the background.
+    /// Until the new contents have been read, the old contents keep being served, so requests don't
+    /// have to wait for the file to be read.
+    ///
+    /// If the file on disk was modified more than `max_staleness` ago, the request waits for the file
+    /// to be read instead.
+    /// By default, the modification times of files in the cache aren't checked.
+    pub fn stale_while_revalidate<'a>(&'a mut self, max_staleness: Duration) -> &mut Self {
+        self.stale_while_revalidate = Some(max_staleness);
+        self
+    }
+
+    /// Sends the `Cache-Control` directive with files whose paths match the glob pattern.
+    /// The pattern is matched against the paths passed to `get()`.
+    ///
+    /// Rules are checked in the order they are added, and the first rule that matches is used.
+    /// The header is only sent with files that are served from memory, files that are served from
+    /// the filesystem are sent as they would be by Rocket's `NamedFile`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::Cache;
+    /// use rocket_file_cache::CacheBuilder;
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .cache_control("*/index.html", "no-cache")
+    ///     .cache_control("*.js", "max-age=31536000, immutable")
+    ///     .default_cache_control("max-age=3600")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn cache_control<'a, S: Into<String>>(&'a mut self, pattern: &str, directive: S) -> &mut Self {
+        self.cache_control.push((pattern.to_string(), directive.into()));
+        self
+    }
+
+    /// Sets the `Cache-Control` directive that is sent with files that don't match any of the rules
+    /// added with `cache_control()`.
+    /// By default, no `Cache-Control` header is sent for those files.
+    pub fn default_cache_control<'a, S: Into<String>>(&'a mut self, directive: S) -> &mut Self {
+        self.default_cache_control = Some(directive.into());
+        self
+    }
+
+    /// Sends `Cache-Control: public, max-age=31536000, immutable` with files whose paths match the
+    /// regex, so content-addressed files like `app.4f3a2b.js` are cached by browsers for a year.
+    /// The regex is searched for anywhere in the paths passed to `get()`, so it should be anchored
+    /// with `$` to only match the end of the path.
+    ///
+    /// The directive takes precedence over the one set with `default_cache_control()`, but rules
+    /// added with `cache_control()` take precedence over it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::Cache;
+    /// use rocket_file_cache::CacheBuilder;
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .immutable_assets(r"\.[0-9a-f]{6,}\.[a-z0-9]+$")
+    ///     .default_cache_control("no-cache")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn immutable_assets<'a>(&'a mut self, regex: &str) -> &mut Self {
+        self.immutable_assets = Some(regex.to_string());
+        self
+    }
+
+    /// Sends a `Content-Disposition` header with files whose paths match the glob pattern, telling
+    /// the browser to either display the file, or to download it.
+    /// The file name in the header is the last component of the path.
+    ///
+    /// Rules are checked in the order they are added, and the first rule that matches is used.
+    /// Like the `Cache-Control` header, it is only sent with files that are served from memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, Disposition};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .content_disposition("*/downloads/*.pdf", Disposition::Inline)
+    ///     .content_disposition("*/downloads/*", Disposition::Attachment)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn content_disposition<'a>(&'a mut self, pattern: &str, disposition: Disposition) -> &mut Self {
+        self.content_disposition.push((pattern.to_string(), disposition));
+        self
+    }
+
+    /// Sends the header with every file, like `X-Content-Type-Options: nosniff`.
+    ///
+    /// The headers that the cache sets itself, `Content-Type`, `Content-Length`, `Content-Encoding`,
+    /// `Content-Range`, and `ETag`, can't be replaced, and building the cache fails if one of them is added.
+    /// Like the `Cache-Control` header, custom headers are only sent with files that are served from memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .header("X-Content-Type-Options", "nosniff")
+    ///     .header_for("*/fonts/*", "Access-Control-Allow-Origin", "*")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn header<'a, S: Into<String>>(&'a mut self, name: &str, value: S) -> &mut Self {
+        self.custom_headers.push((None, name.to_string(), value.into()));
+        self
+    }
+
+    /// Sends the header with files whose paths match the glob pattern, like `header()` does for every file.
+    /// Every header whose pattern matches the file is sent.
+    pub fn header_for<'a, S: Into<String>>(&'a mut self, pattern: &str, name: &str, value: S) -> &mut Self {
+        self.custom_headers.push((Some(pattern.to_string()), name.to_string(), value.into()));
+        self
+    }
+
+    /// Sends CORS headers with files, for requests whose `Origin` header is one of the origins, like `https://example.com`.
+    /// Origins are compared without regard to case. If the origins include `*`, every origin is allowed.
+    ///
+    /// The response's `Access-Control-Allow-Origin` header is set to the request's origin, or to `*` if every origin
+    /// is allowed, and `Origin` is added to its `Vary` header, unless every origin is allowed.
+    /// The CORS headers replace custom headers with the same names, so they are never sent twice.
+    /// Like the `Cache-Control` header, CORS headers are only sent with files that are served from memory,
+    /// or streamed into the cache. Preflight `OPTIONS` requests aren't handled.
+    /// By default, no CORS headers are sent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .cors_allow_origins(vec!["https://example.com", "https://admin.example.com"])
+    ///     .cors_expose_headers(vec!["ETag"])
+    ///     .timing_allow_origin(true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn cors_allow_origins<'a, S: Into<String>>(&'a mut self, origins: Vec<S>) -> &mut Self {
+        self.cors_allowed_origins = origins.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the response headers, like `ETag`, that scripts from the allowed origins may read,
+    /// which are sent as `Access-Control-Expose-Headers`.
+    /// This has no effect unless `cors_allow_origins()` is set.
+    pub fn cors_expose_headers<'a, S: Into<String>>(&'a mut self, headers: Vec<S>) -> &mut Self {
+        self.cors_expose_headers = headers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sends a `Timing-Allow-Origin` header with the same value as `Access-Control-Allow-Origin`, so
+    /// scripts from the allowed origins can read the resource timings of files.
+    /// This has no effect unless `cors_allow_origins()` is set.
+    /// It is disabled by default.
+    pub fn timing_allow_origin<'a>(&'a mut self, enabled: bool) -> &mut Self {
+        self.timing_allow_origin = enabled;
+        self
+    }
+
+    /// Detects the `Content-Type` of files whose extension doesn't imply one, like files without an
+    /// extension, from the first few bytes of their contents.
+    /// PNG, JPEG, GIF, WebP, BMP, PDF, gzip, zip, WebAssembly, and WOFF files are recognized.
+    /// If the type can't be detected, no `Content-Type` header is sent, like without this option,
+    /// unless one is set with `default_content_type()`.
+    ///
+    /// Like the `Cache-Control` header, the detected type is only sent with files that are served from memory.
+    /// By default, the type is only chosen from the extension.
+    pub fn sniff_content_type<'a>(&'a mut self, enabled: bool) -> &mut Self {
+        self.sniff_content_type = enabled;
+        self
+    }
+
+    /// Sets the `Content-Type` that is sent with files whose type can't be determined, like `text/plain; charset=utf-8`.
+    ///
+    /// The type of a file is determined from its extension, or from its contents if `sniff_content_type()`
+    /// is enabled, and only if neither of them determine a type is the default sent.
+    /// Rules added with `default_content_type_for()` take precedence over this default.
+    /// Building the cache fails with `CacheBuildError::InvalidContentType` if the type can't be parsed.
+    /// Like the `Cache-Control` header, the default type is only sent with files that are served from memory.
+    /// By default, no `Content-Type` header is sent with these files.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .default_content_type_for("*/docs/*", "text/plain; charset=utf-8")
+    ///     .default_content_type("application/octet-stream")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn default_content_type<'a>(&'a mut self, content_type: &str) -> &mut Self {
+        self.default_content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// Sets the `Content-Type` that is sent with files whose paths match the glob pattern, if their
+    /// type can't be determined, in place of the one set with `default_content_type()`.
+    /// If several patterns match the file, the one that was added first is used.
+    pub fn default_content_type_for<'a>(&'a mut self, pattern: &str, content_type: &str) -> &mut Self {
+        self.default_content_types.push((pattern.to_string(), content_type.to_string()));
+        self
+    }
+
+    /// Sets the name of a request header, like `X-Cache-Bypass`, that makes `Cache::get_with_request()`
+    /// serve the file from the filesystem when the header is set to `1` or `true`, which is useful for debugging.
+    ///
+    /// Any client can send the header, and every request that does reads the file from the filesystem,
+    /// so it defeats the cache and makes it easier to load the server. It should only be configured
+    /// in development, or when the header is stripped from requests that don't come from a trusted
+    /// source, for example by a reverse proxy.
+    /// By default, no header bypasses the cache.
+    pub fn bypass_header<'a>(&'a mut self, header: &str) -> &mut Self {
+        self.bypass_header = Some(header.to_string());
+        self
+    }
+
+    /// Adds an `X-Cache` header to responses for files, which is `HIT` if the file was served from
+    /// memory, and `MISS` if it was served from the filesystem, so the cache can be checked with `curl -I`.
+    ///
+    /// The header is attached when the file is gotten from the cache, however the cache is shared with handlers.
+    /// A `NamedFile` can't carry headers, so while the header is enabled, files that are served from the
+    /// filesystem are streamed, as `CachedFile::Streaming`.
+    /// The header tells clients how the server is doing, so it should only be enabled while debugging.
+    /// By default, the header isn't sent.
+    pub fn x_cache_header<'a>(&'a mut self, enabled: bool) -> &mut Self {
+        self.x_cache_header = enabled;
+        self
+    }
+
+    /// Sets the size in bytes at which files are memory mapped, instead of being read into memory.
+    /// This avoids keeping a second copy of large files in memory, alongside the operating system's page cache.
+    /// Memory mapped files count towards the size limit of the cache like any other file, and are not precompressed.
+    ///
+    /// If the file is modified while it is mapped, the changes may be visible in the responses, and if it
+    /// is truncated, the process may crash when the truncated part is read.
+    /// To reduce this risk, the cache checks the size and modification time of mapped files whenever they
+    /// are served, and maps them again if they have changed.
+    /// Files should be replaced, for example by renaming a new file over them, rather than modified in place.
+    ///
+    /// This requires the `mmap` feature.
+    /// By default, files are never memory mapped.
+    #[cfg(feature = "mmap")]
+    pub fn mmap_threshold<'a>(&'a mut self, size: usize) -> &mut Self {
+        self.mmap_threshold = Some(size);
+        self
+    }
+
+    /// Restricts the cache to files within the root directory.
+    /// Paths passed to `get()` that resolve to a location outside of the root, after `..` components
+    /// and symbolic links are resolved, will result in `CachedFile::NotFound` without the file being read.
+    ///
+    /// Paths passed to `get()` are still relative to the current directory, not to the root.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rocket_file_cache::Cache;
+    /// use rocket_file_cache::CacheBuilder;
+    ///
+    /// let cache: Cache = CacheBuilder::new()
+    ///     .root("www/")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn root<'a, P: AsRef<Path>>(&'a mut self, root: P) -> &mut Self {
+        self.root = Some(root.as_ref().to_path_buf());
+        self
+    }
+
     /// Finalize the cache.
     ///
     /// # Example
@@ -170,6 +1069,10 @@ impl CacheBuilder {
             None => 0,
         };
 
+        if min_file_size > size_limit {
+            return Err(CacheBuildError::MinFileSizeIsLargerThanSizeLimit);
+        }
+
         let max_file_size: usize = match self.max_file_size {
             Some(max) => max,
             None => usize::MAX,
@@ -177,23 +1080,144 @@ impl CacheBuilder {
 
 
 
+        let root: Option<PathBuf> = match self.root {
+            Some(ref root) => match root.canonicalize() {
+                Ok(ref root) if root.is_dir() => Some(root.clone()),
+                _ => return Err(CacheBuildError::RootIsNotADirectory),
+            },
+            None => None,
+        };
+
+        let cache_control: CacheControl = match CacheControl::new(&self.cache_control, self.default_cache_control.clone()) {
+            Ok(cache_control) => cache_control,
+            Err(_) => return Err(CacheBuildError::InvalidGlobPattern),
+        };
+        let cache_control: CacheControl = match self.immutable_assets {
+            Some(ref immutable_assets) => match Regex::new(immutable_assets) {
+                Ok(immutable_assets) => cache_control.with_immutable(Some(immutable_assets)),
+                Err(_) => return Err(CacheBuildError::InvalidRegex),
+            },
+            None => cache_control,
+        };
+        let custom_headers: CustomHeaders = match CustomHeaders::new(&self.custom_headers) {
+            Ok(custom_headers) => custom_headers,
+            Err(CustomHeaderError::InvalidGlobPattern) => return Err(CacheBuildError::InvalidGlobPattern),
+            Err(CustomHeaderError::InvalidHeader) => return Err(CacheBuildError::InvalidHeader),
+        };
+        let default_content_type: DefaultContentType = match DefaultContentType::new(
+            &self.default_content_types,
+            self.default_content_type.as_ref().map(String::as_str),
+        ) {
+            Ok(default_content_type) => default_content_type,
+            Err(DefaultContentTypeError::InvalidGlobPattern) => return Err(CacheBuildError::InvalidGlobPattern),
+            Err(DefaultContentTypeError::InvalidContentType) => return Err(CacheBuildError::InvalidContentType),
+        };
+        let content_disposition: ContentDisposition = match ContentDisposition::new(&self.content_disposition) {
+            Ok(content_disposition) => content_disposition,
+            Err(_) => return Err(CacheBuildError::InvalidGlobPattern),
+        };
+
+        let spill: Option<Spill> = match self.spill {
+            Some((ref dir, size_limit)) => match Spill::new(dir, size_limit) {
+                Ok(spill) => Some(spill),
+                Err(_) => return Err(CacheBuildError::SpillDirectoryUnavailable),
+            },
+            None => None,
+        };
+
         let mut options_files_map: Options<RandomState> = Options::default();
         let mut options_access_map: Options<RandomState> = Options::default();
+        let mut options_negative_map: Options<RandomState> = Options::default();
+        let mut options_pinned_map: Options<RandomState> = Options::default();
+        let mut options_priority_bias_map: Options<RandomState> = Options::default();
 
         if let Some(conc) = self.concurrency {
             options_files_map.concurrency = conc;
             options_access_map.concurrency = conc;
+            options_negative_map.concurrency = conc;
+            options_pinned_map.concurrency = conc;
+            options_priority_bias_map.concurrency = conc;
         }
 
 
         Ok(Cache {
-            size_limit: size_limit,
+            size_limit: AtomicUsize::new(size_limit),
+            max_entries: self.max_entries,
             min_file_size,
             max_file_size,
-            priority_function,
+            extension_filter: ExtensionFilter::new(
+                self.allowed_extensions.as_ref().map(|allowed| &allowed[..]),
+                &self.denied_extensions[..],
+                self.cache_extensionless.unwrap_or(true),
+            ),
+            dotfiles: match self.dotfile_exceptions {
+                Some(ref exceptions) => Dotfiles::new(self.serve_dotfiles, &exceptions[..]),
+                None => Dotfiles::new(self.serve_dotfiles, &DEFAULT_DOTFILE_EXCEPTIONS),
+            }.with_roots(self.root.iter().cloned().chain(root.iter().cloned()).collect()),
+            priority_function: RwLock::new(priority_function),
+            priority_half_life: self.priority_half_life,
+            eviction_policy: match self.eviction_policy {
+                Some(ref eviction_policy) => eviction_policy.clone(),
+                None => Arc::new(PriorityScorePolicy),
+            },
+            victim_order: self.victim_order,
+            hash_algorithm: self.hash_algorithm.unwrap_or_default(),
+            etag_mode: self.etag_mode,
+            overflow_policy: self.overflow_policy,
+            precompress: self.precompress.clone(),
+            encoding_preference: self.encoding_preference.clone().unwrap_or_default(),
+            min_precompress_size: self.min_precompress_size.unwrap_or(1024),
+            precompress_filter: match self.precompress_deny_list {
+                Some(ref denied) => PrecompressFilter::new(&denied[..]),
+                None => PrecompressFilter::default(),
+            },
+            precompressed_siblings: self.precompressed_siblings,
+            read_buffer_size: self.read_buffer_size,
+            file_source: self.file_source.clone(),
+            stream_misses: self.stream_misses,
+            completed_streams: CompletedStreams::default(),
+            detect_changes: self.detect_changes,
+            counters: Counters::default(),
             accesses_per_refresh: self.accesses_per_refresh,
             file_map: ConcHashMap::with_options(options_files_map),
             access_count_map: ConcHashMap::with_options(options_access_map),
+            negative_cache_ttl: None,
+            negative_map: ConcHashMap::with_options(options_negative_map),
+            pinned_map: ConcHashMap::with_options(options_pinned_map),
+            priority_bias_map: ConcHashMap::with_options(options_priority_bias_map),
+            in_flight: InFlight::default(),
+            preload_limit: PreloadLimit::new(self.preload_concurrency.unwrap_or_else(default_preload_concurrency)),
+            open_limit: self.max_concurrent_opens_per_path.map(OpenLimit::new),
+            range_chunk_size: self.range_chunk_size.map(|chunk_size| chunk_size.max(1)),
+            fallback: None,
+            not_found_page: None,
+            #[cfg(feature = "mmap")]
+            mmap_threshold: self.mmap_threshold,
+            spill,
+            on_evict: None,
+            high_water: None,
+            root,
+            admission: self.admit_after.map(Admission::new),
+            revalidation: self.stale_while_revalidate.map(Revalidation::new),
+            cache_control,
+            content_disposition,
+            custom_headers,
+            cors: if self.cors_allowed_origins.is_empty() {
+                None
+            } else {
+                Some(Arc::new(Cors {
+                    allowed_origins: self.cors_allowed_origins.clone(),
+                    expose_headers: self.cors_expose_headers.clone(),
+                    timing_allow_origin: self.timing_allow_origin,
+                }))
+            },
+            sniff_content_type: self.sniff_content_type,
+            default_content_type,
+            bypass_header: self.bypass_header.clone(),
+            x_cache_header: self.x_cache_header,
+            key_normalization: self.key_normalization,
+            generation: AtomicUsize::new(0),
+            poisoned: AtomicBool::new(false),
         })
 
     }
@@ -202,6 +1226,7 @@ impl CacheBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::eviction_policy::LfuPolicy;
 
     #[test]
     fn min_greater_than_max() {
@@ -214,6 +1239,61 @@ mod tests {
         assert_eq!(CacheBuildError::MinFileSizeIsLargerThanMaxFileSize, e);
     }
 
+    #[test]
+    fn min_greater_than_size_limit() {
+        let e: CacheBuildError = CacheBuilder::default()
+            .size_limit(1024 * 1024)
+            .min_file_size(1024 * 1024 * 2)
+            .build()
+            .unwrap_err();
+        assert_eq!(CacheBuildError::MinFileSizeIsLargerThanSizeLimit, e);
+    }
+
+    #[test]
+    fn root_must_be_a_directory() {
+        let e: CacheBuildError = CacheBuilder::new()
+            .root("this/directory/does/not/exist")
+            .build()
+            .unwrap_err();
+        assert_eq!(CacheBuildError::RootIsNotADirectory, e);
+    }
+
+    #[test]
+    fn invalid_cache_control_pattern() {
+        let e: CacheBuildError = CacheBuilder::new()
+            .cache_control("[", "no-cache")
+            .build()
+            .unwrap_err();
+        assert_eq!(CacheBuildError::InvalidGlobPattern, e);
+    }
+
+    #[test]
+    fn invalid_immutable_assets_regex() {
+        let e: CacheBuildError = CacheBuilder::new()
+            .immutable_assets("(")
+            .build()
+            .unwrap_err();
+        assert_eq!(CacheBuildError::InvalidRegex, e);
+    }
+
+    #[test]
+    fn invalid_default_content_type() {
+        let e: CacheBuildError = CacheBuilder::new()
+            .default_content_type_for("*/docs/*", "not a type")
+            .build()
+            .unwrap_err();
+        assert_eq!(CacheBuildError::InvalidContentType, e);
+    }
+
+    #[test]
+    fn protected_headers_are_rejected() {
+        let e: CacheBuildError = CacheBuilder::new()
+            .header("Content-Length", "0")
+            .build()
+            .unwrap_err();
+        assert_eq!(CacheBuildError::InvalidHeader, e);
+    }
+
     #[test]
     fn all_options_used_in_build() {
         let _: Cache = CacheBuilder::new()
@@ -221,8 +1301,49 @@ mod tests {
             .priority_function(|access_count: usize, size: usize| access_count * size)
             .max_file_size(1024 * 1024 * 10)
             .min_file_size(1024 * 10)
+            .max_entries(100)
+            .allow_extensions(vec!["js", "css"])
+            .deny_extensions(vec!["php"])
+            .cache_extensionless(false)
+            .serve_dotfiles(false)
+            .dotfile_exceptions(vec![".well-known", ".config"])
+            .key_normalization(KeyNormalization { strip_query: true, ..KeyNormalization::default() })
             .concurrency(20)
             .accesses_per_refresh(1000)
+            .eviction_policy(LfuPolicy)
+            .overflow_policy(OverflowPolicy::RejectToDisk)
+            .file_source(FsSource)
+            .hash_algorithm(HashAlgorithm::Crc32)
+            .etag_mode(EtagMode::WeakSizeMtime)
+            .precompress(vec![Encoding::Gzip, Encoding::Brotli])
+            .encoding_preference(vec![Encoding::Brotli, Encoding::Gzip])
+            .min_precompress_size(1024 * 4)
+            .precompress_deny_list(vec!["image/*", "zip"])
+            .precompressed_siblings(true)
+            .read_buffer_size(64 * 1024)
+            .stream_misses(1024 * 1024)
+            .max_concurrent_opens_per_path(8)
+            .preload_concurrency(4)
+            .range_chunk_size(1024 * 1024)
+            .spill_to_disk(std::env::temp_dir().join("rocket-file-cache-all-options"), 1024 * 1024)
+            .detect_changes(true)
+            .admit_after(2)
+            .stale_while_revalidate(Duration::from_secs(60))
+            .priority_half_life(Duration::from_secs(60 * 60))
+            .cache_control("*.js", "max-age=31536000, immutable")
+            .default_cache_control("no-cache")
+            .immutable_assets(r"\.[0-9a-f]{6,}\.js$")
+            .content_disposition("*.zip", Disposition::Attachment)
+            .header("X-Content-Type-Options", "nosniff")
+            .header_for("*.woff2", "Access-Control-Allow-Origin", "*")
+            .cors_allow_origins(vec!["https://example.com"])
+            .cors_expose_headers(vec!["ETag"])
+            .timing_allow_origin(true)
+            .sniff_content_type(true)
+            .default_content_type_for("*/docs/*", "text/plain; charset=utf-8")
+            .default_content_type("application/octet-stream")
+            .bypass_header("X-Cache-Bypass")
+            .x_cache_header(true)
             .build()
             .unwrap();
     }
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use glob::{Pattern, PatternError};
+use regex::Regex;
+
+
+/// The directive that is sent with files that are recognized as immutable assets.
+pub(crate) const IMMUTABLE_DIRECTIVE: &str = "public, max-age=31536000, immutable";
+
+/// Chooses the `Cache-Control` directive that is sent with a file, based on its path.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CacheControl {
+    rules: Vec<(Pattern, String)>,
+    /// Matches the paths of content-addressed files, which never change.
+    immutable: Option<Regex>,
+    default: Option<String>,
+}
+
+impl CacheControl {
+    /// Compiles the glob patterns of the rules.
+    pub(crate) fn new(rules: &[(String, String)], default: Option<String>) -> Result<CacheControl, PatternError> {
+        let rules: Vec<(Pattern, String)> = rules.iter()
+            .map(|&(ref pattern, ref directive)| Ok((Pattern::new(pattern)?, directive.clone())))
+            .collect::<Result<_, PatternError>>()?;
+        Ok(CacheControl { rules, immutable: None, default })
+    }
+
+    /// Sends `IMMUTABLE_DIRECTIVE` with files whose paths match the regex, unless a rule matches them.
+    pub(crate) fn with_immutable(mut self, immutable: Option<Regex>) -> CacheControl {
+        self.immutable = immutable;
+        self
+    }
+
+    /// The directive of the first rule that matches the path.
+    /// If none of them do, the immutable directive is used for paths that match the immutable regex,
+    /// and the default directive for the rest.
+    pub(crate) fn directive(&self, path: &Path) -> Option<&str> {
+        self.rules.iter()
+            .find(|&&(ref pattern, _)| pattern.matches_path(path))
+            .map(|&(_, ref directive)| directive.as_str())
+            .or_else(|| match (&self.immutable, path.to_str()) {
+                (&Some(ref immutable), Some(path)) if immutable.is_match(path) => Some(IMMUTABLE_DIRECTIVE),
+                _ => None,
+            })
+            .or_else(|| self.default.as_ref().map(String::as_str))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_rule_is_used() {
+        let rules: Vec<(String, String)> = vec![
+            ("*/index.html".to_string(), "no-cache".to_string()),
+            ("*.html".to_string(), "max-age=60".to_string()),
+            ("*.js".to_string(), "max-age=31536000, immutable".to_string()),
+        ];
+        let cache_control = CacheControl::new(&rules, Some("max-age=3600".to_string())).unwrap();
+
+        assert_eq!(cache_control.directive(Path::new("www/index.html")), Some("no-cache"));
+        assert_eq!(cache_control.directive(Path::new("www/about.html")), Some("max-age=60"));
+        assert_eq!(cache_control.directive(Path::new("www/js/app.3f2a.js")), Some("max-age=31536000, immutable"));
+        assert_eq!(cache_control.directive(Path::new("www/logo.png")), Some("max-age=3600"));
+
+        let cache_control = CacheControl::new(&rules, None).unwrap();
+        assert_eq!(cache_control.directive(Path::new("www/logo.png")), None);
+    }
+
+    #[test]
+    fn immutable_assets_win_over_default_but_not_over_rules() {
+        let rules: Vec<(String, String)> = vec![
+            ("*/sw.*.js".to_string(), "no-cache".to_string()),
+        ];
+        let immutable: Regex = Regex::new(r"\.[0-9a-f]{6,}\.[a-z0-9]+$").unwrap();
+        let cache_control = CacheControl::new(&rules, Some("max-age=60".to_string()))
+            .unwrap()
+            .with_immutable(Some(immutable));
+
+        assert_eq!(cache_control.directive(Path::new("www/app.4f3a2b.js")), Some(IMMUTABLE_DIRECTIVE));
+        assert_eq!(cache_control.directive(Path::new("www/css/site.0123abcd.css")), Some(IMMUTABLE_DIRECTIVE));
+        assert_eq!(cache_control.directive(Path::new("www/sw.4f3a2b.js")), Some("no-cache"));
+        assert_eq!(cache_control.directive(Path::new("www/app.js")), Some("max-age=60"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(CacheControl::new(&[("[".to_string(), "no-cache".to_string())], None).is_err());
+    }
+}
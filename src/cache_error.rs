@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::cached_file::CachedFile;
+
+
+/// The reasons a file could not be gotten by `Cache::try_get()`.
+#[derive(Debug)]
+pub enum CacheError {
+    /// The file doesn't exist, isn't a file, is outside of the cache's root directory, or was
+    /// recently found to be missing by the negative cache.
+    NotFound,
+    /// The process isn't allowed to read the file.
+    PermissionDenied,
+    /// The file is larger than `max_file_size()` or the size limit of the cache, so it will never be cached.
+    /// It can still be served from the filesystem, with the `CachedFile` this holds.
+    TooLarge(CachedFile),
+    /// Any other error that occurred while reading the file.
+    Io(io::Error),
+}
+
+impl From<io::Error> for CacheError {
+    fn from(e: io::Error) -> CacheError {
+        match e.kind() {
+            io::ErrorKind::NotFound => CacheError::NotFound,
+            io::ErrorKind::PermissionDenied => CacheError::PermissionDenied,
+            _ => CacheError::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CacheError::NotFound => write!(f, "The file could not be found."),
+            CacheError::PermissionDenied => write!(f, "Permission to read the file was denied."),
+            CacheError::TooLarge(_) => write!(f, "The file is too large to be cached."),
+            CacheError::Io(ref e) => write!(f, "The file could not be read: {}", e),
+        }
+    }
+}
+
+impl CacheError {
+    /// The file that `Cache::get()` serves instead of reporting the error.
+    pub(crate) fn into_cached_file(self) -> CachedFile {
+        match self {
+            CacheError::TooLarge(cached_file) => cached_file,
+            _ => CachedFile::NotFound,
+        }
+    }
+}
+
+impl Error for CacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CacheError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cached_file::CachedFile;
+
+
+/// A snapshot of how effective the cache has been.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CacheStats {
+    /// The number of times a file was served from the cache.
+    pub hits: usize,
+    /// The number of times a file had to be served from the filesystem.
+    pub misses: usize,
+    /// The number of times a file could not be found.
+    pub not_found: usize,
+    /// The number of files that were removed from the cache to make room for other files.
+    pub evictions: usize,
+    /// The number of files currently in the cache.
+    pub entries: usize,
+    /// The sum of the sizes of the files currently in the cache.
+    pub bytes: usize,
+    /// The number of bytes the cache can hold.
+    pub capacity_bytes: usize,
+    /// The number of files currently in the cache that are pinned, and won't be evicted.
+    pub pinned: usize,
+}
+
+impl CacheStats {
+    /// The fraction of files found by the cache that were served from memory.
+    /// Returns 0 if no files have been served yet.
+    pub fn hit_ratio(&self) -> f64 {
+        let total: usize = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Formats the stats on one line, for logs, like
+/// `cache: 128/512 MiB, 340 entries, hit_ratio 0.92 (1.2M hits / 98K misses), 4.1K evictions`.
+///
+/// Sizes are in binary units, and both are given in the unit of the larger one.
+/// Counts are abbreviated with decimal suffixes. The `Debug` format has the exact values.
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let size: String = if self.capacity_bytes == usize::MAX {
+            let (unit, scale): (&str, f64) = binary_unit(self.bytes);
+            format!("{} {}/unlimited", abbreviate(self.bytes as f64 / scale), unit)
+        } else {
+            let (unit, scale): (&str, f64) = binary_unit(self.bytes.max(self.capacity_bytes));
+            format!("{}/{} {}", abbreviate(self.bytes as f64 / scale), abbreviate(self.capacity_bytes as f64 / scale), unit)
+        };
+        write!(
+            f,
+            "cache: {}, {} entries, hit_ratio {:.2} ({} hits / {} misses), {} evictions",
+            size,
+            count(self.entries),
+            self.hit_ratio(),
+            count(self.hits),
+            count(self.misses),
+            count(self.evictions),
+        )
+    }
+}
+
+/// The largest binary unit that is at most the number of bytes, and the number of bytes in it.
+fn binary_unit(bytes: usize) -> (&'static str, f64) {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut unit: usize = 0;
+    let mut scale: f64 = 1.0;
+    while unit + 1 < UNITS.len() && bytes as f64 >= scale * 1024.0 {
+        unit += 1;
+        scale *= 1024.0;
+    }
+    (UNITS[unit], scale)
+}
+
+/// Abbreviates the count with a decimal suffix, like `4.1K` or `1.2M`.
+fn count(count: usize) -> String {
+    const SUFFIXES: [&str; 5] = ["", "K", "M", "G", "T"];
+    let mut suffix: usize = 0;
+    let mut value: f64 = count as f64;
+    while suffix + 1 < SUFFIXES.len() && value >= 1000.0 {
+        suffix += 1;
+        value /= 1000.0;
+    }
+    if suffix == 0 {
+        count.to_string()
+    } else {
+        format!("{}{}", abbreviate(value), SUFFIXES[suffix])
+    }
+}
+
+/// Rounds the value to one decimal if it is below 10, and to a whole number otherwise.
+fn abbreviate(value: f64) -> String {
+    if value < 10.0 {
+        format!("{:.1}", value).trim_end_matches(".0").to_string()
+    } else {
+        format!("{:.0}", value)
+    }
+}
+
+
+/// Counters that are updated as the cache is used.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) hits: AtomicUsize,
+    pub(crate) misses: AtomicUsize,
+    pub(crate) not_found: AtomicUsize,
+    pub(crate) evictions: AtomicUsize,
+}
+
+impl Counters {
+    /// Counts the file as a hit, miss, or not found, depending on where it came from.
+    pub(crate) fn record(&self, cached_file: &CachedFile) {
+        let counter: &AtomicUsize = match *cached_file {
+            CachedFile::InMemory(_) => &self.hits,
+            CachedFile::FileSystem(_) | CachedFile::Streaming(_) => &self.misses,
+            // A range is only a hit if all of its chunks were in the cache.
+            CachedFile::Partial(ref file) => if file.from_memory { &self.hits } else { &self.misses },
+            CachedFile::NotFound => &self.not_found,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_eviction(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_ratio() {
+        let mut stats = CacheStats {
+            hits: 0,
+            misses: 0,
+            not_found: 3,
+            evictions: 0,
+            entries: 0,
+            bytes: 0,
+            capacity_bytes: 0,
+            pinned: 0,
+        };
+        assert_eq!(stats.hit_ratio(), 0.0);
+
+        stats.hits = 3;
+        stats.misses = 1;
+        assert_eq!(stats.hit_ratio(), 0.75);
+    }
+
+    #[test]
+    fn display_abbreviates_sizes_and_counts() {
+        let mut stats = CacheStats {
+            hits: 1_200_000,
+            misses: 98_000,
+            not_found: 3,
+            evictions: 4_100,
+            entries: 340,
+            bytes: 128 * 1024 * 1024,
+            capacity_bytes: 512 * 1024 * 1024,
+            pinned: 0,
+        };
+        assert_eq!(stats.to_string(), "cache: 128/512 MiB, 340 entries, hit_ratio 0.92 (1.2M hits / 98K misses), 4.1K evictions");
+
+        stats.bytes = 1536;
+        stats.capacity_bytes = usize::MAX;
+        stats.hits = 0;
+        stats.misses = 0;
+        stats.evictions = 0;
+        assert_eq!(stats.to_string(), "cache: 1.5 KiB/unlimited, 340 entries, hit_ratio 0.00 (0 hits / 0 misses), 0 evictions");
+    }
+}
@@ -3,73 +3,199 @@ use rocket::response::{Response, Responder};
 use rocket::fs::NamedFile;
 use rocket::request::Request;
 use crate::cache::Cache;
+use std::io;
 use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
 
 use crate::named_in_memory_file::NamedInMemoryFile;
+use crate::streaming_file::StreamingFile;
+use crate::partial_file::PartialFile;
 
 
 /// Wrapper around data that can represent a file - either in memory (cache), or on disk.
 ///
 /// When getting a `CachedFile` from the cache:
-/// * An `InMemory` variant indicates that the file was read into the cache and a reference to that file is attached to the variant.
+/// * An `InMemory` variant indicates that the file was read into the cache and a shared copy of that file is attached to the variant.
 /// * A `FileSystem` variant indicates that the file is not in the cache, but it can be accessed from the filesystem.
+/// * A `Streaming` variant indicates that the file is being read into the cache while it is sent to the client.
+/// * A `Partial` variant indicates that only a range of a file that is too large to be cached as a whole is sent, from the chunks of it that are cached.
 /// * A `NotFound` variant indicates that the file can not be found in the filesystem or the cache.
+///
+/// More ways of serving files may be added, so matches on a `CachedFile` need a wildcard arm.
 #[derive(Debug)]
-pub enum CachedFile<'a> {
+#[non_exhaustive]
+pub enum CachedFile {
     /// A file that has been loaded into the cache.
-    InMemory(NamedInMemoryFile<'a>),
+    InMemory(NamedInMemoryFile),
     /// A file that exists in the filesystem.
     FileSystem(NamedFile),
+    /// A large file that is streamed from the filesystem, and added to the cache once it has been sent in full.
+    Streaming(StreamingFile),
+    /// A range of a large file, assembled from the chunks of the file that are cached.
+    Partial(PartialFile),
     /// The file does not exist in either the cache or the filesystem.
+    ///
+    /// This responds with a bare `404 Not Found`, or with the page that was configured with
+    /// `Cache::not_found_body()` or `Cache::not_found_page()`, if Rocket manages the cache.
     NotFound
 }
 
-impl<'a> CachedFile<'a> {
+impl CachedFile {
 
     /// A convenience function that wraps the getting of a cached file.
     ///
     /// This is done to keep the code required to use the cache as similar to the typical use of
     /// rocket::response::NamedFile.
-    pub async fn open<P: AsRef<Path> + std::marker::Send>(path: P, cache: &'a Cache) -> CachedFile<'a> {
+    pub async fn open<P: AsRef<Path> + std::marker::Send>(path: P, cache: &Cache) -> CachedFile {
         cache.get(path).await
     }
+
+    /// Returns true if the file is served from the cache.
+    ///
+    /// This is the case for `InMemory` files, and for `Partial` files whose chunks were all in the cache already.
+    pub fn is_in_memory(&self) -> bool {
+        match *self {
+            CachedFile::InMemory(_) => true,
+            CachedFile::Partial(ref partial_file) => partial_file.from_memory,
+            CachedFile::FileSystem(_) | CachedFile::Streaming(_) | CachedFile::NotFound => false,
+        }
+    }
+
+    /// Returns true if the file, or some of it, is read from the filesystem to be served.
+    ///
+    /// This is the case for `FileSystem` and `Streaming` files, and for `Partial` files with chunks
+    /// that weren't in the cache yet.
+    pub fn is_from_disk(&self) -> bool {
+        match *self {
+            CachedFile::FileSystem(_) | CachedFile::Streaming(_) => true,
+            CachedFile::Partial(ref partial_file) => !partial_file.from_memory,
+            CachedFile::InMemory(_) | CachedFile::NotFound => false,
+        }
+    }
+
+    /// Returns true if the file could not be found.
+    pub fn is_not_found(&self) -> bool {
+        matches!(*self, CachedFile::NotFound)
+    }
+
+    /// The contents of the file, if they are in memory.
+    ///
+    /// For `Partial` files, these are the bytes of the range that is sent.
+    /// Returns `None` for files that are served from the filesystem, and for files that could not be found.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            CachedFile::InMemory(ref file) => Some(file.bytes()),
+            CachedFile::Partial(ref partial_file) => Some(partial_file.bytes()),
+            CachedFile::FileSystem(_) | CachedFile::Streaming(_) | CachedFile::NotFound => None,
+        }
+    }
+
+    /// Returns true if both files have the same contents, regardless of where they are stored.
+    ///
+    /// Unlike `==`, which only compares the paths of files in the filesystem, this reads the contents
+    /// of `FileSystem` variants to compare them. Two `NotFound` variants are equal, and a `NotFound`
+    /// variant is never equal to a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the files in the filesystem can't be read.
+    pub async fn content_eq(&self, other: &CachedFile) -> io::Result<bool> {
+        match (self.contents().await?, other.contents().await?) {
+            (Some(lhs), Some(rhs)) => Ok(lhs == rhs),
+            (None, None) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    /// The contents of the file, read from the filesystem if it isn't in memory.
+    async fn contents(&self) -> io::Result<Option<Bytes>> {
+        match *self {
+            CachedFile::InMemory(ref file) => Ok(Some(file.file.bytes.clone())),
+            CachedFile::FileSystem(ref named_file) => Ok(Some(Bytes::from(tokio::fs::read(named_file.path()).await?))),
+            CachedFile::Streaming(ref streaming_file) => Ok(Some(Bytes::from(tokio::fs::read(streaming_file.path()).await?))),
+            CachedFile::Partial(ref partial_file) => Ok(Some(partial_file.bytes.clone())),
+            CachedFile::NotFound => Ok(None),
+        }
+    }
 }
 
 
-impl<'a> From<NamedInMemoryFile<'a>> for CachedFile<'a> {
-    fn from(cached_file: NamedInMemoryFile<'a>) -> CachedFile<'a> {
+impl From<NamedInMemoryFile> for CachedFile {
+    fn from(cached_file: NamedInMemoryFile) -> CachedFile {
         CachedFile::InMemory(cached_file)
     }
 }
 
-impl From<NamedFile> for CachedFile<'static> {
+impl From<NamedFile> for CachedFile {
     fn from(named_file: NamedFile) -> Self {
         CachedFile::FileSystem(named_file)
     }
 }
 
-impl<'a> Responder<'a, 'a> for CachedFile<'a> {
-    fn respond_to(self, request: &'a Request) -> Result<Response<'a>, Status> {
+impl From<StreamingFile> for CachedFile {
+    fn from(streaming_file: StreamingFile) -> Self {
+        CachedFile::Streaming(streaming_file)
+    }
+}
 
+impl From<PartialFile> for CachedFile {
+    fn from(partial_file: PartialFile) -> Self {
+        CachedFile::Partial(partial_file)
+    }
+}
+
+/// Gets the cache that Rocket manages, either as a `Cache`, or as an `Arc<Cache>`.
+///
+/// Files that are served from the filesystem have no way to refer to the cache, so the parts of responses
+/// that are configured on the cache, but don't depend on the file, are found through Rocket instead.
+fn managed_cache<'r>(request: &'r Request<'_>) -> Option<&'r Cache> {
+    let rocket = request.rocket();
+    match rocket.state::<Cache>() {
+        Some(cache) => Some(cache),
+        None => rocket.state::<Arc<Cache>>().map(|cache| &**cache),
+    }
+}
+
+/// Responds with the file, wherever it is stored.
+impl<'r> Responder<'r, 'static> for CachedFile {
+    fn respond_to(self, request: &'r Request) -> Result<Response<'static>, Status> {
         match self {
-            CachedFile::InMemory(cached_file) => cached_file.respond_to(request),
-            CachedFile::FileSystem(named_file) => named_file.respond_to(request),
+            CachedFile::InMemory(cached_file) => cached_file.respond_to(request)?,
+            CachedFile::FileSystem(named_file) => named_file.respond_to(request)?,
+            CachedFile::Streaming(streaming_file) => streaming_file.respond_to(request)?,
+            CachedFile::Partial(partial_file) => partial_file.respond_to(request)?,
             CachedFile::NotFound => {
                 error!("Response was `FileNotFound`.",);
-                Err(Status::NotFound)
+                let cache: Option<&Cache> = managed_cache(request);
+                if cache.is_none() {
+                    debug!("Rocket doesn't manage a `Cache` or an `Arc<Cache>`, so no not found page can be sent.");
+                }
+                match cache.and_then(|cache| cache.not_found_page.as_ref()) {
+                    Some(page) => Ok(page.respond()),
+                    None => Err(Status::NotFound),
+                }
             }
         }
     }
 }
 
 
-impl<'a> PartialEq for CachedFile<'a> {
+/// Files in memory are equal if their contents and stats are equal.
+/// Files in the filesystem are equal if they have the same path, without their contents being compared,
+/// so a file that changed between being opened twice is still equal to itself. The same goes for streamed files.
+/// Ranges of files are equal if they are the same range of the same file, with the same contents.
+/// Use `content_eq()` to compare the contents of files, wherever they are stored.
+impl PartialEq for CachedFile {
     fn eq(&self, other: &CachedFile) -> bool {
         match *self {
             CachedFile::InMemory(ref lhs_cached_file) => {
                 match *other {
-                    CachedFile::InMemory(ref rhs_cached_file) => (*rhs_cached_file.file).get() == (*lhs_cached_file.file).get(),
+                    CachedFile::InMemory(ref rhs_cached_file) => rhs_cached_file.file == lhs_cached_file.file,
                     CachedFile::FileSystem(_) => false,
+                    CachedFile::Streaming(_) => false,
+                    CachedFile::Partial(_) => false,
                     CachedFile::NotFound => false
                 }
             }
@@ -80,13 +206,33 @@ impl<'a> PartialEq for CachedFile<'a> {
                         // This just compares the file paths
                         *lhs_named_file.path() == *rhs_named_file.path()
                     }
+                    CachedFile::Streaming(_) => false,
+                    CachedFile::Partial(_) => false,
                     CachedFile::NotFound => false
                 }
             }
+            CachedFile::Streaming(ref lhs_streaming_file) => {
+                match *other {
+                    CachedFile::Streaming(ref rhs_streaming_file) => lhs_streaming_file.path() == rhs_streaming_file.path(),
+                    _ => false,
+                }
+            }
+            CachedFile::Partial(ref lhs_partial_file) => {
+                match *other {
+                    CachedFile::Partial(ref rhs_partial_file) => {
+                        lhs_partial_file.path == rhs_partial_file.path
+                            && lhs_partial_file.range == rhs_partial_file.range
+                            && lhs_partial_file.bytes == rhs_partial_file.bytes
+                    }
+                    _ => false,
+                }
+            }
             CachedFile::NotFound => {
                 match *other {
                     CachedFile::InMemory(_) => false,
                     CachedFile::FileSystem(_) => false,
+                    CachedFile::Streaming(_) => false,
+                    CachedFile::Partial(_) => false,
                     CachedFile::NotFound => true
                 }
             }
@@ -94,3 +240,95 @@ impl<'a> PartialEq for CachedFile<'a> {
 
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn content_eq_compares_contents() {
+        let temp_dir = TempDir::new("cached_file").unwrap();
+        let path_a = temp_dir.path().join("a.txt");
+        let path_b = temp_dir.path().join("b.txt");
+        std::fs::write(&path_a, b"same").unwrap();
+        std::fs::write(&path_b, b"same").unwrap();
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+
+        let in_memory: CachedFile = cache.get(&path_a).await;
+        let on_disk: CachedFile = CachedFile::from(NamedFile::open(&path_b).await.unwrap());
+        assert!(in_memory != on_disk);
+        assert!(in_memory.content_eq(&on_disk).await.unwrap());
+
+        // Files in the filesystem with the same path are equal, even if their contents changed.
+        let before: CachedFile = CachedFile::from(NamedFile::open(&path_b).await.unwrap());
+        std::fs::write(&path_b, b"different").unwrap();
+        assert!(before == on_disk);
+        assert!(!in_memory.content_eq(&on_disk).await.unwrap());
+
+        assert!(CachedFile::NotFound.content_eq(&CachedFile::NotFound).await.unwrap());
+        assert!(!CachedFile::NotFound.content_eq(&in_memory).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn predicates_tell_where_the_file_came_from() {
+        let temp_dir = TempDir::new("cached_file").unwrap();
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, b"contents").unwrap();
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+
+        let in_memory: CachedFile = cache.get(&path).await;
+        assert!(in_memory.is_in_memory());
+        assert!(!in_memory.is_from_disk());
+        assert_eq!(in_memory.as_bytes(), Some(&b"contents"[..]));
+
+        let on_disk: CachedFile = CachedFile::from(NamedFile::open(&path).await.unwrap());
+        assert!(on_disk.is_from_disk());
+        assert!(!on_disk.is_in_memory());
+        assert_eq!(on_disk.as_bytes(), None);
+
+        assert!(CachedFile::NotFound.is_not_found());
+        assert!(!CachedFile::NotFound.is_in_memory());
+        assert!(!CachedFile::NotFound.is_from_disk());
+        assert!(!in_memory.is_not_found());
+    }
+
+    #[tokio::test]
+    async fn x_cache_header_tells_hits_from_misses() {
+        use rocket::local::asynchronous::Client;
+
+        let temp_dir = TempDir::new("cached_file").unwrap();
+        let path = temp_dir.path().join("a.txt");
+        std::fs::write(&path, b"contents").unwrap();
+        let large_path = temp_dir.path().join("large.txt");
+        std::fs::write(&large_path, vec![b'a'; 1024]).unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .x_cache_header(true)
+            .max_file_size(512)
+            .header("X-Content-Type-Options", "nosniff")
+            .build()
+            .unwrap();
+        let in_memory: CachedFile = cache.get(&path).await;
+        let on_disk: CachedFile = cache.get(&large_path).await;
+        assert!(on_disk.is_from_disk());
+
+        // The header is attached when the file is gotten, so the cache doesn't have to be managed by Rocket.
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.get("/a.txt");
+        let response = in_memory.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("X-Cache"), Some("HIT"));
+        assert_eq!(response.headers().get_one("X-Content-Type-Options"), Some("nosniff"));
+        let response = on_disk.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("X-Cache"), Some("MISS"));
+
+        // The header is off by default.
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        let in_memory: CachedFile = cache.get(&path).await;
+        let response = in_memory.respond_to(request.inner()).unwrap();
+        assert_eq!(response.headers().get_one("X-Cache"), None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::cache::Cache;
+
+
+/// The paths of the files that were in the cache at one point in time, taken by `Cache::key_snapshot()`.
+#[derive(Debug, Clone)]
+pub struct KeySnapshot {
+    keys: HashSet<PathBuf>,
+    taken_at: Instant,
+}
+
+impl KeySnapshot {
+    /// The number of files that were in the cache.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns true if the cache was empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns true if the file was in the cache.
+    pub fn contains(&self, path: &PathBuf) -> bool {
+        self.keys.contains(path)
+    }
+}
+
+/// How the files in the cache have changed since a `KeySnapshot` was taken, reported by `Cache::churn_since()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChurnReport {
+    /// The number of files that were in the cache when the snapshot was taken, and aren't anymore,
+    /// because they were evicted, expired, or removed.
+    pub removed: usize,
+    /// The number of files that are in the cache, but weren't when the snapshot was taken.
+    pub added: usize,
+    /// The number of files that were in the cache when the snapshot was taken, and still are.
+    pub retained: usize,
+    /// The time that has passed since the snapshot was taken.
+    pub elapsed: Duration,
+}
+
+impl ChurnReport {
+    /// The fraction of the files that were in the cache when the snapshot was taken that have left it since.
+    /// Returns 0 if the cache was empty.
+    ///
+    /// A cache that keeps replacing most of its files between two snapshots, while it misses often,
+    /// is likely too small for the files it serves.
+    pub fn turnover(&self) -> f64 {
+        let before: usize = self.removed + self.retained;
+        if before == 0 {
+            0.0
+        } else {
+            self.removed as f64 / before as f64
+        }
+    }
+}
+
+
+impl Cache {
+    /// Takes a snapshot of the paths of the files in the cache, which `churn_since()` compares to
+    /// the files in the cache at a later point in time.
+    ///
+    /// Like `keys()`, the paths are collected in a single pass over the cache, without gathering the stats of the files.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, ChurnReport, KeySnapshot};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let snapshot: KeySnapshot = cache.key_snapshot();
+    /// // Some time later...
+    /// let churn: ChurnReport = cache.churn_since(&snapshot);
+    /// println!("{} files left the cache and {} were added in {:?}", churn.removed, churn.added, churn.elapsed);
+    /// ```
+    pub fn key_snapshot(&self) -> KeySnapshot {
+        KeySnapshot {
+            keys: self.keys().into_iter().collect(),
+            taken_at: Instant::now(),
+        }
+    }
+
+    /// Compares the files in the cache to the files that were in it when the snapshot was taken.
+    ///
+    /// Only the presence of files is compared, so a file that was evicted and read into the cache
+    /// again in the meantime counts as retained.
+    pub fn churn_since(&self, snapshot: &KeySnapshot) -> ChurnReport {
+        let keys: Vec<PathBuf> = self.keys();
+        let retained: usize = keys.iter().filter(|key| snapshot.keys.contains(*key)).count();
+        ChurnReport {
+            removed: snapshot.keys.len() - retained,
+            added: keys.len() - retained,
+            retained,
+            elapsed: snapshot.taken_at.elapsed(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn churn_counts_files_that_left_and_joined_the_cache() {
+        let cache: Cache = CacheBuilder::new().size_limit(3000).build().unwrap();
+        cache.insert_bytes("www/a.html", vec![0; 1000]).await;
+        cache.insert_bytes("www/b.html", vec![0; 1000]).await;
+        let snapshot: KeySnapshot = cache.key_snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        cache.remove("www/a.html");
+        cache.insert_bytes("www/c.html", vec![0; 1000]).await;
+        cache.insert_bytes("www/d.html", vec![0; 500]).await;
+
+        let churn: ChurnReport = cache.churn_since(&snapshot);
+        assert_eq!(churn.removed, 1);
+        assert_eq!(churn.added, 2);
+        assert_eq!(churn.retained, 1);
+        assert_eq!(churn.turnover(), 0.5);
+    }
+}
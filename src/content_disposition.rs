@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use glob::{Pattern, PatternError};
+
+
+/// How the browser should present a file that is served from the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// The file is displayed in the browser.
+    Inline,
+    /// The file is downloaded, and saved under its file name.
+    Attachment,
+}
+
+impl Disposition {
+    fn name(&self) -> &'static str {
+        match *self {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        }
+    }
+}
+
+
+/// Chooses the `Content-Disposition` header that is sent with a file, based on its path.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ContentDisposition {
+    rules: Vec<(Pattern, Disposition)>,
+}
+
+impl ContentDisposition {
+    /// Compiles the glob patterns of the rules.
+    pub(crate) fn new(rules: &[(String, Disposition)]) -> Result<ContentDisposition, PatternError> {
+        let rules: Vec<(Pattern, Disposition)> = rules.iter()
+            .map(|&(ref pattern, disposition)| Ok((Pattern::new(pattern)?, disposition)))
+            .collect::<Result<_, PatternError>>()?;
+        Ok(ContentDisposition { rules })
+    }
+
+    /// The value of the header for the first rule that matches the path, if any of them do.
+    /// The file name is taken from the last component of the path.
+    pub(crate) fn header_value(&self, path: &Path) -> Option<String> {
+        let disposition: Disposition = self.rules.iter()
+            .find(|&&(ref pattern, _)| pattern.matches_path(path))
+            .map(|&(_, disposition)| disposition)?;
+
+        let file_name: &str = match path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) => file_name,
+            None => return Some(disposition.name().to_string()),
+        };
+        let mut value: String = format!("{}; filename=\"{}\"", disposition.name(), quoted_ascii(file_name));
+        // Clients that understand the extended parameter use it in place of the ASCII approximation.
+        if !file_name.is_ascii() {
+            value.push_str("; filename*=UTF-8''");
+            value.push_str(&percent_encode(file_name));
+        }
+        Some(value)
+    }
+}
+
+/// Makes the file name safe to put between quotes, replacing characters that aren't printable ASCII.
+fn quoted_ascii(file_name: &str) -> String {
+    file_name.chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            ' '..='~' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Percent encodes the UTF-8 bytes of the file name, as described in RFC 5987.
+fn percent_encode(file_name: &str) -> String {
+    let mut encoded = String::with_capacity(file_name.len() * 3);
+    for byte in file_name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_rule_is_used() {
+        let rules: Vec<(String, Disposition)> = vec![
+            ("*/downloads/*.pdf".to_string(), Disposition::Inline),
+            ("*/downloads/*".to_string(), Disposition::Attachment),
+        ];
+        let content_disposition = ContentDisposition::new(&rules).unwrap();
+
+        assert_eq!(content_disposition.header_value(Path::new("www/downloads/manual.pdf")), Some("inline; filename=\"manual.pdf\"".to_string()));
+        assert_eq!(content_disposition.header_value(Path::new("www/downloads/app.zip")), Some("attachment; filename=\"app.zip\"".to_string()));
+        assert_eq!(content_disposition.header_value(Path::new("www/index.html")), None);
+    }
+
+    #[test]
+    fn file_names_are_escaped() {
+        let content_disposition = ContentDisposition::new(&[("*".to_string(), Disposition::Attachment)]).unwrap();
+
+        assert_eq!(content_disposition.header_value(Path::new("www/a \"b\".txt")), Some("attachment; filename=\"a _b_.txt\"".to_string()));
+        assert_eq!(
+            content_disposition.header_value(Path::new("www/résumé.pdf")),
+            Some("attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf".to_string())
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use rocket::request::Request;
+use rocket::response::Response;
+
+
+/// The headers that are managed by the CORS configuration, which replace custom headers with the same names.
+const CORS_HEADERS: &[&str] = &[
+    "Access-Control-Allow-Origin",
+    "Access-Control-Expose-Headers",
+    "Timing-Allow-Origin",
+];
+
+/// Adds CORS and `Timing-Allow-Origin` headers to responses for requests from allowed origins.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cors {
+    /// The origins that are allowed to read the files. `*` allows every origin.
+    pub(crate) allowed_origins: Vec<String>,
+    /// The response headers that scripts from the allowed origins may read.
+    pub(crate) expose_headers: Vec<String>,
+    /// True if the allowed origins may read the resource timings of the files.
+    pub(crate) timing_allow_origin: bool,
+}
+
+impl Cors {
+    /// The value of `Access-Control-Allow-Origin` for the request's origin, if it is allowed.
+    fn allow_origin<'a>(&self, origin: Option<&'a str>) -> Option<&'a str> {
+        if self.allows_any_origin() {
+            return Some("*");
+        }
+        let origin: &str = origin?;
+        self.allowed_origins.iter()
+            .find(|allowed| allowed.eq_ignore_ascii_case(origin))
+            .map(|_| origin)
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*")
+    }
+
+    /// Sets the headers on the response.
+    ///
+    /// The origin of the request is reflected, unless every origin is allowed, in which case `*` is sent.
+    /// Reflected origins make the response depend on the `Origin` header, so it is added to `Vary`,
+    /// whether or not the origin is allowed.
+    pub(crate) fn apply(&self, request: &Request<'_>, response: &mut Response<'_>) {
+        for name in CORS_HEADERS {
+            response.remove_header(name);
+        }
+
+        if !self.allows_any_origin() {
+            let vary: String = match response.headers().get_one("Vary") {
+                Some(vary) => format!("{}, Origin", vary),
+                None => "Origin".to_string(),
+            };
+            response.set_raw_header("Vary", vary);
+        }
+
+        let allow_origin: String = match self.allow_origin(request.headers().get_one("Origin")) {
+            Some(allow_origin) => allow_origin.to_string(),
+            None => return,
+        };
+        if !self.expose_headers.is_empty() {
+            response.set_raw_header("Access-Control-Expose-Headers", self.expose_headers.join(", "));
+        }
+        if self.timing_allow_origin {
+            response.set_raw_header("Timing-Allow-Origin", allow_origin.clone());
+        }
+        response.set_raw_header("Access-Control-Allow-Origin", allow_origin);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_allowed_origins_are_reflected() {
+        let cors = Cors {
+            allowed_origins: vec!["https://example.com".to_string()],
+            ..Cors::default()
+        };
+        assert_eq!(cors.allow_origin(Some("https://example.com")), Some("https://example.com"));
+        assert_eq!(cors.allow_origin(Some("HTTPS://EXAMPLE.COM")), Some("HTTPS://EXAMPLE.COM"));
+        assert_eq!(cors.allow_origin(Some("https://evil.example")), None);
+        assert_eq!(cors.allow_origin(None), None);
+
+        let cors = Cors {
+            allowed_origins: vec!["*".to_string()],
+            ..Cors::default()
+        };
+        assert_eq!(cors.allow_origin(Some("https://evil.example")), Some("*"));
+        assert_eq!(cors.allow_origin(None), Some("*"));
+    }
+}
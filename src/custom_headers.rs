@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use glob::Pattern;
+use rocket::http::Header;
+
+
+/// The headers that the responder sets itself, which would make the response invalid if they were replaced.
+const PROTECTED_HEADERS: &[&str] = &[
+    "Content-Type",
+    "Content-Length",
+    "Content-Encoding",
+    "Content-Range",
+    "ETag",
+];
+
+/// The reasons a custom header can't be added.
+#[derive(Debug, PartialEq)]
+pub(crate) enum CustomHeaderError {
+    InvalidGlobPattern,
+    InvalidHeader,
+}
+
+/// Chooses the custom headers that are sent with a file, based on its path.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CustomHeaders {
+    rules: Vec<(Option<Pattern>, String, String)>,
+}
+
+impl CustomHeaders {
+    /// Compiles the glob patterns of the rules, and checks that the headers can be sent.
+    /// Rules without a pattern match every file.
+    pub(crate) fn new(rules: &[(Option<String>, String, String)]) -> Result<CustomHeaders, CustomHeaderError> {
+        let rules: Vec<(Option<Pattern>, String, String)> = rules.iter()
+            .map(|&(ref pattern, ref name, ref value)| {
+                if !is_valid_name(name) || !is_valid_value(value) || is_protected(name) {
+                    return Err(CustomHeaderError::InvalidHeader);
+                }
+                let pattern: Option<Pattern> = match *pattern {
+                    Some(ref pattern) => Some(Pattern::new(pattern).map_err(|_| CustomHeaderError::InvalidGlobPattern)?),
+                    None => None,
+                };
+                Ok((pattern, name.clone(), value.clone()))
+            })
+            .collect::<Result<_, CustomHeaderError>>()?;
+        Ok(CustomHeaders { rules })
+    }
+
+    /// The headers of every rule that matches the path, in the order the rules were added.
+    pub(crate) fn headers<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = Header<'static>> + 'a {
+        self.rules.iter()
+            .filter(move |&&(ref pattern, _, _)| pattern.as_ref().map_or(true, |pattern| pattern.matches_path(path)))
+            .map(|&(_, ref name, ref value)| Header::new(name.clone(), value.clone()))
+    }
+}
+
+fn is_protected(name: &str) -> bool {
+    PROTECTED_HEADERS.iter().any(|protected| protected.eq_ignore_ascii_case(name))
+}
+
+/// Header names are tokens, as defined in RFC 7230.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+}
+
+/// Header values can't contain control characters, which would allow them to end the header early.
+fn is_valid_value(value: &str) -> bool {
+    value.bytes().all(|byte| byte == b'\t' || !byte.is_ascii_control())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: Option<&str>, name: &str, value: &str) -> (Option<String>, String, String) {
+        (pattern.map(String::from), name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn matching_rules_are_used() {
+        let custom_headers = CustomHeaders::new(&[
+            rule(None, "X-Content-Type-Options", "nosniff"),
+            rule(Some("*/fonts/*"), "Access-Control-Allow-Origin", "*"),
+        ]).unwrap();
+
+        let names = |path: &str| -> Vec<String> {
+            custom_headers.headers(Path::new(path)).map(|header| header.name().to_string()).collect()
+        };
+        assert_eq!(names("www/fonts/a.woff2"), vec!["X-Content-Type-Options", "Access-Control-Allow-Origin"]);
+        assert_eq!(names("www/index.html"), vec!["X-Content-Type-Options"]);
+    }
+
+    #[test]
+    fn essential_and_malformed_headers_are_rejected() {
+        assert_eq!(CustomHeaders::new(&[rule(None, "content-type", "text/plain")]).unwrap_err(), CustomHeaderError::InvalidHeader);
+        assert_eq!(CustomHeaders::new(&[rule(None, "ETag", "\"abc\"")]).unwrap_err(), CustomHeaderError::InvalidHeader);
+        assert_eq!(CustomHeaders::new(&[rule(None, "X Bad", "1")]).unwrap_err(), CustomHeaderError::InvalidHeader);
+        assert_eq!(CustomHeaders::new(&[rule(None, "X-Split", "a\r\nSet-Cookie: b")]).unwrap_err(), CustomHeaderError::InvalidHeader);
+        assert_eq!(CustomHeaders::new(&[rule(Some("["), "X-Frame-Options", "DENY")]).unwrap_err(), CustomHeaderError::InvalidGlobPattern);
+    }
+}
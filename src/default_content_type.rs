@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use glob::Pattern;
+use rocket::http::ContentType;
+
+
+/// The reason the default content types couldn't be compiled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DefaultContentTypeError {
+    InvalidGlobPattern,
+    InvalidContentType,
+}
+
+/// Chooses the `Content-Type` of files whose type can't be determined from their extension or contents, based on their path.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DefaultContentType {
+    rules: Vec<(Pattern, ContentType)>,
+    default: Option<ContentType>,
+}
+
+impl DefaultContentType {
+    /// Compiles the glob patterns of the rules, and parses the content types.
+    pub(crate) fn new(rules: &[(String, String)], default: Option<&str>) -> Result<DefaultContentType, DefaultContentTypeError> {
+        let rules: Vec<(Pattern, ContentType)> = rules.iter()
+            .map(|&(ref pattern, ref content_type)| {
+                let pattern: Pattern = Pattern::new(pattern).map_err(|_| DefaultContentTypeError::InvalidGlobPattern)?;
+                Ok((pattern, parse(content_type)?))
+            })
+            .collect::<Result<_, DefaultContentTypeError>>()?;
+        let default: Option<ContentType> = match default {
+            Some(default) => Some(parse(default)?),
+            None => None,
+        };
+        Ok(DefaultContentType { rules, default })
+    }
+
+    /// The content type of the first rule that matches the path, or the default content type if none of them do.
+    pub(crate) fn content_type(&self, path: &Path) -> Option<&ContentType> {
+        self.rules.iter()
+            .find(|&&(ref pattern, _)| pattern.matches_path(path))
+            .map(|&(_, ref content_type)| content_type)
+            .or(self.default.as_ref())
+    }
+}
+
+fn parse(content_type: &str) -> Result<ContentType, DefaultContentTypeError> {
+    ContentType::parse_flexible(content_type).ok_or(DefaultContentTypeError::InvalidContentType)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_rule_is_used() {
+        let rules: Vec<(String, String)> = vec![
+            ("*/docs/*".to_string(), "text/plain; charset=utf-8".to_string()),
+        ];
+        let default_content_type = DefaultContentType::new(&rules, Some("application/octet-stream")).unwrap();
+        assert_eq!(default_content_type.content_type(Path::new("www/docs/README")), Some(&ContentType::Plain));
+        assert_eq!(default_content_type.content_type(Path::new("www/LICENSE")), Some(&ContentType::Binary));
+
+        assert_eq!(DefaultContentType::new(&rules, None).unwrap().content_type(Path::new("www/LICENSE")), None);
+        assert_eq!(DefaultContentType::new(&rules, Some("not a type")).unwrap_err(), DefaultContentTypeError::InvalidContentType);
+    }
+}
@@ -0,0 +1,131 @@
+use std::path::{Component, Path, PathBuf};
+
+
+/// The directories starting with a `.` that are served by default, as they are meant to be public.
+pub(crate) const DEFAULT_DOTFILE_EXCEPTIONS: [&str; 1] = [".well-known"];
+
+
+/// Decides whether files and directories whose names start with a `.` may be served.
+#[derive(Debug, Clone)]
+pub(crate) struct Dotfiles {
+    serve: bool,
+    /// The names of files and directories that start with a `.`, but are served anyway.
+    exceptions: Vec<String>,
+    /// The directories that files are served from, whose own names don't hide the files below them.
+    roots: Vec<PathBuf>,
+}
+
+impl Default for Dotfiles {
+    fn default() -> Dotfiles {
+        Dotfiles::new(false, &DEFAULT_DOTFILE_EXCEPTIONS)
+    }
+}
+
+impl Dotfiles {
+    pub(crate) fn new<S: AsRef<str>>(serve: bool, exceptions: &[S]) -> Dotfiles {
+        Dotfiles {
+            serve,
+            exceptions: exceptions.iter().map(|exception| exception.as_ref().to_string()).collect(),
+            roots: Vec::new(),
+        }
+    }
+
+    /// Only checks the components of paths below these directories, so a root that is itself hidden,
+    /// like `.output/public/`, still serves its files.
+    pub(crate) fn with_roots(mut self, roots: Vec<PathBuf>) -> Dotfiles {
+        self.roots = roots;
+        self
+    }
+
+    /// Returns true if any component of the path starts with a `.`, and isn't one of the exceptions.
+    /// The `.` and `..` components aren't names, so they don't hide the path.
+    /// If the path is within one of the roots, only the components below the root are checked.
+    pub(crate) fn hides(&self, path: &Path) -> bool {
+        if self.serve {
+            return false;
+        }
+        let path: &Path = self.roots.iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .next()
+            .unwrap_or(path);
+        path.components().any(|component| match component {
+            Component::Normal(name) => {
+                let name = name.to_string_lossy();
+                name.starts_with('.') && !self.exceptions.iter().any(|exception| *exception == name)
+            }
+            _ => false,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use std::path::PathBuf;
+    use crate::cache::Cache;
+    use crate::cache_builder::CacheBuilder;
+    use crate::cached_file::CachedFile;
+
+    #[test]
+    fn dotfiles_are_hidden_unless_excepted() {
+        let dotfiles = Dotfiles::default();
+        assert!(dotfiles.hides(Path::new("www/.env")));
+        assert!(dotfiles.hides(Path::new("www/.git/config")));
+        assert!(!dotfiles.hides(Path::new("www/.well-known/security.txt")));
+        assert!(!dotfiles.hides(Path::new("./www/../www/index.html")));
+
+        let dotfiles = Dotfiles::new(false, &[".config"]);
+        assert!(!dotfiles.hides(Path::new("www/.config/app.json")));
+        assert!(dotfiles.hides(Path::new("www/.well-known/security.txt")));
+
+        assert!(!Dotfiles::new::<&str>(true, &[]).hides(Path::new("www/.env")));
+    }
+
+    #[test]
+    fn only_components_below_the_root_are_checked() {
+        let dotfiles = Dotfiles::default().with_roots(vec![PathBuf::from(".output/public")]);
+        assert!(!dotfiles.hides(Path::new(".output/public/index.html")));
+        assert!(dotfiles.hides(Path::new(".output/public/.env")));
+        assert!(dotfiles.hides(Path::new(".output/private/index.html")));
+    }
+
+    #[tokio::test]
+    async fn files_are_served_from_a_hidden_root() {
+        let temp_dir = TempDir::new("dotfiles").unwrap();
+        let root: PathBuf = temp_dir.path().join(".output/public");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("index.html"), b"<html></html>").unwrap();
+        std::fs::write(root.join(".env"), b"SECRET=1").unwrap();
+
+        let cache: Cache = CacheBuilder::new().root(&root).build().unwrap();
+        assert!(cache.get(root.join("index.html")).await.is_in_memory());
+        assert_eq!(cache.get(root.join(".env")).await, CachedFile::NotFound);
+    }
+
+    #[tokio::test]
+    async fn hidden_files_are_not_served() {
+        let temp_dir = TempDir::new("dotfiles").unwrap();
+        std::fs::create_dir_all(temp_dir.path().join(".well-known")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join(".git")).unwrap();
+        let env: PathBuf = temp_dir.path().join(".env");
+        let git_config: PathBuf = temp_dir.path().join(".git/config");
+        let security: PathBuf = temp_dir.path().join(".well-known/security.txt");
+        std::fs::write(&env, b"SECRET=1").unwrap();
+        std::fs::write(&git_config, b"[core]").unwrap();
+        std::fs::write(&security, b"Contact: mailto:security@example.com").unwrap();
+
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        assert_eq!(cache.get(&env).await, CachedFile::NotFound);
+        assert_eq!(cache.get(&git_config).await, CachedFile::NotFound);
+        assert_eq!(cache.peek(&env).await, CachedFile::NotFound);
+        assert!(!cache.contains_key(&env));
+        assert!(cache.get(&security).await.is_in_memory());
+
+        let cache: Cache = CacheBuilder::new().serve_dotfiles(true).build().unwrap();
+        assert!(cache.get(&env).await.is_in_memory());
+    }
+}
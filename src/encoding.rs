@@ -0,0 +1,154 @@
+use std::io;
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+
+/// A content encoding that files in the cache can be compressed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// The gzip encoding.
+    Gzip,
+    /// The brotli encoding.
+    Brotli,
+    /// The zstd encoding. This requires the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    /// The name of the encoding, as it appears in the `Accept-Encoding` and `Content-Encoding` headers.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// The extension of files that have been compressed with this encoding, without the leading dot.
+    pub(crate) fn extension(&self) -> &'static str {
+        match *self {
+            Encoding::Gzip => "gz",
+            Encoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zst",
+        }
+    }
+
+    /// Compresses the bytes with this encoding.
+    pub fn compress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match *self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => {
+                // Quality 9 gets most of the benefit of the maximum quality of 11, for a fraction of the time.
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 9, 22);
+                encoder.write_all(bytes)?;
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+            // Like the brotli quality, level 10 compresses nearly as well as the highest levels, in much less time.
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => zstd::stream::encode_all(bytes, 10),
+        }
+    }
+}
+
+/// Every encoding, in the order their precompressed copies are looked for.
+pub(crate) const ALL: &[Encoding] = &[
+    Encoding::Brotli,
+    #[cfg(feature = "zstd")]
+    Encoding::Zstd,
+    Encoding::Gzip,
+];
+
+
+/// Picks the encoding that the client prefers the most out of the available encodings,
+/// using the quality values of an `Accept-Encoding` header.
+///
+/// When the client has no preference between encodings, the one that comes first in `available` is chosen.
+/// Returns `None` if none of the available encodings are acceptable.
+pub(crate) fn negotiate<I: IntoIterator<Item = Encoding>>(accept_encoding: &str, available: I) -> Option<Encoding> {
+    let accepted: Vec<(&str, f32)> = accept_encoding
+        .split(',')
+        .filter_map(|coding| {
+            let mut parts = coding.split(';');
+            let name: &str = parts.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let quality: f32 = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .filter_map(|q| q.trim().parse().ok())
+                .next()
+                .unwrap_or(1.0);
+            Some((name, quality))
+        })
+        .collect();
+
+    let quality_of = |encoding: &Encoding| -> f32 {
+        accepted.iter()
+            .find(|&&(name, _)| name.eq_ignore_ascii_case(encoding.name()))
+            .or_else(|| accepted.iter().find(|&&(name, _)| name == "*"))
+            .map(|&(_, quality)| quality)
+            .unwrap_or(0.0)
+    };
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in available {
+        let quality: f32 = quality_of(&encoding);
+        if quality <= 0.0 {
+            continue;
+        }
+        match best {
+            Some((_, best_quality)) if best_quality >= quality => {}
+            _ => best = Some((encoding, quality)),
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use flate2::read::GzDecoder;
+
+    const AVAILABLE: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    #[test]
+    fn negotiate_encodings() {
+        assert_eq!(negotiate("gzip, deflate, br", AVAILABLE.iter().cloned()), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip, br;q=0.5", AVAILABLE.iter().cloned()), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*", AVAILABLE.iter().cloned()), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip;q=0, br;q=0", AVAILABLE.iter().cloned()), None);
+        assert_eq!(negotiate("deflate", AVAILABLE.iter().cloned()), None);
+        assert_eq!(negotiate("", AVAILABLE.iter().cloned()), None);
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let bytes: Vec<u8> = b"hello hello hello hello".to_vec();
+        let compressed: Vec<u8> = Encoding::Gzip.compress(&bytes).unwrap();
+
+        let mut decompressed: Vec<u8> = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, bytes);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_round_trip() {
+        let bytes: Vec<u8> = b"hello hello hello hello".to_vec();
+        let compressed: Vec<u8> = Encoding::Zstd.compress(&bytes).unwrap();
+        assert_eq!(zstd::stream::decode_all(compressed.as_slice()).unwrap(), bytes);
+        assert_eq!(negotiate("gzip, zstd", [Encoding::Zstd, Encoding::Gzip].iter().cloned()), Some(Encoding::Zstd));
+    }
+}
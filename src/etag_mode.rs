@@ -0,0 +1,54 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// How the `ETag` header is generated for files in the cache.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EtagMode {
+    /// A strong entity tag, generated from the hash of the file's contents with the cache's `HashAlgorithm`.
+    ///
+    /// This is the default.
+    Strong,
+    /// A weak entity tag of the form `W/"<size>-<mtime>"`, like many static file servers send, with the size
+    /// of the file in bytes and its modification time in seconds since the Unix epoch, both in hexadecimal.
+    ///
+    /// Files are never hashed, which makes reading large files into the cache cheaper, but a file that is
+    /// changed without changing its size, within the same second, keeps its entity tag, and the cache can't
+    /// detect whether the contents of these files have been corrupted.
+    /// Files whose modification time isn't known, like contents that aren't read from the filesystem, get a
+    /// strong entity tag instead.
+    WeakSizeMtime,
+}
+
+impl Default for EtagMode {
+    fn default() -> Self {
+        EtagMode::Strong
+    }
+}
+
+impl EtagMode {
+    /// The weak entity tag for a file of the size that was modified at the time, if it can be generated without hashing the file.
+    pub(crate) fn weak_etag(&self, size: usize, modified: Option<SystemTime>) -> Option<String> {
+        match *self {
+            EtagMode::Strong => None,
+            EtagMode::WeakSizeMtime => {
+                let modified: u64 = modified?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+                Some(format!("W/\"{:x}-{:x}\"", size, modified))
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn weak_etag_is_made_of_size_and_mtime() {
+        let modified: SystemTime = UNIX_EPOCH + Duration::from_millis(1_600_000_000_500);
+        assert_eq!(EtagMode::WeakSizeMtime.weak_etag(1024, Some(modified)), Some("W/\"400-5f5e1000\"".to_string()));
+        assert_eq!(EtagMode::WeakSizeMtime.weak_etag(1024, None), None);
+        assert_eq!(EtagMode::Strong.weak_etag(1024, Some(modified)), None);
+    }
+}
@@ -0,0 +1,289 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::PathBuf;
+
+use crate::in_memory_file::FileStats;
+
+
+/// A strategy for choosing which files are removed from the cache when it is full.
+///
+/// When a new file doesn't fit in the cache, the cache will repeatedly ask the policy to select a
+/// victim from the files that haven't been selected yet, until enough space would be freed for the new file,
+/// and the cache would hold no more files than its `max_entries`.
+/// After each selection, the policy decides if the selected files should actually be removed to make
+/// room for the new file.
+/// If it decides they shouldn't, or if it runs out of victims, the new file isn't added to the cache.
+pub trait EvictionPolicy: Debug + Send + Sync {
+    /// Chooses the file that should be removed next, out of the files in the cache that are still candidates for removal.
+    /// Returning `None` indicates that no more files should be removed.
+    fn select_victim(&self, entries: &HashMap<PathBuf, FileStats>) -> Option<PathBuf>;
+
+    /// Called whenever the stats of a file are updated, after its priority has been calculated using
+    /// the cache's priority function.
+    /// This allows the policy to keep track of, or alter, the stats it uses to select victims.
+    fn on_access(&self, _stats: &mut FileStats) {}
+
+    /// Decides if the files that have been selected as victims should be removed to make room for the candidate file.
+    /// By default, the victims are always removed.
+    fn should_evict(&self, _victims: &[FileStats], _candidate: &FileStats) -> bool {
+        true
+    }
+}
+
+
+/// Removes the files with the lowest priority score, as calculated by the cache's priority function.
+/// A new file is only accepted if its priority is higher than the combined priority of the files it would replace.
+///
+/// This is the default policy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriorityScorePolicy;
+
+impl EvictionPolicy for PriorityScorePolicy {
+    fn select_victim(&self, entries: &HashMap<PathBuf, FileStats>) -> Option<PathBuf> {
+        entries.iter()
+            .min_by_key(|&(_, stats)| stats.priority())
+            .map(|(path, _)| path.clone())
+    }
+
+    fn should_evict(&self, victims: &[FileStats], candidate: &FileStats) -> bool {
+        let priority_to_free: usize = victims.iter().fold(0usize, |sum, stats| sum.saturating_add(stats.priority_score()));
+        priority_to_free <= candidate.priority_score()
+    }
+}
+
+
+/// Removes the files that were accessed least recently.
+/// A new file is always accepted, as long as it fits within the cache's size limit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LruPolicy;
+
+impl EvictionPolicy for LruPolicy {
+    fn select_victim(&self, entries: &HashMap<PathBuf, FileStats>) -> Option<PathBuf> {
+        entries.iter()
+            .min_by_key(|&(_, stats)| stats.last_accessed)
+            .map(|(path, _)| path.clone())
+    }
+}
+
+
+/// Removes the files that were accessed the fewest number of times.
+/// A new file is only accepted if it has been accessed more than the combined access count of the files it would replace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LfuPolicy;
+
+impl EvictionPolicy for LfuPolicy {
+    fn select_victim(&self, entries: &HashMap<PathBuf, FileStats>) -> Option<PathBuf> {
+        entries.iter()
+            .min_by_key(|&(_, stats)| stats.access_count)
+            .map(|(path, _)| path.clone())
+    }
+
+    fn should_evict(&self, victims: &[FileStats], candidate: &FileStats) -> bool {
+        let accesses_to_free: usize = victims.iter().fold(0usize, |sum, stats| sum.saturating_add(stats.access_count));
+        accesses_to_free <= candidate.access_count
+    }
+}
+
+
+/// The score of a file with one access per this many bytes.
+/// Scaling the ratio keeps large files from all having a score of 0.
+const SIZE_WEIGHTED_SCALE: usize = 1024 * 1024;
+
+/// Removes the files with the fewest accesses per byte, so small files that are requested often are
+/// kept, and large files that are rarely requested are removed first.
+///
+/// The score of a file is its access count per MiB of its size, and it replaces the priority that
+/// was calculated by the cache's priority function, its half life, and its bias, so `FileStats::priority` holds the score.
+/// A new file is only accepted if its score is higher than the scores of each of the files it would replace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeWeightedLfuPolicy;
+
+impl SizeWeightedLfuPolicy {
+    /// The number of accesses per MiB of the file.
+    pub fn score(access_count: usize, size: usize) -> usize {
+        access_count.saturating_mul(SIZE_WEIGHTED_SCALE) / size.max(1)
+    }
+}
+
+impl EvictionPolicy for SizeWeightedLfuPolicy {
+    fn select_victim(&self, entries: &HashMap<PathBuf, FileStats>) -> Option<PathBuf> {
+        entries.iter()
+            .min_by_key(|&(_, stats)| stats.priority())
+            .map(|(path, _)| path.clone())
+    }
+
+    fn on_access(&self, stats: &mut FileStats) {
+        stats.set_priority_score(SizeWeightedLfuPolicy::score(stats.access_count, stats.size));
+    }
+
+    fn should_evict(&self, victims: &[FileStats], candidate: &FileStats) -> bool {
+        victims.iter().all(|stats| stats.priority() < candidate.priority())
+    }
+}
+
+
+/// The order in which one of the built in policies selects victims, lowest first.
+///
+/// This lets the cache rank the candidates once and take as many victims as it needs, instead of
+/// scanning every candidate for each victim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VictimOrder {
+    Priority,
+    LastAccessed,
+    AccessCount,
+}
+
+impl VictimOrder {
+    /// The order of the policy, if it is one of the built in policies.
+    pub(crate) fn of<E: EvictionPolicy + 'static>() -> Option<VictimOrder> {
+        let policy: TypeId = TypeId::of::<E>();
+        if policy == TypeId::of::<PriorityScorePolicy>() || policy == TypeId::of::<SizeWeightedLfuPolicy>() {
+            Some(VictimOrder::Priority)
+        } else if policy == TypeId::of::<LruPolicy>() {
+            Some(VictimOrder::LastAccessed)
+        } else if policy == TypeId::of::<LfuPolicy>() {
+            Some(VictimOrder::AccessCount)
+        } else {
+            None
+        }
+    }
+
+    fn sort(&self, candidates: &mut [(PathBuf, FileStats)]) {
+        match *self {
+            VictimOrder::Priority => candidates.sort_by_key(|&(_, ref stats)| stats.priority()),
+            VictimOrder::LastAccessed => candidates.sort_by_key(|&(_, ref stats)| stats.last_accessed),
+            VictimOrder::AccessCount => candidates.sort_by_key(|&(_, ref stats)| stats.access_count),
+        }
+    }
+}
+
+/// Chooses victims from the candidates, in the order the policy would select them, until they add up
+/// to at least `required_space` bytes and `required_entries` files.
+/// If the candidates run out first, all of them are returned.
+///
+/// If the order of the policy is known, the candidates are ranked in a single pass.
+/// Otherwise the policy is asked to select one victim at a time.
+pub(crate) fn select_victims(
+    policy: &dyn EvictionPolicy,
+    order: Option<VictimOrder>,
+    mut candidates: HashMap<PathBuf, FileStats>,
+    required_space: usize,
+    required_entries: usize,
+) -> Vec<(PathBuf, FileStats)> {
+    let mut victims: Vec<(PathBuf, FileStats)> = Vec::new();
+    let mut freed_space: usize = 0;
+    let is_enough = |freed_space: usize, victims: usize| freed_space >= required_space && victims >= required_entries;
+
+    match order {
+        Some(order) => {
+            let mut ranked: Vec<(PathBuf, FileStats)> = candidates.into_iter().collect();
+            order.sort(&mut ranked);
+            for victim in ranked {
+                if is_enough(freed_space, victims.len()) {
+                    break;
+                }
+                freed_space += victim.1.size;
+                victims.push(victim);
+            }
+        }
+        None => {
+            while !is_enough(freed_space, victims.len()) {
+                match policy.select_victim(&candidates).and_then(|key| candidates.remove_entry(&key)) {
+                    Some(victim) => {
+                        freed_space += victim.1.size;
+                        victims.push(victim);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+    victims
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use super::*;
+    use self::test::Bencher;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    fn candidates(count: usize) -> HashMap<PathBuf, FileStats> {
+        let now = Instant::now();
+        (0..count)
+            .map(|i| {
+                let stats = FileStats {
+                    size: 1024,
+                    access_count: i,
+                    priority: i * 7 % count,
+                    last_accessed: now + Duration::from_millis(i as u64),
+                    modified: None,
+                };
+                (PathBuf::from(format!("www/{}.txt", i)), stats)
+            })
+            .collect()
+    }
+
+    /// Counts how often it is asked for a victim.
+    #[derive(Debug, Default)]
+    struct CountingPolicy {
+        selections: AtomicUsize,
+    }
+
+    impl EvictionPolicy for CountingPolicy {
+        fn select_victim(&self, entries: &HashMap<PathBuf, FileStats>) -> Option<PathBuf> {
+            self.selections.fetch_add(1, Ordering::Relaxed);
+            PriorityScorePolicy.select_victim(entries)
+        }
+    }
+
+    #[test]
+    fn ranked_selection_matches_selecting_one_at_a_time() {
+        let policy = CountingPolicy::default();
+        let one_at_a_time: Vec<(PathBuf, FileStats)> = select_victims(&policy, None, candidates(100), 1024 * 30, 0);
+        assert_eq!(policy.selections.load(Ordering::Relaxed), 30);
+
+        let ranked: Vec<(PathBuf, FileStats)> = select_victims(&policy, Some(VictimOrder::Priority), candidates(100), 1024 * 30, 0);
+        // The policy isn't asked again, the candidates are ranked once.
+        assert_eq!(policy.selections.load(Ordering::Relaxed), 30);
+        assert_eq!(ranked, one_at_a_time);
+
+        // Asking for more than there is returns every candidate.
+        assert_eq!(select_victims(&policy, Some(VictimOrder::AccessCount), candidates(10), 0, 20).len(), 10);
+        assert!(select_victims(&policy, Some(VictimOrder::LastAccessed), candidates(10), 0, 0).is_empty());
+    }
+
+    #[test]
+    fn built_in_policies_have_an_order() {
+        assert_eq!(VictimOrder::of::<PriorityScorePolicy>(), Some(VictimOrder::Priority));
+        assert_eq!(VictimOrder::of::<SizeWeightedLfuPolicy>(), Some(VictimOrder::Priority));
+        assert_eq!(VictimOrder::of::<LruPolicy>(), Some(VictimOrder::LastAccessed));
+        assert_eq!(VictimOrder::of::<LfuPolicy>(), Some(VictimOrder::AccessCount));
+        assert_eq!(VictimOrder::of::<CountingPolicy>(), None);
+    }
+
+    // Making room for a file that needs 500 of 2000 files to be evicted.
+    #[bench]
+    fn select_victims_for_large_file_ranked(b: &mut Bencher) {
+        let candidates: HashMap<PathBuf, FileStats> = candidates(2000);
+        b.iter(|| select_victims(&PriorityScorePolicy, Some(VictimOrder::Priority), candidates.clone(), 1024 * 500, 0));
+    }
+
+    #[bench]
+    fn select_victims_for_large_file_one_at_a_time(b: &mut Bencher) {
+        let candidates: HashMap<PathBuf, FileStats> = candidates(2000);
+        b.iter(|| select_victims(&PriorityScorePolicy, None, candidates.clone(), 1024 * 500, 0));
+    }
+
+    #[test]
+    fn size_weighted_score_prefers_small_warm_files() {
+        assert_eq!(SizeWeightedLfuPolicy::score(1, 1024 * 1024), 1);
+        assert_eq!(SizeWeightedLfuPolicy::score(3, 1024), 3 * 1024);
+        assert!(SizeWeightedLfuPolicy::score(10, 100 * 1024 * 1024) < SizeWeightedLfuPolicy::score(1, 1024));
+        assert_eq!(SizeWeightedLfuPolicy::score(1, 0), SIZE_WEIGHTED_SCALE);
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+
+/// Decides which files may be stored in the cache, based on their extensions.
+///
+/// A file is allowed if its extension isn't denied, and either there is no allowlist, or its
+/// extension is on the allowlist. An extension that is on both lists is denied.
+/// Extensions are compared without regard to case, and a leading `.` is ignored.
+/// Files without an extension are neither allowed nor denied by the lists, they are allowed or
+/// denied as configured on their own.
+#[derive(Debug, Clone)]
+pub(crate) struct ExtensionFilter {
+    allowed: Option<Vec<String>>,
+    denied: Vec<String>,
+    allow_extensionless: bool,
+}
+
+impl Default for ExtensionFilter {
+    fn default() -> ExtensionFilter {
+        ExtensionFilter {
+            allowed: None,
+            denied: Vec::new(),
+            allow_extensionless: true,
+        }
+    }
+}
+
+impl ExtensionFilter {
+    pub(crate) fn new<S: AsRef<str>>(allowed: Option<&[S]>, denied: &[S], allow_extensionless: bool) -> ExtensionFilter {
+        ExtensionFilter {
+            allowed: allowed.map(normalize),
+            denied: normalize(denied),
+            allow_extensionless,
+        }
+    }
+
+    /// Returns true if the file may be stored in the cache.
+    pub(crate) fn allows(&self, path: &Path) -> bool {
+        let extension: String = match path.extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => return self.allow_extensionless,
+        };
+        if self.denied.contains(&extension) {
+            return false;
+        }
+        match self.allowed {
+            Some(ref allowed) => allowed.contains(&extension),
+            None => true,
+        }
+    }
+}
+
+fn normalize<S: AsRef<str>>(extensions: &[S]) -> Vec<String> {
+    extensions.iter()
+        .map(|extension| extension.as_ref().trim().trim_start_matches('.').to_lowercase())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everything_is_allowed_by_default() {
+        let filter = ExtensionFilter::default();
+        assert!(filter.allows(Path::new("www/index.php")));
+        assert!(filter.allows(Path::new("www/LICENSE")));
+    }
+
+    #[test]
+    fn denylist_takes_precedence_over_allowlist() {
+        let filter = ExtensionFilter::new(Some(&["js", ".CSS", "exe"][..]), &["exe"][..], false);
+        assert!(filter.allows(Path::new("www/app.js")));
+        assert!(filter.allows(Path::new("www/site.css")));
+        assert!(!filter.allows(Path::new("www/uploads/setup.exe")));
+        assert!(!filter.allows(Path::new("www/index.php")));
+        assert!(!filter.allows(Path::new("www/LICENSE")));
+
+        let filter = ExtensionFilter::new(None, &["php"][..], true);
+        assert!(!filter.allows(Path::new("www/INDEX.PHP")));
+        assert!(filter.allows(Path::new("www/app.js")));
+        assert!(filter.allows(Path::new("www/LICENSE")));
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rocket::{Build, Request, Response, Rocket};
+use rocket::fairing::{self, Fairing, Info, Kind};
+
+use crate::cache::Cache;
+use crate::cache_stats::CacheStats;
+use crate::preload::PreloadSummary;
+use crate::lock;
+
+
+/// A fairing that manages a `Cache` as state of the Rocket instance it is attached to, so handlers
+/// can take it as a `&State<Cache>`.
+///
+/// When Rocket ignites, the files in the preload list are read into the cache.
+/// If an interval is set with `log_stats_every()`, the cache's stats are logged at most once per
+/// interval, when a response is sent.
+///
+/// # Example
+///
+/// ```
+/// use rocket_file_cache::{Cache, CacheBuilder, CacheFairing};
+/// use std::path::PathBuf;
+/// use std::time::Duration;
+///
+/// let cache: Cache = CacheBuilder::new().build().unwrap();
+/// let rocket = rocket::build()
+///     .attach(CacheFairing::new(cache)
+///         .preload(vec![PathBuf::from("www/index.html")])
+///         .log_stats_every(Duration::from_secs(60)));
+/// ```
+pub struct CacheFairing {
+    /// The cache, until it is handed over to Rocket.
+    cache: Mutex<Option<Cache>>,
+    preload: Vec<PathBuf>,
+    log_interval: Option<Duration>,
+    last_logged: Mutex<Instant>,
+}
+
+impl CacheFairing {
+    /// Creates a fairing that will manage the cache.
+    pub fn new(cache: Cache) -> CacheFairing {
+        CacheFairing {
+            cache: Mutex::new(Some(cache)),
+            preload: Vec::new(),
+            log_interval: None,
+            last_logged: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Sets the files that are read into the cache when Rocket ignites.
+    pub fn preload<I: IntoIterator<Item = PathBuf>>(mut self, paths: I) -> CacheFairing {
+        self.preload = paths.into_iter().collect();
+        self
+    }
+
+    /// Logs the cache's stats at most once per interval.
+    ///
+    /// The stats are logged while responding to requests, so nothing is logged while the server is idle.
+    pub fn log_stats_every(mut self, interval: Duration) -> CacheFairing {
+        self.log_interval = Some(interval);
+        self
+    }
+
+    /// Returns true if the stats haven't been logged within the interval, and marks them as logged.
+    fn should_log(&self, interval: Duration) -> bool {
+        let mut last_logged = lock::lock(&self.last_logged);
+        if last_logged.elapsed() < interval {
+            return false;
+        }
+        *last_logged = Instant::now();
+        true
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for CacheFairing {
+    fn info(&self) -> Info {
+        let kind: Kind = match self.log_interval {
+            Some(_) => Kind::Ignite | Kind::Response,
+            None => Kind::Ignite,
+        };
+        Info {
+            name: "File Cache",
+            kind,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let cache: Cache = lock::lock(&self.cache).take().expect("The cache fairing should only ignite once.");
+        let rocket: Rocket<Build> = rocket.manage(cache);
+
+        if !self.preload.is_empty() {
+            if let Some(cache) = rocket.state::<Cache>() {
+                let summary: PreloadSummary = cache.preload(self.preload.clone()).await;
+                info!(
+                    "Preloaded {} files into the cache, {} were skipped, and {} were rejected.",
+                    summary.loaded.len(),
+                    summary.skipped.len(),
+                    summary.rejected.len()
+                );
+                for (path, e) in summary.failed {
+                    warn!("Could not preload {:?}: {}", path, e);
+                }
+            }
+        }
+        Ok(rocket)
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, _response: &mut Response<'r>) {
+        let interval: Duration = match self.log_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        if !self.should_log(interval) {
+            return;
+        }
+        if let Some(cache) = request.rocket().state::<Cache>() {
+            let stats: CacheStats = cache.stats();
+            info!(
+                "File cache: {} hits, {} misses ({:.1}% hit ratio), {} not found, {} evictions, {} files using {} bytes.",
+                stats.hits,
+                stats.misses,
+                stats.hit_ratio() * 100.0,
+                stats.not_found,
+                stats.evictions,
+                stats.entries,
+                stats.bytes
+            );
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use rocket::local::asynchronous::Client;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn manages_and_preloads_cache() {
+        let temp_dir = TempDir::new("fairing").unwrap();
+        let path: PathBuf = temp_dir.path().join("index.html");
+        std::fs::write(&path, b"<html></html>").unwrap();
+
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        let rocket = rocket::build()
+            .attach(CacheFairing::new(cache)
+                .preload(vec![path.clone()])
+                .log_stats_every(Duration::from_secs(0)));
+        let client = Client::untracked(rocket).await.unwrap();
+
+        let cache: &Cache = client.rocket().state::<Cache>().unwrap();
+        assert!(cache.contains_key(&path));
+        // Responding logs the stats, which must not fail without a route.
+        client.get("/").dispatch().await;
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+
+use crate::cache::Cache;
+use crate::cached_file::CachedFile;
+
+
+/// A file that is served in place of files that could not be found.
+#[derive(Debug, Clone)]
+pub(crate) struct Fallback {
+    path: PathBuf,
+    /// Decides if a path that could not be found should be answered with the fallback file.
+    predicate: fn(&Path) -> bool,
+}
+
+/// Paths without an extension are assumed to be routes handled by the client, rather than requests for files.
+fn has_no_extension(path: &Path) -> bool {
+    path.extension().is_none()
+}
+
+
+impl Cache {
+    /// Serves the file at `path` whenever `get()` can't find the requested file, and the requested
+    /// path doesn't have an extension.
+    ///
+    /// This is intended for single page applications, where routing is handled by the client, so
+    /// requests for `/about` should be answered with `index.html`.
+    /// Requests for missing files like `/js/missing.js` will still result in `CachedFile::NotFound`.
+    ///
+    /// The fallback file is added to, and served from, the cache like any other file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let mut cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.fallback("www/index.html");
+    /// ```
+    pub fn fallback<P: AsRef<Path>>(&mut self, path: P) {
+        self.fallback_with_predicate(path, has_no_extension);
+    }
+
+    /// Serves the file at `path` whenever `get()` can't find the requested file, and the `predicate`
+    /// returns true for the requested path.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::path::Path;
+    ///
+    /// fn is_app_route(path: &Path) -> bool {
+    ///     path.starts_with("www/app/") && path.extension().is_none()
+    /// }
+    ///
+    /// let mut cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.fallback_with_predicate("www/app/index.html", is_app_route);
+    /// ```
+    pub fn fallback_with_predicate<P: AsRef<Path>>(&mut self, path: P, predicate: fn(&Path) -> bool) {
+        self.fallback = Some(Fallback {
+            path: self.normalize_key(path),
+            predicate,
+        });
+    }
+
+    /// Gets the fallback file, if one should be served in place of the file at the path.
+    pub(crate) async fn get_fallback(&self, path: &Path) -> Option<CachedFile> {
+        let fallback: &Fallback = self.fallback.as_ref()?;
+        // Don't try to serve the fallback file in place of itself.
+        if path == fallback.path.as_path() || !(fallback.predicate)(path) {
+            return None;
+        }
+        debug!("File: {:?} could not be found, serving {:?} instead.", path, fallback.path);
+        Some(self.get_file(fallback.path.clone()).await)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn serves_fallback_for_routes() {
+        let temp_dir = TempDir::new("fallback").unwrap();
+        let index_path: PathBuf = temp_dir.path().join("index.html");
+        std::fs::write(&index_path, b"<html></html>").unwrap();
+
+        let mut cache: Cache = CacheBuilder::new().build().unwrap();
+        cache.fallback(&index_path);
+
+        match cache.get(temp_dir.path().join("about")).await {
+            CachedFile::InMemory(file) => assert_eq!(file.path, index_path),
+            other => panic!("Expected the fallback file, got {:?}", other),
+        }
+        assert_eq!(cache.get(temp_dir.path().join("missing.js")).await, CachedFile::NotFound);
+        assert!(cache.contains_key(&index_path));
+    }
+}
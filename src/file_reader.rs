@@ -0,0 +1,75 @@
+use std::io;
+use std::path::Path;
+
+use bytes::Bytes;
+
+use crate::encoding::Encoding;
+use crate::file_source::SharedFileSource;
+use crate::hash_algorithm::HashAlgorithm;
+use crate::etag_mode::EtagMode;
+use crate::in_memory_file::InMemoryFile;
+use crate::precompress_filter::PrecompressFilter;
+
+
+/// The configuration of the cache that determines how files are read into memory.
+/// It is kept apart from the cache, so files can be read by tasks that don't have access to the cache.
+#[derive(Debug, Clone)]
+pub(crate) struct FileReader {
+    pub(crate) hash_algorithm: HashAlgorithm,
+    pub(crate) etag_mode: EtagMode,
+    pub(crate) precompress: Vec<Encoding>,
+    pub(crate) encoding_preference: Vec<Encoding>,
+    pub(crate) min_precompress_size: usize,
+    pub(crate) precompress_filter: PrecompressFilter,
+    pub(crate) precompressed_siblings: bool,
+    pub(crate) read_buffer_size: Option<usize>,
+    /// Where the contents of files are read from, if not from the filesystem.
+    pub(crate) source: Option<SharedFileSource>,
+    /// The generation of the cache when the reader was created, which the files it reads are tagged with.
+    pub(crate) generation: usize,
+    #[cfg(feature = "mmap")]
+    pub(crate) mmap_threshold: Option<usize>,
+}
+
+impl FileReader {
+    /// Reads the file at the path into memory.
+    pub(crate) async fn read(&self, path: &Path) -> io::Result<InMemoryFile> {
+        if let Some(ref source) = self.source {
+            let (bytes, stats) = source.0.read(path).await?;
+            let file: InMemoryFile = InMemoryFile::from_bytes_with_etag_mode(Bytes::from(bytes), stats.modified, self.hash_algorithm, self.etag_mode);
+            return Ok(self.prepare(path, file));
+        }
+        #[cfg(feature = "mmap")]
+        {
+            if let Some(mmap_threshold) = self.mmap_threshold {
+                let size: usize = std::fs::metadata(path).map(|metadata| metadata.len() as usize).unwrap_or(0);
+                if size >= mmap_threshold {
+                    // Compressing the file would copy it into memory, which mapping it is meant to avoid.
+                    let mut file: InMemoryFile = InMemoryFile::open_mapped(path, self.hash_algorithm, self.etag_mode).await?;
+                    if self.precompressed_siblings {
+                        file.add_precompressed_siblings(path).await;
+                    }
+                    file.order_encodings(&self.encoding_preference);
+                    file.generation = self.generation;
+                    return Ok(file);
+                }
+            }
+        }
+        let mut file: InMemoryFile = InMemoryFile::open_with_buffer_size(path, self.hash_algorithm, self.etag_mode, self.read_buffer_size).await?;
+        if self.precompressed_siblings {
+            file.add_precompressed_siblings(path).await;
+        }
+        Ok(self.prepare(path, file))
+    }
+
+    /// Compresses the file, if it is large enough to be compressed and isn't already compressed,
+    /// orders its compressed copies by preference, and tags it with the generation.
+    pub(crate) fn prepare(&self, path: &Path, mut file: InMemoryFile) -> InMemoryFile {
+        if file.stats.size >= self.min_precompress_size && self.precompress_filter.allows(path, file.bytes()) {
+            file.precompress(&self.precompress);
+        }
+        file.order_encodings(&self.encoding_preference);
+        file.generation = self.generation;
+        file
+    }
+}
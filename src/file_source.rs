@@ -0,0 +1,238 @@
+use std::fmt::{self, Debug, Formatter};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use rocket::futures::future::BoxFuture;
+
+use crate::in_memory_file::{ensure_regular_file, FileStats};
+
+
+/// Where the contents of files are read from when they are read into the cache.
+///
+/// The cache reads files from the filesystem by default. A different source can be set with
+/// `CacheBuilder::file_source()`, for example to give tests control over the contents and
+/// modification times of files, without writing them to disk.
+///
+/// Only the files that are read into the cache come from the source. Files that are served from
+/// the filesystem, because they don't fit in the cache, are still opened from the filesystem.
+pub trait FileSource: Send + Sync {
+    /// Reads the contents of the file, along with its stats.
+    /// Only the `size` and `modified` fields of the stats are used.
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<(Vec<u8>, FileStats)>>;
+
+    /// The stats of the file, without its contents.
+    /// The size decides whether the file is read into the cache at all, and the size and modification time
+    /// tell whether a cached file has changed since it was read.
+    ///
+    /// By default, this reads the file. Sources that can find out the size of a file without
+    /// reading it should override this.
+    fn stats<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileStats>> {
+        Box::pin(async move {
+            self.read(path).await.map(|(_, stats)| stats)
+        })
+    }
+}
+
+/// Reads files from the filesystem, like the cache does when no other source is set.
+///
+/// This can be wrapped by sources that only want to change how some files are read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsSource;
+
+impl FileSource for FsSource {
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<(Vec<u8>, FileStats)>> {
+        Box::pin(async move {
+            let metadata = tokio::fs::metadata(path).await?;
+            ensure_regular_file(&metadata)?;
+            let bytes: Vec<u8> = tokio::fs::read(path).await?;
+            let stats = FileStats::new(bytes.len(), metadata.modified().ok());
+            Ok((bytes, stats))
+        })
+    }
+
+    fn stats<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileStats>> {
+        Box::pin(async move {
+            let metadata = tokio::fs::metadata(path).await?;
+            ensure_regular_file(&metadata)?;
+            Ok(FileStats::new(metadata.len() as usize, metadata.modified().ok()))
+        })
+    }
+}
+
+
+/// The file source of a cache, which can be shared with the tasks that read files for it.
+#[derive(Clone)]
+pub(crate) struct SharedFileSource(pub(crate) Arc<dyn FileSource>);
+
+impl Debug for SharedFileSource {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "FileSource")
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use crate::cache::Cache;
+    use crate::cache_builder::CacheBuilder;
+    use crate::cache_error::CacheError;
+    use crate::cached_file::CachedFile;
+
+    /// A file source that holds its files in memory, so tests control their contents and modification times.
+    #[derive(Clone, Default)]
+    pub(crate) struct FakeSource {
+        files: Arc<Mutex<HashMap<PathBuf, (Vec<u8>, SystemTime)>>>,
+    }
+
+    impl FakeSource {
+        pub(crate) fn write<P: AsRef<Path>>(&self, path: P, bytes: &[u8], modified_secs: u64) {
+            let modified: SystemTime = UNIX_EPOCH + Duration::from_secs(modified_secs);
+            self.files.lock().unwrap().insert(path.as_ref().to_path_buf(), (bytes.to_vec(), modified));
+        }
+    }
+
+    impl FileSource for FakeSource {
+        fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<(Vec<u8>, FileStats)>> {
+            let file = self.files.lock().unwrap().get(path).cloned();
+            Box::pin(async move {
+                match file {
+                    Some((bytes, modified)) => {
+                        let stats = FileStats::new(bytes.len(), Some(modified));
+                        Ok((bytes, stats))
+                    }
+                    None => Err(io::Error::new(io::ErrorKind::NotFound, "no such file")),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn files_are_read_from_the_source() {
+        let source = FakeSource::default();
+        source.write("www/a.html", &[b'a'; 600], 1);
+        source.write("www/b.html", &[b'b'; 600], 2);
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1000)
+            .file_source(source.clone())
+            .build()
+            .unwrap();
+
+        let cached_file: CachedFile = cache.get("www/a.html").await;
+        assert_eq!(cached_file.as_bytes(), Some(&[b'a'; 600][..]));
+        assert_eq!(cache.get_stats("www/a.html").unwrap().modified, Some(UNIX_EPOCH + Duration::from_secs(1)));
+        assert_eq!(cache.get("www/missing.html").await, CachedFile::NotFound);
+
+        // The second file only fits once the first has been evicted.
+        cache.get("www/b.html").await;
+        cache.get("www/b.html").await;
+        assert!(cache.get("www/b.html").await.is_in_memory());
+        assert!(!cache.contains_key("www/a.html"));
+
+        source.write("www/b.html", b"changed", 3);
+        cache.remove("www/b.html");
+        assert_eq!(cache.get("www/b.html").await.as_bytes(), Some(&b"changed"[..]));
+    }
+
+    #[tokio::test]
+    async fn changes_are_read_from_the_source() {
+        let source = FakeSource::default();
+        source.write("www/a.html", b"first", 1);
+        let cache: Cache = CacheBuilder::new()
+            .file_source(source.clone())
+            .build()
+            .unwrap();
+        assert_eq!(cache.get("www/a.html").await.as_bytes(), Some(&b"first"[..]));
+
+        source.write("www/a.html", b"second", 2);
+        assert_eq!(cache.refresh("www/a.html").await.as_bytes(), Some(&b"second"[..]));
+
+        source.write("www/a.html", b"third", 3);
+        cache.bump_generation();
+        assert_eq!(cache.get("www/a.html").await.as_bytes(), Some(&b"third"[..]));
+        assert_eq!(cache.get_stats("www/a.html").unwrap().modified, Some(UNIX_EPOCH + Duration::from_secs(3)));
+
+        let cache: Cache = CacheBuilder::new()
+            .file_source(source.clone())
+            .detect_changes(true)
+            .build()
+            .unwrap();
+        cache.get("www/a.html").await;
+        source.write("www/a.html", b"fourth", 4);
+        assert_eq!(cache.get("www/a.html").await.as_bytes(), Some(&b"fourth"[..]));
+    }
+
+    /// A file source whose stats are always a byte larger than the files it reads, as if every file grows while it is read.
+    struct GrowingSource(FakeSource);
+
+    impl FileSource for GrowingSource {
+        fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<(Vec<u8>, FileStats)>> {
+            self.0.read(path)
+        }
+
+        fn stats<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileStats>> {
+            Box::pin(async move {
+                let (bytes, stats) = self.0.read(path).await?;
+                Ok(FileStats::new(bytes.len() + 1, stats.modified))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn files_that_change_size_while_they_are_read_again_are_removed() {
+        let source = FakeSource::default();
+        source.write("www/a.html", b"contents", 1);
+        source.write("www/b.html", b"contents", 1);
+        let cache: Cache = CacheBuilder::new()
+            .file_source(GrowingSource(source))
+            .build()
+            .unwrap();
+        cache.get("www/a.html").await;
+        cache.get("www/b.html").await;
+        assert!(cache.contains_key("www/a.html"));
+
+        let error = cache.reload("www/a.html").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!cache.contains_key("www/a.html"));
+
+        // The file only exists in the source, so it can't be served from the filesystem either.
+        assert_eq!(cache.refresh("www/b.html").await, CachedFile::NotFound);
+        assert!(!cache.contains_key("www/b.html"));
+    }
+
+    /// A file source that finds its files, but fails to read them, like a disk that fails in the middle of a read.
+    struct FailingSource(FakeSource);
+
+    impl FileSource for FailingSource {
+        fn read<'a>(&'a self, _path: &'a Path) -> BoxFuture<'a, io::Result<(Vec<u8>, FileStats)>> {
+            Box::pin(async move {
+                Err(io::Error::new(io::ErrorKind::Other, "input/output error"))
+            })
+        }
+
+        fn stats<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, io::Result<FileStats>> {
+            self.0.stats(path)
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_while_reading_are_reported_by_try_get() {
+        let source = FakeSource::default();
+        source.write("www/a.html", b"contents", 1);
+        let cache: Cache = CacheBuilder::new()
+            .file_source(FailingSource(source))
+            .build()
+            .unwrap();
+
+        match cache.try_get("www/a.html").await {
+            Err(CacheError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::Other),
+            other => panic!("Expected an IO error, got {:?}", other),
+        }
+        assert!(!cache.contains_key("www/a.html"));
+    }
+}
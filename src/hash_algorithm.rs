@@ -0,0 +1,85 @@
+/// The algorithm used to hash the contents of files in the cache.
+///
+/// The hash is used to generate the `ETag` header for files served from the cache.
+/// Both algorithms are fast, non-cryptographic hashes, so large files don't pay a high cost when
+/// they are read into the cache.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    /// The 64 bit FNV-1a hash.
+    ///
+    /// This is the default.
+    Fnv1a,
+    /// The CRC-32 (IEEE) checksum.
+    Crc32,
+}
+
+impl Default for HashAlgorithm {
+    fn default() -> Self {
+        HashAlgorithm::Fnv1a
+    }
+}
+
+impl HashAlgorithm {
+    /// Hashes the bytes.
+    pub fn hash(&self, bytes: &[u8]) -> u64 {
+        match *self {
+            HashAlgorithm::Fnv1a => fnv1a(bytes),
+            HashAlgorithm::Crc32 => crc32(bytes) as u64,
+        }
+    }
+
+    /// Creates a strong entity tag from the hash of the bytes.
+    pub fn etag(&self, bytes: &[u8]) -> String {
+        self.format_etag(self.hash(bytes))
+    }
+
+    /// Creates a strong entity tag from a hash that was created by this algorithm.
+    pub(crate) fn format_etag(&self, hash: u64) -> String {
+        match *self {
+            HashAlgorithm::Fnv1a => format!("\"{:016x}\"", hash),
+            HashAlgorithm::Crc32 => format!("\"{:08x}\"", hash),
+        }
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    const POLYNOMIAL: u32 = 0xedb8_8320;
+
+    let mut crc: u32 = 0xffff_ffff;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            // The polynomial is only applied if the lowest bit is set.
+            let mask: u32 = 0u32.wrapping_sub(crc & 1);
+            crc = (crc >> 1) ^ (POLYNOMIAL & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_hash() {
+        assert_eq!(HashAlgorithm::Fnv1a.hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(HashAlgorithm::Fnv1a.hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn crc32_hash() {
+        assert_eq!(HashAlgorithm::Crc32.hash(b""), 0);
+        assert_eq!(HashAlgorithm::Crc32.hash(b"123456789"), 0xcbf4_3926);
+        assert_eq!(HashAlgorithm::Crc32.etag(b"123456789"), "\"cbf43926\"");
+    }
+}
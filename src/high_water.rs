@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cache::Cache;
+use crate::cache_stats::CacheStats;
+
+
+/// How far the pressure must drop below the threshold before the callback can be called again,
+/// so evicting a few files to make room for another doesn't set it off on every insert.
+const REARM_MARGIN: f64 = 0.05;
+
+
+/// Calls a callback when the cache fills up beyond a fraction of its capacity.
+pub(crate) struct HighWater {
+    fraction: f64,
+    callback: Box<dyn Fn(&CacheStats) + Send + Sync>,
+    /// Set once the callback has been called, until the pressure drops far enough below the threshold.
+    above: AtomicBool,
+}
+
+impl HighWater {
+    pub(crate) fn new(fraction: f64, callback: Box<dyn Fn(&CacheStats) + Send + Sync>) -> HighWater {
+        HighWater {
+            fraction,
+            callback,
+            above: AtomicBool::new(false),
+        }
+    }
+}
+
+
+impl Cache {
+    /// Calls the high water callback if the pressure has crossed the threshold since it was last called.
+    pub(crate) fn check_high_water(&self) {
+        let high_water: &HighWater = match self.high_water {
+            Some(ref high_water) => high_water,
+            None => return,
+        };
+        let pressure: f64 = self.pressure();
+        if pressure >= high_water.fraction {
+            // Only the call that sets the flag calls the callback.
+            if !high_water.above.swap(true, Ordering::Relaxed) {
+                (high_water.callback)(&self.stats());
+            }
+        } else if pressure < high_water.fraction - REARM_MARGIN {
+            high_water.above.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Allows the high water callback to be called again, if files were removed and the pressure has dropped far enough.
+    pub(crate) fn rearm_high_water(&self) {
+        if let Some(ref high_water) = self.high_water {
+            if high_water.above.load(Ordering::Relaxed) && self.pressure() < high_water.fraction - REARM_MARGIN {
+                high_water.above.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn callback_is_called_once_per_crossing() {
+        let mut cache: Cache = CacheBuilder::new().size_limit(1000).build().unwrap();
+        let calls: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let counted: Arc<AtomicUsize> = calls.clone();
+        cache.on_high_water(0.9, Box::new(move |stats| {
+            assert!(stats.bytes >= 900);
+            counted.fetch_add(1, Ordering::Relaxed);
+        }));
+
+        cache.insert_bytes("www/a.bin", vec![0; 500]).await;
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+        cache.insert_bytes("www/b.bin", vec![0; 450]).await;
+        assert_eq!(cache.pressure(), 0.95);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        // The cache stays above the threshold.
+        cache.insert_bytes("www/c.bin", vec![0; 10]).await;
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        cache.remove("www/b.bin");
+        cache.insert_bytes("www/b.bin", vec![0; 450]).await;
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+}
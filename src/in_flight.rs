@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::lock;
+
+
+/// Keeps track of the files that are currently being loaded from the filesystem, so concurrent
+/// misses for the same file wait for a single read instead of each reading the file themselves.
+#[derive(Debug, Default)]
+pub(crate) struct InFlight {
+    loads: Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>,
+}
+
+/// Grants exclusive permission to load a file.
+/// The next request waiting to load the same file is allowed to continue once this is dropped.
+pub(crate) struct LoadGuard<'a> {
+    in_flight: &'a InFlight,
+    path: PathBuf,
+    lock: Arc<AsyncMutex<()>>,
+    held: Option<OwnedMutexGuard<()>>,
+}
+
+impl InFlight {
+    /// Waits until no other request is loading the file at the path.
+    ///
+    /// If the returned future is dropped before it completes, the next waiting request is unaffected.
+    pub(crate) async fn acquire(&self, path: &PathBuf) -> LoadGuard<'_> {
+        let lock: Arc<AsyncMutex<()>> = self.loads()
+            .entry(path.clone())
+            .or_insert_with(Default::default)
+            .clone();
+        let mut guard = LoadGuard {
+            in_flight: self,
+            path: path.clone(),
+            lock,
+            held: None,
+        };
+        guard.held = Some(guard.lock.clone().lock_owned().await);
+        guard
+    }
+
+    fn loads(&self) -> MutexGuard<HashMap<PathBuf, Arc<AsyncMutex<()>>>> {
+        lock::lock(&self.loads)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.loads().is_empty()
+    }
+}
+
+impl<'a> Drop for LoadGuard<'a> {
+    fn drop(&mut self) {
+        self.held = None;
+        let mut loads = self.in_flight.loads();
+        // Every request waiting to load the file holds a reference to the lock, besides this guard and the map.
+        // If there are none, the entry is no longer needed.
+        if Arc::strong_count(&self.lock) == 2 {
+            loads.remove(&self.path);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn second_load_waits_for_first() {
+        let in_flight = InFlight::default();
+        let path_a = PathBuf::from("a.txt");
+        let path_b = PathBuf::from("b.txt");
+
+        let guard = in_flight.acquire(&path_a).await;
+        // Loading a different file isn't held up.
+        drop(in_flight.acquire(&path_b).await);
+        // The second load gives up waiting, which must not prevent the file from being loaded later.
+        assert!(timeout(Duration::from_millis(10), in_flight.acquire(&path_a)).await.is_err());
+
+        drop(guard);
+        drop(in_flight.acquire(&path_a).await);
+        assert!(in_flight.is_empty());
+    }
+}
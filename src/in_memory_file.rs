@@ -4,7 +4,13 @@ use tokio::io::BufReader;
 use tokio::fs::File;
 use std::fmt;
 use std::io;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncBufRead, AsyncReadExt};
+use std::time::{Instant, SystemTime};
+use crate::hash_algorithm::HashAlgorithm;
+use crate::etag_mode::EtagMode;
+use crate::encoding::{self, Encoding};
+use crate::priority_function::Priority;
+use bytes::Bytes;
 
 
 /// The structure that represents a file in memory.
@@ -12,8 +18,33 @@ use tokio::io::AsyncReadExt;
 /// from the cache.
 #[derive(Clone, PartialEq)]
 pub struct InMemoryFile {
-    pub(crate) bytes: Vec<u8>,
+    /// The contents of the file.
+    /// Cloning these is cheap, so responses can hold on to the contents without holding a lock on the cache.
+    pub(crate) bytes: Bytes,
     pub stats: FileStats,
+    /// The point in time after which the file should no longer be served from the cache.
+    /// If this is `None`, the file does not expire.
+    pub expires_at: Option<Instant>,
+    /// The entity tag, which is strong if it was generated from the hash of the file's bytes,
+    /// and weak if it was generated from the file's size and modification time.
+    pub(crate) etag: String,
+    /// The hash of the file's bytes when it was read, which is compared to detect corruption.
+    /// This is `None` if the file wasn't hashed, because it has a weak entity tag.
+    pub(crate) checksum: Option<u64>,
+    /// The algorithm that created the checksum.
+    pub(crate) hash_algorithm: HashAlgorithm,
+    /// Compressed copies of the file's bytes.
+    pub(crate) encoded: Vec<(Encoding, Bytes)>,
+    /// True if the bytes are memory mapped from the file, instead of having been read into memory.
+    pub(crate) mapped: bool,
+    /// The generation of the cache the file was read under.
+    pub(crate) generation: usize,
+}
+
+impl From<Vec<u8>> for InMemoryFile {
+    fn from(bytes: Vec<u8>) -> InMemoryFile {
+        InMemoryFile::from_bytes(bytes)
+    }
 }
 
 impl fmt::Debug for InMemoryFile {
@@ -32,25 +63,275 @@ impl fmt::Debug for InMemoryFile {
 impl InMemoryFile {
     /// Reads the file at the path into an InMemoryFile.
     pub async fn open<P: AsRef<Path>>(path: P) -> io::Result<InMemoryFile> {
+        InMemoryFile::open_with_hash(path, HashAlgorithm::default()).await
+    }
+
+    /// Reads the file at the path into an InMemoryFile, using the provided algorithm to generate its ETag.
+    pub async fn open_with_hash<P: AsRef<Path>>(path: P, hash_algorithm: HashAlgorithm) -> io::Result<InMemoryFile> {
+        InMemoryFile::open_with_buffer_size(path, hash_algorithm, EtagMode::Strong, None).await
+    }
+
+    /// Reads the file at the path into an InMemoryFile, reading it in chunks of `buffer_size` bytes.
+    ///
+    /// The contents are read into a buffer that is allocated up front with the size of the file, as
+    /// reported by its metadata, so large files aren't copied as the buffer grows.
+    /// If no buffer size is provided, it is chosen relative to the size of the file.
+    ///
+    /// Only regular files are read. Reading a FIFO, socket, or device could block forever or never end,
+    /// so an error of the kind `InvalidInput` is returned for them, and for directories.
+    pub(crate) async fn open_with_buffer_size<P: AsRef<Path>>(
+        path: P,
+        hash_algorithm: HashAlgorithm,
+        etag_mode: EtagMode,
+        buffer_size: Option<usize>,
+    ) -> io::Result<InMemoryFile> {
+        // Opening a FIFO blocks until it is opened for writing, so the type is checked before opening it.
+        ensure_regular_file(&tokio::fs::metadata(path.as_ref()).await?)?;
         let file = File::open(path.as_ref()).await?;
-        let mut reader = BufReader::new(file);
-        let mut bytes: Vec<u8> = vec![];
-        let size: usize = reader.read_to_end(&mut bytes).await?;
+        let metadata = file.metadata().await;
+        // Files like those in /proc report a size of 0, so the buffer is left to grow as they are read.
+        let size_hint: usize = metadata.as_ref().map(|metadata| metadata.len() as usize).unwrap_or(0);
+        // Not every platform records when a file was modified.
+        let modified: Option<SystemTime> = metadata
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        let buffer_size: usize = buffer_size.unwrap_or_else(|| default_buffer_size(size_hint));
+        let mut reader = BufReader::with_capacity(buffer_size, file);
+        let mut bytes: Vec<u8> = Vec::new();
+        // The file could have grown since its metadata was read, in which case the buffer still grows as needed.
+        bytes.try_reserve_exact(size_hint).map_err(|e| io::Error::new(io::ErrorKind::OutOfMemory, e))?;
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(InMemoryFile::from_bytes_with_etag_mode(Bytes::from(bytes), modified, hash_algorithm, etag_mode))
+    }
+
+    /// Creates an InMemoryFile from contents that are already in memory, without a file in the filesystem.
+    ///
+    /// The size is the length of the contents, the access count and priority are 0, and there is no
+    /// modification time.
+    pub fn from_bytes(bytes: Vec<u8>) -> InMemoryFile {
+        InMemoryFile::from_bytes_with_hash(Bytes::from(bytes), HashAlgorithm::default())
+    }
 
+    /// Creates an InMemoryFile from contents that are already in memory, using the provided algorithm to generate its ETag.
+    pub(crate) fn from_bytes_with_hash(bytes: Bytes, hash_algorithm: HashAlgorithm) -> InMemoryFile {
         let stats = FileStats {
-            size,
+            size: bytes.len(),
             access_count: 0,
             priority: 0,
+            last_accessed: Instant::now(),
+            modified: None,
         };
 
-        Ok(InMemoryFile { bytes, stats })
+        // The checksum doubles as the ETag, so the file is only hashed once.
+        let checksum: u64 = hash_algorithm.hash(&bytes);
+        let etag: String = hash_algorithm.format_etag(checksum);
+
+        InMemoryFile {
+            bytes,
+            stats,
+            expires_at: None,
+            etag,
+            checksum: Some(checksum),
+            hash_algorithm,
+            encoded: Vec::new(),
+            mapped: false,
+            generation: 0,
+        }
+    }
+
+    /// Memory maps the file at the path, using the provided algorithm to generate its ETag.
+    ///
+    /// The contents of the file aren't copied into memory, they are paged in from the filesystem by
+    /// the operating system when they are needed.
+    /// If the file is modified while it is mapped, the changes may be visible in the mapped contents,
+    /// and if it is truncated, reading the truncated part will crash the process.
+    /// Files should therefore only be mapped if they are replaced, rather than modified in place.
+    #[cfg(feature = "mmap")]
+    pub(crate) async fn open_mapped<P: AsRef<Path>>(path: P, hash_algorithm: HashAlgorithm, etag_mode: EtagMode) -> io::Result<InMemoryFile> {
+        let path: std::path::PathBuf = path.as_ref().to_path_buf();
+        let map_file = move || -> io::Result<InMemoryFile> {
+            ensure_regular_file(&std::fs::metadata(&path)?)?;
+            let file: std::fs::File = std::fs::File::open(&path)?;
+            let modified: Option<SystemTime> = file.metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            // Safety: The risks of the file being modified while it is mapped are documented above.
+            let mmap: memmap2::Mmap = unsafe { memmap2::Mmap::map(&file)? };
+
+            let mut file: InMemoryFile = InMemoryFile::from_bytes_with_etag_mode(Bytes::from_owner(mmap), modified, hash_algorithm, etag_mode);
+            file.mapped = true;
+            Ok(file)
+        };
+        // Hashing the file reads it from the filesystem, which shouldn't block the runtime.
+        match tokio::task::spawn_blocking(map_file).await {
+            Ok(result) => result,
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+        }
+    }
+
+    /// Returns true if the file's contents are memory mapped.
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    /// The contents of the file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// A reader over the contents of the file, for passing them to anything that reads asynchronously.
+    /// The contents are shared with the file rather than copied, and the reader keeps track of how
+    /// much of them has been read.
+    pub fn reader(&self) -> impl AsyncRead + AsyncBufRead + Unpin + Send + Sync + 'static {
+        io::Cursor::new(self.bytes.clone())
+    }
+
+    /// The entity tag that identifies the contents of the file.
+    pub fn etag(&self) -> &str {
+        &self.etag
+    }
+
+    /// Reads the compressed copies of the file that exist next to it in the filesystem, like `app.js.br`
+    /// and `app.js.gz` for `app.js`, keeping them alongside the file.
+    pub(crate) async fn add_precompressed_siblings<P: AsRef<Path>>(&mut self, path: P) {
+        for &encoding in encoding::ALL {
+            let mut sibling_path = path.as_ref().as_os_str().to_os_string();
+            sibling_path.push(".");
+            sibling_path.push(encoding.extension());
+            match tokio::fs::read(&sibling_path).await {
+                Ok(compressed) => self.encoded.push((encoding, Bytes::from(compressed))),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Could not read {:?}: {}", sibling_path, e),
+            }
+        }
+    }
+
+    /// Compresses the file with each of the encodings, keeping the compressed copies alongside the file.
+    /// Copies that don't end up smaller than the file itself are discarded, and encodings that the
+    /// file already has a copy of are skipped.
+    pub(crate) fn precompress(&mut self, encodings: &[Encoding]) {
+        for encoding in encodings {
+            if self.encoded.iter().any(|&(e, _)| e == *encoding) {
+                continue;
+            }
+            match encoding.compress(&self.bytes) {
+                Ok(compressed) => {
+                    if compressed.len() < self.bytes.len() {
+                        self.encoded.push((*encoding, Bytes::from(compressed)));
+                    }
+                }
+                Err(e) => warn!("Could not compress file with {}: {}", encoding.name(), e),
+            }
+        }
+    }
+
+    /// Orders the compressed copies by the position of their encodings in `preference`, so the most
+    /// preferred encoding is chosen when the client accepts several of them equally.
+    /// Encodings that aren't in the list keep their order, after the ones that are.
+    pub(crate) fn order_encodings(&mut self, preference: &[Encoding]) {
+        if preference.is_empty() {
+            return;
+        }
+        self.encoded.sort_by_key(|&(encoding, _)| {
+            preference.iter().position(|&preferred| preferred == encoding).unwrap_or(preference.len())
+        });
+    }
+
+    /// The encodings that the file has compressed copies of.
+    pub(crate) fn encodings<'a>(&'a self) -> impl Iterator<Item = Encoding> + 'a {
+        self.encoded.iter().map(|&(encoding, _)| encoding)
+    }
+
+    /// The bytes of the file in the given encoding, or the raw bytes if no encoding is provided.
+    pub(crate) fn encoded_bytes(&self, encoding: Option<Encoding>) -> &Bytes {
+        encoding
+            .and_then(|encoding| self.encoded.iter().find(|&&(e, _)| e == encoding))
+            .map(|&(_, ref bytes)| bytes)
+            .unwrap_or(&self.bytes)
+    }
+
+    /// Creates an InMemoryFile from the contents of a file that was modified at the given time,
+    /// with an entity tag that is generated according to the mode.
+    /// If a weak entity tag can't be generated, because the modification time isn't known, the file is hashed instead.
+    pub(crate) fn from_bytes_with_etag_mode(bytes: Bytes, modified: Option<SystemTime>, hash_algorithm: HashAlgorithm, etag_mode: EtagMode) -> InMemoryFile {
+        let mut file: InMemoryFile = match etag_mode.weak_etag(bytes.len(), modified) {
+            Some(etag) => InMemoryFile {
+                stats: FileStats {
+                    size: bytes.len(),
+                    access_count: 0,
+                    priority: 0,
+                    last_accessed: Instant::now(),
+                    modified: None,
+                },
+                bytes,
+                expires_at: None,
+                etag,
+                checksum: None,
+                hash_algorithm,
+                encoded: Vec::new(),
+                mapped: false,
+                generation: 0,
+            },
+            None => InMemoryFile::from_bytes_with_hash(bytes, hash_algorithm),
+        };
+        file.stats.modified = modified;
+        file
+    }
+
+    /// The entity tag for the file in the given encoding.
+    /// Each encoding of the file is a different representation, and therefore needs its own entity tag.
+    pub(crate) fn encoded_etag(&self, encoding: Option<Encoding>) -> String {
+        match encoding {
+            Some(encoding) => format!("{}-{}\"", self.etag.trim_end_matches('"'), encoding.name()),
+            None => self.etag.clone(),
+        }
+    }
+
+    /// Hashes the bytes again, and returns true if they still match the checksum from when the file was read.
+    /// Returns `None` for files that weren't hashed when they were read, as there is nothing to compare them to.
+    pub(crate) fn verify(&self) -> Option<bool> {
+        self.checksum.map(|checksum| self.hash_algorithm.hash(&self.bytes) == checksum)
+    }
+
+    /// Returns true if the file has outlived its time to live.
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= Instant::now(),
+            None => false,
+        }
+    }
+}
+
+
+/// The smallest and largest number of bytes that files are read in at a time, if no buffer size is configured.
+const MIN_BUFFER_SIZE: usize = 8 * 1024;
+const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Reads small files with a single read, and larger files in chunks that are large enough to keep the
+/// number of reads low, without a large buffer being allocated for every file.
+fn default_buffer_size(size_hint: usize) -> usize {
+    size_hint.clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE)
+}
+
+/// Returns an error if the metadata isn't that of a regular file.
+pub(crate) fn ensure_regular_file(metadata: &std::fs::Metadata) -> io::Result<()> {
+    if metadata.is_file() {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "Only regular files can be read into memory."))
     }
 }
 
 
 /// Holds information related to the InMemoryFile.
 /// This information will be used to determine if the file should be replaced in the cache.
-#[derive(Debug, PartialEq, Clone)]
+///
+/// With the `serde` feature, the stats can be serialized. The `last_accessed` field is skipped,
+/// because an `Instant` has no meaning outside of the running process.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FileStats {
     /// The number of bytes the file contains.
     pub size: usize,
@@ -62,5 +343,132 @@ pub struct FileStats {
     /// The priority score.
     /// This is updated every time the access count is incremented by running the cache's `priority_function`
     /// on the `size` and `access_count`.
+    ///
+    /// Reading the score from this field is discouraged, as the field will be deprecated, and later
+    /// made private, so its representation can change. Use `priority()`, `priority_score()`, or `set_priority_score()` instead.
     pub priority: usize,
+    /// The last time the file was served from the cache.
+    /// This is used to determine which file should be removed when the `Lru` eviction policy is used.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub last_accessed: Instant,
+    /// The time at which the file was last modified, according to the filesystem.
+    /// This is `None` if the platform doesn't record modification times.
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStats {
+    /// Creates the stats of a file that hasn't been accessed yet, like a `FileSource` returns.
+    pub fn new(size: usize, modified: Option<SystemTime>) -> FileStats {
+        FileStats {
+            size,
+            access_count: 0,
+            priority: 0,
+            last_accessed: Instant::now(),
+            modified,
+        }
+    }
+
+    /// The priority of the file, which can be compared to the priorities of other files.
+    pub fn priority(&self) -> Priority {
+        Priority::new(self.priority)
+    }
+
+    /// The priority score of the file, as calculated by the cache's priority function, plus its priority bias.
+    pub fn priority_score(&self) -> usize {
+        self.priority
+    }
+
+    /// Replaces the priority score of the file, like an `EvictionPolicy` may do in `on_access()`.
+    pub fn set_priority_score(&mut self, score: usize) {
+        self.priority = score;
+    }
+}
+
+/// Stats are compared by their size, access count, priority, and modification time.
+/// The `last_accessed` timestamp is not compared, as it is unique to each access.
+impl PartialEq for FileStats {
+    fn eq(&self, other: &FileStats) -> bool {
+        self.size == other.size
+            && self.access_count == other.access_count
+            && self.priority == other.priority
+            && self.modified == other.modified
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+
+    #[test]
+    fn from_bytes_needs_no_file() {
+        let file: InMemoryFile = InMemoryFile::from(b"hello world".to_vec());
+        assert_eq!(file.bytes(), b"hello world");
+        assert_eq!(file.stats.size, 11);
+        assert_eq!(file.stats.access_count, 0);
+        assert_eq!(file.stats.priority_score(), 0);
+        assert!(file.stats.priority() < Priority::new(1));
+        assert_eq!(file.stats.modified, None);
+        assert_eq!(file.etag(), InMemoryFile::from_bytes(b"hello world".to_vec()).etag());
+    }
+
+    #[tokio::test]
+    async fn reader_reads_contents_in_parts() {
+        let file: InMemoryFile = InMemoryFile::from_bytes_with_hash(Bytes::from_static(b"hello world"), HashAlgorithm::default());
+        let mut reader = file.reader();
+
+        let mut buffer = [0u8; 4];
+        let mut contents: Vec<u8> = Vec::new();
+        loop {
+            let read: usize = reader.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            contents.extend_from_slice(&buffer[..read]);
+        }
+        assert_eq!(contents, b"hello world");
+
+        // Every reader starts from the beginning of the file.
+        let mut contents = String::new();
+        file.reader().read_to_string(&mut contents).await.unwrap();
+        assert_eq!(contents, "hello world");
+    }
+
+    #[tokio::test]
+    async fn open_reads_whole_file_with_small_buffer() {
+        let temp_dir = tempdir::TempDir::new("in_memory_file").unwrap();
+        let path = temp_dir.path().join("file.txt");
+        let contents: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let file: InMemoryFile = InMemoryFile::open_with_buffer_size(&path, HashAlgorithm::default(), EtagMode::Strong, Some(7)).await.unwrap();
+        assert_eq!(file.bytes(), &contents[..]);
+        assert_eq!(file.bytes.len(), file.stats.size);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn open_refuses_special_files() {
+        let temp_dir = tempdir::TempDir::new("in_memory_file").unwrap();
+        let error = InMemoryFile::open(temp_dir.path()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let path = temp_dir.path().join("pipe");
+        let created = std::process::Command::new("mkfifo").arg(&path).status();
+        if !created.map(|status| status.success()).unwrap_or(false) {
+            return; // mkfifo isn't available.
+        }
+        // Opening the pipe would block until something writes to it.
+        let opened = tokio::time::timeout(std::time::Duration::from_secs(5), InMemoryFile::open(&path)).await;
+        assert_eq!(opened.expect("Opening the pipe blocked.").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn buffer_size_depends_on_file_size() {
+        assert_eq!(default_buffer_size(0), MIN_BUFFER_SIZE);
+        assert_eq!(default_buffer_size(100 * 1024), 100 * 1024);
+        assert_eq!(default_buffer_size(1024 * 1024 * 1024), MAX_BUFFER_SIZE);
+    }
 }
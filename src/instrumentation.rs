@@ -0,0 +1,58 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cached_file::CachedFile;
+use crate::in_memory_file::FileStats;
+
+
+// With the `tracing` feature, these emit structured events that can be correlated with the spans of
+// requests. Without it, they do nothing, and the cache only logs through the `log` crate.
+
+/// Reports how a file was served, and how long it took.
+pub(crate) fn served(path: &Path, cached_file: &CachedFile, duration: Duration) {
+    #[cfg(feature = "tracing")]
+    {
+        match *cached_file {
+            CachedFile::InMemory(ref file) => tracing::debug!(
+                path = ?path,
+                size = file.file.stats.size,
+                priority = file.file.stats.priority_score(),
+                ?duration,
+                "cache hit"
+            ),
+            CachedFile::Partial(ref file) if file.from_memory => tracing::debug!(path = ?path, range = ?file.range, ?duration, "cache hit"),
+            CachedFile::FileSystem(_) | CachedFile::Streaming(_) | CachedFile::Partial(_) => tracing::debug!(path = ?path, ?duration, "cache miss"),
+            CachedFile::NotFound => tracing::debug!(path = ?path, ?duration, "file not found"),
+        }
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, cached_file, duration);
+}
+
+/// Reports that a file was added to the cache, and how long it took to read it, if it was read from the filesystem.
+pub(crate) fn inserted(path: &Path, stats: &FileStats, read_duration: Option<Duration>) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        path = ?path,
+        size = stats.size,
+        priority = stats.priority_score(),
+        duration = ?read_duration,
+        "file inserted into cache"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, stats, read_duration);
+}
+
+/// Reports that a file was removed to make room for other files.
+pub(crate) fn evicted(path: &Path, stats: &FileStats) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        path = ?path,
+        size = stats.size,
+        priority = stats.priority_score(),
+        access_count = stats.access_count,
+        "file evicted from cache"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = (path, stats);
+}
@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+
+/// The transformations that are applied to paths before they are used as keys in the cache, so a
+/// file that is requested in slightly different ways is only stored once.
+///
+/// The transformations are applied in the order of the fields below.
+/// Paths that aren't valid UTF-8 are used as they are.
+/// By default, no transformations are applied.
+///
+/// # Example
+///
+/// ```
+/// use rocket_file_cache::{Cache, CacheBuilder, KeyNormalization};
+///
+/// let cache: Cache = CacheBuilder::new()
+///     .key_normalization(KeyNormalization {
+///         strip_query: true,
+///         collapse_separators: true,
+///         strip_trailing_slash: true,
+///         lowercase: false,
+///     })
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyNormalization {
+    /// Removes the first `?` and everything after it, so `www/app.js?v=3` becomes `www/app.js`.
+    pub strip_query: bool,
+    /// Replaces every run of consecutive `/` separators with a single one, so `www//js/app.js` becomes `www/js/app.js`.
+    pub collapse_separators: bool,
+    /// Removes the `/` separators at the end of the path, so `www/docs/` becomes `www/docs`.
+    /// A path that only consists of separators, like `/`, is kept as a single separator.
+    pub strip_trailing_slash: bool,
+    /// Converts the path to lowercase, so `www/Logo.PNG` becomes `www/logo.png`.
+    /// The lowercased path is also used to read the file, so this should only be enabled if the files
+    /// are on a case-insensitive filesystem.
+    pub lowercase: bool,
+}
+
+impl KeyNormalization {
+    /// Applies the enabled transformations to the path.
+    pub(crate) fn normalize(&self, path: &Path) -> PathBuf {
+        if *self == KeyNormalization::default() {
+            return path.to_path_buf();
+        }
+        let mut key: &str = match path.to_str() {
+            Some(key) => key,
+            None => return path.to_path_buf(),
+        };
+
+        if self.strip_query {
+            if let Some(query_start) = key.find('?') {
+                key = &key[..query_start];
+            }
+        }
+
+        let mut normalized: String = if self.collapse_separators {
+            let mut collapsed = String::with_capacity(key.len());
+            for c in key.chars() {
+                if c == '/' && collapsed.ends_with('/') {
+                    continue;
+                }
+                collapsed.push(c);
+            }
+            collapsed
+        } else {
+            key.to_string()
+        };
+
+        if self.strip_trailing_slash && normalized.len() > 1 {
+            let trimmed_len: usize = normalized.trim_end_matches('/').len().max(1);
+            normalized.truncate(trimmed_len);
+        }
+
+        if self.lowercase {
+            normalized = normalized.to_lowercase();
+        }
+        PathBuf::from(normalized)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: KeyNormalization = KeyNormalization {
+        strip_query: true,
+        collapse_separators: true,
+        strip_trailing_slash: true,
+        lowercase: true,
+    };
+
+    #[test]
+    fn applies_enabled_transformations() {
+        assert_eq!(ALL.normalize(Path::new("www//JS/App.js?v=3")), PathBuf::from("www/js/app.js"));
+        assert_eq!(ALL.normalize(Path::new("www/docs///")), PathBuf::from("www/docs"));
+        assert_eq!(ALL.normalize(Path::new("///")), PathBuf::from("/"));
+
+        let strip_query = KeyNormalization { strip_query: true, ..KeyNormalization::default() };
+        assert_eq!(strip_query.normalize(Path::new("www//App.js?v=3")), PathBuf::from("www//App.js"));
+
+        let none = KeyNormalization::default();
+        assert_eq!(none.normalize(Path::new("www//App.js?v=3")), PathBuf::from("www//App.js?v=3"));
+    }
+
+    #[test]
+    fn normalizing_twice_changes_nothing() {
+        let once: PathBuf = ALL.normalize(Path::new("WWW//a/b//?q=/x"));
+        assert_eq!(ALL.normalize(&once), once);
+    }
+}
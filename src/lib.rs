@@ -5,15 +5,98 @@ extern crate rocket;
 extern crate log;
 
 extern crate concurrent_hashmap;
+extern crate flate2;
+extern crate brotli;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+extern crate httpdate;
+extern crate bytes;
+extern crate glob;
+extern crate regex;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "manifest")]
+extern crate serde_json;
+#[cfg(feature = "manifest")]
+extern crate toml;
 
 mod cache;
+mod cache_error;
 mod in_memory_file;
 pub mod named_in_memory_file;
+pub mod streaming_file;
+pub mod partial_file;
 mod cache_builder;
 mod priority_function;
 mod cached_file;
+mod eviction_policy;
+mod hash_algorithm;
+mod etag_mode;
+mod range;
+mod encoding;
+#[cfg(feature = "watch")]
+mod watcher;
+mod preload;
+#[cfg(feature = "manifest")]
+mod manifest;
+mod cache_stats;
+mod access_record;
+mod in_flight;
+mod open_limit;
+mod fallback;
+mod not_found;
+mod admission;
+mod cache_control;
+mod content_disposition;
+mod custom_headers;
+mod cors;
+mod sniff;
+mod default_content_type;
+mod precompress_filter;
+mod extension_filter;
+mod dotfiles;
+mod loader;
+mod shrink;
+mod churn;
+mod high_water;
+mod adaptive;
+mod batch;
+mod verify;
+mod bypass;
+mod range_chunks;
+mod spill;
+mod file_source;
+mod overflow_policy;
+mod file_reader;
+mod instrumentation;
+mod revalidate;
+mod key_normalization;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod fairing;
+mod lock;
 
 pub use cache::Cache;
+pub use cache_error::CacheError;
 pub use cache_builder::{CacheBuilder, CacheBuildError};
 pub use cached_file::CachedFile;
 pub use priority_function::*;
+pub use file_source::{FileSource, FsSource};
+pub use eviction_policy::{EvictionPolicy, PriorityScorePolicy, LruPolicy, LfuPolicy, SizeWeightedLfuPolicy};
+pub use hash_algorithm::HashAlgorithm;
+pub use etag_mode::EtagMode;
+pub use overflow_policy::OverflowPolicy;
+pub use encoding::Encoding;
+pub use content_disposition::Disposition;
+pub use preload::{PreloadSummary, WarmSummary};
+pub use shrink::{PinnedFiles, ShrinkSummary};
+pub use churn::{ChurnReport, KeySnapshot};
+pub use cache_stats::CacheStats;
+pub use fairing::CacheFairing;
+pub use key_normalization::KeyNormalization;
+pub use in_memory_file::{FileStats, InMemoryFile};
+pub use glob::PatternError;
@@ -0,0 +1,198 @@
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+
+use crate::cache::Cache;
+use crate::in_memory_file::InMemoryFile;
+use crate::cached_file::CachedFile;
+use crate::access_record::AccessRecord;
+
+
+impl Cache {
+    /// Gets the contents stored in the cache under the key, or runs the loader to generate them.
+    ///
+    /// This allows content that doesn't exist in the filesystem, like rendered templates or
+    /// concatenated bundles, to be cached alongside files.
+    /// The key doesn't need to correspond to a real path.
+    /// The generated content is subject to the same size limits and eviction policy as files that
+    /// are read from the filesystem. If it isn't accepted into the cache, it is still returned.
+    ///
+    /// Only one loader runs at a time for a given key, concurrent calls wait for it to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the loader, if it fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let bundle = cache.get_or_insert_with("generated/bundle.js", || async {
+    ///     Ok(b"console.log('hello');".to_vec())
+    /// }).await.unwrap();
+    /// assert_eq!(&bundle[..], b"console.log('hello');");
+    /// # }
+    /// ```
+    pub async fn get_or_insert_with<K, F, Fut>(&self, key: K, loader: F) -> io::Result<Bytes>
+    where
+        K: AsRef<Path>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = io::Result<Vec<u8>>>,
+    {
+        let key: PathBuf = self.normalize_key(key);
+        if let Some(bytes) = self.get_cached_bytes(&key) {
+            return Ok(bytes);
+        }
+
+        let _load_guard = self.in_flight.acquire(&key).await;
+        // Another call may have generated the content while this one was waiting.
+        if let Some(bytes) = self.get_cached_bytes(&key) {
+            return Ok(bytes);
+        }
+
+        let bytes: Bytes = Bytes::from(loader().await?);
+        self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        self.increment_access_count(&key);
+        let file: InMemoryFile = self.prepare_file(&key, InMemoryFile::from_bytes_with_hash(bytes.clone(), self.hash_algorithm));
+        self.insert_loaded_file(key, file);
+        Ok(bytes)
+    }
+
+    /// Adds the bytes to the cache under the key, replacing anything that was stored under it before.
+    ///
+    /// This allows content that has already been generated, like a sitemap, to be served from the cache.
+    /// The key doesn't need to correspond to a real path, and calling `get()` with it will serve the bytes.
+    ///
+    /// The new entry starts with an access count of 0, and is subject to the same size limits and
+    /// eviction policy as files that are read from the filesystem.
+    /// If the bytes aren't accepted, whatever was stored under the key before stays in the cache.
+    /// Replacing an entry doesn't count as evicting it, so the callback set with `Cache::on_evict()` isn't called.
+    ///
+    /// # Return
+    ///
+    /// The CachedFile will be the InMemory variant if the bytes were accepted into the cache,
+    /// and NotFound otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, CachedFile};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let sitemap: Vec<u8> = b"<urlset></urlset>".to_vec();
+    /// let cached_file: CachedFile = cache.insert_bytes("www/sitemap.xml", sitemap).await;
+    /// assert!(cache.contains_key("www/sitemap.xml"));
+    /// # }
+    /// ```
+    pub async fn insert_bytes<K: AsRef<Path>>(&self, key: K, bytes: Vec<u8>) -> CachedFile {
+        let key: PathBuf = self.normalize_key(key);
+        let file: InMemoryFile = self.prepare_file(&key, InMemoryFile::from_bytes_with_hash(Bytes::from(bytes), self.hash_algorithm));
+        if !self.insert_loaded_file(key.clone(), file) {
+            return CachedFile::NotFound;
+        }
+        self.access_count_map.upsert(key.clone(), AccessRecord::new(0), &|_| {});
+        self.update_stats(&key);
+        let cached_file: CachedFile = self.get_from_cache(&key).await;
+        self.add_response_headers(cached_file)
+    }
+
+    /// Gets the contents of the file in the cache, counting the access.
+    /// Expired files are removed instead.
+    fn get_cached_bytes(&self, key: &PathBuf) -> Option<Bytes> {
+        if self.is_expired(key) {
+            self.remove(key);
+            return None;
+        }
+        let bytes: Bytes = self.file_map.find(key)?.get().bytes.clone();
+        self.counters.hits.fetch_add(1, Ordering::Relaxed);
+        self.increment_access_count(key);
+        Some(bytes)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn loader_only_runs_on_miss() {
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        let loads = AtomicUsize::new(0);
+        let loads_ref: &AtomicUsize = &loads;
+        let loader = || async move {
+            loads_ref.fetch_add(1, Ordering::Relaxed);
+            Ok(b"generated".to_vec())
+        };
+
+        assert_eq!(&cache.get_or_insert_with("generated/page", loader).await.unwrap()[..], b"generated");
+        assert_eq!(&cache.get_or_insert_with("generated/page", loader).await.unwrap()[..], b"generated");
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+        assert!(cache.contains_key("generated/page"));
+
+        let error = cache.get_or_insert_with("generated/broken", || async {
+            Err(io::Error::new(io::ErrorKind::Other, "could not render"))
+        }).await;
+        assert!(error.is_err());
+        assert!(!cache.contains_key("generated/broken"));
+    }
+
+    #[tokio::test]
+    async fn inserted_bytes_are_served_by_get() {
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1024)
+            .build()
+            .unwrap();
+
+        match cache.insert_bytes("www/sitemap.xml", vec![b'a'; 512]).await {
+            CachedFile::InMemory(file) => assert_eq!(file.file.stats.access_count, 0),
+            other => panic!("Expected the bytes to be cached, got {:?}", other),
+        }
+        match cache.get("www/sitemap.xml").await {
+            CachedFile::InMemory(file) => assert_eq!(file.bytes(), &vec![b'a'; 512][..]),
+            other => panic!("Expected the bytes to be cached, got {:?}", other),
+        }
+
+        // Overwriting the entry replaces its size, rather than adding to it.
+        cache.insert_bytes("www/sitemap.xml", vec![b'b'; 768]).await;
+        assert_eq!(cache.used_bytes(), 768);
+
+        assert_eq!(cache.insert_bytes("www/huge.xml", vec![b'c'; 2048]).await, CachedFile::NotFound);
+        assert_eq!(cache.used_bytes(), 768);
+    }
+
+    #[tokio::test]
+    async fn overwriting_bytes_is_not_an_eviction() {
+        let evictions: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let mut cache: Cache = CacheBuilder::new()
+            .size_limit(1024)
+            .build()
+            .unwrap();
+        let counted: Arc<AtomicUsize> = evictions.clone();
+        cache.on_evict(Box::new(move |_, _| {
+            counted.fetch_add(1, Ordering::Relaxed);
+        }));
+
+        cache.insert_bytes("www/sitemap.xml", vec![b'a'; 512]).await;
+        cache.insert_bytes("www/sitemap.xml", vec![b'b'; 1000]).await;
+        assert_eq!(cache.get("www/sitemap.xml").await.as_bytes(), Some(&[b'b'; 1000][..]));
+        assert_eq!(evictions.load(Ordering::Relaxed), 0);
+
+        // Bytes that aren't accepted leave the old contents in the cache.
+        assert_eq!(cache.insert_bytes("www/sitemap.xml", vec![b'c'; 2048]).await, CachedFile::NotFound);
+        assert_eq!(cache.get("www/sitemap.xml").await.as_bytes(), Some(&[b'b'; 1000][..]));
+        assert_eq!(evictions.load(Ordering::Relaxed), 0);
+    }
+}
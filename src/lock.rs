@@ -0,0 +1,30 @@
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+
+// The cache's own locks are only held while values are read or replaced, so a thread that panics
+// while it holds one can't leave the value half updated. A poisoned lock is therefore used as it is,
+// rather than making every later request panic as well.
+
+/// Locks the mutex, even if a thread panicked while it held the lock.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Locks the lock for reading, even if a thread panicked while it held the lock.
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<T> {
+    match lock.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Locks the lock for writing, even if a thread panicked while it held the lock.
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<T> {
+    match lock.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
@@ -0,0 +1,157 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::cache::Cache;
+use crate::preload::{PreloadOutcome, PreloadSummary};
+
+
+/// A list of the files that should be in the cache, as emitted by a build.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    files: Vec<ManifestEntry>,
+}
+
+/// A file in the manifest, which is either just its path, or its path with options.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ManifestEntry {
+    Path(PathBuf),
+    File {
+        path: PathBuf,
+        /// The number of seconds the file can be served from the cache.
+        #[serde(default)]
+        ttl: Option<u64>,
+        #[serde(default)]
+        pin: bool,
+    },
+}
+
+impl ManifestEntry {
+    fn into_parts(self) -> (PathBuf, Option<Duration>, bool) {
+        match self {
+            ManifestEntry::Path(path) => (path, None, false),
+            ManifestEntry::File { path, ttl, pin } => (path, ttl.map(Duration::from_secs), pin),
+        }
+    }
+}
+
+/// Parses the manifest as TOML if its extension is `toml`, and as JSON otherwise.
+fn parse(manifest_path: &Path, contents: &str) -> io::Result<Manifest> {
+    let is_toml: bool = manifest_path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("toml"));
+    if is_toml {
+        toml::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    } else {
+        serde_json::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+
+impl Cache {
+    /// Reads the files that are listed in a manifest into the cache, so its contents match a deploy right after the server starts.
+    ///
+    /// The manifest is parsed as TOML if its extension is `toml`, and as JSON otherwise.
+    /// It has a list of `files`, each of which is either a path, or a table with a `path`, and optionally
+    /// a `ttl` in seconds, after which the file expires, and a `pin` flag, which keeps the file from being evicted.
+    /// Paths are used as they would be by `get()`, so relative paths are relative to the working directory,
+    /// not to the manifest.
+    ///
+    /// ```json
+    /// { "files": ["www/index.html", { "path": "www/app.4f3a2b.js", "pin": true }, { "path": "www/news.html", "ttl": 60 }] }
+    /// ```
+    ///
+    /// Files are read one at a time, in the order they are listed, so the same manifest always results
+    /// in the same contents. Files are pinned before they are read, even if they can't be read.
+    /// Files that are missing or can't be read are reported in the summary, and don't stop the other
+    /// files from being read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest can't be read, or an error of the kind `InvalidData` if it can't be parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// if let Ok(summary) = cache.warm_from_manifest("dist/manifest.json").await {
+    ///     for (path, error) in summary.failed {
+    ///         println!("Could not preload {:?}: {}", path, error);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn warm_from_manifest<P: AsRef<Path>>(&self, manifest_path: P) -> io::Result<PreloadSummary> {
+        let contents: String = tokio::fs::read_to_string(manifest_path.as_ref()).await?;
+        let manifest: Manifest = parse(manifest_path.as_ref(), &contents)?;
+
+        let mut summary = PreloadSummary::default();
+        for entry in manifest.files {
+            let (path, ttl, pin) = entry.into_parts();
+            if pin {
+                self.pin(&path);
+            }
+            match self.preload_file(&path).await {
+                PreloadOutcome::Loaded => {
+                    if let Some(ttl) = ttl {
+                        if let Some(mut file_entry) = self.file_map.find_mut(&self.normalize_key(&path)) {
+                            file_entry.get().expires_at = Some(Instant::now() + ttl);
+                        }
+                    }
+                    summary.loaded.push(path);
+                }
+                PreloadOutcome::Failed(e) => summary.failed.push((path, e)),
+                PreloadOutcome::Skipped => summary.skipped.push(path),
+                PreloadOutcome::Rejected => summary.rejected.push(path),
+            }
+        }
+        Ok(summary)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn warms_files_listed_in_manifest() {
+        let temp_dir = TempDir::new("manifest").unwrap();
+        let index: PathBuf = temp_dir.path().join("index.html");
+        let app: PathBuf = temp_dir.path().join("app.js");
+        let missing: PathBuf = temp_dir.path().join("missing.css");
+        std::fs::write(&index, b"<html></html>").unwrap();
+        std::fs::write(&app, b"console.log('hello');").unwrap();
+
+        let manifest: PathBuf = temp_dir.path().join("manifest.toml");
+        let contents = format!("[[files]]\npath = {:?}\npin = true\nttl = 0\n\n[[files]]\npath = {:?}\n", app, missing);
+        std::fs::write(&manifest, contents).unwrap();
+        let json_manifest: PathBuf = temp_dir.path().join("manifest.json");
+        std::fs::write(&json_manifest, format!("{{\"files\": [{:?}]}}", index)).unwrap();
+
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        let summary: PreloadSummary = cache.warm_from_manifest(&manifest).await.unwrap();
+        assert_eq!(summary.loaded, vec![app.clone()]);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0, missing);
+        assert!(cache.is_pinned(&app));
+        assert!(cache.is_expired(&app));
+
+        let summary: PreloadSummary = cache.warm_from_manifest(&json_manifest).await.unwrap();
+        assert_eq!(summary.loaded, vec![index.clone()]);
+        assert!(!cache.is_expired(&index));
+
+        std::fs::write(&json_manifest, b"{\"files\": 3}").unwrap();
+        let e = cache.warm_from_manifest(&json_manifest).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
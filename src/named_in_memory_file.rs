@@ -1,14 +1,20 @@
 use rocket::response::{Response, Responder};
-use rocket::http::{Status, ContentType};
+use rocket::http::{Status, ContentType, Header, Method};
 use rocket::request::Request;
 
 use std::result;
-use std::sync::Arc;
+use std::io::Cursor;
 use std::path::{PathBuf, Path};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::in_memory_file::InMemoryFile;
+use crate::range::{self, ByteRange};
+use crate::encoding::{self, Encoding};
+use crate::cors::Cors;
 
 use concurrent_hashmap::Accessor;
+use bytes::Bytes;
 
 use std::fmt::{Formatter, Debug};
 use std::fmt;
@@ -17,39 +23,143 @@ use std::fmt;
 /// A wrapper around an in-memory file.
 /// This struct is created when when a request to the cache is made.
 /// The CachedFile knows its path, so it can set the content type when it is serialized to a response.
-pub struct NamedInMemoryFile<'a> {
+///
+/// The file's contents are shared with the cache, so the file doesn't hold a lock on the cache,
+/// and it isn't affected if the file is removed from the cache.
+pub struct NamedInMemoryFile {
     pub(crate) path: PathBuf,
-    pub(crate) file: Arc<Accessor<'a, PathBuf, InMemoryFile>>,
+    pub(crate) file: InMemoryFile,
+    /// Headers configured by the cache that are added to the response.
+    pub(crate) headers: Vec<Header<'static>>,
+    /// Adds CORS headers to the response, depending on the origin of the request.
+    pub(crate) cors: Option<Arc<Cors>>,
 }
 
 
-impl<'a> Debug for NamedInMemoryFile<'a> {
+impl Debug for NamedInMemoryFile {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        write!(fmt, "path: {:?}, file: {:?}", self.path, self.file.get())
+        write!(fmt, "path: {:?}, file: {:?}", self.path, self.file)
     }
 }
 
 
-impl<'a> NamedInMemoryFile<'a> {
-    /// Reads the file at the path into a NamedInMemoryFile.
-    pub(crate) fn new<P: AsRef<Path>>(path: P, m: Accessor<'a, PathBuf, InMemoryFile>) -> NamedInMemoryFile<'a> {
+impl NamedInMemoryFile {
+    /// Takes a cheap copy of the file that the accessor points to, releasing the lock on the cache.
+    pub(crate) fn new<P: AsRef<Path>>(path: P, m: Accessor<PathBuf, InMemoryFile>) -> NamedInMemoryFile {
         NamedInMemoryFile {
             path: path.as_ref().to_path_buf(),
-            file: Arc::new(m),
+            file: m.get().clone(),
+            headers: Vec::new(),
+            cors: None,
         }
     }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The contents of the file.
+    pub fn bytes(&self) -> &[u8] {
+        self.file.bytes()
+    }
+
+    /// A reader over the contents of the file, which doesn't copy them.
+    pub fn reader(&self) -> impl tokio::io::AsyncRead + tokio::io::AsyncBufRead + Unpin + Send + Sync + 'static {
+        self.file.reader()
+    }
+}
+
+
+/// Returns true if any of the entity tags in an `If-None-Match` header match the file's entity tag.
+///
+/// This uses the weak comparison function, as is required for `If-None-Match`.
+fn if_none_match(header: &str, etag: &str) -> bool {
+    let etag: &str = etag.trim_start_matches("W/");
+    header.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+
+/// Returns true if the validator in an `If-Range` header still matches the file, so the range it comes with can be sent.
+///
+/// Entity tags are compared with the strong comparison function, as is required for `If-Range`, so weak
+/// entity tags never match. Dates must be the file's modification time, truncated to the second.
+/// Headers that can't be parsed never match, so the whole file is sent instead.
+fn if_range(header: &str, etag: &str, modified: Option<SystemTime>) -> bool {
+    let header: &str = header.trim();
+    if header.starts_with('"') || header.starts_with("W/") {
+        return !etag.starts_with("W/") && header == etag;
+    }
+    let date: SystemTime = match httpdate::parse_http_date(header) {
+        Ok(date) => date,
+        Err(_) => return false,
+    };
+    match modified.map(|modified| modified.duration_since(UNIX_EPOCH)) {
+        Some(Ok(modified)) => match date.duration_since(UNIX_EPOCH) {
+            Ok(date) => modified.as_secs() == date.as_secs(),
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+
+/// Returns true if the file hasn't been modified since the date in an `If-Modified-Since` header.
+///
+/// HTTP dates only have a resolution of one second, so the modification time is truncated to the second.
+/// Headers that can't be parsed are treated as if the file has been modified.
+fn not_modified_since(header: &str, modified: SystemTime) -> bool {
+    let since: SystemTime = match httpdate::parse_http_date(header) {
+        Ok(since) => since,
+        Err(_) => return false,
+    };
+    match (modified.duration_since(UNIX_EPOCH), since.duration_since(UNIX_EPOCH)) {
+        (Ok(modified), Ok(since)) => modified.as_secs() <= since.as_secs(),
+        _ => false,
+    }
 }
 
 
 /// Streams the cached file to the client. Sets or overrides the Content-Type in
 /// the response according to the file's extension if the extension is recognized.
 ///
+/// The response will include an `ETag` header.
+/// If the request has an `If-None-Match` header that matches the ETag, a `304 Not Modified`
+/// response without a body is returned instead.
+///
+/// Any headers configured by the cache, like `Cache-Control`, are added to the response, including `304 Not Modified` responses.
+/// If CORS is configured, and the request's `Origin` is allowed, the CORS headers are added as well.
+///
+/// If the modification time of the file is known, the response will include a `Last-Modified` header.
+/// If the request has an `If-Modified-Since` header, and no `If-None-Match` header, a `304 Not Modified`
+/// response is returned if the file hasn't been modified since that date.
+///
+/// If the file was compressed when it was read into the cache, the compressed copy that best matches
+/// the request's `Accept-Encoding` header is sent, with the matching `Content-Encoding` header.
+/// Responses for files with compressed copies include a `Vary: Accept-Encoding` header, so shared caches
+/// don't send a compressed copy to a client that can't decode it. Other responses don't include it.
+///
+/// If the request has a `Range` header for a single range of bytes, a `206 Partial Content` response
+/// containing only that range is returned.
+/// If it requests several ranges, they are sent as the parts of a `multipart/byteranges` body instead.
+/// Ranges that overlap or touch are coalesced, so they are sent once, in the order in which they are in the file.
+/// If all of the ranges lie outside of the file, a `416 Range Not Satisfiable` response is returned.
+/// Malformed `Range` headers are ignored.
+/// If the request also has an `If-Range` header, the range is only sent if the header has the file's
+/// strong `ETag`, or its `Last-Modified` date. Otherwise, the file has changed since the client
+/// got the rest of it, so the whole file is sent, with a `200 OK` status.
+///
+/// Responses to `HEAD` requests have the same headers as responses to `GET` requests, including
+/// `Content-Length`, but no body.
+///
 /// If you would like to stream a file with a different Content-Type than that implied by its
 /// extension, convert the `CachedFile` to a `File`, and respond with that instead.
 ///
 /// Based on NamedFile from rocket::response::NamedFile
-impl<'a> Responder<'a, 'a> for NamedInMemoryFile<'a> {
-    fn respond_to(self, _: &'a Request) -> result::Result<Response<'a>, Status> {
+impl<'r> Responder<'r, 'static> for NamedInMemoryFile {
+    fn respond_to(self, request: &'r Request) -> result::Result<Response<'static>, Status> {
         let mut response = Response::new();
         if let Some(ext) = self.path.extension() {
             if let Some(ct) = ContentType::from_extension(&ext.to_string_lossy()) {
@@ -57,12 +167,126 @@ impl<'a> Responder<'a, 'a> for NamedInMemoryFile<'a> {
             }
         }
 
-        unsafe {
-            let cloned_wrapper: *const Accessor<'a, PathBuf, InMemoryFile> = Arc::into_raw(self.file);
-            response.set_sized_body(Some((*cloned_wrapper).get().stats.size as usize), std::io::Cursor::new((*cloned_wrapper).get().bytes.as_slice()) );
-            let _ = Arc::from_raw(cloned_wrapper); // To prevent a memory leak, an Arc needs to be reconstructed from the raw pointer.
+        for header in self.headers {
+            response.set_header(header);
+        }
+
+        let encoding: Option<Encoding> = request.headers()
+            .get_one("Accept-Encoding")
+            .and_then(|header| encoding::negotiate(header, self.file.encodings()));
+        if let Some(encoding) = encoding {
+            response.set_raw_header("Content-Encoding", encoding.name());
+        }
+        // The body depends on the Accept-Encoding header whenever there is an encoded copy to choose,
+        // even if the raw bytes were chosen for this request.
+        if self.file.encodings().next().is_some() {
+            response.set_raw_header("Vary", "Accept-Encoding");
+        }
+
+        if let Some(ref cors) = self.cors {
+            cors.apply(request, &mut response);
+        }
+
+        let etag: String = self.file.encoded_etag(encoding);
+        response.set_raw_header("ETag", etag.clone());
+
+        let modified: Option<SystemTime> = self.file.stats.modified;
+        if let Some(modified) = modified {
+            response.set_raw_header("Last-Modified", httpdate::fmt_http_date(modified));
+        }
+
+        // If-Modified-Since is ignored when If-None-Match is present.
+        let not_modified: bool = match request.headers().get_one("If-None-Match") {
+            Some(header) => if_none_match(header, &etag),
+            None => match (request.headers().get_one("If-Modified-Since"), modified) {
+                (Some(header), Some(modified)) => not_modified_since(header, modified),
+                _ => false,
+            },
+        };
+        if not_modified {
+            response.set_status(Status::NotModified);
+            return Ok(response);
+        }
+
+        response.set_raw_header("Accept-Ranges", "bytes");
+        let len: usize = self.file.encoded_bytes(encoding).len();
+        // A range is only sent if the file hasn't changed since the client got the rest of it.
+        let range_is_current: bool = match request.headers().get_one("If-Range") {
+            Some(header) => if_range(header, &etag, modified),
+            None => true,
+        };
+        let byte_range: Option<ByteRange> = request.headers()
+            .get_one("Range")
+            .filter(|_| range_is_current)
+            .and_then(|header| ByteRange::parse(header, len));
+
+        let body: Bytes = match byte_range {
+            Some(ByteRange::Satisfiable(body_range)) => {
+                response.set_status(Status::PartialContent);
+                response.set_raw_header("Content-Range", range::content_range(&body_range, len));
+                self.file.encoded_bytes(encoding).slice(body_range)
+            }
+            Some(ByteRange::Multiple(body_ranges)) => {
+                // The boundary is derived from the entity tag, so it is the same for every response for the file.
+                let boundary: String = format!(
+                    "rocket-file-cache-{}",
+                    etag.chars().filter(char::is_ascii_alphanumeric).collect::<String>()
+                );
+                let part_type: Option<String> = response.headers().get_one("Content-Type").map(String::from);
+                response.set_status(Status::PartialContent);
+                response.set_raw_header("Content-Type", format!("multipart/byteranges; boundary={}", boundary));
+                Bytes::from(range::multipart_body(self.file.encoded_bytes(encoding), &body_ranges, &boundary, part_type.as_deref()))
+            }
+            Some(ByteRange::Unsatisfiable) => {
+                response.set_status(Status::RangeNotSatisfiable);
+                response.set_raw_header("Content-Range", format!("bytes */{}", len));
+                return Ok(response);
+            }
+            None => self.file.encoded_bytes(encoding).clone(),
+        };
+
+        if request.method() == Method::Head {
+            response.set_raw_header("Content-Length", body.len().to_string());
+        } else {
+            response.set_sized_body(Some(body.len()), Cursor::new(body));
         }
 
         Ok(response)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if_none_match_compares_etags() {
+        assert!(if_none_match("\"abc\"", "\"abc\""));
+        assert!(if_none_match("\"xyz\", \"abc\"", "\"abc\""));
+        assert!(if_none_match("W/\"abc\"", "\"abc\""));
+        assert!(if_none_match("*", "\"abc\""));
+        assert!(!if_none_match("\"xyz\"", "\"abc\""));
+    }
+
+    #[test]
+    fn if_range_needs_a_strong_match() {
+        let modified: SystemTime = UNIX_EPOCH + std::time::Duration::from_millis(784_111_777_500);
+        assert!(if_range("\"abc\"", "\"abc\"", None));
+        assert!(!if_range("\"xyz\"", "\"abc\"", None));
+        assert!(!if_range("W/\"abc\"", "W/\"abc\"", None));
+        assert!(if_range("Sun, 06 Nov 1994 08:49:37 GMT", "\"abc\"", Some(modified)));
+        assert!(!if_range("Sun, 06 Nov 1994 08:49:38 GMT", "\"abc\"", Some(modified)));
+        assert!(!if_range("Sun, 06 Nov 1994 08:49:37 GMT", "\"abc\"", None));
+        assert!(!if_range("yesterday", "\"abc\"", Some(modified)));
+    }
+
+    #[test]
+    fn not_modified_since_compares_whole_seconds() {
+        let modified: SystemTime = UNIX_EPOCH + std::time::Duration::from_millis(784_111_777_500);
+        assert!(not_modified_since("Sun, 06 Nov 1994 08:49:37 GMT", modified));
+        assert!(not_modified_since("Sun, 06 Nov 1994 08:49:38 GMT", modified));
+        assert!(!not_modified_since("Sun, 06 Nov 1994 08:49:36 GMT", modified));
+        assert!(!not_modified_since("yesterday", modified));
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::{self, Cursor};
+use std::path::Path;
+
+use bytes::Bytes;
+use rocket::http::{ContentType, Status};
+use rocket::response::Response;
+
+use crate::cache::Cache;
+
+
+/// The response that is sent in place of a bare `404 Not Found`, for files that could not be found.
+#[derive(Debug, Clone)]
+pub(crate) struct NotFoundPage {
+    content_type: Option<ContentType>,
+    body: Bytes,
+}
+
+impl NotFoundPage {
+    /// Creates a `404 Not Found` response with the page as its body.
+    pub(crate) fn respond(&self) -> Response<'static> {
+        let mut response = Response::new();
+        response.set_status(Status::NotFound);
+        if let Some(ref content_type) = self.content_type {
+            response.set_header(content_type.clone());
+        }
+        response.set_sized_body(Some(self.body.len()), Cursor::new(self.body.clone()));
+        response
+    }
+}
+
+
+impl Cache {
+    /// Responds to requests for files that could not be found with the body, and a `Content-Type`
+    /// of `content_type`, instead of a bare `404 Not Found`, which Rocket answers with its default error page.
+    /// The response still has the `404 Not Found` status.
+    ///
+    /// `CachedFile::NotFound` has no way to refer to the cache, so the body is only sent if Rocket manages
+    /// the cache as exactly a `Cache` or an `Arc<Cache>`, like `CacheFairing` does.
+    /// A cache that is managed in another way, like behind a `RwLock`, or not managed at all, can't be found
+    /// by the response, which then is a bare `404 Not Found`, as it is by default. This is logged at the debug level.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use rocket::http::ContentType;
+    ///
+    /// let mut cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.not_found_body(ContentType::JSON, br#"{"error":"not found"}"#.to_vec());
+    /// let rocket = rocket::build().manage(cache);
+    /// ```
+    pub fn not_found_body(&mut self, content_type: ContentType, body: Vec<u8>) {
+        self.not_found_page = Some(NotFoundPage {
+            content_type: Some(content_type),
+            body: Bytes::from(body),
+        });
+    }
+
+    /// Reads the file at `path` into memory, and responds to requests for files that could not be
+    /// found with it, like `not_found_body()` does.
+    /// The `Content-Type` is derived from the file's extension.
+    /// Like with `not_found_body()`, the page is only sent if Rocket manages the cache as a `Cache` or an `Arc<Cache>`.
+    ///
+    /// The page is kept apart from the files in the cache, so it doesn't count towards the size limit,
+    /// and it is never evicted. Call this again to pick up changes to the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read, in which case the previous page is kept.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut cache: Cache = CacheBuilder::new().build().unwrap();
+    /// if cache.not_found_page("www/404.html").await.is_err() {
+    ///     println!("Missing files will be answered with Rocket's default error page.");
+    /// }
+    /// # }
+    /// ```
+    pub async fn not_found_page<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let body: Vec<u8> = tokio::fs::read(path.as_ref()).await?;
+        self.not_found_page = Some(NotFoundPage {
+            content_type: path.as_ref()
+                .extension()
+                .and_then(|ext| ContentType::from_extension(&ext.to_string_lossy())),
+            body: Bytes::from(body),
+        });
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use rocket::local::asynchronous::Client;
+    use rocket::response::Responder;
+    use std::sync::Arc;
+    use crate::cache_builder::CacheBuilder;
+    use crate::cached_file::CachedFile;
+
+    #[tokio::test]
+    async fn not_found_responds_with_page_of_managed_cache() {
+        let temp_dir = TempDir::new("not_found").unwrap();
+        let page_path = temp_dir.path().join("404.html");
+        std::fs::write(&page_path, b"<h1>Not here</h1>").unwrap();
+        let mut cache: Cache = CacheBuilder::new().build().unwrap();
+        cache.not_found_page(&page_path).await.unwrap();
+        assert!(cache.not_found_page(temp_dir.path().join("missing.html")).await.is_err());
+        let missing: CachedFile = cache.get(temp_dir.path().join("missing.css")).await;
+
+        let client = Client::untracked(rocket::build().manage(Arc::new(cache))).await.unwrap();
+        let request = client.get("/missing.css");
+        let mut response = missing.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::HTML));
+        assert_eq!(response.body_mut().to_bytes().await.unwrap(), b"<h1>Not here</h1>".to_vec());
+
+        // Without a managed cache, there is no body.
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.get("/missing.css");
+        assert_eq!(CachedFile::NotFound.respond_to(request.inner()).unwrap_err(), Status::NotFound);
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::lock;
+
+
+/// Limits the number of responses that hold the same file open, for files that are served from the filesystem.
+#[derive(Debug)]
+pub(crate) struct OpenLimit {
+    max_per_path: usize,
+    /// The semaphores of the paths that are currently open. Each permit keeps its semaphore alive.
+    open: Mutex<HashMap<PathBuf, Weak<Semaphore>>>,
+}
+
+impl OpenLimit {
+    /// Allows `max_per_path` responses to hold a file open at once. At least one is always allowed.
+    pub(crate) fn new(max_per_path: usize) -> OpenLimit {
+        OpenLimit {
+            max_per_path: max_per_path.max(1),
+            open: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until fewer than `max_per_path` permits for the path are held.
+    pub(crate) async fn acquire(&self, path: &Path) -> OwnedSemaphorePermit {
+        let semaphore: Arc<Semaphore> = {
+            let mut open = self.open();
+            match open.get(path).and_then(Weak::upgrade) {
+                Some(semaphore) => semaphore,
+                None => {
+                    // Paths are only added here, so this is where the paths that are no longer open are forgotten.
+                    open.retain(|_, semaphore| semaphore.strong_count() > 0);
+                    let semaphore: Arc<Semaphore> = Arc::new(Semaphore::new(self.max_per_path));
+                    open.insert(path.to_path_buf(), Arc::downgrade(&semaphore));
+                    semaphore
+                }
+            }
+        };
+        // The semaphore is never closed.
+        semaphore.acquire_owned().await.expect("The semaphore was closed")
+    }
+
+    fn open(&self) -> MutexGuard<HashMap<PathBuf, Weak<Semaphore>>> {
+        lock::lock(&self.open)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn opens_past_the_limit_wait_for_a_permit() {
+        let open_limit = OpenLimit::new(2);
+        let path_a = PathBuf::from("a.bin");
+        let path_b = PathBuf::from("b.bin");
+
+        let first = open_limit.acquire(&path_a).await;
+        let _second = open_limit.acquire(&path_a).await;
+        assert!(timeout(Duration::from_millis(50), open_limit.acquire(&path_a)).await.is_err());
+        // Other paths have permits of their own.
+        let _other = open_limit.acquire(&path_b).await;
+
+        drop(first);
+        assert!(timeout(Duration::from_millis(50), open_limit.acquire(&path_a)).await.is_ok());
+    }
+}
@@ -0,0 +1,20 @@
+/// What the cache does with a new file when it is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Files are evicted to make room for the new file, if the eviction policy decides the new file is worth more.
+    ///
+    /// This is the default.
+    Evict,
+    /// The new file is served from the filesystem, and the cache is left as it is.
+    ///
+    /// The files in the cache only change when they are removed, expire, or are evicted by methods
+    /// like `Cache::shrink_to()`, so a steady workload is served from the same files.
+    /// New files are still read into the cache while there is room for them.
+    RejectToDisk,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::Evict
+    }
+}
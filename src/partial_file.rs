@@ -0,0 +1,105 @@
+use rocket::response::{Response, Responder};
+use rocket::http::{Status, ContentType, Header, Method};
+use rocket::request::Request;
+
+use std::fmt;
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::result;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use bytes::Bytes;
+
+use crate::range;
+use crate::cors::Cors;
+
+
+/// A range of bytes of a file that is too large to be cached as a whole, assembled from the chunks
+/// of the file that are cached.
+///
+/// This is created by `Cache::get_with_request()` for `Range` requests, if the cache was built with
+/// `CacheBuilder::range_chunk_size()`.
+pub struct PartialFile {
+    pub(crate) path: PathBuf,
+    /// The bytes of the range.
+    pub(crate) bytes: Bytes,
+    /// The range of bytes in the file that `bytes` holds.
+    pub(crate) range: Range<usize>,
+    /// The size of the whole file.
+    pub(crate) size: usize,
+    pub(crate) modified: Option<SystemTime>,
+    /// True if every chunk of the range was already in the cache.
+    pub(crate) from_memory: bool,
+    /// Headers configured by the cache that are added to the response.
+    pub(crate) headers: Vec<Header<'static>>,
+    /// Adds CORS headers to the response, depending on the origin of the request.
+    pub(crate) cors: Option<Arc<Cors>>,
+}
+
+impl fmt::Debug for PartialFile {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "path: {:?}, range: {:?}, size: {}", self.path, self.range, self.size)
+    }
+}
+
+impl PartialFile {
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The range of bytes in the file that is sent.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// The bytes of the range.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The size of the whole file.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+
+/// Sends the range as a `206 Partial Content` response, with a `Content-Range` header that tells the
+/// client which bytes of the file it contains, and a `Content-Type` derived from the file's extension.
+///
+/// Any headers configured by the cache, like `Cache-Control` and CORS headers, are added to the response.
+/// If the modification time of the file is known, the response will include a `Last-Modified` header.
+impl<'r> Responder<'r, 'static> for PartialFile {
+    fn respond_to(self, request: &'r Request) -> result::Result<Response<'static>, Status> {
+        let mut response = Response::new();
+        if let Some(ext) = self.path.extension() {
+            if let Some(ct) = ContentType::from_extension(&ext.to_string_lossy()) {
+                response.set_header(ct);
+            }
+        }
+
+        for header in self.headers {
+            response.set_header(header);
+        }
+        if let Some(ref cors) = self.cors {
+            cors.apply(request, &mut response);
+        }
+
+        if let Some(modified) = self.modified {
+            response.set_raw_header("Last-Modified", httpdate::fmt_http_date(modified));
+        }
+
+        response.set_status(Status::PartialContent);
+        response.set_raw_header("Accept-Ranges", "bytes");
+        response.set_raw_header("Content-Range", range::content_range(&self.range, self.size));
+        if request.method() == Method::Head {
+            response.set_raw_header("Content-Length", self.bytes.len().to_string());
+        } else {
+            response.set_sized_body(Some(self.bytes.len()), Cursor::new(self.bytes));
+        }
+        Ok(response)
+    }
+}
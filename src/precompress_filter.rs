@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use rocket::http::ContentType;
+
+use crate::sniff;
+
+
+/// The types of files that are skipped by default when precompressing, because they are already compressed.
+pub(crate) const DEFAULT_DENIED: &[&str] = &[
+    "image/png", "image/jpeg", "image/gif", "image/webp", "image/avif",
+    "video/*", "audio/*",
+    "zip", "gz", "tgz", "br", "bz2", "xz", "zst", "7z", "rar", "jar",
+    "woff", "woff2",
+];
+
+/// Decides which files are worth compressing, based on their type.
+///
+/// Entries that contain a `/` are media types, like `image/png`, or `video/*` to match every subtype.
+/// Other entries are file extensions, like `zip`.
+/// The media type of a file is taken from its extension, or detected from its contents if the
+/// extension doesn't imply one.
+#[derive(Debug, Clone)]
+pub(crate) struct PrecompressFilter {
+    denied: Vec<String>,
+}
+
+impl Default for PrecompressFilter {
+    fn default() -> PrecompressFilter {
+        PrecompressFilter::new(DEFAULT_DENIED)
+    }
+}
+
+impl PrecompressFilter {
+    pub(crate) fn new<S: AsRef<str>>(denied: &[S]) -> PrecompressFilter {
+        let denied: Vec<String> = denied.iter()
+            .map(|entry| entry.as_ref().trim().trim_start_matches('.').to_lowercase())
+            .collect();
+        PrecompressFilter { denied }
+    }
+
+    /// Returns true if the file should be compressed, because it isn't of one of the denied types.
+    pub(crate) fn allows(&self, path: &Path, bytes: &[u8]) -> bool {
+        if self.denied.is_empty() {
+            return true;
+        }
+        let extension: Option<String> = path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        let content_type: Option<ContentType> = extension.as_ref()
+            .and_then(|ext| ContentType::from_extension(ext))
+            .or_else(|| sniff::sniff(bytes));
+        let media_type: Option<String> = content_type
+            .map(|content_type| format!("{}/{}", content_type.top(), content_type.sub()).to_lowercase());
+
+        !self.denied.iter().any(|entry| match entry.split_once('/') {
+            Some((top, "*")) => media_type.as_ref().map_or(false, |media_type| media_type.split('/').next() == Some(top)),
+            Some(_) => media_type.as_ref() == Some(entry),
+            None => extension.as_ref() == Some(entry),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_media_is_denied_by_default() {
+        let filter = PrecompressFilter::default();
+        assert!(!filter.allows(Path::new("www/logo.png"), b""));
+        assert!(!filter.allows(Path::new("www/photo.JPG"), b""));
+        assert!(!filter.allows(Path::new("www/intro.mp4"), b""));
+        assert!(!filter.allows(Path::new("www/release.tar.gz"), b""));
+        assert!(!filter.allows(Path::new("www/font.woff2"), b""));
+        // The type of extensionless files is detected from their contents.
+        assert!(!filter.allows(Path::new("www/image"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+
+        assert!(filter.allows(Path::new("www/app.js"), b""));
+        assert!(filter.allows(Path::new("www/icon.svg"), b""));
+        assert!(filter.allows(Path::new("www/README"), b"plain text"));
+    }
+
+    #[test]
+    fn deny_list_can_be_replaced() {
+        let filter = PrecompressFilter::new(&["image/*", ".JSON"]);
+        assert!(!filter.allows(Path::new("www/icon.svg"), b""));
+        assert!(!filter.allows(Path::new("www/data.json"), b""));
+        assert!(filter.allows(Path::new("www/intro.mp4"), b""));
+
+        let filter = PrecompressFilter::new::<&str>(&[]);
+        assert!(filter.allows(Path::new("www/logo.png"), b""));
+    }
+}
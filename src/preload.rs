@@ -0,0 +1,393 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glob::{Pattern, PatternError};
+use rocket::futures::stream::{self, StreamExt};
+use tokio::sync::Semaphore;
+
+use crate::cache::Cache;
+use crate::cached_file::CachedFile;
+
+
+/// The number of files that are read at once when loading files in bulk, unless it is configured
+/// with `CacheBuilder::preload_concurrency()`: twice the number of CPUs, so reads that wait for
+/// the disk don't leave the CPUs idle, or 16 if the number of CPUs isn't known.
+pub(crate) fn default_preload_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|cpus| cpus.get() * 2)
+        .unwrap_or(16)
+}
+
+/// Limits the number of files that are read at once by `preload()`, `warm_dir()`, and `warm_from_manifest()`.
+#[derive(Debug)]
+pub(crate) struct PreloadLimit {
+    permits: usize,
+    semaphore: Semaphore,
+}
+
+impl PreloadLimit {
+    /// Allows `permits` files to be read at once. At least one is always allowed.
+    pub(crate) fn new(permits: usize) -> PreloadLimit {
+        let permits: usize = permits.max(1);
+        PreloadLimit {
+            permits,
+            semaphore: Semaphore::new(permits),
+        }
+    }
+}
+
+
+/// A summary of the outcome of preloading files into the cache.
+#[derive(Debug, Default)]
+pub struct PreloadSummary {
+    /// The files that were read into the cache.
+    pub loaded: Vec<PathBuf>,
+    /// The files that could not be read.
+    pub failed: Vec<(PathBuf, io::Error)>,
+    /// The files that were not read because they are smaller than the cache's `min_file_size`, larger
+    /// than its `max_file_size`, or have an extension the cache doesn't store.
+    pub skipped: Vec<PathBuf>,
+    /// The files that were not accepted into the cache, because there wasn't enough room for them,
+    /// or because the files already in the cache have a higher priority.
+    pub rejected: Vec<PathBuf>,
+}
+
+/// A summary of the outcome of warming the cache with the files in a directory.
+#[derive(Debug, Default)]
+pub struct WarmSummary {
+    /// The number of files that were read into the cache.
+    pub files_loaded: usize,
+    /// The sum of the sizes of the files that were read into the cache.
+    pub bytes_loaded: usize,
+    /// The number of matching files that were not read into the cache, because they didn't fit
+    /// within the cache's size constraints, weren't accepted, or the cache was already full.
+    pub files_skipped: usize,
+    /// The sum of the sizes of the files that were skipped.
+    pub bytes_skipped: usize,
+    /// The files and directories that could not be read.
+    pub failed: Vec<(PathBuf, io::Error)>,
+}
+
+pub(crate) enum PreloadOutcome {
+    Loaded,
+    Failed(io::Error),
+    Skipped,
+    Rejected,
+}
+
+
+impl Cache {
+    /// Reads the files into the cache ahead of time, so the first requests for them don't have to
+    /// wait for the disk.
+    ///
+    /// Files are read concurrently, but the number of files that are read at once is bounded by
+    /// `CacheBuilder::preload_concurrency()` to avoid exhausting file descriptors.
+    /// The files are subject to the same size limits and eviction policy as files that are added by `get()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, PreloadSummary};
+    /// use std::path::PathBuf;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let summary: PreloadSummary = cache.preload(vec![PathBuf::from("www/index.html")]).await;
+    /// for (path, error) in summary.failed {
+    ///     println!("Could not preload {:?}: {}", path, error);
+    /// }
+    /// # }
+    /// ```
+    pub async fn preload<I: IntoIterator<Item = PathBuf>>(&self, paths: I) -> PreloadSummary {
+        let outcomes: Vec<(PathBuf, PreloadOutcome)> = stream::iter(paths)
+            .map(|path| async move {
+                let outcome: PreloadOutcome = self.preload_file(&path).await;
+                (path, outcome)
+            })
+            .buffer_unordered(self.preload_limit.permits)
+            .collect()
+            .await;
+
+        let mut summary = PreloadSummary::default();
+        for (path, outcome) in outcomes {
+            match outcome {
+                PreloadOutcome::Loaded => summary.loaded.push(path),
+                PreloadOutcome::Failed(e) => summary.failed.push((path, e)),
+                PreloadOutcome::Skipped => summary.skipped.push(path),
+                PreloadOutcome::Rejected => summary.rejected.push(path),
+            }
+        }
+        summary
+    }
+
+    /// Reads the files in the directory and its subdirectories that match the glob patterns into the cache.
+    ///
+    /// Multiple patterns can be provided by separating them with commas, like `"*.js,*.css,*.woff2"`.
+    /// The patterns are matched against the paths of the files, which start with `root`.
+    ///
+    /// At most `concurrency` files are read at once, and no more than `CacheBuilder::preload_concurrency()`
+    /// files are read at once by all of the bulk loads of the cache together.
+    /// Once the cache is full, the remaining files are skipped without being read.
+    /// Symbolic links are followed, but every directory is only walked once, so loops of links are not a problem.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `PatternError` if one of the patterns is not a valid glob.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, WarmSummary};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let summary: WarmSummary = cache.warm_dir("www/", "*.js,*.css", 8).await.unwrap();
+    /// println!("Loaded {} bytes from {} files", summary.bytes_loaded, summary.files_loaded);
+    /// # }
+    /// ```
+    pub async fn warm_dir<P: AsRef<Path>>(&self, root: P, patterns: &str, concurrency: usize) -> Result<WarmSummary, PatternError> {
+        let patterns: Vec<Pattern> = patterns.split(',')
+            .map(|pattern| Pattern::new(pattern.trim()))
+            .collect::<Result<_, PatternError>>()?;
+
+        let mut summary = WarmSummary::default();
+        let paths: Vec<PathBuf> = walk_dir(root.as_ref(), &mut summary.failed).await
+            .into_iter()
+            .filter(|path| patterns.iter().any(|pattern| pattern.matches_path(path)))
+            .collect();
+
+        let outcomes: Vec<(PathBuf, PreloadOutcome)> = stream::iter(paths)
+            .map(|path| async move {
+                let outcome: PreloadOutcome = if self.used_bytes() >= self.capacity_bytes() {
+                    PreloadOutcome::Skipped // The cache is full.
+                } else {
+                    self.preload_file(&path).await
+                };
+                (path, outcome)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+
+        for (path, outcome) in outcomes {
+            let size: usize = match tokio::fs::metadata(&path).await {
+                Ok(metadata) => metadata.len() as usize,
+                Err(_) => 0,
+            };
+            match outcome {
+                PreloadOutcome::Loaded => {
+                    summary.files_loaded += 1;
+                    summary.bytes_loaded += size;
+                }
+                PreloadOutcome::Skipped | PreloadOutcome::Rejected => {
+                    summary.files_skipped += 1;
+                    summary.bytes_skipped += size;
+                }
+                PreloadOutcome::Failed(e) => summary.failed.push((path, e)),
+            }
+        }
+        Ok(summary)
+    }
+
+    pub(crate) async fn preload_file(&self, path: &PathBuf) -> PreloadOutcome {
+        // The semaphore is never closed.
+        let _permit = self.preload_limit.semaphore.acquire().await.expect("The semaphore was closed");
+        let size: usize = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.len() as usize,
+            Err(e) => return PreloadOutcome::Failed(e),
+        };
+        if size > self.max_file_size || size < self.min_file_size || !self.extension_filter.allows(path) {
+            return PreloadOutcome::Skipped;
+        }
+
+        // A request may be reading the same file, so the file is only read once, like in `get()`.
+        let key: PathBuf = self.normalize_key(path);
+        let _load_guard = self.in_flight.acquire(&key).await;
+        if self.contains_key(&key) {
+            return PreloadOutcome::Loaded;
+        }
+
+        match self.try_insert(key).await {
+            CachedFile::InMemory(_) => PreloadOutcome::Loaded,
+            CachedFile::FileSystem(_) | CachedFile::Partial(_) => PreloadOutcome::Rejected,
+            // Nobody is waiting for the file, so it is read to the end to add it to the cache.
+            CachedFile::Streaming(streaming_file) => {
+                if let Err(e) = streaming_file.drain().await {
+                    return PreloadOutcome::Failed(e);
+                }
+                self.insert_streamed_files().await;
+                if self.contains_key(path) {
+                    PreloadOutcome::Loaded
+                } else {
+                    PreloadOutcome::Rejected
+                }
+            }
+            CachedFile::NotFound => PreloadOutcome::Failed(io::Error::new(io::ErrorKind::NotFound, "The file could not be read.")),
+        }
+    }
+}
+
+/// Finds every file in the directory and its subdirectories.
+/// Directories that can't be read are added to `failed`.
+async fn walk_dir(root: &Path, failed: &mut Vec<(PathBuf, io::Error)>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    // Directories are identified by their canonical path, so symbolic links to directories that have already been walked are skipped.
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    let mut dirs: Vec<PathBuf> = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        match tokio::fs::canonicalize(&dir).await {
+            Ok(canonical_dir) => {
+                if !visited.insert(canonical_dir) {
+                    continue;
+                }
+            }
+            Err(e) => {
+                failed.push((dir, e));
+                continue;
+            }
+        }
+
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                failed.push((dir, e));
+                continue;
+            }
+        };
+        loop {
+            match entries.next_entry().await {
+                Ok(Some(entry)) => {
+                    let path: PathBuf = entry.path();
+                    // Follows symbolic links.
+                    match tokio::fs::metadata(&path).await {
+                        Ok(metadata) if metadata.is_dir() => dirs.push(path),
+                        Ok(metadata) if metadata.is_file() => files.push(path),
+                        Ok(_) => {}
+                        Err(e) => failed.push((path, e)),
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    failed.push((dir.clone(), e));
+                    break;
+                }
+            }
+        }
+    }
+    files
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn preload_files() {
+        let temp_dir = TempDir::new("preload").unwrap();
+        let small_path: PathBuf = temp_dir.path().join("small.txt");
+        let large_path: PathBuf = temp_dir.path().join("large.txt");
+        let missing_path: PathBuf = temp_dir.path().join("missing.txt");
+        std::fs::write(&small_path, vec![0u8; 1024]).unwrap();
+        std::fs::write(&large_path, vec![0u8; 1024 * 8]).unwrap();
+
+        let cache: Cache = CacheBuilder::new()
+            .max_file_size(1024 * 4)
+            .build()
+            .unwrap();
+
+        let summary: PreloadSummary = cache.preload(vec![small_path.clone(), large_path.clone(), missing_path.clone()]).await;
+
+        assert_eq!(summary.loaded, vec![small_path.clone()]);
+        assert_eq!(summary.skipped, vec![large_path]);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0, missing_path);
+        assert!(cache.contains_key(&small_path));
+    }
+
+    #[tokio::test]
+    async fn preloading_a_file_that_is_being_read_waits_for_it() {
+        let temp_dir = TempDir::new("preload").unwrap();
+        let path: PathBuf = temp_dir.path().join("file.txt");
+        std::fs::write(&path, vec![0u8; 1024]).unwrap();
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+
+        let (cached_file, summary) = tokio::join!(cache.get(&path), cache.preload(vec![path.clone()]));
+        assert!(cached_file.is_in_memory());
+        assert_eq!(summary.loaded, vec![path.clone()]);
+        assert_eq!(cache.used_bytes(), 1024);
+        assert!(cache.in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn warm_dir_loads_matching_files() {
+        let temp_dir = TempDir::new("warm").unwrap();
+        let root: PathBuf = temp_dir.path().join("www");
+        std::fs::create_dir_all(root.join("js/vendor")).unwrap();
+        std::fs::write(root.join("js/app.js"), vec![0u8; 1024]).unwrap();
+        std::fs::write(root.join("js/vendor/lib.js"), vec![0u8; 2048]).unwrap();
+        std::fs::write(root.join("style.css"), vec![0u8; 512]).unwrap();
+        std::fs::write(root.join("index.html"), vec![0u8; 512]).unwrap();
+        // A link back to the root must not make the walk loop forever.
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("js/loop")).unwrap();
+
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        let summary: WarmSummary = cache.warm_dir(&root, "*.js, *.css", 2).await.unwrap();
+
+        assert_eq!(summary.files_loaded, 3);
+        assert_eq!(summary.bytes_loaded, 1024 + 2048 + 512);
+        assert_eq!(summary.files_skipped, 0);
+        assert!(summary.failed.is_empty());
+        assert!(cache.contains_key(root.join("js/vendor/lib.js")));
+        assert!(!cache.contains_key(root.join("index.html")));
+
+        assert!(cache.warm_dir(&root, "[", 2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn warm_dir_stops_once_cache_is_full() {
+        let temp_dir = TempDir::new("warm").unwrap();
+        for i in 0..4 {
+            std::fs::write(temp_dir.path().join(format!("{}.txt", i)), vec![0u8; 1024]).unwrap();
+        }
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(2048)
+            .build()
+            .unwrap();
+        let summary: WarmSummary = cache.warm_dir(temp_dir.path(), "*.txt", 1).await.unwrap();
+
+        assert_eq!(summary.files_loaded, 2);
+        assert_eq!(summary.files_skipped, 2);
+        assert_eq!(summary.bytes_skipped, 2048);
+    }
+
+    #[tokio::test]
+    async fn bulk_loads_wait_for_a_permit() {
+        let temp_dir = TempDir::new("preload").unwrap();
+        let path: PathBuf = temp_dir.path().join("index.html");
+        std::fs::write(&path, vec![0u8; 1024]).unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .preload_concurrency(1)
+            .build()
+            .unwrap();
+
+        let permit = cache.preload_limit.semaphore.acquire().await.unwrap();
+        let preload = cache.preload(vec![path.clone()]);
+        tokio::pin!(preload);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), &mut preload).await.is_err());
+        assert!(!cache.contains_key(&path));
+
+        drop(permit);
+        assert_eq!(preload.await.loaded, vec![path]);
+    }
+}
@@ -1,5 +1,34 @@
+use std::fmt;
 use std::usize;
 
+
+/// The priority of a file in the cache, as returned by `FileStats::priority()`.
+///
+/// Files with a higher priority are kept in the cache over files with a lower priority.
+/// Priorities can be compared, and turned into a score with `score()`. Nothing else about how they
+/// are represented is promised, so it can change, for example to let priorities decay over time,
+/// without breaking code that compares them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Priority(usize);
+
+impl Priority {
+    pub(crate) fn new(score: usize) -> Priority {
+        Priority(score)
+    }
+
+    /// The score that the cache's priority function calculated for the file, plus its priority bias.
+    pub fn score(&self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+
 /// The default priority function used for determining if a file should be in the cache.
 ///
 /// This function takes the square root of the size of the file times the number of times it has been accessed.
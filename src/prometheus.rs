@@ -0,0 +1,80 @@
+use std::fmt::Write;
+
+use crate::cache::Cache;
+use crate::cache_stats::CacheStats;
+
+
+/// The prefix of the names of every metric.
+const NAMESPACE: &str = "rocket_file_cache";
+
+
+impl Cache {
+    /// Renders the cache's stats in the Prometheus text exposition format, so they can be served
+    /// from a `/metrics` route and scraped.
+    ///
+    /// The hits, misses, not founds, and evictions are counters, which only ever increase while the
+    /// cache exists. The number of bytes and files in the cache, and its capacity, are gauges.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// let metrics: String = cache.render_prometheus();
+    /// assert!(metrics.contains("rocket_file_cache_hits_total 0"));
+    /// ```
+    pub fn render_prometheus(&self) -> String {
+        let stats: CacheStats = self.stats();
+        let metrics: [(&str, &str, &str, usize); 7] = [
+            ("hits_total", "counter", "The number of times a file was served from the cache.", stats.hits),
+            ("misses_total", "counter", "The number of times a file had to be served from the filesystem.", stats.misses),
+            ("not_found_total", "counter", "The number of times a file could not be found.", stats.not_found),
+            ("evictions_total", "counter", "The number of files that were removed from the cache to make room for other files.", stats.evictions),
+            ("bytes", "gauge", "The sum of the sizes of the files in the cache.", stats.bytes),
+            ("capacity_bytes", "gauge", "The number of bytes the cache can hold.", stats.capacity_bytes),
+            ("entries", "gauge", "The number of files in the cache.", stats.entries),
+        ];
+
+        let mut rendered = String::new();
+        for &(name, metric_type, help, value) in metrics.iter() {
+            // Writing to a String can't fail.
+            let _ = writeln!(rendered, "# HELP {}_{} {}", NAMESPACE, name, help);
+            let _ = writeln!(rendered, "# TYPE {}_{} {}", NAMESPACE, name, metric_type);
+            let _ = writeln!(rendered, "{}_{} {}", NAMESPACE, name, value);
+        }
+        rendered
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+    use super::*;
+
+    #[tokio::test]
+    async fn renders_every_metric() {
+        let temp_dir = TempDir::new("prometheus").unwrap();
+        let path = temp_dir.path().join("index.html");
+        std::fs::write(&path, vec![0u8; 1024]).unwrap();
+
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1024 * 1024)
+            .build()
+            .unwrap();
+        cache.get(&path).await;
+        cache.get(&path).await;
+
+        let metrics: String = cache.render_prometheus();
+        assert!(metrics.contains("# TYPE rocket_file_cache_hits_total counter\nrocket_file_cache_hits_total 2\n"));
+        assert!(metrics.contains("rocket_file_cache_misses_total 0\n"));
+        assert!(metrics.contains("# TYPE rocket_file_cache_bytes gauge\nrocket_file_cache_bytes 1024\n"));
+        assert!(metrics.contains("rocket_file_cache_capacity_bytes 1048576\n"));
+        assert!(metrics.contains("rocket_file_cache_entries 1\n"));
+        assert_eq!(metrics.lines().filter(|line| line.starts_with("# HELP")).count(), 7);
+    }
+}
@@ -0,0 +1,177 @@
+use std::ops::Range;
+
+
+/// The outcome of parsing a `Range` header for a file of a known length.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ByteRange {
+    /// The range of bytes in the file that should be sent.
+    Satisfiable(Range<usize>),
+    /// The ranges of bytes in the file that should be sent as a `multipart/byteranges` body.
+    /// They are sorted, and don't overlap or touch each other.
+    Multiple(Vec<Range<usize>>),
+    /// None of the requested ranges lie inside of the file.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Parses the ranges in a `Range` header, such as `bytes=0-499`, `bytes=500-`, `bytes=-500`,
+    /// or `bytes=0-99,200-299`.
+    ///
+    /// Ranges that lie outside of the file are dropped, and ranges that overlap or touch are coalesced
+    /// into one, so a header with several ranges may still result in a single range.
+    ///
+    /// Returns `None` if any of the ranges are malformed, in which case the header should be ignored
+    /// and the whole file should be sent.
+    pub(crate) fn parse(header: &str, len: usize) -> Option<ByteRange> {
+        let specs: &str = header.trim().strip_prefix("bytes=")?;
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for spec in specs.split(',') {
+            let spec: &str = spec.trim();
+            // Empty elements are allowed in the list, like in `bytes=0-1,,5-6`.
+            if spec.is_empty() {
+                continue;
+            }
+            if let Some(range) = parse_spec(spec, len)? {
+                ranges.push(range);
+            }
+        }
+
+        let mut ranges: Vec<Range<usize>> = coalesce(ranges);
+        match ranges.len() {
+            0 => Some(ByteRange::Unsatisfiable),
+            1 => Some(ByteRange::Satisfiable(ranges.remove(0))),
+            _ => Some(ByteRange::Multiple(ranges)),
+        }
+    }
+}
+
+/// Parses a single range, returning `Some(None)` if it lies outside of the file, and `None` if it is malformed.
+fn parse_spec(spec: &str, len: usize) -> Option<Option<Range<usize>>> {
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // A suffix range, requesting the last n bytes of the file.
+        let suffix: usize = end.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(None);
+        }
+        return Some(Some(len.saturating_sub(suffix)..len));
+    }
+
+    let start: usize = start.parse().ok()?;
+    let end: Option<usize> = if end.is_empty() {
+        None
+    } else {
+        Some(end.parse().ok()?)
+    };
+
+    if let Some(end) = end {
+        if end < start {
+            return None;
+        }
+    }
+    if start >= len {
+        return Some(None);
+    }
+
+    // The last byte position is inclusive, and is clamped to the length of the file.
+    let end: usize = match end {
+        Some(end) => usize::min(end.saturating_add(1), len),
+        None => len,
+    };
+    Some(Some(start..end))
+}
+
+/// Sorts the ranges, and merges the ones that overlap or are adjacent.
+fn coalesce(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut coalesced: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if range.start <= last.end => last.end = usize::max(last.end, range.end),
+            _ => coalesced.push(range),
+        }
+    }
+    coalesced
+}
+
+/// Creates the value of a `Content-Range` header for a range of a file.
+pub(crate) fn content_range(range: &Range<usize>, len: usize) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, len)
+}
+
+/// Creates a `multipart/byteranges` body, containing each of the ranges of the bytes in its own part.
+///
+/// Each part has a `Content-Range` header, and a `Content-Type` header if the type of the file is known.
+pub(crate) fn multipart_body(bytes: &[u8], ranges: &[Range<usize>], boundary: &str, content_type: Option<&str>) -> Vec<u8> {
+    let mut body: Vec<u8> = Vec::with_capacity(ranges.iter().map(|range| range.len() + 128).sum());
+    for range in ranges {
+        body.extend_from_slice(format!("\r\n--{}\r\n", boundary).as_bytes());
+        if let Some(content_type) = content_type {
+            body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        body.extend_from_slice(format!("Content-Range: {}\r\n\r\n", content_range(range, bytes.len())).as_bytes());
+        body.extend_from_slice(&bytes[range.clone()]);
+    }
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(ByteRange::parse("bytes=0-499", 1000), Some(ByteRange::Satisfiable(0..500)));
+        assert_eq!(ByteRange::parse("bytes=500-", 1000), Some(ByteRange::Satisfiable(500..1000)));
+        assert_eq!(ByteRange::parse("bytes=-100", 1000), Some(ByteRange::Satisfiable(900..1000)));
+        assert_eq!(ByteRange::parse("bytes=900-2000", 1000), Some(ByteRange::Satisfiable(900..1000)));
+        assert_eq!(ByteRange::parse("bytes=1000-", 1000), Some(ByteRange::Unsatisfiable));
+        assert_eq!(ByteRange::parse("bytes=-0", 1000), Some(ByteRange::Unsatisfiable));
+    }
+
+    #[test]
+    fn ignore_malformed_ranges() {
+        assert_eq!(ByteRange::parse("bytes=500-100", 1000), None);
+        assert_eq!(ByteRange::parse("items=0-1", 1000), None);
+        assert_eq!(ByteRange::parse("bytes=a-b", 1000), None);
+        assert_eq!(ByteRange::parse("bytes=0-1,5-b", 1000), None);
+    }
+
+    #[test]
+    fn parse_multiple_ranges() {
+        assert_eq!(ByteRange::parse("bytes=0-99, 200-299", 1000), Some(ByteRange::Multiple(vec![0..100, 200..300])));
+        assert_eq!(ByteRange::parse("bytes=200-299,0-99", 1000), Some(ByteRange::Multiple(vec![0..100, 200..300])));
+        assert_eq!(ByteRange::parse("bytes=0-1,,-100", 1000), Some(ByteRange::Multiple(vec![0..2, 900..1000])));
+    }
+
+    #[test]
+    fn overlapping_ranges_are_coalesced() {
+        assert_eq!(ByteRange::parse("bytes=0-99,50-149", 1000), Some(ByteRange::Satisfiable(0..150)));
+        assert_eq!(ByteRange::parse("bytes=100-199,0-99", 1000), Some(ByteRange::Satisfiable(0..200)));
+        assert_eq!(ByteRange::parse("bytes=0-9,5-6,800-,-100", 1000), Some(ByteRange::Multiple(vec![0..10, 800..1000])));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges_are_dropped() {
+        assert_eq!(ByteRange::parse("bytes=0-99,5000-5999", 1000), Some(ByteRange::Satisfiable(0..100)));
+        assert_eq!(ByteRange::parse("bytes=1000-,2000-2999", 1000), Some(ByteRange::Unsatisfiable));
+    }
+
+    #[test]
+    fn multipart_body_has_a_part_per_range() {
+        let body: Vec<u8> = multipart_body(b"0123456789", &[0..2, 5..7], "b", Some("text/plain"));
+        let expected: &str = "\r\n--b\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+            \r\n--b\r\nContent-Type: text/plain\r\nContent-Range: bytes 5-6/10\r\n\r\n56\
+            \r\n--b--\r\n";
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+    }
+
+    #[test]
+    fn content_range_header() {
+        assert_eq!(content_range(&(0..500), 1000), "bytes 0-499/1000");
+    }
+}
@@ -0,0 +1,286 @@
+use std::ffi::OsString;
+use std::io::{self, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use bytes::{Bytes, BytesMut};
+use rocket::request::Request;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::cache::Cache;
+use crate::cached_file::CachedFile;
+use crate::etag_mode::EtagMode;
+use crate::in_memory_file::InMemoryFile;
+use crate::partial_file::PartialFile;
+use crate::range::ByteRange;
+
+
+/// The largest number of chunks that are sent in response to a single request.
+const MAX_CHUNKS_PER_RESPONSE: usize = 4;
+
+
+/// The key under which a chunk of a file is cached.
+///
+/// Paths can't contain NUL bytes, so the key is never the path of a real file.
+pub(crate) fn chunk_key(path: &Path, index: usize) -> PathBuf {
+    let mut key: OsString = path.as_os_str().to_os_string();
+    key.push(format!("\0range-chunk-{}", index));
+    PathBuf::from(key)
+}
+
+/// Returns true if the key is the key of a chunk, rather than the path of a file.
+pub(crate) fn is_chunk_key(key: &Path) -> bool {
+    key.to_string_lossy().contains("\0range-chunk-")
+}
+
+/// The path of the file that the chunk belongs to, or the key itself if it isn't the key of a chunk.
+pub(crate) fn file_of_key(key: &Path) -> PathBuf {
+    let key_string = key.to_string_lossy();
+    match key_string.find("\0range-chunk-") {
+        Some(end) => PathBuf::from(&key_string[..end]),
+        None => key.to_path_buf(),
+    }
+}
+
+/// The indices of the chunks that hold the start of the range, and the part of the range that they hold.
+/// At most `MAX_CHUNKS_PER_RESPONSE` chunks are used, so the end of a long range may be cut off.
+fn chunks_for_range(range: &Range<usize>, chunk_size: usize) -> (Range<usize>, Range<usize>) {
+    let first: usize = range.start / chunk_size;
+    let last: usize = usize::min((range.end - 1) / chunk_size, first + MAX_CHUNKS_PER_RESPONSE - 1);
+    let end: usize = usize::min(range.end, (last + 1) * chunk_size);
+    (first..last + 1, range.start..end)
+}
+
+
+impl Cache {
+    /// Serves the range of bytes the request asks for from the cached chunks of the file, if the
+    /// cache was built with `CacheBuilder::range_chunk_size()`, and the file is too large to be cached as a whole.
+    /// Chunks that aren't in the cache yet are read from the filesystem, and offered to the cache.
+    ///
+    /// Returns `None` if the request should be handled like any other, because it doesn't ask for a
+    /// single range, has an `If-Range` header, or is for a file that may be cached as a whole.
+    pub(crate) async fn get_range_from_chunks(&self, path: &PathBuf, request: &Request<'_>) -> Option<CachedFile> {
+        let chunk_size: usize = self.range_chunk_size?;
+        let header: &str = request.headers().get_one("Range")?;
+        // Without an entity tag, the cache can't tell whether the range the client has is still valid.
+        if request.headers().contains("If-Range") || self.contains_key(path) || !self.is_within_root(path).await {
+            return None;
+        }
+        let metadata = tokio::fs::metadata(path).await.ok()?;
+        let size: usize = metadata.len() as usize;
+        if !metadata.is_file() || (size <= self.max_file_size && size <= self.capacity_bytes()) {
+            return None;
+        }
+        let requested: Range<usize> = match ByteRange::parse(header, size)? {
+            ByteRange::Satisfiable(range) => range,
+            ByteRange::Multiple(_) | ByteRange::Unsatisfiable => return None,
+        };
+        let modified: Option<SystemTime> = metadata.modified().ok();
+
+        let (chunks, range) = chunks_for_range(&requested, chunk_size);
+        let mut bytes = BytesMut::with_capacity(range.len());
+        let mut from_memory: bool = true;
+        for index in chunks {
+            let chunk_start: usize = index * chunk_size;
+            let chunk_range: Range<usize> = chunk_start..usize::min(chunk_start + chunk_size, size);
+            let (chunk, cached) = match self.get_chunk(path, index, chunk_range, modified).await {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    debug!("Chunk {} of {:?} could not be read: {}", index, path, e);
+                    return None;
+                }
+            };
+            from_memory &= cached;
+            let start: usize = range.start.saturating_sub(chunk_start);
+            let end: usize = usize::min(range.end - chunk_start, chunk.len());
+            bytes.extend_from_slice(&chunk[start..end]);
+        }
+
+        let cached_file = CachedFile::Partial(PartialFile {
+            path: path.clone(),
+            bytes: bytes.freeze(),
+            range,
+            size,
+            modified,
+            from_memory,
+            headers: Vec::new(),
+            cors: None,
+        });
+        self.counters.record(&cached_file);
+        Some(self.add_response_headers(cached_file))
+    }
+
+    /// Removes the cached chunks of the file, so they aren't served after the file itself was invalidated.
+    pub(crate) fn remove_chunks(&self, path: &Path) {
+        if self.range_chunk_size.is_none() {
+            return;
+        }
+        let chunks: Vec<PathBuf> = self.file_map
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| is_chunk_key(key) && file_of_key(key) == path)
+            .cloned()
+            .collect();
+        for key in chunks {
+            self.remove_entry(&key);
+        }
+    }
+
+    /// Gets the bytes of the chunk from the cache, or reads them from the file and offers them to the cache.
+    /// Returns true along with the bytes if they were in the cache.
+    ///
+    /// Cached chunks of a file that was modified since they were read are read again.
+    async fn get_chunk(&self, path: &Path, index: usize, range: Range<usize>, modified: Option<SystemTime>) -> io::Result<(Bytes, bool)> {
+        let key: PathBuf = chunk_key(path, index);
+        self.increment_access_count(&key);
+        let cached: Option<Bytes> = self.file_map.find(&key)
+            .filter(|entry| entry.get().stats.modified == modified && entry.get().stats.size == range.len())
+            .map(|entry| entry.get().bytes.clone());
+        if let Some(bytes) = cached {
+            if !self.is_expired(&key) {
+                self.update_stats(&key);
+                return Ok((bytes, true));
+            }
+        }
+
+        let mut file: File = File::open(path).await?;
+        file.seek(SeekFrom::Start(range.start as u64)).await?;
+        let mut buffer: Vec<u8> = vec![0; range.len()];
+        file.read_exact(&mut buffer).await?;
+        let bytes: Bytes = Bytes::from(buffer);
+
+        // Chunks are only sent as part of a range, so they don't need to be hashed for an entity tag.
+        let mut chunk: InMemoryFile = InMemoryFile::from_bytes_with_etag_mode(bytes.clone(), modified, self.hash_algorithm, EtagMode::WeakSizeMtime);
+        chunk.generation = self.generation.load(Ordering::Relaxed);
+        self.insert_loaded_file(key, chunk);
+        Ok((bytes, false))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use rocket::http::{Header, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::response::Responder;
+    use crate::cache_builder::CacheBuilder;
+
+    #[test]
+    fn long_ranges_are_cut_off() {
+        assert_eq!(chunks_for_range(&(1000..1101), 1024), (0..2, 1000..1101));
+        assert_eq!(chunks_for_range(&(2048..3072), 1024), (2..3, 2048..3072));
+        assert_eq!(chunks_for_range(&(100..100_000), 1024), (0..4, 100..4096));
+    }
+
+    #[tokio::test]
+    async fn ranges_of_large_files_are_served_from_cached_chunks() {
+        let temp_dir = TempDir::new("range_chunks").unwrap();
+        let path: PathBuf = temp_dir.path().join("video.mp4");
+        let contents: Vec<u8> = (0..8192).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .max_file_size(1024)
+            .range_chunk_size(1024)
+            .build()
+            .unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+
+        let request = client.get("/").header(Header::new("Range", "bytes=1000-1100"));
+        match cache.get_with_request(&path, request.inner()).await {
+            CachedFile::Partial(file) => {
+                assert_eq!(file.range(), 1000..1101);
+                assert_eq!(file.bytes(), &contents[1000..1101]);
+                assert!(!file.from_memory);
+            }
+            other => panic!("Expected a partial file, got {:?}", other),
+        }
+        // The file itself isn't cached, only the two chunks that hold the range, which aren't listed as files.
+        assert!(!cache.contains_key(&path));
+        assert_eq!(cache.file_map.iter().count(), 2);
+        assert_eq!(cache.entry_count(), 0);
+        assert!(cache.keys().is_empty());
+        assert!(cache.snapshot().is_empty());
+
+        let cached_file: CachedFile = cache.get_with_request(&path, request.inner()).await;
+        match cached_file {
+            CachedFile::Partial(ref file) => assert!(file.from_memory),
+            ref other => panic!("Expected a partial file, got {:?}", other),
+        }
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+        let response = cached_file.respond_to(request.inner()).unwrap();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.headers().get_one("Content-Range"), Some("bytes 1000-1100/8192"));
+
+        // Open-ended ranges are cut off after a few chunks.
+        let request = client.get("/").header(Header::new("Range", "bytes=0-"));
+        match cache.get_with_request(&path, request.inner()).await {
+            CachedFile::Partial(file) => assert_eq!(file.bytes(), &contents[..4096]),
+            other => panic!("Expected a partial file, got {:?}", other),
+        }
+
+        // Requests without a range are served like before.
+        let request = client.get("/");
+        match cache.get_with_request(&path, request.inner()).await {
+            CachedFile::FileSystem(_) => {}
+            other => panic!("Expected the file to be served from the filesystem, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn chunks_of_modified_files_are_read_again() {
+        let temp_dir = TempDir::new("range_chunks").unwrap();
+        let path: PathBuf = temp_dir.path().join("video.mp4");
+        std::fs::write(&path, vec![b'a'; 4096]).unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .max_file_size(1024)
+            .range_chunk_size(1024)
+            .build()
+            .unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.get("/").header(Header::new("Range", "bytes=0-9"));
+        cache.get_with_request(&path, request.inner()).await;
+
+        std::fs::write(&path, vec![b'b'; 5000]).unwrap();
+        match cache.get_with_request(&path, request.inner()).await {
+            CachedFile::Partial(file) => {
+                assert_eq!(file.bytes(), &[b'b'; 10][..]);
+                assert_eq!(file.size(), 5000);
+            }
+            other => panic!("Expected a partial file, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn removing_a_file_removes_its_chunks() {
+        let temp_dir = TempDir::new("range_chunks").unwrap();
+        let path: PathBuf = temp_dir.path().join("video.mp4");
+        let other_path: PathBuf = temp_dir.path().join("other.mp4");
+        std::fs::write(&path, vec![b'a'; 4096]).unwrap();
+        std::fs::write(&other_path, vec![b'a'; 4096]).unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .max_file_size(1024)
+            .range_chunk_size(1024)
+            .build()
+            .unwrap();
+        let client = Client::untracked(rocket::build()).await.unwrap();
+        let request = client.get("/").header(Header::new("Range", "bytes=1000-1100"));
+        cache.get_with_request(&path, request.inner()).await;
+        cache.get_with_request(&other_path, request.inner()).await;
+        assert_eq!(cache.file_map.iter().count(), 4);
+
+        assert!(!cache.remove(&path));
+        assert_eq!(cache.file_map.iter().count(), 2);
+        assert!(cache.file_map.iter().all(|(key, _)| file_of_key(key) == other_path));
+
+        cache.invalidate_glob(other_path.to_str().unwrap()).unwrap();
+        assert!(cache.file_map.iter().next().is_none());
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use tokio::task::JoinHandle;
+
+use crate::cache::Cache;
+use crate::cached_file::CachedFile;
+use crate::in_memory_file::InMemoryFile;
+use crate::lock;
+
+
+/// Keeps track of the files that have changed on disk and are being read again in the background,
+/// while their old contents are still being served.
+#[derive(Debug)]
+pub(crate) struct Revalidation {
+    /// How long a file can have been changed for before requests wait for it to be read again.
+    max_staleness: Duration,
+    pending: Mutex<HashMap<PathBuf, JoinHandle<io::Result<InMemoryFile>>>>,
+}
+
+impl Revalidation {
+    pub(crate) fn new(max_staleness: Duration) -> Revalidation {
+        Revalidation {
+            max_staleness,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<HashMap<PathBuf, JoinHandle<io::Result<InMemoryFile>>>> {
+        lock::lock(&self.pending)
+    }
+}
+
+
+impl Cache {
+    /// Brings the file in the cache up to date with the file on disk, if stale-while-revalidate is enabled.
+    ///
+    /// A file that was read again in the background replaces the old contents once the read is done.
+    /// Otherwise, the modification time of the file on disk is compared to the one that was read into the cache.
+    /// If the file on disk is newer, it is read again in the background, unless it changed more than
+    /// `max_staleness` ago, in which case it is refreshed right away and the refreshed file is returned.
+    ///
+    /// Returns `None` if the file in the cache should be served.
+    pub(crate) async fn revalidate(&self, path: &PathBuf) -> Option<CachedFile> {
+        let revalidation: &Revalidation = self.revalidation.as_ref()?;
+
+        let finished: Option<JoinHandle<io::Result<InMemoryFile>>> = {
+            let mut pending = revalidation.pending();
+            match pending.get(path) {
+                Some(handle) if handle.is_finished() => pending.remove(path),
+                // The file is still being read, so the old contents are served in the meantime.
+                Some(_) => return None,
+                None => None,
+            }
+        };
+        if let Some(handle) = finished {
+            match handle.await {
+                Ok(Ok(new_file)) => {
+                    debug!("Replacing file: {:?} with the contents that were read in the background.", path);
+                    self.replace_contents(path, new_file);
+                }
+                Ok(Err(e)) => warn!("Could not read {:?} again: {}", path, e),
+                Err(e) => warn!("Reading {:?} again failed: {}", path, e),
+            }
+            return None;
+        }
+
+        let cached_modified: SystemTime = self.file_map.find(path)?.get().stats.modified?;
+        let modified: SystemTime = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+        if modified <= cached_modified {
+            return None;
+        }
+
+        let staleness: Duration = SystemTime::now().duration_since(modified).unwrap_or_default();
+        if staleness > revalidation.max_staleness {
+            debug!("File: {:?} has been stale for too long, refreshing it.", path);
+            return Some(self.refresh(path).await);
+        }
+
+        debug!("File: {:?} is stale, reading it again in the background.", path);
+        let file_reader = self.file_reader();
+        let background_path: PathBuf = path.clone();
+        let handle = tokio::spawn(async move { file_reader.read(&background_path).await });
+        revalidation.pending().insert(path.clone(), handle);
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+
+    /// Writes the contents to the file, making sure its modification time changes.
+    fn write_later(path: &PathBuf, contents: &[u8], seconds: u64) {
+        std::fs::write(path, contents).unwrap();
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600) + Duration::from_secs(seconds)).unwrap();
+    }
+
+    async fn get_bytes(cache: &Cache, path: &PathBuf) -> Vec<u8> {
+        match cache.get(path).await {
+            CachedFile::InMemory(file) => file.bytes().to_vec(),
+            other => panic!("Expected the file to be in the cache, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_files_are_served_while_revalidating() {
+        let temp_dir = TempDir::new("revalidate").unwrap();
+        let path: PathBuf = temp_dir.path().join("app.js");
+        write_later(&path, b"old", 0);
+
+        let cache: Cache = CacheBuilder::new()
+            .stale_while_revalidate(Duration::from_secs(60 * 60 * 2))
+            .build()
+            .unwrap();
+        assert_eq!(get_bytes(&cache, &path).await, b"old");
+
+        write_later(&path, b"new", 1);
+        // The old contents are served right away, while the new ones are read.
+        assert_eq!(get_bytes(&cache, &path).await, b"old");
+
+        let mut bytes: Vec<u8> = get_bytes(&cache, &path).await;
+        for _ in 0..100 {
+            if bytes == b"new" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            bytes = get_bytes(&cache, &path).await;
+        }
+        assert_eq!(bytes, b"new");
+    }
+
+    #[tokio::test]
+    async fn files_that_are_too_stale_are_refreshed_right_away() {
+        let temp_dir = TempDir::new("revalidate").unwrap();
+        let path: PathBuf = temp_dir.path().join("app.js");
+        write_later(&path, b"old", 0);
+
+        let cache: Cache = CacheBuilder::new()
+            .stale_while_revalidate(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        assert_eq!(get_bytes(&cache, &path).await, b"old");
+
+        // The new file was modified almost an hour ago.
+        write_later(&path, b"new", 1);
+        assert_eq!(get_bytes(&cache, &path).await, b"new");
+    }
+}
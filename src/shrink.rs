@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::cache::Cache;
+use crate::eviction_policy;
+use crate::in_memory_file::FileStats;
+
+
+/// The number of files that are evicted before `shrink_to()` lets other tasks run.
+const EVICTIONS_PER_YIELD: usize = 64;
+
+
+/// How `Cache::shrink_to()` treats pinned files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinnedFiles {
+    /// Pinned files are never evicted, so the cache may stay larger than the target.
+    Keep,
+    /// Pinned files are only evicted if the cache is still larger than the target once every other file has been evicted.
+    EvictLast,
+}
+
+/// The files that were evicted by `Cache::shrink_to()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShrinkSummary {
+    /// The sum of the sizes of the files that were evicted.
+    pub freed_bytes: usize,
+    /// The number of files that were evicted.
+    pub freed_entries: usize,
+}
+
+
+impl Cache {
+    /// Evicts files according to the cache's eviction policy until it holds at most `target_bytes`,
+    /// to give memory back quickly, for example when the process is under memory pressure.
+    ///
+    /// Unlike `set_capacity_bytes()`, the capacity of the cache isn't changed, so it can fill up again afterwards.
+    /// Evicted files are counted in the stats, and passed to the eviction callback.
+    /// Pinned files that are evicted stay pinned, so they are kept once they are read into the cache again.
+    /// The task yields to the runtime every few files, so a large cache can be shrunk without
+    /// holding up other requests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder, PinnedFiles};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().size_limit(1024 * 1024 * 10).build().unwrap();
+    /// // Under memory pressure, free up to a quarter of the cache.
+    /// let summary = cache.shrink_to(cache.used_bytes() / 4 * 3, PinnedFiles::Keep).await;
+    /// println!("Freed {} bytes from {} files", summary.freed_bytes, summary.freed_entries);
+    /// # }
+    /// ```
+    pub async fn shrink_to(&self, target_bytes: usize, pinned_files: PinnedFiles) -> ShrinkSummary {
+        let (pinned, unpinned): (Vec<(PathBuf, FileStats)>, Vec<(PathBuf, FileStats)>) = self.current_stats()
+            .into_iter()
+            .partition(|&(ref path, _)| self.is_pinned(path));
+
+        let mut summary: ShrinkSummary = self.evict_down_to(target_bytes, unpinned.into_iter().collect()).await;
+        if pinned_files == PinnedFiles::EvictLast {
+            let pinned_summary: ShrinkSummary = self.evict_down_to(target_bytes, pinned.into_iter().collect()).await;
+            summary.freed_bytes += pinned_summary.freed_bytes;
+            summary.freed_entries += pinned_summary.freed_entries;
+        }
+        summary
+    }
+
+    /// Evicts candidates until the cache holds at most `target_bytes`, or the candidates run out.
+    async fn evict_down_to(&self, target_bytes: usize, candidates: HashMap<PathBuf, FileStats>) -> ShrinkSummary {
+        let mut summary = ShrinkSummary::default();
+        let used_bytes: usize = self.used_bytes();
+        if used_bytes <= target_bytes {
+            return summary;
+        }
+        let victims: Vec<(PathBuf, FileStats)> = eviction_policy::select_victims(
+            &*self.eviction_policy,
+            self.victim_order,
+            candidates,
+            used_bytes - target_bytes,
+            0,
+        );
+        for (victim_key, _) in victims {
+            // The file may have been removed by another task since the stats were collected.
+            if let Some(file) = self.remove_entry(&victim_key) {
+                self.record_eviction(&victim_key, &file);
+                summary.freed_bytes += file.stats.size;
+                summary.freed_entries += 1;
+                if summary.freed_entries % EVICTIONS_PER_YIELD == 0 {
+                    tokio::task::yield_now().await;
+                }
+            }
+        }
+        summary
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn pinned_files_are_only_evicted_as_a_last_resort() {
+        let mut cache: Cache = CacheBuilder::new()
+            .size_limit(1024 * 10)
+            .build()
+            .unwrap();
+        let evicted: Arc<Mutex<Vec<PathBuf>>> = Arc::new(Mutex::new(Vec::new()));
+        let evicted_ref = evicted.clone();
+        cache.on_evict(Box::new(move |path, _| evicted_ref.lock().unwrap().push(path.to_path_buf())));
+
+        cache.pin("www/pinned.js");
+        for name in &["www/pinned.js", "www/a.js", "www/b.js", "www/c.js"] {
+            cache.insert_bytes(name, vec![b'a'; 1024]).await;
+        }
+
+        let summary: ShrinkSummary = cache.shrink_to(2048, PinnedFiles::Keep).await;
+        assert_eq!(summary, ShrinkSummary { freed_bytes: 2048, freed_entries: 2 });
+        assert_eq!(cache.used_bytes(), 2048);
+        assert!(cache.contains_key("www/pinned.js"));
+        assert_eq!(evicted.lock().unwrap().len(), 2);
+
+        let summary: ShrinkSummary = cache.shrink_to(0, PinnedFiles::Keep).await;
+        assert_eq!(summary, ShrinkSummary { freed_bytes: 1024, freed_entries: 1 });
+        assert_eq!(cache.keys(), vec![PathBuf::from("www/pinned.js")]);
+
+        let summary: ShrinkSummary = cache.shrink_to(0, PinnedFiles::EvictLast).await;
+        assert_eq!(summary, ShrinkSummary { freed_bytes: 1024, freed_entries: 1 });
+        assert_eq!(cache.used_bytes(), 0);
+        assert!(cache.is_pinned("www/pinned.js"));
+        assert_eq!(evicted.lock().unwrap().len(), 4);
+        assert_eq!(cache.capacity_bytes(), 1024 * 10);
+    }
+}
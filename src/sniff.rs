@@ -0,0 +1,64 @@
+use rocket::http::ContentType;
+
+
+/// The number of bytes at the start of a file that are looked at to detect its type.
+pub(crate) const SNIFF_LEN: usize = 16;
+
+/// The signatures at the start of files of common types, and the types they indicate.
+/// Within the signatures, `None` matches any byte.
+const SIGNATURES: &[(&[Option<u8>], fn() -> ContentType)] = &[
+    (&[Some(0x89), Some(b'P'), Some(b'N'), Some(b'G'), Some(b'\r'), Some(b'\n'), Some(0x1A), Some(b'\n')], || ContentType::PNG),
+    (&[Some(0xFF), Some(0xD8), Some(0xFF)], || ContentType::JPEG),
+    (&[Some(b'G'), Some(b'I'), Some(b'F'), Some(b'8'), None, Some(b'a')], || ContentType::GIF),
+    (&[Some(b'R'), Some(b'I'), Some(b'F'), Some(b'F'), None, None, None, None, Some(b'W'), Some(b'E'), Some(b'B'), Some(b'P')], || ContentType::WEBP),
+    (&[Some(b'B'), Some(b'M')], || ContentType::BMP),
+    (&[Some(b'%'), Some(b'P'), Some(b'D'), Some(b'F'), Some(b'-')], || ContentType::PDF),
+    (&[Some(0x1F), Some(0x8B)], || ContentType::GZIP),
+    (&[Some(b'P'), Some(b'K'), Some(0x03), Some(0x04)], || ContentType::ZIP),
+    (&[Some(0x00), Some(b'a'), Some(b's'), Some(b'm')], || ContentType::WASM),
+    (&[Some(b'w'), Some(b'O'), Some(b'F'), Some(b'F')], || ContentType::WOFF),
+    (&[Some(b'w'), Some(b'O'), Some(b'F'), Some(b'2')], || ContentType::WOFF2),
+];
+
+/// Detects the type of a file from the signature at the start of its contents.
+///
+/// Only the first `SNIFF_LEN` bytes are looked at.
+/// Returns `None` if the contents don't start with any of the known signatures.
+pub(crate) fn sniff(bytes: &[u8]) -> Option<ContentType> {
+    let prefix: &[u8] = &bytes[..usize::min(bytes.len(), SNIFF_LEN)];
+    SIGNATURES.iter()
+        .find(|&&(signature, _)| matches_signature(prefix, signature))
+        .map(|&(_, content_type)| content_type())
+}
+
+fn matches_signature(prefix: &[u8], signature: &[Option<u8>]) -> bool {
+    prefix.len() >= signature.len()
+        && signature.iter()
+            .zip(prefix)
+            .all(|(expected, &byte)| expected.map_or(true, |expected| expected == byte))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_common_types() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(ContentType::PNG));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\0\x10JFIF"), Some(ContentType::JPEG));
+        assert_eq!(sniff(b"GIF89a\x01\0"), Some(ContentType::GIF));
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some(ContentType::WEBP));
+        assert_eq!(sniff(b"%PDF-1.7\n"), Some(ContentType::PDF));
+        assert_eq!(sniff(b"\x1f\x8b\x08\0"), Some(ContentType::GZIP));
+        assert_eq!(sniff(b"PK\x03\x04\x14\0"), Some(ContentType::ZIP));
+    }
+
+    #[test]
+    fn inconclusive_contents_are_not_detected() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"\x89PN"), None);
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff(b"plain text"), None);
+    }
+}
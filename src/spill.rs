@@ -0,0 +1,431 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+use bytes::Bytes;
+
+use crate::cache::Cache;
+use crate::cached_file::CachedFile;
+use crate::in_memory_file::InMemoryFile;
+use crate::named_in_memory_file::NamedInMemoryFile;
+use crate::range_chunks;
+use crate::lock;
+
+
+/// The start of the names of the files the spill writes, so only the files it owns are cleaned up.
+const SPILL_PREFIX: &str = "rocket-file-cache-";
+/// The extension of the files the spill writes.
+const SPILL_EXTENSION: &str = "spill";
+
+
+/// Writes the files that are evicted from the cache to a directory, so they can be read back from
+/// there instead of from where they came from.
+pub(crate) struct Spill {
+    shared: Arc<SpillShared>,
+}
+
+/// The parts of the spill that are shared with the thread that writes evicted files to the directory.
+struct SpillShared {
+    dir: PathBuf,
+    size_limit: usize,
+    state: Mutex<SpillState>,
+}
+
+#[derive(Default)]
+struct SpillState {
+    /// Files that were evicted, which are waiting to be written to the directory.
+    queued: VecDeque<(PathBuf, SpilledContents)>,
+    /// The file that is being written to the directory, so it can still be read back while it is written.
+    writing: Option<(PathBuf, SpilledContents)>,
+    /// Set if the file that is being written was read back or removed while it was written, so it is thrown away.
+    writing_discarded: bool,
+    /// Set while a thread is writing the queued files.
+    writer_running: bool,
+    /// Set once the spill has been dropped, after which nothing is written anymore.
+    closed: bool,
+    entries: HashMap<PathBuf, SpilledFile>,
+    /// The keys of the files in the directory, oldest first.
+    order: VecDeque<PathBuf>,
+    bytes: usize,
+    next_id: u64,
+}
+
+/// A file that has been written to the spill directory.
+struct SpilledFile {
+    spill_path: PathBuf,
+    size: usize,
+    modified: Option<SystemTime>,
+    generation: usize,
+}
+
+/// The contents of a spilled file, and what is needed to tell if they are still valid.
+#[derive(Clone)]
+pub(crate) struct SpilledContents {
+    pub(crate) bytes: Bytes,
+    pub(crate) modified: Option<SystemTime>,
+    pub(crate) generation: usize,
+}
+
+impl Spill {
+    /// Creates the directory if it doesn't exist, and removes the files an earlier spill left in it.
+    /// Other files in the directory are left alone.
+    pub(crate) fn new(dir: &Path, size_limit: usize) -> io::Result<Spill> {
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(dir)? {
+            let path: PathBuf = entry?.path();
+            if is_spill_file(&path) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(Spill {
+            shared: Arc::new(SpillShared {
+                dir: dir.to_path_buf(),
+                size_limit,
+                state: Mutex::new(SpillState::default()),
+            }),
+        })
+    }
+
+    /// Queues a file that was evicted to be written to the directory, and starts writing it.
+    /// Files that are larger than the whole spill, memory mapped, or chunks of ranges are skipped,
+    /// as they are cheap to read from the filesystem again.
+    ///
+    /// The files are written by a blocking task, so evicting a file doesn't wait for the disk,
+    /// and the memory they take up is freed as soon as they have been written.
+    /// Without a runtime to spawn the task on, the file is written before this returns.
+    pub(crate) fn offer(&self, path: &Path, file: &InMemoryFile) {
+        if file.stats.size > self.shared.size_limit || file.mapped || range_chunks::is_chunk_key(path) {
+            // An older copy of the file may still be in the spill, which shouldn't be served in place of this one.
+            self.discard_matching(|key| key == path);
+            return;
+        }
+        let contents = SpilledContents {
+            bytes: file.bytes.clone(),
+            modified: file.stats.modified,
+            generation: file.generation,
+        };
+        {
+            let mut state = self.shared.lock();
+            if state.closed {
+                return;
+            }
+            state.discard_queued(|queued| queued == path);
+            state.queued.push_back((path.to_path_buf(), contents));
+            if state.writer_running {
+                return;
+            }
+            state.writer_running = true;
+        }
+        let shared: Arc<SpillShared> = self.shared.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || shared.write_queued());
+            }
+            Err(_) => shared.write_queued(),
+        }
+    }
+
+    /// The number of bytes in the directory.
+    pub(crate) fn used_bytes(&self) -> usize {
+        self.shared.lock().bytes
+    }
+
+    /// Returns true if no evicted files are waiting to be written.
+    #[cfg(test)]
+    pub(crate) fn is_idle(&self) -> bool {
+        !self.shared.lock().writer_running
+    }
+
+    /// Takes the file out of the spill, reading it from the directory if it has been written there.
+    pub(crate) async fn take(&self, path: &Path) -> Option<SpilledContents> {
+        let spilled: SpilledFile = {
+            let mut state = self.shared.lock();
+            if let Some(index) = state.queued.iter().position(|&(ref queued, _)| queued == path) {
+                return state.queued.remove(index).map(|(_, contents)| contents);
+            }
+            let written: Option<SpilledContents> = match state.writing {
+                Some((ref writing, ref contents)) if writing == path && !state.writing_discarded => Some(contents.clone()),
+                _ => None,
+            };
+            if written.is_some() {
+                state.writing_discarded = true;
+                return written;
+            }
+            state.remove(path)?
+        };
+        let bytes: io::Result<Vec<u8>> = tokio::fs::read(&spilled.spill_path).await;
+        let _ = tokio::fs::remove_file(&spilled.spill_path).await;
+        match bytes {
+            Ok(bytes) => Some(SpilledContents {
+                bytes: Bytes::from(bytes),
+                modified: spilled.modified,
+                generation: spilled.generation,
+            }),
+            Err(e) => {
+                warn!("Could not read {:?} back from {:?}: {}", path, spilled.spill_path, e);
+                None
+            }
+        }
+    }
+
+    /// Removes the files whose keys match the predicate from the spill.
+    pub(crate) fn discard_matching<F: Fn(&Path) -> bool>(&self, predicate: F) {
+        let obsolete: Vec<PathBuf> = {
+            let mut state = self.shared.lock();
+            state.discard_queued(&predicate);
+            let matching: Vec<PathBuf> = state.entries
+                .keys()
+                .filter(|key| predicate(key))
+                .cloned()
+                .collect();
+            matching.iter()
+                .filter_map(|key| state.remove(key))
+                .map(|spilled| spilled.spill_path)
+                .collect()
+        };
+        for spill_path in obsolete {
+            let _ = fs::remove_file(spill_path);
+        }
+    }
+}
+
+impl SpillShared {
+    fn lock(&self) -> MutexGuard<SpillState> {
+        lock::lock(&self.state)
+    }
+
+    /// Writes the queued files to the directory one after the other, removing the oldest spilled files
+    /// to stay within the size limit, until no more files are queued.
+    fn write_queued(&self) {
+        loop {
+            let (key, contents, spill_path): (PathBuf, SpilledContents, PathBuf) = {
+                let mut state = self.lock();
+                let (key, contents) = match state.queued.pop_front() {
+                    Some(queued) if !state.closed => queued,
+                    _ => {
+                        state.writer_running = false;
+                        return;
+                    }
+                };
+                state.next_id += 1;
+                let spill_path: PathBuf = self.dir.join(format!("{}{:016x}.{}", SPILL_PREFIX, state.next_id, SPILL_EXTENSION));
+                state.writing = Some((key.clone(), contents.clone()));
+                state.writing_discarded = false;
+                (key, contents, spill_path)
+            };
+
+            let written: io::Result<()> = fs::write(&spill_path, &contents.bytes);
+            let size: usize = contents.bytes.len();
+            let SpilledContents { modified, generation, .. } = contents;
+
+            let mut obsolete: Vec<PathBuf> = Vec::new();
+            {
+                let mut state = self.lock();
+                state.writing = None;
+                match written {
+                    Err(e) => warn!("Could not spill {:?} to {:?}: {}", key, spill_path, e),
+                    // The copy was read back or removed while it was written, or the spill is gone.
+                    Ok(()) if state.writing_discarded || state.closed => obsolete.push(spill_path),
+                    Ok(()) => {
+                        obsolete.extend(state.remove(&key).map(|spilled| spilled.spill_path));
+                        while state.bytes + size > self.size_limit {
+                            let oldest: PathBuf = match state.order.front() {
+                                Some(oldest) => oldest.clone(),
+                                None => break,
+                            };
+                            obsolete.extend(state.remove(&oldest).map(|spilled| spilled.spill_path));
+                        }
+                        state.order.push_back(key.clone());
+                        state.bytes += size;
+                        state.entries.insert(key, SpilledFile {
+                            spill_path,
+                            size,
+                            modified,
+                            generation,
+                        });
+                    }
+                }
+            }
+            for spill_path in obsolete {
+                let _ = fs::remove_file(spill_path);
+            }
+        }
+    }
+}
+
+impl SpillState {
+    /// Forgets the spilled file. The file it was written to is left for the caller to remove.
+    fn remove(&mut self, key: &Path) -> Option<SpilledFile> {
+        let spilled: SpilledFile = self.entries.remove(key)?;
+        self.order.retain(|spilled_key| spilled_key != key);
+        self.bytes -= spilled.size;
+        Some(spilled)
+    }
+
+    /// Forgets the queued files whose keys match the predicate, and throws away the file that is
+    /// being written once it is done, if its key matches.
+    fn discard_queued<F: Fn(&Path) -> bool>(&mut self, predicate: F) {
+        self.queued.retain(|&(ref queued, _)| !predicate(queued));
+        if let Some((ref writing, _)) = self.writing {
+            if predicate(writing) {
+                self.writing_discarded = true;
+            }
+        }
+    }
+}
+
+/// The spilled files only make sense to the process that wrote them, so they are removed along with the cache.
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.closed = true;
+        state.queued.clear();
+        for spilled in state.entries.values() {
+            let _ = fs::remove_file(&spilled.spill_path);
+        }
+        state.entries.clear();
+    }
+}
+
+/// Returns true if the file was written by a spill.
+fn is_spill_file(path: &Path) -> bool {
+    let name_is_owned: bool = path.file_name().map_or(false, |name| name.to_string_lossy().starts_with(SPILL_PREFIX));
+    name_is_owned && path.extension().map_or(false, |ext| ext == SPILL_EXTENSION)
+}
+
+
+impl Cache {
+    /// The number of bytes that files evicted from the cache take up in the spill directory,
+    /// or 0 if the cache wasn't built with `CacheBuilder::spill_to_disk()`.
+    ///
+    /// Evicted files are written to the directory in the background,
+    /// so they are only counted once they have been written.
+    pub fn spilled_bytes(&self) -> usize {
+        self.spill.as_ref().map_or(0, Spill::used_bytes)
+    }
+
+    /// Offers a file that was evicted to the spill, if there is one.
+    pub(crate) fn spill_evicted(&self, path: &Path, file: &InMemoryFile) {
+        if let Some(ref spill) = self.spill {
+            spill.offer(path, file);
+        }
+    }
+
+    /// Reads the file back from the spill into the cache, if it was spilled.
+    ///
+    /// Spilled copies are discarded if they were read before the generation was bumped, or if the
+    /// file they were read from has been modified since.
+    /// If the cache won't take the file back, it is served anyway, and spilled again.
+    pub(crate) async fn get_spilled_file(&self, path: &PathBuf) -> Option<CachedFile> {
+        let spill: &Spill = self.spill.as_ref()?;
+        let contents: SpilledContents = spill.take(path).await?;
+        if contents.generation != self.generation.load(Ordering::Relaxed) {
+            debug!("Spilled copy of {:?} is from an older generation, discarding it.", path);
+            return None;
+        }
+        // Generated contents may not exist in the filesystem at all, in which case the copy is all there is.
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            let modified: Option<SystemTime> = metadata.modified().ok();
+            if contents.modified.is_some() && modified.is_some() && contents.modified != modified {
+                debug!("File: {:?} was modified after it was spilled, discarding the spilled copy.", path);
+                return None;
+            }
+        }
+
+        debug!("Reading {:?} back from the spill.", path);
+        let file: InMemoryFile = InMemoryFile::from_bytes_with_etag_mode(contents.bytes, contents.modified, self.hash_algorithm, self.etag_mode);
+        let file: InMemoryFile = self.prepare_file(path, file);
+        self.increment_access_count(path);
+        if self.insert_loaded_file(path.clone(), file.clone()) {
+            return Some(self.get_from_cache(path).await);
+        }
+        spill.offer(path, &file);
+        Some(CachedFile::from(NamedInMemoryFile {
+            path: path.clone(),
+            file,
+            headers: Vec::new(),
+            cors: None,
+        }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use crate::cache_builder::CacheBuilder;
+    use std::time::Duration;
+
+    /// Waits until the files that were evicted have been written to the spill.
+    fn wait_for_spill(cache: &Cache) {
+        while !cache.spill.as_ref().unwrap().is_idle() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[tokio::test]
+    async fn evicted_files_are_read_back_from_the_spill() {
+        let spill_dir = TempDir::new("spill").unwrap();
+        std::fs::write(spill_dir.path().join("rocket-file-cache-left-over.spill"), b"old").unwrap();
+        std::fs::write(spill_dir.path().join("notes.spill"), b"someone else's").unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1500)
+            .spill_to_disk(spill_dir.path(), 1500)
+            .build()
+            .unwrap();
+        assert!(!spill_dir.path().join("rocket-file-cache-left-over.spill").exists());
+        assert!(spill_dir.path().join("notes.spill").exists());
+
+        // The generated files don't exist anywhere but in the cache.
+        cache.insert_bytes("generated/a.html", vec![b'a'; 1000]).await;
+        cache.insert_bytes("generated/b.html", vec![b'b'; 1000]).await;
+        assert!(!cache.contains_key("generated/a.html"));
+
+        // The evicted file is read back from the spill, whether or not it has been written yet.
+        let cached_file: CachedFile = cache.get("generated/a.html").await;
+        assert_eq!(cached_file.as_bytes(), Some(&[b'a'; 1000][..]));
+        assert!(cache.contains_key("generated/a.html"));
+        wait_for_spill(&cache);
+        assert_eq!(cache.spilled_bytes(), 1000);
+
+        // Removing a file removes its spilled copy as well.
+        cache.remove("generated/b.html");
+        assert_eq!(cache.spilled_bytes(), 0);
+        assert_eq!(cache.get("generated/b.html").await, CachedFile::NotFound);
+
+        cache.insert_bytes("generated/d.html", vec![b'd'; 1000]).await;
+        wait_for_spill(&cache);
+        assert_eq!(cache.spilled_bytes(), 1000);
+        cache.clear();
+        assert_eq!(cache.spilled_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn spill_stays_within_its_size_limit() {
+        let spill_dir = TempDir::new("spill").unwrap();
+        let cache: Cache = CacheBuilder::new()
+            .size_limit(1000)
+            .spill_to_disk(spill_dir.path(), 1500)
+            .build()
+            .unwrap();
+        cache.insert_bytes("generated/a.html", vec![b'a'; 1000]).await;
+        cache.insert_bytes("generated/b.html", vec![b'b'; 1000]).await;
+        cache.insert_bytes("generated/c.html", vec![b'c'; 1000]).await;
+        wait_for_spill(&cache);
+
+        // Only the most recently evicted file fits.
+        assert_eq!(cache.spilled_bytes(), 1000);
+        assert_eq!(cache.get("generated/a.html").await, CachedFile::NotFound);
+        assert!(cache.get("generated/b.html").await.is_in_memory());
+        wait_for_spill(&cache);
+        drop(cache);
+        assert_eq!(std::fs::read_dir(spill_dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,330 @@
+use rocket::response::{Response, Responder};
+use rocket::http::{Status, ContentType, Header};
+use rocket::request::Request;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, SeekFrom};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::result;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use tokio::sync::OwnedSemaphorePermit;
+use bytes::Bytes;
+
+use crate::in_memory_file::ensure_regular_file;
+use crate::cors::Cors;
+use crate::lock;
+
+
+/// The contents of a file that was streamed to a client in full.
+#[derive(Debug)]
+pub(crate) struct StreamedContents {
+    pub(crate) path: PathBuf,
+    pub(crate) bytes: Bytes,
+    pub(crate) modified: Option<SystemTime>,
+}
+
+/// The files whose contents are collected by streams.
+#[derive(Debug, Default)]
+pub(crate) struct StreamedFiles {
+    /// The paths of the files that a stream is collecting the contents of.
+    /// Only one stream collects the contents of a file at a time, so concurrent misses don't each hold a copy.
+    teeing: HashSet<PathBuf>,
+    /// The contents of the files that have been sent in full, until the cache adds them.
+    pub(crate) completed: Vec<StreamedContents>,
+}
+
+/// The streamed files, along with a flag that tells whether any of them have been sent in full,
+/// so the cache doesn't have to lock them on every request to find out.
+#[derive(Debug, Default)]
+pub(crate) struct Streams {
+    files: Mutex<StreamedFiles>,
+    has_completed: AtomicBool,
+}
+
+impl Streams {
+    /// Returns true if a stream has completed since this was last called.
+    pub(crate) fn take_has_completed(&self) -> bool {
+        self.has_completed.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Where streams leave the contents of files once they have been sent in full, until the cache adds them.
+pub(crate) type CompletedStreams = Arc<Streams>;
+
+/// Locks the streamed files.
+pub(crate) fn lock_streams(streams: &CompletedStreams) -> MutexGuard<StreamedFiles> {
+    lock::lock(&streams.files)
+}
+
+/// The right of a stream to collect the contents of a file, which is given up when it is dropped.
+pub(crate) struct TeeClaim {
+    path: PathBuf,
+    streams: CompletedStreams,
+}
+
+impl TeeClaim {
+    /// Claims the file, unless another stream is already collecting its contents.
+    pub(crate) fn claim(streams: &CompletedStreams, path: &Path) -> Option<TeeClaim> {
+        if !lock_streams(streams).teeing.insert(path.to_path_buf()) {
+            return None;
+        }
+        Some(TeeClaim {
+            path: path.to_path_buf(),
+            streams: streams.clone(),
+        })
+    }
+}
+
+impl Drop for TeeClaim {
+    fn drop(&mut self) {
+        lock_streams(&self.streams).teeing.remove(&self.path);
+    }
+}
+
+
+/// A file that is streamed from the filesystem to the client, while its contents are collected so
+/// they can be added to the cache once the whole file has been sent.
+///
+/// This is created for large files that aren't in the cache yet, if the cache was built with
+/// `CacheBuilder::stream_misses()`, so the response can start before the file has been read.
+/// If the response is dropped before the whole file was sent, because the client disconnected,
+/// the collected contents are discarded.
+///
+/// If the cache was built with `CacheBuilder::max_concurrent_opens_per_path()`, files that are served from
+/// the filesystem are streamed with this as well, without collecting their contents, so the cache can
+/// tell when the response no longer holds the file open. The same goes for caches built with
+/// `CacheBuilder::x_cache_header()`, so the response can carry the header.
+pub struct StreamingFile {
+    pub(crate) path: PathBuf,
+    reader: TeeReader,
+    /// Headers configured by the cache that are added to the response.
+    pub(crate) headers: Vec<Header<'static>>,
+    /// Adds CORS headers to the response, depending on the origin of the request.
+    pub(crate) cors: Option<Arc<Cors>>,
+}
+
+impl fmt::Debug for StreamingFile {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "path: {:?}, size: {}", self.path, self.reader.size)
+    }
+}
+
+impl StreamingFile {
+    /// Opens the file, which will be handed to the cache once it has been read to the end.
+    pub(crate) async fn open(path: PathBuf, claim: TeeClaim) -> io::Result<StreamingFile> {
+        StreamingFile::open_with(path, Some(claim), None).await
+    }
+
+    /// Opens the file without collecting its contents, holding the permit, if there is one, until the file is dropped.
+    pub(crate) async fn open_uncached(path: PathBuf, permit: Option<OwnedSemaphorePermit>) -> io::Result<StreamingFile> {
+        StreamingFile::open_with(path, None, permit).await
+    }
+
+    async fn open_with(path: PathBuf, claim: Option<TeeClaim>, permit: Option<OwnedSemaphorePermit>) -> io::Result<StreamingFile> {
+        // Opening a FIFO blocks until it is opened for writing, so the type is checked before opening it.
+        ensure_regular_file(&tokio::fs::metadata(&path).await?)?;
+        let file: File = File::open(&path).await?;
+        let metadata = file.metadata().await?;
+        let size: usize = metadata.len() as usize;
+        let reader = TeeReader {
+            path: path.clone(),
+            file,
+            size,
+            modified: metadata.modified().ok(),
+            buffer: if claim.is_some() { Vec::with_capacity(size) } else { Vec::new() },
+            // Without anywhere to hand the contents to, there is nothing to collect.
+            done: claim.is_none(),
+            claim,
+            _permit: permit,
+        };
+        Ok(StreamingFile {
+            path,
+            reader,
+            headers: Vec::new(),
+            cors: None,
+        })
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The size of the file when it was opened.
+    pub fn size(&self) -> usize {
+        self.reader.size
+    }
+
+    /// Reads the rest of the file without sending it anywhere, so its contents are handed to the cache.
+    pub(crate) async fn drain(mut self) -> io::Result<()> {
+        tokio::io::copy(&mut self.reader, &mut tokio::io::sink()).await.map(|_| ())
+    }
+}
+
+
+/// Streams the file with a `Content-Type` derived from its extension, and a `Content-Length` of the
+/// size the file had when it was opened.
+///
+/// Any headers configured by the cache, like `Cache-Control` and CORS headers, are added to the response.
+/// Like Rocket's `NamedFile`, the whole file is sent, regardless of the `Range` and conditional
+/// headers of the request. Once the file is in the cache, those headers are respected.
+impl<'r> Responder<'r, 'static> for StreamingFile {
+    fn respond_to(self, request: &'r Request) -> result::Result<Response<'static>, Status> {
+        let mut response = Response::new();
+        if let Some(ext) = self.path.extension() {
+            if let Some(ct) = ContentType::from_extension(&ext.to_string_lossy()) {
+                response.set_header(ct);
+            }
+        }
+
+        for header in self.headers {
+            response.set_header(header);
+        }
+        if let Some(ref cors) = self.cors {
+            cors.apply(request, &mut response);
+        }
+
+        if let Some(modified) = self.reader.modified {
+            response.set_raw_header("Last-Modified", httpdate::fmt_http_date(modified));
+        }
+
+        let size: usize = self.reader.size;
+        response.set_sized_body(size, self.reader);
+        Ok(response)
+    }
+}
+
+
+/// Reads a file, keeping a copy of everything that is read.
+///
+/// Once the end of the file is reached, the copy is handed to the cache, unless the file turned out
+/// to have a different size than it had when it was opened, or the reader was seeked.
+struct TeeReader {
+    path: PathBuf,
+    file: File,
+    size: usize,
+    modified: Option<SystemTime>,
+    buffer: Vec<u8>,
+    /// Set once the copy has been handed over, or discarded.
+    done: bool,
+    /// Keeps other streams from collecting the contents of the file, until the reader is dropped.
+    claim: Option<TeeClaim>,
+    /// Limits how many responses hold the file open at once, until the reader is dropped.
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl TeeReader {
+    /// Hands the copy to the cache if it contains the whole file.
+    fn finish(&mut self) {
+        let buffer: Vec<u8> = mem::take(&mut self.buffer);
+        self.done = true;
+        if buffer.len() != self.size {
+            debug!("File: {:?} changed while it was streamed, it won't be cached.", self.path);
+            return;
+        }
+        let claim: &TeeClaim = match self.claim {
+            Some(ref claim) => claim,
+            None => return,
+        };
+        let contents = StreamedContents {
+            path: self.path.clone(),
+            bytes: Bytes::from(buffer),
+            modified: self.modified,
+        };
+        lock_streams(&claim.streams).completed.push(contents);
+        claim.streams.has_completed.store(true, Ordering::Release);
+    }
+
+    /// Frees the copy, which can no longer be trusted to match the file.
+    fn discard(&mut self) {
+        self.buffer = Vec::new();
+        self.done = true;
+    }
+}
+
+impl AsyncRead for TeeReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this: &mut TeeReader = &mut *self;
+        let filled_before: usize = buf.filled().len();
+        match Pin::new(&mut this.file).poll_read(cx, buf) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Err(e)) => {
+                this.discard();
+                return Poll::Ready(Err(e));
+            }
+            Poll::Ready(Ok(())) => {}
+        }
+        if !this.done {
+            let read: &[u8] = &buf.filled()[filled_before..];
+            if read.is_empty() {
+                this.finish();
+            } else if this.buffer.len() + read.len() > this.size {
+                debug!("File: {:?} grew while it was streamed, it won't be cached.", this.path);
+                this.discard();
+            } else {
+                this.buffer.extend_from_slice(read);
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for TeeReader {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        // The copy is only complete if the file is read from start to end.
+        self.discard();
+        Pin::new(&mut self.file).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Pin::new(&mut self.file).poll_complete(cx)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use self::tempdir::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn contents_are_only_kept_if_read_to_the_end() {
+        let temp_dir = TempDir::new("streaming_file").unwrap();
+        let path: PathBuf = temp_dir.path().join("large.bin");
+        std::fs::write(&path, vec![b'a'; 64 * 1024]).unwrap();
+        let streams: CompletedStreams = CompletedStreams::default();
+
+        // Dropping the file part of the way through, as happens when a client disconnects, discards the copy.
+        let claim: TeeClaim = TeeClaim::claim(&streams, &path).unwrap();
+        let mut partial: StreamingFile = StreamingFile::open(path.clone(), claim).await.unwrap();
+        let mut start = [0; 1024];
+        partial.reader.read_exact(&mut start).await.unwrap();
+        // Only one stream at a time collects the contents of the file.
+        assert!(TeeClaim::claim(&streams, &path).is_none());
+        drop(partial);
+        assert!(lock_streams(&streams).completed.is_empty());
+        assert!(!streams.take_has_completed());
+
+        let claim: TeeClaim = TeeClaim::claim(&streams, &path).unwrap();
+        let full: StreamingFile = StreamingFile::open(path.clone(), claim).await.unwrap();
+        full.drain().await.unwrap();
+        assert!(streams.take_has_completed());
+        let files = lock_streams(&streams);
+        let completed = &files.completed;
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].path, path);
+        assert_eq!(&completed[0].bytes[..], &vec![b'a'; 64 * 1024][..]);
+    }
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use crate::cache::Cache;
+use crate::in_memory_file::InMemoryFile;
+
+
+impl Cache {
+    /// Checks that the contents of the file in the cache haven't been corrupted since it was read,
+    /// by hashing them again and comparing the hash to the one that was taken when the file was read.
+    ///
+    /// The hash is the one that the file's `ETag` is generated from, so it uses the cache's `HashAlgorithm`,
+    /// and isn't a cryptographic hash. It detects accidental corruption, not tampering.
+    /// Memory mapped files also fail verification if the file they are mapped from has been modified.
+    /// A file that fails verification stays in the cache, and can be read again with `reload()`.
+    ///
+    /// # Return
+    ///
+    /// `None` if the file isn't in the cache, or can't be verified because it wasn't hashed when it was read,
+    /// which is the case for files with a weak `ETag` from `EtagMode::WeakSizeMtime`.
+    /// Otherwise, whether its contents are intact.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Cache = CacheBuilder::new().build().unwrap();
+    /// cache.insert_bytes("www/robots.txt", b"User-agent: *".to_vec()).await;
+    /// assert_eq!(cache.verify("www/robots.txt"), Some(true));
+    /// assert_eq!(cache.verify("www/missing.txt"), None);
+    /// # }
+    /// ```
+    pub fn verify<P: AsRef<Path>>(&self, path: P) -> Option<bool> {
+        // The contents are hashed after the lock is released, as hashing large files takes a while.
+        let file: InMemoryFile = self.file_map.find(&self.normalize_key(path))?.get().clone();
+        file.verify()
+    }
+
+    /// Checks every file in the cache like `verify()` does, returning their paths and whether their
+    /// contents are intact, sorted by path. Files that can't be verified are left out.
+    pub fn verify_all(&self) -> Vec<(PathBuf, bool)> {
+        let files: Vec<(PathBuf, InMemoryFile)> = self.file_map
+            .iter()
+            .map(|(path, file)| (path.clone(), file.clone()))
+            .collect();
+        let mut results: Vec<(PathBuf, bool)> = files.into_iter()
+            .filter_map(|(path, file)| file.verify().map(|intact| (path, intact)))
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::cache_builder::CacheBuilder;
+
+    #[tokio::test]
+    async fn corrupted_files_fail_verification() {
+        let cache: Cache = CacheBuilder::new().build().unwrap();
+        cache.insert_bytes("www/a.txt", b"aaaa".to_vec()).await;
+        cache.insert_bytes("www/b.txt", b"bbbb".to_vec()).await;
+        assert_eq!(cache.verify_all(), vec![(PathBuf::from("www/a.txt"), true), (PathBuf::from("www/b.txt"), true)]);
+
+        // Overwrite the contents without updating the checksum.
+        if let Some(mut file) = cache.file_map.find_mut(&PathBuf::from("www/b.txt")) {
+            file.get().bytes = Bytes::from_static(b"bbbc");
+        }
+        assert_eq!(cache.verify("www/a.txt"), Some(true));
+        assert_eq!(cache.verify("www/b.txt"), Some(false));
+        assert_eq!(cache.verify("www/c.txt"), None);
+        assert_eq!(cache.verify_all(), vec![(PathBuf::from("www/a.txt"), true), (PathBuf::from("www/b.txt"), false)]);
+    }
+}
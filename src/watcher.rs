@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::cache::Cache;
+
+
+impl Cache {
+    /// Watches a directory for changes, and updates the files in the cache when the files in the
+    /// directory are modified or deleted.
+    ///
+    /// Modified files are refreshed from the filesystem, and deleted files are removed from the cache.
+    /// Editors that save files by writing to a temporary file, then renaming it over the original
+    /// file, are handled as a modification of the original file.
+    ///
+    /// The paths reported by the filesystem are converted back into paths that start with `dir`,
+    /// so `dir` should be provided in the same form as the paths passed to `get()`.
+    /// For example, if files are gotten with `Path::new("www/").join(file)`, then `"www/"` should be watched.
+    ///
+    /// The watcher runs on its own task, and only holds a weak reference to the cache.
+    /// It will stop once the cache is dropped.
+    ///
+    /// This requires the `watch` feature.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rocket_file_cache::{Cache, CacheBuilder};
+    /// use std::sync::Arc;
+    ///
+    /// #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let cache: Arc<Cache> = Arc::new(CacheBuilder::new().build().unwrap());
+    /// let _watcher = cache.clone().watch("www/").unwrap();
+    /// # }
+    /// ```
+    pub fn watch<P: AsRef<Path>>(self: Arc<Self>, dir: P) -> notify::Result<JoinHandle<()>> {
+        let dir: PathBuf = dir.as_ref().to_path_buf();
+        let canonical_dir: PathBuf = dir.canonicalize()?;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Event>();
+        let mut watcher: RecommendedWatcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            match event {
+                Ok(event) => {
+                    let _ = sender.send(event); // The task has stopped if this fails.
+                }
+                Err(e) => warn!("Error watching files: {}", e),
+            }
+        })?;
+        watcher.watch(&canonical_dir, RecursiveMode::Recursive)?;
+
+        let cache: Weak<Cache> = Arc::downgrade(&self);
+        drop(self);
+
+        Ok(tokio::spawn(async move {
+            // The watcher stops watching once it is dropped, so it must be kept alive by the task.
+            let _watcher: RecommendedWatcher = watcher;
+
+            while let Some(event) = receiver.recv().await {
+                if let EventKind::Access(_) = event.kind {
+                    continue; // Reading a file doesn't change it.
+                }
+                let cache: Arc<Cache> = match cache.upgrade() {
+                    Some(cache) => cache,
+                    None => break, // The cache has been dropped.
+                };
+                for path in event.paths {
+                    if let Some(key) = cache_key(&dir, &canonical_dir, &path) {
+                        cache.update_watched_file(key).await;
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Brings the entry for a watched file up to date with the filesystem.
+    async fn update_watched_file(&self, path: PathBuf) {
+        if !self.contains_key(&path) {
+            return;
+        }
+        if path.is_file() {
+            debug!("Watched file {:?} changed, refreshing it.", path);
+            let _ = self.refresh(&path).await;
+        } else {
+            debug!("Watched file {:?} was removed, removing it from the cache.", path);
+            self.remove(&path);
+        }
+    }
+}
+
+/// Converts a path reported by the filesystem watcher into the key used by the cache.
+fn cache_key(dir: &Path, canonical_dir: &Path, path: &Path) -> Option<PathBuf> {
+    path.strip_prefix(canonical_dir)
+        .ok()
+        .map(|relative_path| dir.join(relative_path))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_watched_paths_to_cache_keys() {
+        assert_eq!(
+            cache_key(Path::new("www/"), Path::new("/srv/site/www"), Path::new("/srv/site/www/js/app.js")),
+            Some(PathBuf::from("www/js/app.js"))
+        );
+        assert_eq!(
+            cache_key(Path::new("www/"), Path::new("/srv/site/www"), Path::new("/etc/passwd")),
+            None
+        );
+    }
+}